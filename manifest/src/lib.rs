@@ -0,0 +1,636 @@
+//! The `packed.json` manifest and the sdf font files written by assetpacker, without any of the
+//! dependencies of the packer itself, so game runtimes can read packs with just this crate.
+//!
+//! The types follow semver: fields are only added with serde defaults, so runtimes keep reading
+//! manifests of newer packers, and removing or renaming a field is a breaking change. New texture
+//! formats are not breaking either, match on [`TextureFormat`] with a fallback arm.
+
+use std::collections::{BTreeMap, HashMap};
+
+use glam::{UVec2, Vec2};
+use serde::{Deserialize, Serialize};
+
+pub mod binary;
+pub mod pak;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextureTile {
+    pub atlas: String,
+    pub min: UVec2,
+    pub max: UVec2,
+    /// only present if the transparent border of the image was trimmed before packing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim: Option<Trim>,
+    /// the image was rotated 90° clockwise to fit into the atlas, so the top left pixel of the source
+    /// image sits at the top right corner of the tile
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub rotated: bool,
+    #[serde(default, skip_serializing_if = "TileKind::is_sprite")]
+    pub kind: TileKind,
+    /// borders of a scalable ui panel, only present for images flagged as nine slices
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nine_slice: Option<NineSlice>,
+    /// origin of the sprite in pixels from the top left of the tile (after trimming, before
+    /// rotation), only present if one was given, renderers pick their own default otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pivot: Option<Vec2>,
+    /// draw layer of the sprite for scene builders that sort sprites by it, drawn from low to high.
+    /// Only present if one was given with a flag like `bg` (-1), `fg` (1) or `layer=3` or a config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layer: Option<i32>,
+}
+
+/// Border sizes in pixels of an image that is drawn as a nine slice: the corners keep their size, the
+/// edges stretch along one axis and the center along both. Relative to the unrotated image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NineSlice {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+/// What the pixels of a tile are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TileKind {
+    /// colors with alpha
+    #[default]
+    Sprite,
+    /// a single channel of coverage, e.g. for dissolve or transition effects, in a single channel
+    /// texture. The alpha of the source image, or its luminance if it is opaque.
+    Mask,
+}
+
+impl TileKind {
+    pub fn is_sprite(&self) -> bool {
+        *self == TileKind::Sprite
+    }
+}
+
+/// Where a trimmed tile sits in its source image, so renderers can draw it at the original position.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Trim {
+    /// size of the source image before trimming
+    pub original_size: UVec2,
+    /// position of the top left pixel of the tile in the source image
+    pub offset: UVec2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TextureFlags {
+    pub repeat_x: bool,
+    pub repeat_y: bool,
+    /// all pixels have full alpha, only determined for repeat textures, which are mostly opaque backgrounds
+    #[serde(default)]
+    pub is_opaque: bool,
+    /// file format of the texture, missing in manifests of older versions which only wrote pngs
+    #[serde(default)]
+    pub format: TextureFormat,
+    /// colors are srgb encoded, false for linear data like normal maps, which should be sampled
+    /// without srgb conversion. Missing in manifests of older versions, which only had srgb textures.
+    #[serde(default = "srgb_default")]
+    pub srgb: bool,
+    /// only the red channel is meaningful, written as grayscale png, see [`TileKind::Mask`]
+    #[serde(default)]
+    pub single_channel: bool,
+    /// how the colors relate to the alpha, renderers need a different blend state for premultiplied
+    /// textures. Missing in manifests of older versions, which only had straight alpha.
+    #[serde(default)]
+    pub alpha: AlphaMode,
+    /// the colors are quantized to a palette of at most 256, written as indexed png
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub indexed: bool,
+    /// how the texture should be sampled, `repeat_x` and `repeat_y` are kept next to it for older
+    /// loaders. Missing in manifests of older versions, use [`TextureFlags::sampler`] to read it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampler: Option<Sampler>,
+}
+
+/// Renderer agnostic sampler state of a texture, which maps directly to the samplers of wgpu, vulkan,
+/// metal or opengl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Sampler {
+    pub address_x: AddressMode,
+    pub address_y: AddressMode,
+    /// filter for magnification and minification
+    pub filter: FilterMode,
+}
+
+impl Sampler {
+    /// Linear filtering, clamped or repeated on each axis.
+    pub const fn repeat(repeat_x: bool, repeat_y: bool) -> Sampler {
+        Sampler {
+            address_x: AddressMode::repeat(repeat_x),
+            address_y: AddressMode::repeat(repeat_y),
+            filter: FilterMode::Linear,
+        }
+    }
+}
+
+/// What a texture sampled outside of its uv range from 0 to 1 shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressMode {
+    /// the edge pixels, for atlases and everything that does not repeat
+    #[default]
+    ClampToEdge,
+    Repeat,
+    /// repeated with every other copy mirrored, for images named like `water.mirror.png`, which
+    /// repeat seamlessly without being made tileable
+    MirrorRepeat,
+}
+
+impl AddressMode {
+    pub const fn repeat(repeat: bool) -> AddressMode {
+        match repeat {
+            true => AddressMode::Repeat,
+            false => AddressMode::ClampToEdge,
+        }
+    }
+}
+
+/// Texture filtering between pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterMode {
+    #[default]
+    Linear,
+    /// sharp pixels for pixel art, images named like `hero.nearest.png`
+    Nearest,
+}
+
+/// Alpha of the pixels of a texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum AlphaMode {
+    /// colors are independent of the alpha, blend with `src * src_alpha + dst * (1 - src_alpha)`
+    #[default]
+    Straight,
+    /// colors are already multiplied by the alpha, blend with `src + dst * (1 - src_alpha)`. Sprites
+    /// named like `smoke.pma.png`, or all sprites with the `premultiply_alpha` setting.
+    Premultiplied,
+}
+
+fn srgb_default() -> bool {
+    true
+}
+
+fn scale_default() -> f32 {
+    1.0
+}
+
+/// File format textures are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum TextureFormat {
+    #[default]
+    Png,
+    /// lossless WebP, a lot smaller than png, for builds that are downloaded by browsers
+    Webp,
+    /// rgba8 KTX2 with zlib supercompression, which only needs inflating before it can be uploaded to
+    /// the gpu, with the mip levels inside of the file
+    Ktx2,
+    /// ASTC with 4x4 blocks in a KTX2 file, for mobile gpus
+    Astc4x4,
+    /// ASTC with 6x6 blocks, less than half the memory of 4x4 at lower quality
+    Astc6x6,
+    /// DDS with BC1 compression, for opaque textures on desktop gpus
+    Bc1,
+    /// DDS with BC3 compression
+    Bc3,
+    /// DDS with BC7 compression, the best quality for textures with alpha on desktop gpus
+    Bc7,
+}
+
+impl TextureFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            TextureFormat::Png => "png",
+            TextureFormat::Webp => "webp",
+            TextureFormat::Ktx2 | TextureFormat::Astc4x4 | TextureFormat::Astc6x6 => "ktx2",
+            TextureFormat::Bc1 | TextureFormat::Bc3 | TextureFormat::Bc7 => "dds",
+        }
+    }
+
+    /// Whether textures are common image files, with their mip levels in separate files like
+    /// `atlas_0.mip1.png` instead of inside of the texture file.
+    pub fn is_image_file(self) -> bool {
+        matches!(self, TextureFormat::Png | TextureFormat::Webp)
+    }
+}
+
+impl Default for TextureFlags {
+    fn default() -> Self {
+        TextureFlags::NO_REPEAT
+    }
+}
+
+impl TextureFlags {
+    /// The sampler of the texture, or for manifests of older versions without one, the sampler that
+    /// `repeat_x` and `repeat_y` imply.
+    pub fn sampler(&self) -> Sampler {
+        self.sampler
+            .unwrap_or(Sampler::repeat(self.repeat_x, self.repeat_y))
+    }
+
+    pub const REPEAT: TextureFlags = TextureFlags {
+        repeat_x: true,
+        repeat_y: true,
+        is_opaque: false,
+        format: TextureFormat::Png,
+        srgb: true,
+        single_channel: false,
+        alpha: AlphaMode::Straight,
+        indexed: false,
+        sampler: Some(Sampler::repeat(true, true)),
+    };
+    pub const REPEAT_X: TextureFlags = TextureFlags {
+        repeat_x: true,
+        repeat_y: false,
+        is_opaque: false,
+        format: TextureFormat::Png,
+        srgb: true,
+        single_channel: false,
+        alpha: AlphaMode::Straight,
+        indexed: false,
+        sampler: Some(Sampler::repeat(true, false)),
+    };
+    pub const REPEAT_Y: TextureFlags = TextureFlags {
+        repeat_x: false,
+        repeat_y: true,
+        is_opaque: false,
+        format: TextureFormat::Png,
+        srgb: true,
+        single_channel: false,
+        alpha: AlphaMode::Straight,
+        indexed: false,
+        sampler: Some(Sampler::repeat(false, true)),
+    };
+    pub const NO_REPEAT: TextureFlags = TextureFlags {
+        repeat_x: false,
+        repeat_y: false,
+        is_opaque: false,
+        format: TextureFormat::Png,
+        srgb: true,
+        single_channel: false,
+        alpha: AlphaMode::Straight,
+        indexed: false,
+        sampler: Some(Sampler::repeat(false, false)),
+    };
+    pub const NO_REPEAT_LINEAR: TextureFlags = TextureFlags {
+        srgb: false,
+        ..TextureFlags::NO_REPEAT
+    };
+    pub const NO_REPEAT_MASK: TextureFlags = TextureFlags {
+        srgb: false,
+        single_channel: true,
+        ..TextureFlags::NO_REPEAT
+    };
+}
+
+/// Revision of the manifest format written by this version of the crate. It is bumped when a change
+/// would make older loaders misread manifests, not for added fields that older loaders can ignore.
+///
+/// Revision 2 added [`PackedAssets::file_names`], older loaders would look for files that do not
+/// exist.
+pub const FORMAT_REVISION: u32 = 2;
+
+/// The version fields of a manifest, which can be read from manifests of any revision, to check
+/// them before reading the whole manifest.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ManifestVersion {
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub format_revision: u32,
+}
+
+/// Contents of `packed.json`, the index of everything in a pack. Packs written with the binary manifest
+/// format have a `packed.bin` instead, see [`binary`].
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PackedAssets {
+    /// version of the packer that wrote the manifest, like `0.1.0`. Missing in manifests of older
+    /// versions.
+    #[serde(default)]
+    pub version: String,
+    /// [`FORMAT_REVISION`] of the packer that wrote the manifest, loaders reject manifests of newer
+    /// revisions. 0 for manifests of older versions.
+    #[serde(default)]
+    pub format_revision: u32,
+    pub textures: Vec<(String, TextureFlags)>, // file names
+    pub tiles: HashMap<String, TextureTile>,
+    pub fonts: Vec<String>, // font names
+    /// `None` if the pack has no fonts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_font: Option<String>,
+    /// images larger than the atlas that were split into several tiles, with the `split_oversized` setting
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub splits: HashMap<String, SplitImage>,
+    /// animation name -> its frames, all on the same texture
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub animations: BTreeMap<String, Animation>,
+    /// texture name -> number of downscaled levels, written next to it as `{name}.mip{level}.png` or
+    /// as further levels of its ktx2 or dds file
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub mip_levels: BTreeMap<String, u32>,
+    /// texture name -> highest streaming priority of its tiles, so engines that stream textures know
+    /// which ones to keep resident. Textures without prioritized tiles are left out.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub texture_priorities: BTreeMap<String, u32>,
+    /// tile name -> the source file it was packed from, only written with the `provenance` setting,
+    /// for tracing wrong looking tiles back to their source in debug builds
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub provenance: BTreeMap<String, TileProvenance>,
+    /// tile name -> tags from the sidecar of its image like `player.png.meta`, see
+    /// [`PackedAssets::tiles_with_tag`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, Vec<String>>,
+    /// tile name -> user data from the sidecar of its image, with values that are not strings
+    /// written as json like `12` or `[1, 2]`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub user_data: BTreeMap<String, BTreeMap<String, String>>,
+    /// name -> path relative to the pack dir like `data/level_1.lvl`, of the files copied into the
+    /// pack unchanged because their extension is mapped to `data` in the settings
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub data_files: BTreeMap<String, String>,
+    /// factor the images of this pack were resized with, like 2 for the `@2x` variant of a pack made
+    /// for high dpi screens. Missing in manifests of older versions, which were not scaled.
+    #[serde(default = "scale_default")]
+    pub scale: f32,
+    /// missing in manifests of older versions, loaders skip the checks then
+    #[serde(default)]
+    pub checksums: SectionChecksums,
+    /// content hashed names of the files of the pack, like `atlas_0.png` -> `atlas_0.1a2b3c4d.png`,
+    /// if it was packed with hashed file names for long lived http caching. Resolve names with
+    /// [`PackedAssets::file_name`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_names: BTreeMap<String, String>,
+    /// bundles packed next to this manifest, each a pack of its own in the dir of its name like
+    /// `ui/`, that games load and unload independently
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bundles: Vec<String>,
+}
+
+impl PackedAssets {
+    /// The name of the file `name`, like `atlas_0.png` or `serif.sdf_font.json`, in the pack dir.
+    pub fn file_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.file_names.get(name).map_or(name, String::as_str)
+    }
+
+    /// Names of the tiles tagged with `tag`, sorted.
+    pub fn tiles_with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.tags
+            .iter()
+            .filter(move |(_, tags)| tags.iter().any(|e| e == tag))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Frames named like `walk_0.anim.png`, `walk_1.anim.png` or just numbered like `walk_0.png`,
+/// `walk_1.png`, which are packed into the same texture so renderers never switch textures while
+/// playing the animation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Animation {
+    pub texture: String,
+    /// tile names, ordered by the frame index
+    pub frames: Vec<String>,
+    /// playback speed, only present if one of the frames has a flag like `walk_0.12fps.png`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fps: Option<u32>,
+    /// milliseconds each frame is shown, only present for animations from aseprite and gif files
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub durations: Vec<u32>,
+    /// what happens after the last frame, from the `animation_playback` setting of the packer
+    #[serde(default, skip_serializing_if = "Playback::is_loop")]
+    pub playback: Playback,
+}
+
+/// Frames per second of animations with neither `fps` nor `durations`.
+pub const DEFAULT_FPS: u32 = 12;
+
+impl Animation {
+    /// Seconds each frame is shown, from `durations` or `fps`.
+    pub fn frame_seconds(&self, frame: usize) -> f32 {
+        match self.durations.get(frame) {
+            Some(duration) => *duration as f32 / 1000.0,
+            None => 1.0 / self.fps.unwrap_or(DEFAULT_FPS).max(1) as f32,
+        }
+    }
+
+    /// Seconds of one pass through the frames, for ping-pong animations forth and back.
+    pub fn duration(&self) -> f32 {
+        (0..self.frames.len())
+            .chain(self.ping_pong_return())
+            .map(|frame| self.frame_seconds(frame))
+            .sum()
+    }
+
+    /// Index into `frames` of the frame shown `seconds` after the animation started. Animations
+    /// played once stay on their last frame.
+    pub fn frame_index_at(&self, seconds: f32) -> usize {
+        let duration = self.duration();
+        if self.frames.is_empty() || duration <= 0.0 {
+            return 0;
+        }
+        let mut time = match self.playback {
+            Playback::Once if seconds >= duration => return self.frames.len() - 1,
+            Playback::Once => seconds.max(0.0),
+            Playback::Loop | Playback::PingPong => seconds.rem_euclid(duration),
+        };
+        let order = (0..self.frames.len()).chain(self.ping_pong_return());
+        let mut frame = 0;
+        for next in order {
+            frame = next;
+            time -= self.frame_seconds(frame);
+            if time < 0.0 {
+                break;
+            }
+        }
+        frame
+    }
+
+    /// Tile name of the frame shown `seconds` after the animation started, see
+    /// [`Animation::frame_index_at`]. `None` for an animation without frames.
+    pub fn frame_at(&self, seconds: f32) -> Option<&str> {
+        self.frames
+            .get(self.frame_index_at(seconds))
+            .map(String::as_str)
+    }
+
+    /// The frames played backwards after the last one by ping-pong animations, without repeating
+    /// the first and last frame.
+    fn ping_pong_return(&self) -> impl Iterator<Item = usize> {
+        let len = match self.playback {
+            Playback::PingPong => self.frames.len(),
+            _ => 0,
+        };
+        (1..len.saturating_sub(1)).rev()
+    }
+}
+
+/// Playback of an animation after its last frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Playback {
+    /// start over with the first frame
+    #[default]
+    Loop,
+    /// stay on the last frame
+    Once,
+    /// play the frames backwards back to the first one, then forwards again
+    PingPong,
+}
+
+impl Playback {
+    pub fn is_loop(&self) -> bool {
+        *self == Playback::Loop
+    }
+}
+
+/// An image larger than the atlas, split into a grid of tiles named `{image}_{column}_{row}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitImage {
+    pub columns: u32,
+    pub rows: u32,
+    /// tile names, row by row
+    pub tiles: Vec<String>,
+}
+
+/// The source file of a tile, see [`PackedAssets::provenance`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TileProvenance {
+    /// path of the file relative to the source dir, like `images/ui/button.png`
+    pub source: String,
+    /// hash of the file contents when it was packed, the same hash the packer cache uses
+    pub hash: u64,
+    /// flags in the file name joined by dots, like `rep` for `background.rep.png` or `repx.pad=8`
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub flags: String,
+}
+
+/// Content hashes of the files of each section of a pack, so loaders can tell exactly which files of
+/// an install are corrupted and still load the intact ones.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct SectionChecksums {
+    /// texture name (or `{name}.mip{level}` for mip levels) -> hash of the texture file
+    pub textures: BTreeMap<String, u64>,
+    /// font name -> hash of the sdf font json and png, see `assetpacker::pack::font_checksum`
+    pub fonts: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SdfFont {
+    pub font_size: usize,
+    pub line_metrics: LineMetrics,
+    pub name: String,
+    #[serde(serialize_with = "serialize_sorted")]
+    pub glyphs: HashMap<char, Glyph>,
+    /// pixel size -> line metrics of the font at exactly that size, for the sizes configured when
+    /// packing. `line_metrics` are for `font_size`, scaling them to other sizes accumulates rounding
+    /// errors in ui code that lays out text at fixed sizes.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub sized_line_metrics: BTreeMap<u32, LineMetrics>,
+    /// icon name -> codepoint, only used by icon fonts assembled from svgs
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        serialize_with = "serialize_sorted"
+    )]
+    pub icons: HashMap<String, char>,
+    /// how the distances are stored in the `<name>.sdf_font.png`, only present for msdf fonts
+    #[serde(default, skip_serializing_if = "DistanceField::is_sdf")]
+    pub distance_field: DistanceField,
+}
+
+/// The kind of distance field of an [`SdfFont`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceField {
+    /// a single channel signed distance field in a gray png, the glyph edge is at 0.5
+    #[default]
+    Sdf,
+    /// multi-channel signed distance field in an rgb png, the edge is where the median of the
+    /// three channels is 0.5. Keeps corners sharp when glyphs are drawn much larger than the
+    /// `font_size`, shaders take `max(min(r, g), min(max(r, g), b))` instead of the red channel.
+    Msdf,
+}
+
+impl DistanceField {
+    pub fn is_sdf(&self) -> bool {
+        *self == DistanceField::Sdf
+    }
+}
+
+/// Writes a map sorted by its keys, so the same font always gives the same file and checksum.
+fn serialize_sorted<K: Serialize + Ord, V: Serialize, S: serde::Serializer>(
+    map: &HashMap<K, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(map.iter().collect::<BTreeMap<_, _>>())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+
+pub struct LineMetrics {
+    /// The highest point that any glyph in the font extends to above the baseline. Typically
+    /// positive.
+    pub ascent: f32,
+    /// The lowest point that any glyph in the font extends to below the baseline. Typically
+    /// negative.
+    pub descent: f32,
+    /// The gap to leave between the descent of one line and the ascent of the next. This is of
+    /// course only a guideline given by the font's designers.
+    pub line_gap: f32,
+    /// A precalculated value for the height or width of the line depending on if the font is laid
+    /// out horizontally or vertically. It's calculated by: ascent - descent + line_gap.
+    pub new_line_size: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Glyph {
+    pub xmin: f32,
+    pub ymin: f32,
+    pub width: f32,
+    pub height: f32,
+    pub advance: f32,
+    pub is_white_space: bool,
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+    /// Metrics of the tabular figure variant, only present for digits of fonts supporting `tnum`
+    /// whose tabular digits differ from the default ones. Renderers that want numbers that do not
+    /// jitter (e.g. score counters) should use these instead of `xmin` and `advance`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tabular: Option<TabularMetrics>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TabularMetrics {
+    pub xmin: f32,
+    pub advance: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Animation, Playback};
+
+    #[test]
+    fn frame_at_follows_the_playback() {
+        let mut animation = Animation {
+            texture: "atlas_0".into(),
+            frames: vec!["walk_0".into(), "walk_1".into(), "walk_2".into()],
+            fps: Some(10),
+            durations: vec![],
+            playback: Playback::PingPong,
+        };
+        assert_eq!(animation.frame_at(0.05), Some("walk_0"));
+        assert_eq!(animation.frame_at(0.35), Some("walk_1"));
+        animation.playback = Playback::Once;
+        assert_eq!(animation.frame_at(5.0), Some("walk_2"));
+
+        animation.frames.clear();
+        assert_eq!(animation.frame_at(0.35), None);
+    }
+}
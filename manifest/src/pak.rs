@@ -0,0 +1,329 @@
+//! The `packed.pak` bundle, all files of a pack as chunks of one file, so shipping builds open and
+//! memory map a single file instead of dozens of loose textures.
+//!
+//! All numbers are little endian. The file starts with a 16 byte header, `APAK`, the format version
+//! as u32 and 8 reserved zero bytes. The chunks follow, each starting at an offset that is a
+//! multiple of [`ALIGNMENT`], then the index and a 16 byte footer with the offset of the index as
+//! u64, the number of entries as u32 and `APAK` again. Every entry of the index is the offset,
+//! stored size and size of its chunk as u64, the [`Compression`] as u8, the length of the name as
+//! u32 and the utf-8 name, like `atlas_0.png`. With the index at the end, a pak is written in one
+//! pass and read by seeking to the footer.
+//!
+//! Uncompressed chunks are returned as slices of the pak without copying them, so a reader that
+//! memory maps the file only touches the pages of the chunks it reads.
+//!
+//! ```no_run
+//! let bytes = std::fs::read("packed/packed.pak").unwrap();
+//! let pak = assetpacker_manifest::pak::PakIndex::parse(&bytes).unwrap();
+//! let manifest = pak.read(&bytes, "packed.json").unwrap();
+//! ```
+
+use std::{borrow::Cow, collections::BTreeMap, fmt::Display, ops::Range};
+
+pub const MAGIC: [u8; 4] = *b"APAK";
+pub const VERSION: u32 = 2;
+/// alignment of the chunk offsets, enough for uploading memory mapped textures directly
+pub const ALIGNMENT: usize = 16;
+
+const HEADER_LEN: usize = 16;
+const FOOTER_LEN: usize = 16;
+
+/// A truncated or otherwise invalid pak file, or a chunk that is missing or cannot be decompressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PakError(String);
+
+impl Display for PakError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for PakError {}
+
+/// How a chunk is stored. Paks are written with the stored chunks that are larger when compressed,
+/// like png and webp textures, uncompressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// zlib stream, read with the default `deflate` feature of this crate
+    Deflate,
+}
+
+impl Compression {
+    fn to_u8(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Deflate => 1,
+        }
+    }
+
+    fn from_u8(byte: u8) -> Option<Compression> {
+        match byte {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Deflate),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PakEntry {
+    /// where the stored chunk is in the pak
+    pub range: Range<usize>,
+    /// size of the file after decompressing the chunk
+    pub size: usize,
+    pub compression: Compression,
+}
+
+/// Where the chunks of a pak are, parsed from its index without reading any of them.
+#[derive(Debug, Clone, Default)]
+pub struct PakIndex {
+    pub entries: BTreeMap<String, PakEntry>,
+}
+
+impl PakIndex {
+    pub fn parse(bytes: &[u8]) -> Result<PakIndex, PakError> {
+        let mut header = Reader::at(bytes, 0);
+        if bytes.len() < HEADER_LEN + FOOTER_LEN || header.take(4)? != MAGIC {
+            return Err(PakError(String::from("not a pak file")));
+        }
+        let version = header.u32()?;
+        if version != VERSION {
+            return Err(PakError(format!(
+                "pak version {version} is not the supported version {VERSION}, pack the assets again"
+            )));
+        }
+        let mut footer = Reader::at(bytes, bytes.len() - FOOTER_LEN);
+        let index_offset = footer.u64()? as usize;
+        let count = footer.u32()?;
+        if footer.take(4)? != MAGIC {
+            return Err(PakError(String::from("the pak is truncated")));
+        }
+
+        let mut index = Reader::at(bytes, index_offset);
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            let offset = index.u64()? as usize;
+            let stored_size = index.u64()? as usize;
+            let size = index.u64()? as usize;
+            let compression = index.u8()?;
+            let name_len = index.u32()? as usize;
+            let name = std::str::from_utf8(index.take(name_len)?)
+                .map_err(|_| PakError(String::from("entry name is not utf-8")))?;
+            let compression = Compression::from_u8(compression).ok_or_else(|| {
+                PakError(format!(
+                    "chunk {name} has the unknown compression {compression}"
+                ))
+            })?;
+            let range = offset..offset.saturating_add(stored_size);
+            if range.end > index_offset {
+                return Err(PakError(format!("chunk {name} is out of bounds")));
+            }
+            let entry = PakEntry {
+                range,
+                size,
+                compression,
+            };
+            entries.insert(name.to_string(), entry);
+        }
+        Ok(PakIndex { entries })
+    }
+
+    /// The contents of the file `name` in `bytes`, the pak this index was parsed from. Borrowed from
+    /// `bytes` if the chunk is not compressed.
+    pub fn read<'a>(&self, bytes: &'a [u8], name: &str) -> Result<Cow<'a, [u8]>, PakError> {
+        let entry = self
+            .entries
+            .get(name)
+            .ok_or_else(|| PakError(format!("the pak has no file {name}")))?;
+        let chunk = bytes
+            .get(entry.range.clone())
+            .ok_or_else(|| PakError(format!("chunk {name} is out of bounds")))?;
+        match entry.compression {
+            Compression::None => Ok(Cow::Borrowed(chunk)),
+            Compression::Deflate => inflate(chunk, entry.size)
+                .map(Cow::Owned)
+                .map_err(|e| PakError(format!("chunk {name}: {e}"))),
+        }
+    }
+}
+
+/// Bundles the files, given as name and contents, into a pak. With `compression`, each chunk is
+/// compressed if that makes it smaller by at least an eighth.
+pub fn to_vec(files: &[(String, Vec<u8>)], compression: Compression) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(files.iter().map(|e| e.1.len()).sum::<usize>());
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&[0; 8]);
+    let mut index: Vec<u8> = vec![];
+    for (name, contents) in files {
+        let (stored, chunk_compression) = match compression {
+            Compression::None => (Cow::Borrowed(contents.as_slice()), Compression::None),
+            Compression::Deflate => match deflate(contents) {
+                Some(deflated) if deflated.len() < contents.len() - contents.len() / 8 => {
+                    (Cow::Owned(deflated), Compression::Deflate)
+                }
+                _ => (Cow::Borrowed(contents.as_slice()), Compression::None),
+            },
+        };
+        out.resize(out.len().next_multiple_of(ALIGNMENT), 0);
+        index.extend_from_slice(&(out.len() as u64).to_le_bytes());
+        index.extend_from_slice(&(stored.len() as u64).to_le_bytes());
+        index.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+        index.push(chunk_compression.to_u8());
+        index.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        index.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(&stored);
+    }
+    out.resize(out.len().next_multiple_of(ALIGNMENT), 0);
+    let index_offset = out.len() as u64;
+    out.extend_from_slice(&index);
+    out.extend_from_slice(&index_offset.to_le_bytes());
+    out.extend_from_slice(&(files.len() as u32).to_le_bytes());
+    out.extend_from_slice(&MAGIC);
+    out
+}
+
+#[cfg(feature = "deflate")]
+fn deflate(bytes: &[u8]) -> Option<Vec<u8>> {
+    use std::io::Write;
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes).ok()?;
+    encoder.finish().ok()
+}
+
+#[cfg(not(feature = "deflate"))]
+fn deflate(_bytes: &[u8]) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(feature = "deflate")]
+fn inflate(chunk: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    use std::io::Read;
+    // the size comes from the index and is not trusted, zlib shrinks data by a factor of 1032 at most
+    let mut bytes = Vec::with_capacity(size.min(chunk.len().saturating_mul(1032)));
+    // one byte more than expected is enough to tell that the size is wrong
+    flate2::read::ZlibDecoder::new(chunk)
+        .take(size as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+    match bytes.len() == size {
+        true => Ok(bytes),
+        false => Err(format!(
+            "decompressed to {} instead of {size} bytes",
+            bytes.len()
+        )),
+    }
+}
+
+#[cfg(not(feature = "deflate"))]
+fn inflate(_chunk: &[u8], _size: usize) -> Result<Vec<u8>, String> {
+    Err(String::from("deflate chunks need the deflate feature"))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn at(bytes: &'a [u8], pos: usize) -> Self {
+        Reader { bytes, pos }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PakError> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or_else(|| PakError(String::from("unexpected end of the pak index")))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, PakError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, PakError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, PakError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files() -> Vec<(String, Vec<u8>)> {
+        vec![
+            (String::from("packed.json"), b"{ \"tiles\": {} }".repeat(20)),
+            (String::from("atlas_0.png"), (0..=255).collect()),
+            (String::from("empty.bin"), vec![]),
+        ]
+    }
+
+    #[test]
+    fn paks_round_trip() {
+        for compression in [Compression::None, Compression::Deflate] {
+            let bytes = to_vec(&files(), compression);
+            let pak = PakIndex::parse(&bytes).unwrap();
+            assert_eq!(pak.entries.len(), 3);
+            for (name, contents) in files() {
+                let entry = &pak.entries[&name];
+                assert_eq!(entry.range.start % ALIGNMENT, 0);
+                assert_eq!(pak.read(&bytes, &name).unwrap(), contents.as_slice());
+            }
+            // only the repetitive json shrinks enough to be stored compressed
+            let json_compression = match cfg!(feature = "deflate") {
+                true => compression,
+                false => Compression::None,
+            };
+            assert_eq!(pak.entries["packed.json"].compression, json_compression);
+            assert_eq!(pak.entries["atlas_0.png"].compression, Compression::None);
+        }
+    }
+
+    #[test]
+    fn truncated_paks_are_rejected() {
+        for compression in [Compression::None, Compression::Deflate] {
+            let bytes = to_vec(&files(), compression);
+            for len in 0..bytes.len() {
+                assert!(
+                    PakIndex::parse(&bytes[..len]).is_err(),
+                    "parsed {len} bytes"
+                );
+            }
+            // an index parsed from the whole pak, with the chunks read from a truncated copy
+            let pak = PakIndex::parse(&bytes).unwrap();
+            let truncated = &bytes[..pak.entries["atlas_0.png"].range.end - 1];
+            assert!(pak.read(truncated, "atlas_0.png").is_err());
+            assert!(pak.read(&bytes, "missing.png").is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn corrupted_chunks_are_rejected() {
+        let mut bytes = to_vec(&files(), Compression::Deflate);
+        let pak = PakIndex::parse(&bytes).unwrap();
+        let range = pak.entries["packed.json"].range.clone();
+        bytes[range.start + 2..range.end].fill(0xFF);
+        assert!(pak.read(&bytes, "packed.json").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "deflate")]
+    fn corrupted_sizes_are_rejected() {
+        let bytes = to_vec(&files(), Compression::Deflate);
+        let mut pak = PakIndex::parse(&bytes).unwrap();
+        let json_size = pak.entries["packed.json"].size;
+        for size in [0, json_size - 1, json_size + 1, usize::MAX / 2] {
+            pak.entries.get_mut("packed.json").unwrap().size = size;
+            assert!(pak.read(&bytes, "packed.json").is_err(), "size {size}");
+        }
+    }
+}
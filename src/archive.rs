@@ -0,0 +1,171 @@
+use std::io::Write;
+
+use flate2::{write::DeflateEncoder, Compression, Crc};
+
+use crate::error::{PackError, PackResult};
+
+/// Archives a pack can be written into instead of a directory, picked by the extension of the
+/// destination, like `assets.zip`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// entries that shrink by it are deflated, the others like pngs are stored
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub const EXTENSIONS: [(&'static str, ArchiveFormat); 4] = [
+        (".zip", ArchiveFormat::Zip),
+        (".tar", ArchiveFormat::Tar),
+        (".tar.gz", ArchiveFormat::TarGz),
+        (".tgz", ArchiveFormat::TarGz),
+    ];
+
+    pub fn from_path(path: &str) -> Option<ArchiveFormat> {
+        ArchiveFormat::EXTENSIONS
+            .iter()
+            .find(|(extension, _)| path.ends_with(extension))
+            .map(|(_, format)| *format)
+    }
+}
+
+/// `path` with `suffix` appended to its name, before the archive extension if it has one, so the
+/// variant `@2x` of `assets.zip` is `assets@2x.zip`.
+pub fn with_suffix(path: &str, suffix: &str) -> String {
+    let path = path.trim_end_matches('/');
+    match ArchiveFormat::EXTENSIONS
+        .iter()
+        .find(|(extension, _)| path.ends_with(extension))
+    {
+        Some((extension, _)) => {
+            format!(
+                "{}{suffix}{extension}",
+                &path[..path.len() - extension.len()]
+            )
+        }
+        None => format!("{path}{suffix}"),
+    }
+}
+
+/// Writes the files of the pack in `dir`, including those of its bundles, into the archive at
+/// `path`, sorted by name and without timestamps, so the same pack always gives the same archive.
+pub fn write_archive(dir: &str, path: &str, format: ArchiveFormat) -> PackResult<()> {
+    let mut names: Vec<String> = vec![];
+    collect_file_names(dir, "", &mut names)?;
+    names.retain(|e| e != "fingerprint");
+    names.sort();
+    let files: Vec<(String, Vec<u8>)> = names
+        .into_iter()
+        .map(|name| {
+            let file_path = format!("{dir}/{name}");
+            let bytes = std::fs::read(&file_path).map_err(PackError::io(&file_path))?;
+            Ok((name, bytes))
+        })
+        .collect::<PackResult<_>>()?;
+    let archive = match format {
+        ArchiveFormat::Zip => zip(&files),
+        ArchiveFormat::Tar => tar(&files),
+        ArchiveFormat::TarGz => {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], Compression::best());
+            encoder.write_all(&tar(&files)).unwrap();
+            encoder.finish().unwrap()
+        }
+    };
+    std::fs::write(path, archive).map_err(PackError::io(path))
+}
+
+/// Names of the files in `dir` and its subdirs, relative to the pack dir with `prefix` like `ui/`.
+fn collect_file_names(dir: &str, prefix: &str, names: &mut Vec<String>) -> PackResult<()> {
+    let read_dir = std::fs::read_dir(dir).map_err(PackError::io(dir))?;
+    for entry in read_dir.flatten() {
+        let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => {
+                let sub_dir = format!("{dir}/{}", entry.file_name().to_string_lossy());
+                collect_file_names(&sub_dir, &format!("{name}/"), names)?;
+            }
+            Ok(file_type) if file_type.is_file() => names.push(name),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn zip(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut out: Vec<u8> = vec![];
+    let mut central: Vec<u8> = vec![];
+    for (name, bytes) in files {
+        let mut crc = Crc::new();
+        crc.update(bytes);
+        let mut encoder = DeflateEncoder::new(vec![], Compression::best());
+        encoder.write_all(bytes).unwrap();
+        let deflated = encoder.finish().unwrap();
+        let (method, stored): (u16, &[u8]) = match deflated.len() < bytes.len() {
+            true => (8, &deflated),
+            false => (0, bytes),
+        };
+        // version, flags (utf-8 names), method, dos time and date of 1980-01-01, crc and sizes
+        let mut fields: Vec<u8> = vec![];
+        fields.extend_from_slice(&20u16.to_le_bytes());
+        fields.extend_from_slice(&(1u16 << 11).to_le_bytes());
+        fields.extend_from_slice(&method.to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        fields.extend_from_slice(&((1 << 5) | 1u16).to_le_bytes());
+        fields.extend_from_slice(&crc.sum().to_le_bytes());
+        fields.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+
+        central.extend_from_slice(b"PK\x01\x02");
+        central.extend_from_slice(&20u16.to_le_bytes()); // made by
+        central.extend_from_slice(&fields);
+        central.extend_from_slice(&[0; 6]); // comment length, disk, internal attributes
+        central.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        central.extend_from_slice(&(out.len() as u32).to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+
+        out.extend_from_slice(b"PK\x03\x04");
+        out.extend_from_slice(&fields);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(stored);
+    }
+    let central_offset = out.len() as u32;
+    out.extend_from_slice(&central);
+    out.extend_from_slice(b"PK\x05\x06");
+    out.extend_from_slice(&[0; 4]); // disk numbers
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    out.extend_from_slice(&central_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    out
+}
+
+fn tar(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut out: Vec<u8> = vec![];
+    for (name, bytes) in files {
+        let mut header = [0u8; 512];
+        let mut field = |offset: usize, value: &[u8]| {
+            header[offset..offset + value.len()].copy_from_slice(value);
+        };
+        field(0, &name.as_bytes()[..name.len().min(100)]);
+        field(100, b"0000644\0");
+        field(108, b"0000000\0");
+        field(116, b"0000000\0");
+        field(124, format!("{:011o}\0", bytes.len()).as_bytes());
+        field(136, b"00000000000\0");
+        field(148, b"        "); // checksum, counted as spaces
+        field(156, b"0");
+        field(257, b"ustar\0");
+        field(263, b"00");
+        let checksum: u32 = header.iter().map(|e| *e as u32).sum();
+        header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+        out.extend_from_slice(&header);
+        out.extend_from_slice(bytes);
+        out.resize(out.len().next_multiple_of(512), 0);
+    }
+    out.extend_from_slice(&[0; 1024]);
+    out
+}
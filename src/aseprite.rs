@@ -0,0 +1,358 @@
+use std::{collections::HashMap, io::Read};
+
+use image::{Rgba, RgbaImage};
+
+/// The frames of an Aseprite file, composited from its visible layers, and its tags. Blend modes
+/// other than normal are drawn as normal, tilemap layers are not supported.
+#[derive(Debug, Clone)]
+pub struct Aseprite {
+    pub frames: Vec<AsepriteFrame>,
+    pub tags: Vec<AsepriteTag>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsepriteFrame {
+    pub rgba: RgbaImage,
+    /// in milliseconds
+    pub duration: u32,
+}
+
+/// A named range of frames, played as its own animation.
+#[derive(Debug, Clone)]
+pub struct AsepriteTag {
+    pub name: String,
+    pub from: u32,
+    /// inclusive
+    pub to: u32,
+    pub direction: TagDirection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagDirection {
+    Forward,
+    Reverse,
+    PingPong,
+}
+
+impl AsepriteTag {
+    /// Frame indices in playback order of one loop.
+    pub fn frame_order(&self) -> Vec<u32> {
+        let forward: Vec<u32> = (self.from..=self.to).collect();
+        match self.direction {
+            TagDirection::Forward => forward,
+            TagDirection::Reverse => forward.into_iter().rev().collect(),
+            // the first and last frame are not repeated when turning around
+            TagDirection::PingPong => {
+                let back = forward.iter().rev().skip(1);
+                let back: Vec<u32> = back
+                    .take(forward.len().saturating_sub(2))
+                    .copied()
+                    .collect();
+                forward.into_iter().chain(back).collect()
+            }
+        }
+    }
+}
+
+const HEADER_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+
+struct Layer {
+    visible: bool,
+    opacity: u8,
+}
+
+struct Cel {
+    x: i32,
+    y: i32,
+    opacity: u8,
+    image: RgbaImage,
+}
+
+impl Aseprite {
+    pub fn parse(bytes: &[u8]) -> Result<Aseprite, String> {
+        let mut header = Reader::new(bytes);
+        header.u32()?; // file size
+        if header.u16()? != HEADER_MAGIC {
+            return Err(String::from("not an aseprite file"));
+        }
+        let frame_count = header.u16()?;
+        let width = header.u16()? as u32;
+        let height = header.u16()? as u32;
+        let color_depth = header.u16()?;
+        let flags = header.u32()?;
+        header.skip(2 + 4 + 4)?; // speed, reserved
+        let transparent_index = header.u8()?;
+        if ![32, 16, 8].contains(&color_depth) {
+            return Err(format!("unknown color depth {color_depth}"));
+        }
+        // older files have no valid layer opacity
+        let layer_opacity_valid = flags & 1 != 0;
+
+        let mut rest = Reader::new(bytes.get(128..).ok_or("truncated header")?);
+        let mut layers: Vec<Layer> = vec![];
+        // the visibility of the group at each child level, hidden groups hide their children
+        let mut group_visible: Vec<bool> = vec![];
+        let mut palette: Vec<[u8; 4]> = vec![];
+        let mut cels: HashMap<(usize, usize), Cel> = HashMap::new();
+        let mut frames: Vec<AsepriteFrame> = vec![];
+        let mut tags: Vec<AsepriteTag> = vec![];
+
+        for frame in 0..frame_count as usize {
+            let frame_size = rest.u32()? as usize;
+            if rest.u16()? != FRAME_MAGIC {
+                return Err(format!("frame {frame} is corrupted"));
+            }
+            let old_chunk_count = rest.u16()? as u32;
+            let duration = rest.u16()? as u32;
+            rest.skip(2)?;
+            let chunk_count = match rest.u32()? {
+                0 => old_chunk_count,
+                count => count,
+            };
+            let mut frame_reader = Reader::new(rest.bytes(frame_size.saturating_sub(16))?);
+            for _ in 0..chunk_count {
+                let chunk_size = frame_reader.u32()? as usize;
+                let chunk_type = frame_reader.u16()?;
+                let mut chunk = Reader::new(frame_reader.bytes(chunk_size.saturating_sub(6))?);
+                match chunk_type {
+                    CHUNK_LAYER => {
+                        let flags = chunk.u16()?;
+                        let layer_type = chunk.u16()?;
+                        let level = chunk.u16()? as usize;
+                        chunk.skip(2 + 2 + 2)?; // default size, blend mode
+                        let opacity = chunk.u8()?;
+                        if layer_type == 2 {
+                            return Err(String::from("tilemap layers are not supported"));
+                        }
+                        group_visible.truncate(level);
+                        let visible = flags & 1 != 0 && group_visible.iter().all(|e| *e);
+                        if layer_type == 1 {
+                            group_visible.push(visible);
+                        }
+                        layers.push(Layer {
+                            // groups have no cels of their own
+                            visible: visible && layer_type == 0,
+                            opacity: if layer_opacity_valid { opacity } else { 255 },
+                        });
+                    }
+                    CHUNK_CEL => {
+                        let layer = chunk.u16()? as usize;
+                        let x = chunk.u16()? as i16 as i32;
+                        let y = chunk.u16()? as i16 as i32;
+                        let opacity = chunk.u8()?;
+                        let cel_type = chunk.u16()?;
+                        chunk.skip(2 + 5)?; // z-index, reserved
+                        let pixels = |w: u32, h: u32, data: &[u8]| {
+                            cel_image(w, h, data, color_depth, &palette, transparent_index)
+                        };
+                        let image = match cel_type {
+                            0 => {
+                                let (w, h) = (chunk.u16()? as u32, chunk.u16()? as u32);
+                                pixels(w, h, chunk.remaining())?
+                            }
+                            1 => {
+                                let linked = chunk.u16()? as usize;
+                                let cel = cels
+                                    .get(&(linked, layer))
+                                    .ok_or(format!("frame {frame} links to a missing cel"))?;
+                                cel.image.clone()
+                            }
+                            2 => {
+                                let (w, h) = (chunk.u16()? as u32, chunk.u16()? as u32);
+                                let mut data: Vec<u8> = vec![];
+                                flate2::read::ZlibDecoder::new(chunk.remaining())
+                                    .read_to_end(&mut data)
+                                    .map_err(|e| format!("frame {frame}: {e}"))?;
+                                pixels(w, h, &data)?
+                            }
+                            _ => return Err(String::from("tilemap cels are not supported")),
+                        };
+                        cels.insert(
+                            (frame, layer),
+                            Cel {
+                                x,
+                                y,
+                                opacity,
+                                image,
+                            },
+                        );
+                    }
+                    CHUNK_TAGS => {
+                        let count = chunk.u16()?;
+                        chunk.skip(8)?;
+                        for _ in 0..count {
+                            let from = chunk.u16()? as u32;
+                            let to = chunk.u16()? as u32;
+                            let direction = match chunk.u8()? {
+                                1 | 3 => TagDirection::Reverse,
+                                2 | 4 => TagDirection::PingPong,
+                                _ => TagDirection::Forward,
+                            };
+                            chunk.skip(2 + 6 + 3 + 1)?; // repeat, reserved, color
+                            let name = chunk.string()?;
+                            tags.push(AsepriteTag {
+                                name,
+                                from,
+                                to,
+                                direction,
+                            });
+                        }
+                    }
+                    CHUNK_PALETTE => {
+                        let size = chunk.u32()? as usize;
+                        let first = chunk.u32()? as usize;
+                        let last = chunk.u32()? as usize;
+                        chunk.skip(8)?;
+                        palette.resize(size.max(palette.len()), [0, 0, 0, 0]);
+                        for index in first..=last {
+                            let has_name = chunk.u16()? & 1 != 0;
+                            let color = [chunk.u8()?, chunk.u8()?, chunk.u8()?, chunk.u8()?];
+                            if let Some(entry) = palette.get_mut(index) {
+                                *entry = color;
+                            }
+                            if has_name {
+                                chunk.string()?;
+                            }
+                        }
+                    }
+                    // only used if the file has no new palette chunk, which always comes first
+                    CHUNK_OLD_PALETTE if palette.is_empty() => {
+                        let mut index = 0;
+                        for _ in 0..chunk.u16()? {
+                            index += chunk.u8()? as usize;
+                            let count = match chunk.u8()? {
+                                0 => 256,
+                                count => count as usize,
+                            };
+                            palette.resize(palette.len().max(index + count), [0, 0, 0, 0]);
+                            for _ in 0..count {
+                                palette[index] = [chunk.u8()?, chunk.u8()?, chunk.u8()?, 255];
+                                index += 1;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            let mut rgba = RgbaImage::new(width, height);
+            for (index, layer) in layers.iter().enumerate() {
+                let Some(cel) = cels.get(&(frame, index)).filter(|_| layer.visible) else {
+                    continue;
+                };
+                let opacity = cel.opacity as u32 * layer.opacity as u32 / 255;
+                draw_image(&mut rgba, &cel.image, cel.x, cel.y, opacity);
+            }
+            frames.push(AsepriteFrame { rgba, duration });
+        }
+
+        if let Some(tag) = tags
+            .iter()
+            .find(|tag| tag.from > tag.to || tag.to as usize >= frames.len())
+        {
+            return Err(format!("tag {} has an invalid frame range", tag.name));
+        }
+        Ok(Aseprite { frames, tags })
+    }
+}
+
+/// Converts the pixels of a cel to rgba.
+fn cel_image(
+    w: u32,
+    h: u32,
+    data: &[u8],
+    color_depth: u16,
+    palette: &[[u8; 4]],
+    transparent_index: u8,
+) -> Result<RgbaImage, String> {
+    let bytes_per_pixel = color_depth as usize / 8;
+    if data.len() < (w * h) as usize * bytes_per_pixel {
+        return Err(String::from("cel with too few pixels"));
+    }
+    let pixels = data.chunks_exact(bytes_per_pixel).take((w * h) as usize);
+    let rgba: Vec<u8> = pixels
+        .flat_map(|p| match color_depth {
+            32 => [p[0], p[1], p[2], p[3]],
+            16 => [p[0], p[0], p[0], p[1]],
+            _ if p[0] == transparent_index => [0, 0, 0, 0],
+            _ => palette.get(p[0] as usize).copied().unwrap_or([0, 0, 0, 0]),
+        })
+        .collect();
+    Ok(RgbaImage::from_raw(w, h, rgba).unwrap())
+}
+
+/// Draws `image` over `rgba` at `x`, `y` with normal blending, `opacity` out of 255.
+pub(crate) fn draw_image(rgba: &mut RgbaImage, image: &RgbaImage, x: i32, y: i32, opacity: u32) {
+    for (sx, sy, src) in image.enumerate_pixels() {
+        let (tx, ty) = (x + sx as i32, y + sy as i32);
+        if tx < 0 || ty < 0 || tx >= rgba.width() as i32 || ty >= rgba.height() as i32 {
+            continue;
+        }
+        let dst = rgba.get_pixel_mut(tx as u32, ty as u32);
+        let sa = src[3] as f32 * opacity as f32 / (255.0 * 255.0);
+        let da = dst[3] as f32 / 255.0;
+        let out_a = sa + da * (1.0 - sa);
+        if out_a <= 0.0 {
+            continue;
+        }
+        let blend =
+            |s: u8, d: u8| ((s as f32 * sa + d as f32 * da * (1.0 - sa)) / out_a).round() as u8;
+        *dst = Rgba([
+            blend(src[0], dst[0]),
+            blend(src[1], dst[1]),
+            blend(src[2], dst[2]),
+            (out_a * 255.0).round() as u8,
+        ]);
+    }
+}
+
+/// Little endian reads that fail with a message instead of panicking on truncated files.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < len {
+            return Err(String::from("unexpected end of file"));
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn remaining(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), String> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+}
@@ -0,0 +1,231 @@
+//! ASTC encoder for mobile gpus, the textures are stored in KTX2 files, see [`crate::ktx2`].
+//!
+//! Every block uses the same simple configuration: one partition, direct rgba endpoints with 8 bits
+//! per channel and a 4x4 grid of weights with 4 levels each. That keeps the integer sequence
+//! encoding to plain bits and is good enough for sprites, larger blocks interpolate their weights
+//! from the grid. The decoder only understands these blocks.
+
+use image::RgbaImage;
+
+use crate::{dds::principal_endpoints, pack::TextureFormat};
+
+/// Footprint of an ASTC block. Blocks are always 16 bytes, larger blocks trade quality for memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AstcBlockSize {
+    /// 8 bits per pixel
+    Astc4x4,
+    /// 3.56 bits per pixel
+    Astc6x6,
+}
+
+impl AstcBlockSize {
+    pub fn dimensions(self) -> (u32, u32) {
+        match self {
+            AstcBlockSize::Astc4x4 => (4, 4),
+            AstcBlockSize::Astc6x6 => (6, 6),
+        }
+    }
+}
+
+/// Block size of a texture format, `None` if it is not ASTC.
+pub fn astc_block_size(format: TextureFormat) -> Option<AstcBlockSize> {
+    match format {
+        TextureFormat::Astc4x4 => Some(AstcBlockSize::Astc4x4),
+        TextureFormat::Astc6x6 => Some(AstcBlockSize::Astc6x6),
+        _ => None,
+    }
+}
+
+/// 2d block mode with a 4x4 weight grid, 4 weight levels, no dual plane
+const BLOCK_MODE: u128 = 0x42;
+/// color endpoint mode LDR RGBA direct
+const ENDPOINT_MODE: u128 = 12;
+const GRID: usize = 4;
+/// the 4 weight levels unquantized to 0..=64
+const WEIGHTS: [u32; 4] = [0, 21, 43, 64];
+
+/// Compresses one level into blocks, row by row.
+pub fn compress_astc(rgba: &RgbaImage, size: AstcBlockSize) -> Vec<u8> {
+    let (w, h) = rgba.dimensions();
+    let (block_w, block_h) = size.dimensions();
+    let mut out: Vec<u8> = vec![];
+    for block_y in 0..h.div_ceil(block_h) {
+        for block_x in 0..w.div_ceil(block_w) {
+            // pixels outside of the image repeat the edge, so they do not disturb the endpoints
+            let pixels: Vec<[u8; 4]> = (0..block_w * block_h)
+                .map(|i| {
+                    let x = (block_x * block_w + i % block_w).min(w - 1);
+                    let y = (block_y * block_h + i / block_w).min(h - 1);
+                    rgba.get_pixel(x, y).0
+                })
+                .collect();
+            out.extend(encode_block(&pixels, size));
+        }
+    }
+    out
+}
+
+/// Decompresses one level written by [`compress_astc`], `None` for other blocks or too little data.
+pub fn decompress_astc(
+    data: &[u8],
+    w: u32,
+    h: u32,
+    size: AstcBlockSize,
+    srgb: bool,
+) -> Option<RgbaImage> {
+    let (block_w, block_h) = size.dimensions();
+    let blocks_per_row = w.div_ceil(block_w);
+    if data.len() != (blocks_per_row * h.div_ceil(block_h)) as usize * 16 {
+        return None;
+    }
+    let mut rgba = RgbaImage::new(w, h);
+    for (i, bytes) in data.chunks_exact(16).enumerate() {
+        let pixels = decode_block(bytes.try_into().unwrap(), size, srgb)?;
+        let (block_x, block_y) = (i as u32 % blocks_per_row, i as u32 / blocks_per_row);
+        for (j, pixel) in pixels.into_iter().enumerate() {
+            let x = block_x * block_w + j as u32 % block_w;
+            let y = block_y * block_h + j as u32 / block_w;
+            if x < w && y < h {
+                rgba.put_pixel(x, y, image::Rgba(pixel));
+            }
+        }
+    }
+    Some(rgba)
+}
+
+fn encode_block(pixels: &[[u8; 4]], size: AstcBlockSize) -> [u8; 16] {
+    let points: Vec<[f32; 4]> = pixels.iter().map(|p| p.map(|e| e as f32)).collect();
+    let (lo, hi) = principal_endpoints(&points);
+    let (mut e0, mut e1) = (lo.map(|e| e.round() as u8), hi.map(|e| e.round() as u8));
+    // a second endpoint with less rgb than the first means blue contraction to the decoder
+    let rgb_sum = |e: [u8; 4]| e[0] as u32 + e[1] as u32 + e[2] as u32;
+    if rgb_sum(e1) < rgb_sum(e0) {
+        std::mem::swap(&mut e0, &mut e1);
+    }
+
+    // position of every pixel along the endpoints, then sampled at the grid points
+    let axis: [f32; 4] = std::array::from_fn(|c| e1[c] as f32 - e0[c] as f32);
+    let length = axis.iter().map(|e| e * e).sum::<f32>();
+    let ideal: Vec<f32> = points
+        .iter()
+        .map(|p| match length > 0.0 {
+            true => (0..4).map(|c| (p[c] - e0[c] as f32) * axis[c]).sum::<f32>() / length,
+            false => 0.0,
+        })
+        .collect();
+    let (block_w, block_h) = size.dimensions();
+    let sample = |x: f32, y: f32| {
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(block_w - 1), (y0 + 1).min(block_h - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let at = |x: u32, y: u32| ideal[(y * block_w + x) as usize];
+        let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+        let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    };
+    let mut weights = [0u8; GRID * GRID];
+    for (i, weight) in weights.iter_mut().enumerate() {
+        let x = (i % GRID) as f32 * (block_w - 1) as f32 / (GRID - 1) as f32;
+        let y = (i / GRID) as f32 * (block_h - 1) as f32 / (GRID - 1) as f32;
+        let t = sample(x, y).clamp(0.0, 1.0) * 64.0;
+        *weight = (0..4)
+            .min_by(|a, b| {
+                (WEIGHTS[*a] as f32 - t)
+                    .abs()
+                    .total_cmp(&(WEIGHTS[*b] as f32 - t).abs())
+            })
+            .unwrap() as u8;
+    }
+
+    // block mode, one partition and the endpoint mode, then the endpoints as r0 r1 g0 g1 b0 b1 a0 a1
+    let mut bits: u128 = BLOCK_MODE | (ENDPOINT_MODE << 13);
+    for c in 0..4 {
+        bits |= (e0[c] as u128) << (17 + c * 16);
+        bits |= (e1[c] as u128) << (17 + c * 16 + 8);
+    }
+    // weights are stored from the top of the block downwards, bit reversed
+    for (i, weight) in weights.into_iter().enumerate() {
+        for bit in 0..2 {
+            bits |= (((weight >> bit) & 1) as u128) << (127 - (i * 2 + bit));
+        }
+    }
+    bits.to_le_bytes()
+}
+
+fn decode_block(bytes: [u8; 16], size: AstcBlockSize, srgb: bool) -> Option<Vec<[u8; 4]>> {
+    let bits = u128::from_le_bytes(bytes);
+    if bits & 0x1FFFF != BLOCK_MODE | (ENDPOINT_MODE << 13) {
+        return None;
+    }
+    let value = |i: usize| ((bits >> (17 + i * 8)) & 0xFF) as u32;
+    let e0: [u32; 4] = std::array::from_fn(|c| value(c * 2));
+    let e1: [u32; 4] = std::array::from_fn(|c| value(c * 2 + 1));
+    let grid: Vec<u32> = (0..GRID * GRID)
+        .map(|i| {
+            let bit = |b: usize| ((bits >> (127 - (i * 2 + b))) & 1) as usize;
+            WEIGHTS[bit(0) | (bit(1) << 1)]
+        })
+        .collect();
+
+    // weight infill as in the spec, in fixed point
+    let (block_w, block_h) = size.dimensions();
+    let n = GRID as u32;
+    let ds = (1024 + block_w / 2) / (block_w - 1);
+    let dt = (1024 + block_h / 2) / (block_h - 1);
+    let mut pixels: Vec<[u8; 4]> = vec![];
+    for t in 0..block_h {
+        for s in 0..block_w {
+            let gs = (ds * s * (n - 1) + 32) >> 6;
+            let gt = (dt * t * (n - 1) + 32) >> 6;
+            let (js, fs, jt, ft) = (gs >> 4, gs & 0xF, gt >> 4, gt & 0xF);
+            let v0 = (js + jt * n) as usize;
+            let at = |i: usize| grid.get(i).copied().unwrap_or(0);
+            let w11 = (fs * ft + 8) >> 4;
+            let (w10, w01) = (ft - w11, fs - w11);
+            let w00 = 16 + w11 - fs - ft;
+            let weight = (at(v0) * w00
+                + at(v0 + 1) * w01
+                + at(v0 + GRID) * w10
+                + at(v0 + GRID + 1) * w11
+                + 8)
+                >> 4;
+            // srgb decoding expands endpoints with 0x80 in the low byte, linear by repeating them
+            let expand = |e: u32| if srgb { (e << 8) | 0x80 } else { e * 257 };
+            pixels.push(std::array::from_fn(|c| {
+                let (c0, c1) = (expand(e0[c]), expand(e1[c]));
+                ((c0 * (64 - weight) + c1 * weight + 32) >> 6 >> 8) as u8
+            }));
+        }
+    }
+    Some(pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{gradient, psnr};
+
+    #[test]
+    fn astc_blocks_round_trip() {
+        // 62 is not a multiple of either block size, the blocks at the edges are partial
+        for (size, min_psnr) in [
+            (AstcBlockSize::Astc4x4, 35.0),
+            (AstcBlockSize::Astc6x6, 30.0),
+        ] {
+            let texture = gradient(62, 62, false);
+            let data = compress_astc(&texture, size);
+            let decoded = decompress_astc(&data, 62, 62, size, false).unwrap();
+            let psnr = psnr(&texture, &decoded);
+            assert!(psnr > min_psnr, "{size:?} has a psnr of {psnr}");
+        }
+    }
+
+    #[test]
+    fn truncated_astc_data_is_rejected() {
+        let data = compress_astc(&gradient(8, 8, false), AstcBlockSize::Astc4x4);
+        for len in 0..data.len() {
+            let decoded = decompress_astc(&data[..len], 8, 8, AstcBlockSize::Astc4x4, false);
+            assert!(decoded.is_none(), "decoded {len} bytes");
+        }
+    }
+}
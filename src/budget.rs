@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache::{Cache, PngChannels},
+    error::{PackError, PackResult},
+    pack::PackedAssets,
+    palette::quantize,
+    warnings::{WarningCode, Warnings},
+};
+
+/// Size the atlas pages of a pack should fit into, part of the [`crate::pack::PackSettings`]. Pages
+/// over it are written as lossy indexed pngs with at most 256 colors, see [`fit_size_budget`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SizeBudget {
+    /// kilobytes of all atlas page files together
+    pub kb: u64,
+    /// lowest peak signal to noise ratio in dB a lossy page may have, pages that would lose more
+    /// quality stay lossless. 35 dB is hard to tell apart from the original for most sprites.
+    pub min_psnr: f32,
+}
+
+impl Default for SizeBudget {
+    fn default() -> Self {
+        SizeBudget {
+            kb: 4096,
+            min_psnr: 35.0,
+        }
+    }
+}
+
+/// Color statistics of an atlas page, which predict how well it compresses losslessly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageStats {
+    /// shannon entropy of the rgba colors in bits per pixel, between 0 for a single color and 32
+    pub entropy: f32,
+    /// variance of the luma of the visible pixels, high for noisy or photographic pages
+    pub variance: f32,
+    /// number of distinct rgba colors
+    pub colors: usize,
+}
+
+impl PageStats {
+    pub fn of(rgba: &RgbaImage) -> PageStats {
+        let mut histogram: HashMap<[u8; 4], u32> = HashMap::new();
+        let (mut sum, mut sum_squared, mut visible) = (0.0f64, 0.0f64, 0u32);
+        for pixel in rgba.pixels() {
+            *histogram.entry(pixel.0).or_default() += 1;
+            if pixel[3] > 0 {
+                let [r, g, b, _] = pixel.0.map(|e| e as f64);
+                let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+                sum += luma;
+                sum_squared += luma * luma;
+                visible += 1;
+            }
+        }
+        let pixels = rgba.pixels().len().max(1) as f64;
+        let entropy: f64 = histogram
+            .values()
+            .map(|count| {
+                let p = *count as f64 / pixels;
+                -p * p.log2()
+            })
+            .sum();
+        let variance = match visible {
+            0 => 0.0,
+            n => {
+                let mean = sum / n as f64;
+                sum_squared / n as f64 - mean * mean
+            }
+        };
+        PageStats {
+            entropy: entropy as f32,
+            variance: variance.max(0.0) as f32,
+            colors: histogram.len(),
+        }
+    }
+}
+
+/// Peak signal to noise ratio in dB of the image quantized to at most 256 colors, infinite if the
+/// image has no more colors than that.
+fn quantized_psnr(rgba: &RgbaImage) -> f32 {
+    let (palette, indices) = quantize(rgba);
+    let squared_error: f64 = rgba
+        .pixels()
+        .zip(indices)
+        .flat_map(|(pixel, index)| pixel.0.into_iter().zip(palette[index as usize]))
+        .map(|(a, b)| (a as f64 - b as f64).powi(2))
+        .sum();
+    let mse = squared_error / (rgba.len().max(1)) as f64;
+    match mse == 0.0 {
+        true => f32::INFINITY,
+        false => (10.0 * (255.0 * 255.0 / mse).log10()) as f32,
+    }
+}
+
+/// Writes atlas pages as lossy indexed pngs while the atlas pages of `packed` in `out_path` are
+/// larger than the budget. `pages` are the names, pixels and channels of the pages written as
+/// lossless pngs. Pages with the highest color entropy compress worst losslessly, so they are tried
+/// first, and only kept lossy if that keeps their psnr above the minimum and shrinks their file.
+/// Every decision is printed, and a warning tells if the budget could not be met.
+pub fn fit_size_budget(
+    pages: &[(String, &RgbaImage, PngChannels)],
+    packed: &mut PackedAssets,
+    out_path: &str,
+    budget: &SizeBudget,
+    cache: &mut Cache,
+    warnings: &mut Warnings,
+) -> PackResult<()> {
+    let file_size = |path: &str| -> PackResult<u64> {
+        Ok(std::fs::metadata(path).map_err(PackError::io(path))?.len())
+    };
+    let budget_bytes = budget.kb * 1024;
+    let mut total: u64 = 0;
+    for (name, flags) in packed.textures.iter() {
+        total += file_size(&format!("{out_path}/{name}.{}", flags.format.extension()))?;
+    }
+    let kb = |bytes: u64| bytes.div_ceil(1024);
+    if total <= budget_bytes {
+        println!(
+            "atlas pages take {} KB of the {} KB budget, all lossless",
+            kb(total),
+            budget.kb
+        );
+        return Ok(());
+    }
+
+    let mut pages: Vec<(&String, &RgbaImage, PngChannels, PageStats)> = pages
+        .iter()
+        .map(|(name, rgba, channels)| (name, *rgba, *channels, PageStats::of(rgba)))
+        .collect();
+    pages.sort_by(|a, b| b.3.entropy.total_cmp(&a.3.entropy));
+    for (name, rgba, channels, stats) in pages {
+        if total <= budget_bytes {
+            break;
+        }
+        let path = format!("{out_path}/{name}.png");
+        let lossless_size = file_size(&path)?;
+        let description = format!(
+            "{name}: entropy {:.1} bits, luma variance {:.0}, {} colors, {} KB lossless",
+            stats.entropy,
+            stats.variance,
+            stats.colors,
+            kb(lossless_size)
+        );
+        let psnr = quantized_psnr(rgba);
+        if psnr < budget.min_psnr {
+            println!(
+                "{description}, kept lossless: indexed it would have {psnr:.1} dB, below {:.1} dB",
+                budget.min_psnr
+            );
+            continue;
+        }
+        cache.save_texture(rgba, &path, PngChannels::Indexed)?;
+        let lossy_size = file_size(&path)?;
+        if lossy_size >= lossless_size {
+            cache.save_texture(rgba, &path, channels)?;
+            println!(
+                "{description}, kept lossless: indexed it would not be smaller ({} KB)",
+                kb(lossy_size)
+            );
+            continue;
+        }
+        if let Some((_, flags)) = packed.textures.iter_mut().find(|e| e.0 == *name) {
+            flags.indexed = true;
+        }
+        total -= lossless_size - lossy_size;
+        let quality = match psnr.is_finite() {
+            true => format!("at {psnr:.1} dB"),
+            false => String::from("without loss, it has at most 256 colors"),
+        };
+        println!(
+            "{description}, written indexed: {} KB {quality}",
+            kb(lossy_size)
+        );
+    }
+    if total > budget_bytes {
+        warnings.warn(
+            WarningCode::W008,
+            None,
+            format!(
+                "the atlas pages take {} KB, over the size budget of {} KB",
+                kb(total),
+                budget.kb
+            ),
+        );
+    } else {
+        println!(
+            "atlas pages take {} KB of the {} KB budget",
+            kb(total),
+            budget.kb
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,325 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use image::{DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{PackError, PackResult},
+    layout::AtlasLayout,
+    palette::save_indexed_png,
+};
+
+/// On-disk cache that lets repeated packs skip decoding images, generating sdf fonts and encoding atlases
+/// whose inputs did not change since the last run.
+///
+/// Everything in the cache dir is content-addressed by a hash of its inputs, so a stale entry can never be
+/// picked up by accident. The manifest remembers the hashes of the last run, entries not used by a run
+/// are removed when the cache is saved. It also keeps the atlas layout of every pack, so unchanged sprites
+/// stay in place and their atlases encode to the same textures, see [`crate::layout`].
+pub struct Cache {
+    dir: PathBuf,
+    previous: CacheManifest,
+    manifest: CacheManifest,
+    /// behind a mutex, so images can be looked up and stored from several threads while gathering
+    used: Mutex<HashSet<String>>,
+    /// nesting depth of batches of packs sharing the cache, see [`Cache::start_batch`]
+    batch_depth: u32,
+    /// a pack of the batch was up to date without looking up its entries, so none can be deleted
+    keep_unused: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+struct CacheManifest {
+    /// source file path -> content hash
+    files: BTreeMap<String, u64>,
+    /// output file name -> content hash of the texture written there
+    outputs: BTreeMap<String, u64>,
+    /// output path of a pack -> the layout of its sprite atlases
+    #[serde(default)]
+    layouts: BTreeMap<String, AtlasLayout>,
+}
+
+impl Cache {
+    /// Loads the cache manifest from `dir`, starting with an empty cache if there is none yet.
+    pub fn load(dir: impl Into<PathBuf>) -> PackResult<Cache> {
+        let dir: PathBuf = dir.into();
+        let manifest = std::fs::read(dir.join("cache.json"))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        for sub_dir in ["images", "fonts", "textures", "optimized"] {
+            let sub_dir = dir.join(sub_dir);
+            std::fs::create_dir_all(&sub_dir).map_err(PackError::io(&sub_dir))?;
+        }
+        Ok(Cache {
+            dir,
+            previous: manifest,
+            manifest: CacheManifest::default(),
+            used: Mutex::new(HashSet::new()),
+            batch_depth: 0,
+            keep_unused: false,
+        })
+    }
+
+    /// Writes the manifest and deletes all cache entries that were not used by this run.
+    pub fn save(&mut self) -> PackResult<()> {
+        let changed = self
+            .manifest
+            .files
+            .iter()
+            .filter(|(path, hash)| self.previous.files.get(*path) != Some(*hash))
+            .count();
+        println!(
+            "cache: {changed} of {} source files changed since last run",
+            self.manifest.files.len()
+        );
+
+        let mut manifest = std::mem::take(&mut self.manifest);
+        match self.batch_depth {
+            0 => {
+                self.remove_unused();
+                // packs that were skipped or did not run keep their layout
+                let mut layouts = std::mem::take(&mut self.previous.layouts);
+                layouts.append(&mut manifest.layouts);
+                self.previous = CacheManifest {
+                    layouts,
+                    ..manifest
+                };
+            }
+            // the other packs of the batch recorded their files in the previous manifest
+            _ => {
+                self.previous.files.extend(manifest.files);
+                self.previous.outputs.extend(manifest.outputs);
+                self.previous.layouts.extend(manifest.layouts);
+            }
+        }
+        let manifest_path = self.dir.join("cache.json");
+        let json =
+            serde_json::to_string(&self.previous).map_err(PackError::json(&manifest_path))?;
+        std::fs::write(&manifest_path, json).map_err(PackError::io(&manifest_path))
+    }
+
+    /// Starts a batch of packs that share the cache, like the variants of a pack or the projects of
+    /// a workspace. Until the batch is finished, `save` keeps the entries that the other packs used.
+    pub fn start_batch(&mut self) {
+        self.batch_depth += 1;
+    }
+
+    /// Deletes the entries that none of the packs of the batch used, once the outermost batch is finished.
+    pub fn finish_batch(&mut self) {
+        self.batch_depth = self.batch_depth.saturating_sub(1);
+        if self.batch_depth == 0 {
+            if !std::mem::take(&mut self.keep_unused) {
+                self.remove_unused();
+            }
+            self.used.get_mut().unwrap().clear();
+        }
+    }
+
+    /// Called for packs that are skipped because they are up to date. Within a batch, their entries
+    /// are kept, as they were not looked up.
+    pub fn skip_pack(&mut self) {
+        if self.batch_depth > 0 {
+            self.keep_unused = true;
+        }
+    }
+
+    fn remove_unused(&mut self) {
+        for sub_dir in ["images", "fonts", "textures", "optimized"] {
+            let Ok(dir) = std::fs::read_dir(self.dir.join(sub_dir)) else {
+                continue;
+            };
+            for entry in dir.flatten() {
+                let name = format!("{sub_dir}/{}", entry.file_name().to_string_lossy());
+                if !self.used.get_mut().unwrap().contains(&name) {
+                    _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+        self.used.get_mut().unwrap().clear();
+    }
+
+    fn mark_used(&self, name: String) {
+        self.used.lock().unwrap().insert(name);
+    }
+
+    /// Records the content hash of a source file for this run.
+    pub fn record_file(&mut self, path: &Path, hash: u64) {
+        self.manifest
+            .files
+            .insert(path.to_string_lossy().into_owned(), hash);
+    }
+
+    /// The atlas layout the last pack to `out_path` left behind.
+    pub fn atlas_layout(&self, out_path: &str) -> Option<AtlasLayout> {
+        self.previous.layouts.get(out_path).cloned()
+    }
+
+    pub fn store_atlas_layout(&mut self, out_path: &str, layout: AtlasLayout) {
+        self.manifest.layouts.insert(out_path.to_owned(), layout);
+    }
+
+    /// Forgets the atlas layouts of all packs, so the next packs lay out their atlases from scratch.
+    pub fn discard_atlas_layouts(&mut self) {
+        self.previous.layouts.clear();
+        self.manifest.layouts.clear();
+    }
+
+    /// Returns the decoded image for a png with the given content hash, if it was decoded before.
+    pub fn decoded_image(&self, hash: u64) -> Option<RgbaImage> {
+        let name = format!("images/{hash:016x}.rgba");
+        let bytes = std::fs::read(self.dir.join(&name)).ok()?;
+        let (size, pixels) = bytes.split_at_checked(8)?;
+        let w = u32::from_le_bytes(size[0..4].try_into().unwrap());
+        let h = u32::from_le_bytes(size[4..8].try_into().unwrap());
+        let rgba = RgbaImage::from_raw(w, h, pixels.to_vec())?;
+        self.mark_used(name);
+        Some(rgba)
+    }
+
+    pub fn store_decoded_image(&self, hash: u64, rgba: &RgbaImage) -> PackResult<()> {
+        let name = format!("images/{hash:016x}.rgba");
+        let mut bytes: Vec<u8> = Vec::with_capacity(8 + rgba.as_raw().len());
+        bytes.extend(rgba.width().to_le_bytes());
+        bytes.extend(rgba.height().to_le_bytes());
+        bytes.extend(rgba.as_raw());
+        let path = self.dir.join(&name);
+        std::fs::write(&path, bytes).map_err(PackError::io(&path))?;
+        self.mark_used(name);
+        Ok(())
+    }
+
+    /// Copies the cached sdf font json and png for the font with the given hash to `json_path` and `png_path`.
+    /// Returns false if the font has not been cached yet.
+    pub fn restore_sdf_font(&mut self, hash: u64, json_path: &str, png_path: &str) -> bool {
+        let json_name = format!("fonts/{hash:016x}.sdf_font.json");
+        let png_name = format!("fonts/{hash:016x}.sdf_font.png");
+        let restored = std::fs::copy(self.dir.join(&json_name), json_path).is_ok()
+            && std::fs::copy(self.dir.join(&png_name), png_path).is_ok();
+        if restored {
+            self.mark_used(json_name);
+            self.mark_used(png_name);
+        }
+        restored
+    }
+
+    pub fn store_sdf_font(&mut self, hash: u64, json_path: &str, png_path: &str) -> PackResult<()> {
+        let json_name = format!("fonts/{hash:016x}.sdf_font.json");
+        let png_name = format!("fonts/{hash:016x}.sdf_font.png");
+        std::fs::copy(json_path, self.dir.join(&json_name)).map_err(PackError::io(json_path))?;
+        std::fs::copy(png_path, self.dir.join(&png_name)).map_err(PackError::io(png_path))?;
+        self.mark_used(json_name);
+        self.mark_used(png_name);
+        Ok(())
+    }
+
+    /// Copies the optimized version of the png with the given content hash to `path`, see
+    /// [`crate::optimize::optimize_png`]. Returns false if it has not been optimized before.
+    pub fn restore_optimized_png(&self, hash: u64, path: &Path) -> bool {
+        let name = format!("optimized/{hash:016x}.png");
+        let restored = std::fs::copy(self.dir.join(&name), path).is_ok();
+        if restored {
+            self.mark_used(name);
+        }
+        restored
+    }
+
+    pub fn store_optimized_png(&self, hash: u64, path: &Path) -> PackResult<()> {
+        let name = format!("optimized/{hash:016x}.png");
+        std::fs::copy(path, self.dir.join(&name)).map_err(PackError::io(path))?;
+        self.mark_used(name);
+        Ok(())
+    }
+
+    /// Saves `rgba` as a png at `path`, reusing the already encoded png of a previous run if the pixels are the same.
+    /// Rgb only makes sense for opaque textures, gray for textures whose channels are all the same,
+    /// indexed for textures whose colors may be quantized.
+    pub fn save_texture(
+        &mut self,
+        rgba: &RgbaImage,
+        path: &str,
+        channels: PngChannels,
+    ) -> PackResult<()> {
+        let hash = texture_hash(rgba);
+
+        let name = match channels {
+            PngChannels::Rgba => format!("textures/{hash:016x}.png"),
+            PngChannels::Rgb => format!("textures/{hash:016x}.rgb.png"),
+            PngChannels::Gray => format!("textures/{hash:016x}.gray.png"),
+            PngChannels::Indexed => format!("textures/{hash:016x}.indexed.png"),
+        };
+        let cached = self.dir.join(&name);
+        if std::fs::copy(&cached, path).is_err() {
+            let image = DynamicImage::ImageRgba8(rgba.clone());
+            match channels {
+                PngChannels::Rgba => rgba.save(path).map_err(PackError::image(path)),
+                PngChannels::Rgb => image.to_rgb8().save(path).map_err(PackError::image(path)),
+                PngChannels::Gray => image.to_luma8().save(path).map_err(PackError::image(path)),
+                PngChannels::Indexed => save_indexed_png(rgba, path),
+            }?;
+            std::fs::copy(path, &cached).map_err(PackError::io(&cached))?;
+        }
+        self.mark_used(name);
+        let file_name = Path::new(path)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        self.manifest.outputs.insert(file_name.into_owned(), hash);
+        Ok(())
+    }
+}
+
+/// Channels a texture png is written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PngChannels {
+    Rgba,
+    Rgb,
+    Gray,
+    /// at most 256 colors, see [`crate::palette::quantize`]
+    Indexed,
+}
+
+/// 64 bit FNV-1a, stable across runs and platforms unlike the std `DefaultHasher`.
+pub struct ContentHasher(u64);
+
+impl Default for ContentHasher {
+    fn default() -> Self {
+        ContentHasher(0xcbf29ce484222325)
+    }
+}
+
+impl ContentHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    let mut hasher = ContentHasher::new();
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+/// Hash over the size and pixels of a texture.
+pub fn texture_hash(rgba: &RgbaImage) -> u64 {
+    let mut hasher = ContentHasher::new();
+    hasher.write(&rgba.width().to_le_bytes());
+    hasher.write(&rgba.height().to_le_bytes());
+    hasher.write(rgba.as_raw());
+    hasher.finish()
+}
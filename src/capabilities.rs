@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{PackError, PackResult},
+    gather::GatheredAssets,
+    pack::PackSettings,
+    warnings::{WarningCode, Warnings},
+};
+
+/// What the engine that loads the pack can handle. If set in the [`PackSettings`], the packer adjusts
+/// its settings to stay within these limits and fails early for assets that can never fit, instead of
+/// producing packs that only fail to load on the target device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineCapabilities {
+    /// maximum width and height of a texture
+    pub max_texture_size: u32,
+    /// file formats the engine can load textures from, e.g. "png" or "ktx2"
+    pub supported_formats: Vec<String>,
+    /// whether textures with sizes that are not a power of two are supported
+    pub non_power_of_two: bool,
+}
+
+impl Default for EngineCapabilities {
+    fn default() -> Self {
+        EngineCapabilities {
+            max_texture_size: 8192,
+            supported_formats: vec![String::from("png")],
+            non_power_of_two: true,
+        }
+    }
+}
+
+impl EngineCapabilities {
+    /// Returns the settings adjusted to the capabilities, or all problems if some assets cannot be
+    /// packed for the engine at all.
+    pub fn adjust_settings(
+        &self,
+        gathered: &GatheredAssets,
+        settings: &PackSettings,
+        warnings: &mut Warnings,
+    ) -> PackResult<PackSettings> {
+        let mut settings = settings.clone();
+        let mut errors: Vec<PackError> = vec![];
+        let incompatible = |message: String| PackError::Incompatible { message };
+
+        let formats =
+            std::iter::once(settings.texture_format).chain(settings.repeat_texture_format);
+        for format in formats.map(|e| e.extension()) {
+            if !self.supported_formats.iter().any(|e| e == format) {
+                errors.push(incompatible(format!(
+                    "the packer writes {format} textures, but the engine only supports {:?}",
+                    self.supported_formats
+                )));
+            }
+        }
+
+        let mut fit_atlas_size = |size: &mut u32, dimension: &str| {
+            let mut fitting = (*size).min(self.max_texture_size);
+            if !self.non_power_of_two && !fitting.is_power_of_two() {
+                fitting = 1 << fitting.ilog2();
+            }
+            if fitting != *size {
+                warnings.warn(
+                    WarningCode::W005,
+                    None,
+                    format!("reducing atlas {dimension} from {size} to {fitting} for the engine capabilities"),
+                );
+                *size = fitting;
+            }
+        };
+        fit_atlas_size(&mut settings.atlas_width, "width");
+        fit_atlas_size(&mut settings.atlas_height, "height");
+
+        let mut names: Vec<&String> = gathered.images.keys().collect();
+        names.sort();
+        for name in names {
+            let asset = &gathered.images[name];
+            let (w, h) = asset.rgba.dimensions();
+            let in_atlas = !asset.no_pack && !asset.repeat_x && !asset.repeat_y;
+            let oversized = in_atlas && (w > settings.atlas_width || h > settings.atlas_height);
+            let split = oversized && settings.split_oversized;
+            if !split && (w > self.max_texture_size || h > self.max_texture_size) {
+                errors.push(incompatible(format!(
+                    "image {name} ({w}x{h}) exceeds the maximum texture size {}",
+                    self.max_texture_size
+                )));
+            }
+            // everything else is packed into atlases, which have a valid size anyway
+            let is_own_texture =
+                asset.no_pack || asset.has_own_repeat_texture() || (oversized && !split);
+            if is_own_texture
+                && !self.non_power_of_two
+                && !(w.is_power_of_two() && h.is_power_of_two())
+            {
+                errors.push(incompatible(format!(
+                    "image {name} ({w}x{h}) gets its own texture, but its size is not a power of two"
+                )));
+            }
+        }
+
+        PackError::from_errors(errors)?;
+        Ok(settings)
+    }
+
+    /// Fails if a texture produced by the packer does not meet the capabilities.
+    pub fn check_texture(&self, name: &str, (w, h): (u32, u32)) -> PackResult<()> {
+        if w > self.max_texture_size || h > self.max_texture_size {
+            return Err(PackError::Incompatible {
+                message: format!(
+                    "texture {name} ({w}x{h}) exceeds the maximum texture size {}",
+                    self.max_texture_size
+                ),
+            });
+        }
+        let is_power_of_two = w.is_power_of_two() && h.is_power_of_two();
+        if !self.non_power_of_two && !is_power_of_two {
+            return Err(PackError::Incompatible {
+                message: format!("texture {name} ({w}x{h}) has a size that is not a power of two"),
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use std::{collections::HashMap, fmt::Write};
+
+use heck::{ToShoutySnakeCase, ToSnakeCase, ToUpperCamelCase};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{PackError, PackResult},
+    font::icon_codepoints,
+    gather::GatheredAssets,
+    pack::PackedAssets,
+    warnings::{WarningCode, Warnings},
+};
+
+/// How asset names are turned into identifiers of a codegen target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentCase {
+    /// `BUTTON_LARGE`, constants in one module per asset kind
+    #[default]
+    ScreamingSnake,
+    /// `button_large`, constants in one module per asset kind
+    Snake,
+    /// `ButtonLarge`, variants of one enum per asset kind
+    Pascal,
+    /// `tiles.button_large`, string keys for scripting languages
+    Dotted,
+}
+
+impl IdentCase {
+    /// The identifier of the asset `name` of the kind `namespace` (`tiles`, `fonts` or `icons`).
+    pub fn ident(self, namespace: &str, name: &str) -> String {
+        let ident = match self {
+            IdentCase::ScreamingSnake => name.to_shouty_snake_case(),
+            IdentCase::Snake => name.to_snake_case(),
+            IdentCase::Pascal => name.to_upper_camel_case(),
+            IdentCase::Dotted => return format!("{namespace}.{}", name.to_snake_case()),
+        };
+        if ident.starts_with(|c: char| c.is_ascii_digit()) {
+            format!("_{ident}")
+        } else if RUST_KEYWORDS.contains(&ident.as_str()) {
+            format!("{ident}_")
+        } else {
+            ident
+        }
+    }
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "yield",
+];
+
+/// Generates a rust file with a constant (or enum variant) for every tile, font and icon of a pack, so
+/// games get compile errors instead of runtime lookup failures for misspelled asset names.
+pub fn generate_rust(
+    packed: &PackedAssets,
+    gathered: &GatheredAssets,
+    case: IdentCase,
+    warnings: &mut Warnings,
+) -> PackResult<String> {
+    let mut tiles: Vec<&str> = packed.tiles.keys().map(|e| e.as_str()).collect();
+    tiles.sort();
+    let mut fonts: Vec<&str> = packed.fonts.iter().map(|e| e.as_str()).collect();
+    fonts.sort();
+    let icons: Vec<(&str, char)> = icon_codepoints(&gathered.icons);
+
+    match case {
+        IdentCase::ScreamingSnake | IdentCase::Snake => Ok(rust_constants(
+            packed, &tiles, &fonts, &icons, case, warnings,
+        )),
+        IdentCase::Pascal => Ok(rust_enums(packed, &tiles, &fonts, &icons, warnings)),
+        IdentCase::Dotted => Err(PackError::Unsupported {
+            message: String::from(
+                "dotted identifiers are not valid rust, use them for scripting exports only",
+            ),
+        }),
+    }
+}
+
+fn rust_constants(
+    packed: &PackedAssets,
+    tiles: &[&str],
+    fonts: &[&str],
+    icons: &[(&str, char)],
+    case: IdentCase,
+    warnings: &mut Warnings,
+) -> String {
+    let default_ident = case.ident("fonts", "default");
+    let mut out = String::new();
+    out.push_str("// generated by assetpacker, do not edit\n\n");
+    out.push_str("#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]\n");
+    out.push_str("pub struct TileId(pub &'static str);\n\n");
+    out.push_str("#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]\n");
+    out.push_str("pub struct FontId(pub &'static str);\n\n");
+    let allow = if case == IdentCase::Snake {
+        "#[allow(non_upper_case_globals)]\n"
+    } else {
+        ""
+    };
+
+    write!(out, "{allow}pub mod tiles {{\n    use super::TileId;\n").unwrap();
+    for (name, ident) in unique_idents("tiles", tiles, case, &[], warnings) {
+        writeln!(out, "    pub const {ident}: TileId = TileId({name:?});").unwrap();
+    }
+    out.push_str("}\n\n");
+
+    write!(out, "{allow}pub mod fonts {{\n    use super::FontId;\n").unwrap();
+    for (name, ident) in unique_idents("fonts", fonts, case, &[&default_ident], warnings) {
+        writeln!(out, "    pub const {ident}: FontId = FontId({name:?});").unwrap();
+    }
+    if let Some(default_font) = &packed.default_font {
+        writeln!(
+            out,
+            "    pub const {default_ident}: FontId = FontId({default_font:?});"
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+
+    if !icons.is_empty() {
+        write!(
+            out,
+            "\n/// codepoints of the icons in the icon font\n{allow}pub mod icons {{\n"
+        )
+        .unwrap();
+        let chars: HashMap<&str, char> = icons.iter().copied().collect();
+        let names: Vec<&str> = icons.iter().map(|(name, _)| *name).collect();
+        for (name, ident) in unique_idents("icons", &names, case, &[], warnings) {
+            writeln!(out, "    pub const {ident}: char = {:?};", chars[name]).unwrap();
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+fn rust_enums(
+    packed: &PackedAssets,
+    tiles: &[&str],
+    fonts: &[&str],
+    icons: &[(&str, char)],
+    warnings: &mut Warnings,
+) -> String {
+    let case = IdentCase::Pascal;
+    let mut out = String::new();
+    out.push_str("// generated by assetpacker, do not edit\n");
+
+    let tiles = unique_idents("tiles", tiles, case, &[], warnings);
+    rust_enum(&mut out, "Tile", &tiles);
+
+    let fonts = unique_idents("fonts", fonts, case, &[], warnings);
+    rust_enum(&mut out, "Font", &fonts);
+    let default_font = packed.default_font.as_deref();
+    if let Some((_, ident)) = fonts.iter().find(|(name, _)| Some(*name) == default_font) {
+        writeln!(
+            out,
+            "\nimpl Font {{\n    pub const DEFAULT: Font = Font::{ident};\n}}"
+        )
+        .unwrap();
+    }
+
+    if !icons.is_empty() {
+        let chars: HashMap<&str, char> = icons.iter().copied().collect();
+        let names: Vec<&str> = icons.iter().map(|(name, _)| *name).collect();
+        let icons = unique_idents("icons", &names, case, &[], warnings);
+        rust_enum(&mut out, "Icon", &icons);
+        out.push_str("\nimpl Icon {\n    /// codepoint of the icon in the icon font\n");
+        out.push_str("    pub const fn char(self) -> char {\n        match self {\n");
+        for (name, ident) in icons.iter() {
+            writeln!(out, "            Icon::{ident} => {:?},", chars[name]).unwrap();
+        }
+        out.push_str("        }\n    }\n}\n");
+    }
+    out
+}
+
+/// An enum with one variant per asset, plus `ALL` and a `name` method returning the asset name.
+fn rust_enum(out: &mut String, enum_name: &str, variants: &[(&str, String)]) {
+    writeln!(
+        out,
+        "\n#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]\npub enum {enum_name} {{"
+    )
+    .unwrap();
+    for (_, ident) in variants {
+        writeln!(out, "    {ident},").unwrap();
+    }
+    writeln!(out, "}}\n\nimpl {enum_name} {{").unwrap();
+    write!(out, "    pub const ALL: &'static [{enum_name}] = &[").unwrap();
+    for (_, ident) in variants {
+        write!(out, "{enum_name}::{ident}, ").unwrap();
+    }
+    out.push_str("];\n\n    pub const fn name(self) -> &'static str {\n        match self {\n");
+    for (name, ident) in variants {
+        writeln!(out, "            {enum_name}::{ident} => {name:?},").unwrap();
+    }
+    out.push_str("        }\n    }\n}\n");
+}
+
+/// Identifiers for the sorted asset `names` of one kind. Names whose identifier collides with an
+/// earlier name or one of the `reserved` identifiers are reported and left out, so the generated code
+/// still compiles.
+pub fn unique_idents<'a>(
+    namespace: &str,
+    names: &[&'a str],
+    case: IdentCase,
+    reserved: &[&str],
+    warnings: &mut Warnings,
+) -> Vec<(&'a str, String)> {
+    let mut taken: HashMap<String, &str> = HashMap::new();
+    let mut idents: Vec<(&'a str, String)> = vec![];
+    for name in names {
+        let ident = case.ident(namespace, name);
+        if reserved.contains(&ident.as_str()) {
+            warnings.warn(
+                WarningCode::W004,
+                None,
+                format!("{namespace} {name} maps to the reserved identifier {ident}, skipping it"),
+            );
+        } else if let Some(other) = taken.get(&ident) {
+            warnings.warn(
+                WarningCode::W004,
+                None,
+                format!("{namespace} {other} and {name} both map to the identifier {ident}, skipping {name}"),
+            );
+        } else {
+            taken.insert(ident.clone(), name);
+            idents.push((name, ident));
+        }
+    }
+    idents
+}
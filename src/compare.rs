@@ -0,0 +1,241 @@
+use std::{collections::BTreeSet, fmt::Write, io::ErrorKind, path::Path, process::Command};
+
+use crate::{
+    error::{PackError, PackResult},
+    pack::{pack_file_names, parse_manifest, read_manifest, ManifestFormat, PackedAssets},
+};
+
+/// What happened to a tile, texture, font or animation between a baseline and a new pack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+    /// with what changed, like `moved from atlas_0 (2, 2) to atlas_1 (34, 2)`
+    Changed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ManifestChange {
+    /// `tile`, `texture`, `font` or `animation`
+    pub kind: &'static str,
+    pub name: String,
+    pub change: Change,
+}
+
+/// Differences between the manifests of two packs.
+#[derive(Debug, Clone, Default)]
+pub struct ManifestDiff {
+    /// sorted by kind and name
+    pub changes: Vec<ManifestChange>,
+    /// bytes of the added and changed texture and font files of the new pack, which a release has to
+    /// upload
+    pub upload_bytes: u64,
+}
+
+impl ManifestDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Reads the manifest of a baseline pack, from a `http://` or `https://` url like the manifest of
+/// the last release on a CDN, a manifest file or a pack dir. Urls are downloaded with `curl`, which
+/// ships with all current desktop systems and has to be on the `PATH`.
+pub fn read_baseline(baseline: &str) -> PackResult<PackedAssets> {
+    let format = match baseline.ends_with(".bin") {
+        true => ManifestFormat::Binary,
+        false => ManifestFormat::Json,
+    };
+    if baseline.starts_with("http://") || baseline.starts_with("https://") {
+        let output = Command::new("curl")
+            .args(["--fail", "--silent", "--show-error", "--location", baseline])
+            .output()
+            .map_err(|e| {
+                let message = match e.kind() {
+                    ErrorKind::NotFound => String::from(
+                        "downloading a baseline needs curl, install it or pass a manifest file or pack dir",
+                    ),
+                    _ => format!("could not run curl: {e}"),
+                };
+                PackError::io(baseline)(std::io::Error::new(e.kind(), message))
+            })?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = format!("curl failed with {}: {}", output.status, stderr.trim());
+            return Err(PackError::io(baseline)(std::io::Error::other(message)));
+        }
+        return parse_manifest(baseline, &output.stdout, format);
+    }
+    if Path::new(baseline).is_dir() {
+        return read_manifest(baseline);
+    }
+    let bytes = std::fs::read(baseline).map_err(PackError::io(baseline))?;
+    parse_manifest(baseline, &bytes, format)
+}
+
+/// Compares the manifest of the pack in `dir` to the `baseline` manifest. Textures and fonts are
+/// compared by their checksums, so baselines of versions without checksums only show added and
+/// removed ones.
+pub fn compare_to_baseline(baseline: &PackedAssets, dir: &str) -> PackResult<ManifestDiff> {
+    let new = read_manifest(dir)?;
+    let mut diff = ManifestDiff::default();
+    let mut push = |kind: &'static str, name: &str, change: Change| {
+        diff.changes.push(ManifestChange {
+            kind,
+            name: name.to_string(),
+            change,
+        })
+    };
+
+    let tile_names: BTreeSet<&String> = baseline.tiles.keys().chain(new.tiles.keys()).collect();
+    for name in tile_names {
+        let change = match (baseline.tiles.get(name), new.tiles.get(name)) {
+            (Some(_), None) => Some(Change::Removed),
+            (None, Some(_)) => Some(Change::Added),
+            (Some(old), Some(new)) => {
+                let (old_size, new_size) = (old.max - old.min, new.max - new.min);
+                if old_size != new_size {
+                    Some(Change::Changed(format!(
+                        "resized {}x{} -> {}x{}",
+                        old_size.x, old_size.y, new_size.x, new_size.y
+                    )))
+                } else if old.atlas != new.atlas || old.min != new.min {
+                    Some(Change::Changed(format!(
+                        "moved from {} ({}, {}) to {} ({}, {})",
+                        old.atlas, old.min.x, old.min.y, new.atlas, new.min.x, new.min.y
+                    )))
+                } else if serde_json::to_value(old).ok() != serde_json::to_value(new).ok() {
+                    Some(Change::Changed(String::from("flags changed")))
+                } else {
+                    None
+                }
+            }
+            (None, None) => None,
+        };
+        if let Some(change) = change {
+            push("tile", name, change);
+        }
+    }
+
+    // mip levels have checksums of their own, named like `atlas_0.mip1`
+    let texture_names = |packed: &PackedAssets| -> BTreeSet<String> {
+        let mut names: BTreeSet<String> = packed.checksums.textures.keys().cloned().collect();
+        names.extend(packed.textures.iter().map(|(name, _)| name.clone()));
+        names
+    };
+    let (old_textures, new_textures) = (texture_names(baseline), texture_names(&new));
+    for name in old_textures.union(&new_textures) {
+        let change = match (old_textures.contains(name), new_textures.contains(name)) {
+            (true, false) => Change::Removed,
+            (false, true) => Change::Added,
+            _ => match (
+                baseline.checksums.textures.get(name),
+                new.checksums.textures.get(name),
+            ) {
+                (Some(old), Some(new)) if old != new => {
+                    Change::Changed(String::from("contents changed"))
+                }
+                _ => continue,
+            },
+        };
+        push("texture", name, change);
+    }
+
+    let font_names: BTreeSet<&String> = baseline.fonts.iter().chain(new.fonts.iter()).collect();
+    for name in font_names {
+        let change = match (baseline.fonts.contains(name), new.fonts.contains(name)) {
+            (true, false) => Change::Removed,
+            (false, true) => Change::Added,
+            _ => match (
+                baseline.checksums.fonts.get(name),
+                new.checksums.fonts.get(name),
+            ) {
+                (Some(old), Some(new)) if old != new => {
+                    Change::Changed(String::from("glyphs changed"))
+                }
+                _ => continue,
+            },
+        };
+        push("font", name, change);
+    }
+
+    let animation_names: BTreeSet<&String> = baseline
+        .animations
+        .keys()
+        .chain(new.animations.keys())
+        .collect();
+    for name in animation_names {
+        let change = match (baseline.animations.get(name), new.animations.get(name)) {
+            (Some(_), None) => Change::Removed,
+            (None, Some(_)) => Change::Added,
+            (Some(old), Some(new)) if old.frames.len() != new.frames.len() => Change::Changed(
+                format!("{} -> {} frames", old.frames.len(), new.frames.len()),
+            ),
+            (Some(old), Some(new)) if old.frames != new.frames => {
+                Change::Changed(String::from("frames changed"))
+            }
+            (Some(old), Some(new))
+                if serde_json::to_value(old).ok() != serde_json::to_value(new).ok() =>
+            {
+                Change::Changed(String::from("playback changed"))
+            }
+            _ => continue,
+        };
+        push("animation", name, change);
+    }
+
+    // the files of added and changed textures and fonts
+    let uploaded: BTreeSet<&str> = diff
+        .changes
+        .iter()
+        .filter(|e| matches!(e.kind, "texture" | "font") && e.change != Change::Removed)
+        .map(|e| e.name.as_str())
+        .collect();
+    for (name, extension) in pack_file_names(&new) {
+        let checksum_name = match extension.split_once('.') {
+            Some((mip, _)) if mip.starts_with("mip") => format!("{name}.{mip}"),
+            _ => name.to_string(),
+        };
+        if uploaded.contains(checksum_name.as_str()) {
+            let file_name = format!("{name}.{extension}");
+            let path = format!("{dir}/{}", new.file_name(&file_name));
+            diff.upload_bytes += std::fs::metadata(&path).map(|e| e.len()).unwrap_or(0);
+        }
+    }
+    Ok(diff)
+}
+
+/// Renders the changes as a table followed by a summary line.
+pub fn format_manifest_diff(diff: &ManifestDiff) -> String {
+    let mut out = String::new();
+    for change in diff.changes.iter() {
+        let description = match &change.change {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Changed(description) => description.as_str(),
+        };
+        writeln!(out, "{:<10} {:<32} {description}", change.kind, change.name).unwrap();
+    }
+    writeln!(
+        out,
+        "{} changes, {} KB of textures and fonts to upload",
+        diff.changes.len(),
+        diff.upload_bytes.div_ceil(1024)
+    )
+    .unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_baseline;
+
+    #[test]
+    fn failed_downloads_name_the_url_and_curl() {
+        // nothing listens on the discard port, so curl fails right away without a network
+        let url = "http://127.0.0.1:9/packed.json";
+        let error = read_baseline(url).unwrap_err().to_string();
+        assert!(error.contains(url), "{error}");
+        assert!(error.contains("curl"), "{error}");
+    }
+}
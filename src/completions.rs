@@ -0,0 +1,288 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+/// The commands and options of the cli, parsed from its usage text so they never get out of sync
+/// with it. Printed by `assetpacker --help-json` for launchers and wrappers.
+#[derive(Debug, Clone, Serialize)]
+pub struct CliHelp {
+    pub name: String,
+    pub version: String,
+    pub commands: Vec<CliCommand>,
+    /// the options of packing, accepted by the commands with `pack_options`
+    pub options: Vec<CliOption>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CliCommand {
+    /// `pack` for packing without a command
+    pub name: String,
+    pub usage: String,
+    /// whether the command takes the packing options
+    pub pack_options: bool,
+    /// options only this command takes, like `--highlight` of `visual-diff`
+    pub options: Vec<CliOption>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CliOption {
+    /// like `--atlas-width`
+    pub name: String,
+    /// placeholder of the value, like `N` for `--atlas-width=N`, `None` for flags
+    pub value: Option<String>,
+    pub description: String,
+}
+
+impl CliHelp {
+    /// Parses a usage text with a line per command like `assetpacker stats path/to/srcdir` and an
+    /// `options:` section with a line per option, whose description may continue on indented lines.
+    pub fn parse(usage: &str) -> CliHelp {
+        let (commands_text, options_text) = usage.split_once("\noptions:").unwrap_or((usage, ""));
+        let mut commands: Vec<CliCommand> = vec![];
+        for line in commands_text.lines() {
+            let Some((_, usage)) = line.split_once("assetpacker ") else {
+                continue;
+            };
+            let mut tokens = usage.split_whitespace().peekable();
+            // packing has no command, `--help-json` is a command of its own
+            let name = tokens
+                .next_if(|e| !e.starts_with('[') && !e.contains('/'))
+                .unwrap_or("pack");
+            let mut options: Vec<CliOption> = vec![];
+            while let Some(token) = tokens.next() {
+                let token = token.trim_matches(['[', ']']);
+                if !token.starts_with("--") {
+                    continue;
+                }
+                // `--font NAME` takes the next token as its value
+                let value = tokens
+                    .next_if(|e| e.chars().all(|c| c.is_ascii_uppercase() || c == ']'))
+                    .map(|e| e.trim_end_matches(']').to_string());
+                options.push(CliOption {
+                    name: token.to_string(),
+                    value,
+                    description: String::new(),
+                });
+            }
+            commands.push(CliCommand {
+                name: name.to_string(),
+                usage: format!("assetpacker {usage}"),
+                pack_options: usage.contains("[options]"),
+                options,
+            });
+        }
+
+        let mut options: Vec<CliOption> = vec![];
+        for line in options_text.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if !line.starts_with("--") {
+                if let Some(option) = options.last_mut() {
+                    option.description.push(' ');
+                    option.description.push_str(line);
+                }
+                continue;
+            }
+            let (name, description) = line.split_once("  ").unwrap_or((line, ""));
+            let (name, value) = match name.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (name, None),
+            };
+            options.push(CliOption {
+                name: name.to_string(),
+                value,
+                description: description.trim().to_string(),
+            });
+        }
+        CliHelp {
+            name: String::from("assetpacker"),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            commands,
+            options,
+        }
+    }
+
+    fn all_options(&self) -> Vec<&CliOption> {
+        let mut all: Vec<&CliOption> = self.options.iter().collect();
+        for option in self.commands.iter().flat_map(|e| e.options.iter()) {
+            if !all.iter().any(|e| e.name == option.name) {
+                all.push(option);
+            }
+        }
+        all
+    }
+
+    /// Commands besides packing, which is the default.
+    fn named_commands(&self) -> impl Iterator<Item = &CliCommand> {
+        self.commands.iter().filter(|e| e.name != "pack")
+    }
+}
+
+/// Shells that [`completion_script`] writes scripts for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+}
+
+impl Shell {
+    pub const NAMES: [(&'static str, Shell); 4] = [
+        ("bash", Shell::Bash),
+        ("zsh", Shell::Zsh),
+        ("fish", Shell::Fish),
+        ("powershell", Shell::PowerShell),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Shell> {
+        Shell::NAMES
+            .iter()
+            .find(|(e, _)| *e == name)
+            .map(|(_, shell)| *shell)
+    }
+}
+
+/// A script completing the commands and options of `help` in `shell`, to be sourced from the
+/// config of the shell, like `assetpacker completions bash > ~/.local/share/bash-completion/completions/assetpacker`.
+/// Paths are completed as files.
+pub fn completion_script(help: &CliHelp, shell: Shell) -> String {
+    let mut out = String::new();
+    let name = &help.name;
+    let options = help.all_options();
+    match shell {
+        Shell::Bash => {
+            let words = |iter: &mut dyn Iterator<Item = String>| iter.collect::<Vec<_>>().join(" ");
+            let commands = words(&mut help.named_commands().map(|e| e.name.clone()));
+            let options = words(&mut options.iter().map(|e| match e.value {
+                Some(_) => format!("{}=", e.name),
+                None => e.name.clone(),
+            }));
+            write!(
+                out,
+                r#"_{name}() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    case "$cur" in
+        --*)
+            COMPREPLY=($(compgen -W "{options}" -- "$cur"))
+            [[ "${{COMPREPLY[0]}}" == *= ]] && compopt -o nospace
+            ;;
+        *)
+            COMPREPLY=()
+            if [ "$COMP_CWORD" -eq 1 ]; then
+                COMPREPLY=($(compgen -W "{commands}" -- "$cur"))
+            fi
+            COMPREPLY+=($(compgen -f -- "$cur"))
+            ;;
+    esac
+}}
+complete -F _{name} {name}
+"#
+            )
+            .unwrap();
+        }
+        Shell::Zsh => {
+            // brackets and colons separate the parts of the specs of `_arguments`
+            let escape = |e: &str| {
+                e.replace('\'', "'\\''")
+                    .replace('[', "\\[")
+                    .replace(']', "\\]")
+                    .replace(':', "\\:")
+            };
+            writeln!(out, "#compdef {name}").unwrap();
+            writeln!(out).unwrap();
+            writeln!(out, "local -a commands").unwrap();
+            writeln!(out, "commands=(").unwrap();
+            for command in help.named_commands() {
+                let usage = command.usage.replace('\'', "'\\''");
+                writeln!(out, "    '{}:{usage}'", command.name).unwrap();
+            }
+            writeln!(out, ")").unwrap();
+            writeln!(out, "_arguments \\").unwrap();
+            for option in options {
+                let description = escape(&option.description);
+                match &option.value {
+                    Some(value) => writeln!(
+                        out,
+                        "    '{}=[{description}]:{}:_files' \\",
+                        option.name,
+                        escape(value)
+                    ),
+                    None => writeln!(out, "    '{}[{description}]' \\", option.name),
+                }
+                .unwrap();
+            }
+            writeln!(out, "    '1: :{{_describe command commands; _files}}' \\").unwrap();
+            writeln!(out, "    '*:path:_files'").unwrap();
+        }
+        Shell::Fish => {
+            let escape = |e: &str| e.replace('\\', "\\\\").replace('\'', "\\'");
+            for command in help.named_commands() {
+                writeln!(
+                    out,
+                    "complete -c {name} -n __fish_use_subcommand -a {} -d '{}'",
+                    command.name,
+                    escape(&command.usage)
+                )
+                .unwrap();
+            }
+            for option in options {
+                let requires_value = match option.value {
+                    Some(_) => " -r",
+                    None => "",
+                };
+                writeln!(
+                    out,
+                    "complete -c {name} -l {}{requires_value} -d '{}'",
+                    option.name.trim_start_matches("--"),
+                    escape(&option.description)
+                )
+                .unwrap();
+            }
+        }
+        Shell::PowerShell => {
+            let escape = |e: &str| e.replace('\'', "''");
+            writeln!(
+                out,
+                "Register-ArgumentCompleter -Native -CommandName {name} -ScriptBlock {{"
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "    param($wordToComplete, $commandAst, $cursorPosition)"
+            )
+            .unwrap();
+            writeln!(out, "    $completions = @(").unwrap();
+            let results: Vec<(String, &str, &str)> = help
+                .named_commands()
+                .map(|e| (e.name.clone(), "ParameterValue", e.usage.as_str()))
+                .chain(options.iter().map(|e| {
+                    let text = match e.value {
+                        Some(_) => format!("{}=", e.name),
+                        None => e.name.clone(),
+                    };
+                    (text, "ParameterName", e.description.as_str())
+                }))
+                .collect();
+            for (i, (text, kind, tooltip)) in results.iter().enumerate() {
+                let separator = if i + 1 < results.len() { "," } else { "" };
+                writeln!(
+                    out,
+                    "        [System.Management.Automation.CompletionResult]::new('{text}', '{text}', '{kind}', '{}'){separator}",
+                    escape(tooltip)
+                )
+                .unwrap();
+            }
+            writeln!(out, "    )").unwrap();
+            writeln!(
+                out,
+                "    $completions | Where-Object {{ $_.CompletionText -like \"$wordToComplete*\" }}"
+            )
+            .unwrap();
+            writeln!(out, "}}").unwrap();
+        }
+    }
+    out
+}
@@ -0,0 +1,498 @@
+//! Block compression encoders and a DDS writer and reader for desktop engines, which can upload BCn
+//! textures to the gpu as they are. The encoders favor speed over quality: BC1 and BC3 fit the
+//! endpoints along the principal axis of each block, BC7 only uses mode 6 (one subset, rgba
+//! endpoints, 16 levels). The reader only understands what the writer produces.
+
+use image::RgbaImage;
+
+use crate::pack::TextureFormat;
+
+/// Block compressed formats, each encodes blocks of 4x4 pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockCompression {
+    /// 8 bytes per block, rgb with 1 bit alpha, for opaque textures
+    Bc1,
+    /// 16 bytes per block, BC1 colors with smooth alpha
+    Bc3,
+    /// 16 bytes per block, the best quality for rgba
+    Bc7,
+}
+
+impl BlockCompression {
+    fn block_bytes(self) -> usize {
+        match self {
+            BlockCompression::Bc1 => 8,
+            BlockCompression::Bc3 | BlockCompression::Bc7 => 16,
+        }
+    }
+
+    /// the srgb variants directly follow the unorm formats
+    fn dxgi_format(self, srgb: bool) -> u32 {
+        let unorm = match self {
+            BlockCompression::Bc1 => 71, // DXGI_FORMAT_BC1_UNORM
+            BlockCompression::Bc3 => 77, // DXGI_FORMAT_BC3_UNORM
+            BlockCompression::Bc7 => 98, // DXGI_FORMAT_BC7_UNORM
+        };
+        unorm + srgb as u32
+    }
+
+    fn from_dxgi_format(format: u32) -> Option<BlockCompression> {
+        [
+            BlockCompression::Bc1,
+            BlockCompression::Bc3,
+            BlockCompression::Bc7,
+        ]
+        .into_iter()
+        .find(|e| e.dxgi_format(false) == format || e.dxgi_format(true) == format)
+    }
+}
+
+/// Block compression of a texture format, `None` if it is not written as DDS.
+pub fn block_compression(format: TextureFormat) -> Option<BlockCompression> {
+    match format {
+        TextureFormat::Bc1 => Some(BlockCompression::Bc1),
+        TextureFormat::Bc3 => Some(BlockCompression::Bc3),
+        TextureFormat::Bc7 => Some(BlockCompression::Bc7),
+        _ => None,
+    }
+}
+
+const MAGIC: &[u8; 4] = b"DDS ";
+const HEADER_LENGTH: usize = 4 + 124 + 20;
+
+/// Pads an image with transparent pixels to a multiple of the block size, which d3d requires for
+/// the top level of block compressed textures.
+pub fn pad_to_blocks(rgba: &RgbaImage) -> RgbaImage {
+    let (w, h) = rgba.dimensions();
+    let mut padded = RgbaImage::new(w.next_multiple_of(4), h.next_multiple_of(4));
+    image::imageops::replace(&mut padded, rgba, 0, 0);
+    padded
+}
+
+/// Encodes `levels` (the texture followed by its mip levels, each half the size of the previous one)
+/// as a DDS file with a DX10 header.
+pub fn encode_dds(levels: &[&RgbaImage], compression: BlockCompression, srgb: bool) -> Vec<u8> {
+    let (w, h) = levels[0].dimensions();
+    let data: Vec<Vec<u8>> = levels
+        .iter()
+        .map(|level| compress(level, compression))
+        .collect();
+
+    let mut out: Vec<u8> =
+        Vec::with_capacity(HEADER_LENGTH + data.iter().map(Vec::len).sum::<usize>());
+    out.extend(MAGIC);
+    // caps, height, width, pixel format, linear size and mip map count
+    let flags: u32 = 0x1 | 0x2 | 0x4 | 0x1000 | 0x80000 | 0x20000;
+    let mut caps: u32 = 0x1000; // texture
+    if levels.len() > 1 {
+        caps |= 0x8 | 0x400000; // complex, mip map
+    }
+    for field in [
+        124,
+        flags,
+        h,
+        w,
+        data[0].len() as u32,
+        0,
+        levels.len() as u32,
+    ] {
+        out.extend(field.to_le_bytes());
+    }
+    out.extend([0; 11 * 4]);
+    // pixel format: size, fourcc flag, "DX10", bit count and masks
+    out.extend(32u32.to_le_bytes());
+    out.extend(0x4u32.to_le_bytes());
+    out.extend(b"DX10");
+    out.extend([0; 5 * 4]);
+    for field in [caps, 0, 0, 0, 0] {
+        out.extend(field.to_le_bytes());
+    }
+    // DX10 header: format, 2d texture, no flags, one element, straight alpha
+    for field in [compression.dxgi_format(srgb), 3, 0, 1, 1] {
+        out.extend(field.to_le_bytes());
+    }
+    for level in data {
+        out.extend(level);
+    }
+    out
+}
+
+/// Decodes a DDS file written by [`encode_dds`] into the texture and its mip levels. Returns `None`
+/// for files in any other format.
+pub fn decode_dds(bytes: &[u8]) -> Option<Vec<RgbaImage>> {
+    let u32_at = |i: usize| Some(u32::from_le_bytes(bytes.get(i..i + 4)?.try_into().unwrap()));
+    if bytes.get(0..4)? != MAGIC || bytes.get(84..88)? != b"DX10" {
+        return None;
+    }
+    let (h, w) = (u32_at(12)?, u32_at(16)?);
+    let level_count = u32_at(28)?.max(1);
+    let compression = BlockCompression::from_dxgi_format(u32_at(128)?)?;
+    let mut offset = HEADER_LENGTH;
+    let mut levels: Vec<RgbaImage> = vec![];
+    for level in 0..level_count {
+        let (level_w, level_h) = ((w >> level).max(1), (h >> level).max(1));
+        let blocks = (level_w.div_ceil(4) * level_h.div_ceil(4)) as usize;
+        let length = blocks * compression.block_bytes();
+        let data = bytes.get(offset..offset + length)?;
+        levels.push(decompress(data, level_w, level_h, compression)?);
+        offset += length;
+    }
+    Some(levels)
+}
+
+/// Width and height from the header of a DDS file.
+pub fn dds_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let u32_at = |i: usize| Some(u32::from_le_bytes(bytes.get(i..i + 4)?.try_into().unwrap()));
+    (bytes.get(0..4)? == MAGIC).then_some(())?;
+    Some((u32_at(16)?, u32_at(12)?))
+}
+
+type Block = [[u8; 4]; 16];
+
+fn compress(rgba: &RgbaImage, compression: BlockCompression) -> Vec<u8> {
+    let (w, h) = rgba.dimensions();
+    let mut out: Vec<u8> = vec![];
+    for block_y in 0..h.div_ceil(4) {
+        for block_x in 0..w.div_ceil(4) {
+            // pixels outside of the image repeat the edge, so they do not disturb the endpoints
+            let mut block: Block = [[0; 4]; 16];
+            for (i, pixel) in block.iter_mut().enumerate() {
+                let x = (block_x * 4 + i as u32 % 4).min(w - 1);
+                let y = (block_y * 4 + i as u32 / 4).min(h - 1);
+                *pixel = rgba.get_pixel(x, y).0;
+            }
+            match compression {
+                BlockCompression::Bc1 => out.extend(encode_bc1_block(&block, true)),
+                BlockCompression::Bc3 => {
+                    out.extend(encode_alpha_block(&block));
+                    out.extend(encode_bc1_block(&block, false));
+                }
+                BlockCompression::Bc7 => out.extend(encode_bc7_block(&block)),
+            }
+        }
+    }
+    out
+}
+
+fn decompress(data: &[u8], w: u32, h: u32, compression: BlockCompression) -> Option<RgbaImage> {
+    let mut rgba = RgbaImage::new(w, h);
+    let blocks_per_row = w.div_ceil(4);
+    for (i, bytes) in data.chunks_exact(compression.block_bytes()).enumerate() {
+        let block = match compression {
+            BlockCompression::Bc1 => decode_bc1_block(bytes, true),
+            BlockCompression::Bc3 => {
+                let mut block = decode_bc1_block(&bytes[8..], false);
+                for (pixel, alpha) in block.iter_mut().zip(decode_alpha_block(&bytes[..8])) {
+                    pixel[3] = alpha;
+                }
+                block
+            }
+            BlockCompression::Bc7 => decode_bc7_block(bytes)?,
+        };
+        let (block_x, block_y) = (i as u32 % blocks_per_row, i as u32 / blocks_per_row);
+        for (j, pixel) in block.iter().enumerate() {
+            let (x, y) = (block_x * 4 + j as u32 % 4, block_y * 4 + j as u32 / 4);
+            if x < w && y < h {
+                rgba.put_pixel(x, y, image::Rgba(*pixel));
+            }
+        }
+    }
+    Some(rgba)
+}
+
+/// The two ends of the line through `points` along their largest variance, found with a few
+/// iterations of the power method on the covariance matrix.
+pub(crate) fn principal_endpoints<const N: usize>(points: &[[f32; N]]) -> ([f32; N], [f32; N]) {
+    let mut mean = [0.0; N];
+    for point in points {
+        for c in 0..N {
+            mean[c] += point[c] / points.len() as f32;
+        }
+    }
+    let mut covariance = [[0.0; N]; N];
+    for point in points {
+        for a in 0..N {
+            for b in 0..N {
+                covariance[a][b] += (point[a] - mean[a]) * (point[b] - mean[b]);
+            }
+        }
+    }
+    let mut axis = [1.0; N];
+    for _ in 0..8 {
+        let mut next = [0.0; N];
+        for a in 0..N {
+            for b in 0..N {
+                next[a] += covariance[a][b] * axis[b];
+            }
+        }
+        let length = next.iter().map(|e| e * e).sum::<f32>().sqrt();
+        if length < 1e-6 {
+            // all points are the same
+            return (mean, mean);
+        }
+        axis = next.map(|e| e / length);
+    }
+    let project = |point: &[f32; N]| (0..N).map(|c| (point[c] - mean[c]) * axis[c]).sum::<f32>();
+    let (min, max) = points
+        .iter()
+        .map(project)
+        .fold((f32::MAX, f32::MIN), |(min, max), t| {
+            (min.min(t), max.max(t))
+        });
+    let along = |t: f32| std::array::from_fn(|c| (mean[c] + axis[c] * t).clamp(0.0, 255.0));
+    (along(min), along(max))
+}
+
+fn distance<const N: usize>(a: &[f32; N], b: &[f32; N]) -> f32 {
+    (0..N).map(|c| (a[c] - b[c]) * (a[c] - b[c])).sum()
+}
+
+fn nearest<const N: usize>(palette: &[[f32; N]], point: &[f32; N]) -> usize {
+    (0..palette.len())
+        .min_by(|a, b| distance(&palette[*a], point).total_cmp(&distance(&palette[*b], point)))
+        .unwrap()
+}
+
+fn to_565(color: [f32; 3]) -> u16 {
+    let r = (color[0] * 31.0 / 255.0).round() as u16;
+    let g = (color[1] * 63.0 / 255.0).round() as u16;
+    let b = (color[2] * 31.0 / 255.0).round() as u16;
+    (r << 11) | (g << 5) | b
+}
+
+fn from_565(color: u16) -> [f32; 3] {
+    let (r, g, b) = ((color >> 11) & 31, (color >> 5) & 63, color & 31);
+    [
+        ((r << 3) | (r >> 2)) as f32,
+        ((g << 2) | (g >> 4)) as f32,
+        ((b << 3) | (b >> 2)) as f32,
+    ]
+}
+
+/// Palette of a BC1 color block, 4 colors if `c0 > c1` (or always, for BC3), otherwise 3 colors
+/// and transparent black.
+fn bc1_palette(c0: u16, c1: u16, four_colors: bool) -> Vec<[f32; 3]> {
+    let (a, b) = (from_565(c0), from_565(c1));
+    let mix = |wa: f32, wb: f32| std::array::from_fn(|c| (a[c] * wa + b[c] * wb) / (wa + wb));
+    match four_colors || c0 > c1 {
+        true => vec![a, b, mix(2.0, 1.0), mix(1.0, 2.0)],
+        false => vec![a, b, mix(1.0, 1.0)],
+    }
+}
+
+/// With `allow_transparent` pixels with less than half alpha become transparent black (BC1),
+/// otherwise alpha is ignored (the color part of BC3).
+fn encode_bc1_block(block: &Block, allow_transparent: bool) -> [u8; 8] {
+    let transparent = |pixel: &[u8; 4]| allow_transparent && pixel[3] < 128;
+    let colors: Vec<[f32; 3]> = block
+        .iter()
+        .filter(|pixel| !transparent(pixel))
+        .map(|pixel| [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32])
+        .collect();
+    if colors.is_empty() {
+        // 3 color mode, everything transparent
+        return [0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF];
+    }
+    let (lo, hi) = principal_endpoints(&colors);
+    let (mut c0, mut c1) = (to_565(hi), to_565(lo));
+    let has_transparent = block.iter().any(transparent);
+    // the order of the endpoints selects the mode
+    if has_transparent != (c0 <= c1) {
+        std::mem::swap(&mut c0, &mut c1);
+    }
+    let palette = bc1_palette(c0, c1, !allow_transparent);
+    let mut indices: u32 = 0;
+    for (i, pixel) in block.iter().enumerate() {
+        let index = match transparent(pixel) {
+            true => 3,
+            false if c0 == c1 => 0,
+            false => nearest(
+                &palette,
+                &[pixel[0] as f32, pixel[1] as f32, pixel[2] as f32],
+            ),
+        };
+        indices |= (index as u32) << (i * 2);
+    }
+    let mut out = [0; 8];
+    out[0..2].copy_from_slice(&c0.to_le_bytes());
+    out[2..4].copy_from_slice(&c1.to_le_bytes());
+    out[4..8].copy_from_slice(&indices.to_le_bytes());
+    out
+}
+
+fn decode_bc1_block(bytes: &[u8], allow_transparent: bool) -> Block {
+    let c0 = u16::from_le_bytes([bytes[0], bytes[1]]);
+    let c1 = u16::from_le_bytes([bytes[2], bytes[3]]);
+    let indices = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    let palette = bc1_palette(c0, c1, !allow_transparent);
+    std::array::from_fn(|i| match palette.get(((indices >> (i * 2)) & 3) as usize) {
+        Some(color) => [color[0] as u8, color[1] as u8, color[2] as u8, 255],
+        None => [0, 0, 0, 0],
+    })
+}
+
+/// Palette of a BC3 alpha block, 8 values if `a0 > a1`, otherwise 6 values, 0 and 255.
+fn alpha_palette(a0: u8, a1: u8) -> [f32; 8] {
+    let (a, b) = (a0 as f32, a1 as f32);
+    match a0 > a1 {
+        true => std::array::from_fn(|i| match i {
+            0 => a,
+            1 => b,
+            i => (a * (8 - i) as f32 + b * (i - 1) as f32) / 7.0,
+        }),
+        false => std::array::from_fn(|i| match i {
+            0 => a,
+            1 => b,
+            6 => 0.0,
+            7 => 255.0,
+            i => (a * (6 - i) as f32 + b * (i - 1) as f32) / 5.0,
+        }),
+    }
+}
+
+fn encode_alpha_block(block: &Block) -> [u8; 8] {
+    let a0 = block.iter().map(|pixel| pixel[3]).max().unwrap();
+    let a1 = block.iter().map(|pixel| pixel[3]).min().unwrap();
+    let palette = alpha_palette(a0, a1).map(|e| [e]);
+    let mut indices: u64 = 0;
+    if a0 != a1 {
+        for (i, pixel) in block.iter().enumerate() {
+            indices |= (nearest(&palette, &[pixel[3] as f32]) as u64) << (i * 3);
+        }
+    }
+    let mut out = [0; 8];
+    out[0] = a0;
+    out[1] = a1;
+    out[2..8].copy_from_slice(&indices.to_le_bytes()[0..6]);
+    out
+}
+
+fn decode_alpha_block(bytes: &[u8]) -> [u8; 16] {
+    let palette = alpha_palette(bytes[0], bytes[1]);
+    let mut index_bytes = [0; 8];
+    index_bytes[0..6].copy_from_slice(&bytes[2..8]);
+    let indices = u64::from_le_bytes(index_bytes);
+    std::array::from_fn(|i| palette[((indices >> (i * 3)) & 7) as usize].round() as u8)
+}
+
+const BC7_WEIGHTS: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn bc7_palette(e0: [u8; 4], e1: [u8; 4]) -> [[f32; 4]; 16] {
+    std::array::from_fn(|i| {
+        let w = BC7_WEIGHTS[i];
+        std::array::from_fn(|c| (((64 - w) * e0[c] as u32 + w * e1[c] as u32 + 32) >> 6) as f32)
+    })
+}
+
+/// Mode 6: 7 bit rgba endpoints with one extra low bit (p-bit) per endpoint, 4 bit indices.
+fn encode_bc7_block(block: &Block) -> [u8; 16] {
+    let pixels: Vec<[f32; 4]> = block.iter().map(|p| p.map(|e| e as f32)).collect();
+    let (lo, hi) = principal_endpoints(&pixels);
+    // the 7 bit values and p-bit closest to an endpoint
+    let quantize = |endpoint: [f32; 4]| {
+        (0..2u8)
+            .map(|p| {
+                let q = endpoint.map(|v| ((v - p as f32) / 2.0).round().clamp(0.0, 127.0) as u8);
+                let error: f32 = (0..4)
+                    .map(|c| (((q[c] << 1) | p) as f32 - endpoint[c]).powi(2))
+                    .sum();
+                (q, p, error)
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(q, p, _)| (q, p))
+            .unwrap()
+    };
+    let (mut q0, mut p0) = quantize(lo);
+    let (mut q1, mut p1) = quantize(hi);
+    let expand = |q: [u8; 4], p: u8| q.map(|e| (e << 1) | p);
+    let palette = bc7_palette(expand(q0, p0), expand(q1, p1));
+    let mut indices: Vec<u8> = pixels
+        .iter()
+        .map(|pixel| nearest(&palette, pixel) as u8)
+        .collect();
+    // the highest bit of the first index is implicitly 0
+    if indices[0] >= 8 {
+        std::mem::swap(&mut q0, &mut q1);
+        std::mem::swap(&mut p0, &mut p1);
+        for index in indices.iter_mut() {
+            *index = 15 - *index;
+        }
+    }
+
+    let mut bits: u128 = 1 << 6;
+    let mut position = 7;
+    let mut push = |value: u128, length: u32| {
+        bits |= value << position;
+        position += length;
+    };
+    for c in 0..4 {
+        push(q0[c] as u128, 7);
+        push(q1[c] as u128, 7);
+    }
+    push(p0 as u128, 1);
+    push(p1 as u128, 1);
+    for (i, index) in indices.into_iter().enumerate() {
+        push(index as u128, if i == 0 { 3 } else { 4 });
+    }
+    bits.to_le_bytes()
+}
+
+fn decode_bc7_block(bytes: &[u8]) -> Option<Block> {
+    let bits = u128::from_le_bytes(bytes.try_into().ok()?);
+    if bits & 0x7F != 1 << 6 {
+        // only mode 6 is written by the packer
+        return None;
+    }
+    let mut position = 7;
+    let mut take = |length: u32| {
+        let value = (bits >> position) & ((1 << length) - 1);
+        position += length;
+        value as u8
+    };
+    let (mut q0, mut q1) = ([0; 4], [0; 4]);
+    for c in 0..4 {
+        q0[c] = take(7);
+        q1[c] = take(7);
+    }
+    let (p0, p1) = (take(1), take(1));
+    let palette = bc7_palette(q0.map(|e| (e << 1) | p0), q1.map(|e| (e << 1) | p1));
+    Some(std::array::from_fn(|i| {
+        let index = take(if i == 0 { 3 } else { 4 });
+        palette[index as usize].map(|e| e as u8)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{gradient, psnr};
+
+    #[test]
+    fn block_compressed_textures_round_trip() {
+        for (compression, opaque, min_psnr) in [
+            (BlockCompression::Bc1, true, 32.0),
+            (BlockCompression::Bc3, false, 32.0),
+            (BlockCompression::Bc7, false, 33.0),
+        ] {
+            let texture = gradient(64, 64, opaque);
+            let mip = gradient(32, 32, opaque);
+            let bytes = encode_dds(&[&texture, &mip], compression, true);
+            assert_eq!(dds_dimensions(&bytes), Some((64, 64)));
+
+            let levels = decode_dds(&bytes).unwrap();
+            assert_eq!(levels.len(), 2);
+            for (original, decoded) in [&texture, &mip].into_iter().zip(&levels) {
+                let psnr = psnr(original, decoded);
+                assert!(psnr > min_psnr, "{compression:?} has a psnr of {psnr}");
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_dds_files_are_rejected() {
+        let bytes = encode_dds(&[&gradient(8, 8, false)], BlockCompression::Bc7, false);
+        for len in 0..bytes.len() {
+            assert!(decode_dds(&bytes[..len]).is_none(), "decoded {len} bytes");
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+/// Everything that can go wrong while gathering, packing or loading assets. Errors caused by a file
+/// carry its path, so they can be reported in a way that artists can act on.
+#[derive(Debug)]
+pub enum PackError {
+    /// reading or writing a file failed
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// decoding a source image or encoding a texture failed
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    /// a json file of the pack (manifest, sdf font, cache) could not be read or written
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// the `assetpacker.json` config file is invalid
+    Config { path: PathBuf, message: String },
+    /// a font file could not be parsed or converted to an sdf font
+    Font { path: PathBuf, message: String },
+    /// an svg file could not be parsed
+    Svg { path: PathBuf, message: String },
+    /// an aseprite file could not be parsed
+    Aseprite { path: PathBuf, message: String },
+    /// a psd file could not be parsed or lacks a layer group of its sidecar
+    Psd { path: PathBuf, message: String },
+    /// two assets of the same kind map to the same identifier
+    DuplicateIdentifier {
+        kind: &'static str,
+        ident: String,
+        paths: (PathBuf, PathBuf),
+    },
+    /// fonts were gathered, but none of them is flagged as `default`
+    MissingDefaultFont,
+    /// an image does not fit into an atlas, even when it is the only image on it
+    ImageTooLarge {
+        ident: String,
+        size: (u32, u32),
+        max: (u32, u32),
+    },
+    /// the frames of an animation are misnamed or do not fit into one atlas together
+    Animation { name: String, message: String },
+    /// the guides or sidecar borders of a nine slice image are missing or do not fit the image
+    NineSlice { path: PathBuf, message: String },
+    /// the assets or produced textures do not meet the configured engine capabilities
+    Incompatible { message: String },
+    /// a font of a locale lacks characters of its charset, or the charset is invalid
+    LocaleCoverage { locale: String, message: String },
+    /// a feature of the asset tree that the packer does not support yet
+    Unsupported { message: String },
+    /// a file of a pack does not match the checksum recorded in its manifest
+    ChecksumMismatch { path: PathBuf },
+    /// a manifest references textures or fonts that are not part of the pack
+    InvalidManifest { message: String },
+    /// several independent errors, e.g. all files of an asset tree that failed to load
+    Multiple(Vec<PackError>),
+}
+
+pub type PackResult<T> = Result<T, PackError>;
+
+impl PackError {
+    /// For `map_err`, e.g. `std::fs::read(&path).map_err(PackError::io(&path))?`.
+    pub fn io(path: impl AsRef<Path>) -> impl FnOnce(std::io::Error) -> PackError {
+        let path = path.as_ref().to_path_buf();
+        move |source| PackError::Io { path, source }
+    }
+
+    pub fn image(path: impl AsRef<Path>) -> impl FnOnce(image::ImageError) -> PackError {
+        let path = path.as_ref().to_path_buf();
+        move |source| PackError::Image { path, source }
+    }
+
+    pub fn json(path: impl AsRef<Path>) -> impl FnOnce(serde_json::Error) -> PackError {
+        let path = path.as_ref().to_path_buf();
+        move |source| PackError::Json { path, source }
+    }
+
+    /// Returns `Ok` for no errors, the error itself for one error and `PackError::Multiple` otherwise.
+    pub fn from_errors(mut errors: Vec<PackError>) -> PackResult<()> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.pop().unwrap()),
+            _ => Err(PackError::Multiple(errors)),
+        }
+    }
+
+    /// The individual errors, with nested `Multiple` errors flattened.
+    pub fn errors(&self) -> Vec<&PackError> {
+        match self {
+            PackError::Multiple(errors) => errors.iter().flat_map(|e| e.errors()).collect(),
+            e => vec![e],
+        }
+    }
+}
+
+impl Display for PackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PackError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            PackError::Image { path, source } => {
+                write!(f, "{}: invalid image: {source}", path.display())
+            }
+            PackError::Json { path, source } => {
+                write!(f, "{}: invalid json: {source}", path.display())
+            }
+            PackError::Config { path, message } => {
+                write!(f, "{}: invalid config: {message}", path.display())
+            }
+            PackError::Font { path, message } => {
+                write!(f, "{}: invalid font: {message}", path.display())
+            }
+            PackError::Aseprite { path, message } => {
+                write!(f, "{}: invalid aseprite file: {message}", path.display())
+            }
+            PackError::Psd { path, message } => {
+                write!(f, "{}: invalid psd file: {message}", path.display())
+            }
+            PackError::Svg { path, message } => {
+                write!(f, "{}: invalid svg: {message}", path.display())
+            }
+            PackError::DuplicateIdentifier {
+                kind,
+                ident,
+                paths: (a, b),
+            } => write!(
+                f,
+                "duplicate {kind} identifier {ident} for {} and {}",
+                a.display(),
+                b.display()
+            ),
+            PackError::MissingDefaultFont => write!(
+                f,
+                "no default font, flag one of the fonts like this: fonts/name.default.ttf"
+            ),
+            PackError::ImageTooLarge {
+                ident,
+                size: (w, h),
+                max: (max_w, max_h),
+            } => write!(
+                f,
+                "image {ident} ({w}x{h} including padding) does not fit into an atlas of {max_w}x{max_h}, increase the atlas size"
+            ),
+            PackError::Animation { name, message } => write!(f, "animation {name}: {message}"),
+            PackError::NineSlice { path, message } => {
+                write!(f, "{}: invalid nine slice: {message}", path.display())
+            }
+            PackError::Incompatible { message } => {
+                write!(f, "incompatible with engine capabilities: {message}")
+            }
+            PackError::LocaleCoverage { locale, message } => write!(f, "locale {locale}: {message}"),
+            PackError::Unsupported { message } => write!(f, "unsupported: {message}"),
+            PackError::ChecksumMismatch { path } => write!(
+                f,
+                "{}: does not match the checksum of the manifest, the file is corrupted",
+                path.display()
+            ),
+            PackError::InvalidManifest { message } => write!(f, "invalid manifest: {message}"),
+            PackError::Multiple(_) => {
+                let errors = self.errors();
+                write!(f, "{} errors:", errors.len())?;
+                for e in errors {
+                    write!(f, "\n    {e}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PackError::Io { source, .. } => Some(source),
+            PackError::Image { source, .. } => Some(source),
+            PackError::Json { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use image::{
+    error::{DecodingError, ImageFormatHint},
+    ImageError, ImageFormat, ImageResult, RgbaImage,
+};
+use png::{ColorType, Decoder, Transformations};
+use rayon::prelude::*;
+
+/// rows converted to rgba per task
+const ROWS_PER_CHUNK: usize = 64;
+
+/// Decodes a png straight into an rgba image, faster than `image::load_from_memory` for large
+/// sources like 4k backgrounds. Checksums are not verified, rgba pngs are used without copying and
+/// the pixels of other pngs are converted to rgba in chunks of rows on all cores.
+pub fn decode_png(bytes: &[u8]) -> ImageResult<RgbaImage> {
+    let png_error = |e: png::DecodingError| {
+        ImageError::Decoding(DecodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Png),
+            e,
+        ))
+    };
+    let mut decoder = Decoder::new(bytes);
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    decoder.ignore_checksums(true);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+    let mut pixels: Vec<u8> = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).map_err(png_error)?;
+    pixels.truncate(info.buffer_size());
+    let (w, h) = (info.width, info.height);
+    if info.color_type == ColorType::Rgba {
+        return Ok(RgbaImage::from_raw(w, h, pixels).unwrap());
+    }
+
+    let samples = info.color_type.samples();
+    let mut rgba: Vec<u8> = vec![0; w as usize * h as usize * 4];
+    rgba.par_chunks_mut(w as usize * 4 * ROWS_PER_CHUNK)
+        .zip(pixels.par_chunks(w as usize * samples * ROWS_PER_CHUNK))
+        .for_each(|(rgba, pixels)| {
+            for (rgba, pixel) in rgba.chunks_exact_mut(4).zip(pixels.chunks_exact(samples)) {
+                let converted = match pixel {
+                    [gray] => [*gray, *gray, *gray, u8::MAX],
+                    [gray, alpha] => [*gray, *gray, *gray, *alpha],
+                    [r, g, b] => [*r, *g, *b, u8::MAX],
+                    _ => unreachable!("palettes are expanded to rgb by the decoder"),
+                };
+                rgba.copy_from_slice(&converted);
+            }
+        });
+    Ok(RgbaImage::from_raw(w, h, rgba).unwrap())
+}
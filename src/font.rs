@@ -1,69 +1,387 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
+pub use assetpacker_manifest::{DistanceField, Glyph, LineMetrics, SdfFont, TabularMetrics};
+use fontdue::layout::{CoordinateSystem, Layout, TextStyle};
 use glam::{vec2, Vec2};
 use guillotiere::size2;
-use image::GenericImage;
+use image::{GenericImage, ImageBuffer, Pixel};
+use rayon::prelude::*;
 use sdfer::{Image2d, Unorm8};
 use serde::{Deserialize, Serialize};
+use ttf_parser::{gsub::SubstitutionSubtable, GlyphId, Tag};
 
-use crate::{gather::FontAsset, pack::next_pow2_number};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SdfFont {
-    font_size: usize,
-    line_metrics: LineMetrics,
-    name: String,
-    glyphs: HashMap<char, Glyph>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-
-pub struct LineMetrics {
-    /// The highest point that any glyph in the font extends to above the baseline. Typically
-    /// positive.
-    pub ascent: f32,
-    /// The lowest point that any glyph in the font extends to below the baseline. Typically
-    /// negative.
-    pub descent: f32,
-    /// The gap to leave between the descent of one line and the ascent of the next. This is of
-    /// course only a guideline given by the font's designers.
-    pub line_gap: f32,
-    /// A precalculated value for the height or width of the line depending on if the font is laid
-    /// out horizontally or vertically. It's calculated by: ascent - descent + line_gap.
-    pub new_line_size: f32,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Glyph {
-    pub xmin: f32,
-    pub ymin: f32,
-    pub width: f32,
-    pub height: f32,
-    pub advance: f32,
-    pub is_white_space: bool,
-    pub uv_min: Vec2,
-    pub uv_max: Vec2,
-}
-
-pub fn font_to_sdf_font(font_asset: &FontAsset) -> (SdfFont, image::GrayImage) {
+use crate::{
+    error::{PackError, PackResult},
+    gather::{FontAsset, IconAsset},
+    locale::parse_range,
+    msdf::glyph_msdf,
+    pack::{next_pow2_number, SizeRounding},
+};
+
+/// Settings for the generation of sdf fonts, changing them invalidates cached fonts.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct FontSettings {
+    /// record the advances of the `tnum` (tabular figures) variants of the digits, if the font has them.
+    pub tabular_figures: bool,
+    /// pixel sizes the line metrics are additionally recorded for, see [`SdfFont::sized_line_metrics`]
+    pub metric_sizes: Vec<u32>,
+    /// rare symbols like `©™→`, generated after the charset or [`ALPHABET`] and the characters of
+    /// the locales the font is used for, if they fit into the budget
+    pub extra_characters: String,
+    /// largest number of glyphs of a font, no limit if `None`. Glyphs beyond it are skipped, see
+    /// [`font_characters`] for their priority.
+    pub max_glyphs: Option<usize>,
+    /// largest width and height of the sdf atlas of a font, rounded up to a power of two, 1024 if
+    /// `None`. Glyphs that do not fit anymore are skipped.
+    pub max_atlas_size: Option<u32>,
+    /// font name -> characters it is generated with instead of the [`ALPHABET`], for games with
+    /// umlauts, accents or CJK text. The `chars=` flag of a font file overrides it.
+    pub charsets: BTreeMap<String, FontCharset>,
+    /// names of the fonts generated as multi-channel sdfs, like the `msdf` flag of a font file
+    pub msdf_fonts: Vec<String>,
+}
+
+/// The characters of a font, in the order they are generated, see [`FontSettings::charsets`].
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontCharset {
+    pub characters: String,
+    /// inclusive codepoint ranges like `U+0400-U+04FF`, single codepoints like `U+00A0`, or the
+    /// names of the [`NAMED_RANGES`] like `latin` or `cyrillic`
+    pub ranges: Vec<String>,
+    /// text file relative to the source dir whose characters are added, like a file with all the
+    /// strings of the game
+    pub file: Option<String>,
+}
+
+/// Named unicode ranges for the `ranges` of a [`FontCharset`] and the `chars=` flag of font files,
+/// like `chars=latin,cyrillic`. Names are case insensitive.
+pub const NAMED_RANGES: [(&str, &[(char, char)]); 16] = [
+    ("ascii", &[(' ', '~')]),
+    ("latin1", &[('\u{a0}', '\u{ff}')]),
+    ("latin", &[(' ', '~'), ('\u{a0}', '\u{ff}')]),
+    // latin extended a and b, and the additional letters of vietnamese
+    (
+        "latin-ext",
+        &[('\u{100}', '\u{24f}'), ('\u{1e00}', '\u{1eff}')],
+    ),
+    ("greek", &[('\u{370}', '\u{3ff}')]),
+    ("cyrillic", &[('\u{400}', '\u{52f}')]),
+    // general punctuation like `–…„“` and currency symbols like `€`
+    (
+        "punctuation",
+        &[('\u{2000}', '\u{206f}'), ('\u{20a0}', '\u{20cf}')],
+    ),
+    ("cjk-symbols", &[('\u{3000}', '\u{303f}')]),
+    ("hiragana", &[('\u{3040}', '\u{309f}')]),
+    ("katakana", &[('\u{30a0}', '\u{30ff}')]),
+    ("kana", &[('\u{3040}', '\u{30ff}')]),
+    ("cjk", &[('\u{4e00}', '\u{9fff}')]),
+    ("cjk-ext-a", &[('\u{3400}', '\u{4dbf}')]),
+    ("fullwidth", &[('\u{ff00}', '\u{ffef}')]),
+    ("hangul", &[('\u{ac00}', '\u{d7a3}')]),
+    (
+        "hangul-jamo",
+        &[('\u{1100}', '\u{11ff}'), ('\u{3130}', '\u{318f}')],
+    ),
+];
+
+/// The characters of a [`NAMED_RANGES`] name or a codepoint range like `U+0400-U+04FF`.
+pub fn charset_range(range: &str) -> Result<Vec<char>, String> {
+    if let Some((_, spans)) = NAMED_RANGES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(range.trim()))
+    {
+        return Ok(spans
+            .iter()
+            .flat_map(|(start, end)| *start..=*end)
+            .collect());
+    }
+    let (start, end) = parse_range(range).ok_or_else(|| {
+        let names: Vec<&str> = NAMED_RANGES.iter().map(|(name, _)| *name).collect();
+        format!(
+            "invalid range {range}, use one like U+0400-U+04FF or one of {}",
+            names.join(", ")
+        )
+    })?;
+    Ok((start..=end).collect())
+}
+
+impl FontCharset {
+    /// The characters in the order of the `characters`, `ranges` and `file`, without duplicates.
+    /// Whitespace except for the space and control characters are not rendered and left out.
+    pub fn chars(&self, src_dir: &str) -> Result<Vec<char>, String> {
+        let mut chars: Vec<char> = self.characters.chars().collect();
+        for range in self.ranges.iter() {
+            chars.extend(charset_range(range)?);
+        }
+        if let Some(file) = &self.file {
+            let path = format!("{src_dir}/{file}");
+            let text = std::fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+            chars.extend(text.chars());
+        }
+        let mut seen: HashSet<char> = HashSet::new();
+        chars.retain(|ch| {
+            (*ch == ' ' || !ch.is_whitespace()) && !ch.is_control() && seen.insert(*ch)
+        });
+        Ok(chars)
+    }
+}
+
+/// A string like a logo or level title that is rendered into a sprite at pack time, so it is drawn
+/// as a single quad instead of a run of glyphs, see [`crate::pack::PackSettings::texts`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StaticText {
+    /// lines are separated by `\n`
+    pub text: String,
+    /// name of a font in the fonts dir, the default font if `None`
+    pub font: Option<String>,
+    /// font size in pixels, multiplied by the `scale` of the settings
+    pub size: f32,
+    /// rgba, white by default so renderers can tint the sprite
+    pub color: [u8; 4],
+}
+
+impl Default for StaticText {
+    fn default() -> Self {
+        StaticText {
+            text: String::new(),
+            font: None,
+            size: 32.0,
+            color: [255; 4],
+        }
+    }
+}
+
+/// Parses the face of the font asset, the selected one of a collection.
+fn fontdue_font(font_asset: &FontAsset) -> PackResult<fontdue::Font> {
+    let settings = fontdue::FontSettings {
+        collection_index: font_asset.face_index,
+        ..Default::default()
+    };
+    fontdue::Font::from_bytes(&*font_asset.bytes, settings).map_err(|message| PackError::Font {
+        path: font_asset.entry.path.clone(),
+        message: message.to_string(),
+    })
+}
+
+/// Renders `text` into an image as wide as its longest line and as high as its lines.
+pub fn render_static_text(
+    font_asset: &FontAsset,
+    text: &StaticText,
+    scale: f32,
+) -> PackResult<image::RgbaImage> {
+    let font = fontdue_font(font_asset)?;
+    let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
+    layout.append(&[&font], &TextStyle::new(&text.text, text.size * scale, 0));
+    let glyphs: Vec<(i32, i32, fontdue::layout::GlyphPosition)> = layout
+        .glyphs()
+        .iter()
+        .filter(|e| e.width > 0 && e.height > 0)
+        .map(|e| (e.x.round() as i32, e.y.round() as i32, *e))
+        .collect();
+    // glyphs like a `j` at the start of a line can reach past the layout
+    let min_x = glyphs.iter().map(|e| e.0).min().unwrap_or(0).min(0);
+    let min_y = glyphs.iter().map(|e| e.1).min().unwrap_or(0).min(0);
+    let max_x = glyphs
+        .iter()
+        .map(|e| e.0 + e.2.width as i32)
+        .max()
+        .unwrap_or(1);
+    let max_y = glyphs
+        .iter()
+        .map(|e| e.1 + e.2.height as i32)
+        .max()
+        .unwrap_or(0)
+        .max(layout.height().ceil() as i32);
+    let (w, h) = ((max_x - min_x).max(1) as u32, (max_y - min_y).max(1) as u32);
+    let [r, g, b, a] = text.color;
+    let mut rgba = image::RgbaImage::from_pixel(w, h, image::Rgba([r, g, b, 0]));
+    for (x, y, glyph) in glyphs {
+        let (metrics, coverage) = font.rasterize_config(glyph.key);
+        for (i, c) in coverage.into_iter().enumerate() {
+            let px = (x - min_x) as u32 + (i % metrics.width) as u32;
+            let py = (y - min_y) as u32 + (i / metrics.width) as u32;
+            if px < w && py < h {
+                // overlapping glyphs keep the larger coverage
+                let alpha = (c as u32 * a as u32 / 255) as u8;
+                let pixel = rgba.get_pixel_mut(px, py);
+                pixel[3] = pixel[3].max(alpha);
+            }
+        }
+    }
+    Ok(rgba)
+}
+
+/// Characters every font gets, ascii letters, digits and punctuation.
+pub const ALPHABET: &str =
+    "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.,!:;/?|(){}[]+-_=* \n\t'\"><~`";
+
+/// The characters of a font in the order they are generated, which is their priority when the
+/// budget is exceeded: the charset of the font or the [`ALPHABET`] first, then the characters of
+/// the locales, then the `extra_characters` of the settings.
+pub fn font_characters(
+    settings: &FontSettings,
+    font_asset: &FontAsset,
+    locale_characters: impl IntoIterator<Item = char>,
+) -> Vec<char> {
+    let mut seen: HashSet<char> = HashSet::new();
+    let charset: Vec<char> = match &font_asset.charset {
+        Some(charset) => charset.clone(),
+        None => ALPHABET.chars().collect(),
+    };
+    charset
+        .into_iter()
+        .chain(locale_characters)
+        .chain(settings.extra_characters.chars())
+        .filter(|ch| seen.insert(*ch))
+        .collect()
+}
+
+/// Generates the sdf font of the `characters`, in their order until the budget of the settings is
+/// used up. The skipped characters are left out of the font.
+pub fn font_to_sdf_font(
+    font_asset: &FontAsset,
+    settings: &FontSettings,
+    rounding: SizeRounding,
+    characters: &[char],
+) -> PackResult<(SdfFont, image::DynamicImage)> {
     let font_size: usize = 64;
     let pad: usize = 16;
 
-    let font: fontdue::Font = fontdue::Font::from_bytes(&*font_asset.bytes, Default::default())
-        .expect("data must be valid ttf");
+    let font_error = |message: &str| PackError::Font {
+        path: font_asset.entry.path.clone(),
+        message: message.to_string(),
+    };
+    let font = fontdue_font(font_asset)?;
     let mut glyphs: HashMap<char, Glyph> = HashMap::new();
 
-    let atlas_size = next_pow2_number((font_size + 2 * pad) * 8); // this gives us space for at least 256 glyphs, which should be enough in most cases
-    let mut atlas_allocator =
-        guillotiere::AtlasAllocator::new(size2(atlas_size as i32, atlas_size as i32));
-    let mut atlas_image = image::GrayImage::new(atlas_size as u32, atlas_size as u32);
+    let max_atlas_size = match settings.max_atlas_size {
+        Some(size) => size as usize,
+        None => next_pow2_number((font_size + 2 * pad) * 8), // space for at least 256 glyphs
+    };
+    let characters = &characters[..characters
+        .len()
+        .min(settings.max_glyphs.unwrap_or(usize::MAX))];
+
+    let face = ttf_parser::Face::parse(&font_asset.bytes, font_asset.face_index)
+        .map_err(|e| font_error(&e.to_string()))?;
+    // rasterizing and generating the sdfs is the expensive part, the atlas allocation afterwards is
+    // sequential so the layout does not depend on thread scheduling
+    let image = match font_asset.distance_field {
+        DistanceField::Sdf => {
+            let rendered = characters
+                .par_iter()
+                .map(|&ch| {
+                    let (metrics, img) = font.rasterize(ch, font_size as f32);
+                    if ch.is_whitespace() {
+                        return (ch, metrics, None);
+                    }
+                    let coverage = image::GrayImage::from_raw(
+                        metrics.width as u32,
+                        metrics.height as u32,
+                        img,
+                    )
+                    .unwrap();
+                    let origin = vec2(metrics.bounds.xmin, metrics.bounds.ymin) - pad as f32;
+                    (
+                        ch,
+                        metrics,
+                        Some((origin, trim_sdf(glyph_sdf(coverage, pad)))),
+                    )
+                })
+                .collect();
+            let atlas = allocate_glyphs(rendered, max_atlas_size, &mut glyphs);
+            image::DynamicImage::ImageLuma8(atlas.finish(&mut glyphs, rounding))
+        }
+        DistanceField::Msdf => {
+            let scale = font_size as f32 / face.units_per_em() as f32;
+            let rendered = characters
+                .par_iter()
+                .map(|&ch| {
+                    let metrics = font.metrics(ch, font_size as f32);
+                    if ch.is_whitespace() {
+                        return (ch, metrics, None);
+                    }
+                    // fontdue draws the notdef glyph for characters the font does not have
+                    let id = face.glyph_index(ch).unwrap_or(GlyphId(0));
+                    let (msdf, origin) = glyph_msdf(&face, id, scale, pad);
+                    (ch, metrics, Some((origin, trim_sdf(msdf))))
+                })
+                .collect();
+            let atlas = allocate_glyphs(rendered, max_atlas_size, &mut glyphs);
+            image::DynamicImage::ImageRgb8(atlas.finish(&mut glyphs, rounding))
+        }
+    };
 
-    const ALPHABET: &str =
-    "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.,!:;/?|(){}[]!+-_=* \n\t'\"><~`";
-    for ch in ALPHABET.chars() {
-        let (metrics, img) = font.rasterize(ch, font_size as f32);
-        let glyph = if ch.is_whitespace() {
-            Glyph {
+    if settings.tabular_figures {
+        for ch in '0'..='9' {
+            let (Some(glyph), Some(id)) = (glyphs.get_mut(&ch), face.glyph_index(ch)) else {
+                continue;
+            };
+            if let Some(tabular_id) = tabular_substitute(&face, id) {
+                let metrics = font.metrics_indexed(tabular_id.0, font_size as f32);
+                // the tabular variant is drawn with the (trimmed) sdf of the default digit
+                let default_metrics = font.metrics(ch, font_size as f32);
+                glyph.tabular = Some(TabularMetrics {
+                    xmin: glyph.xmin + metrics.bounds.xmin - default_metrics.bounds.xmin,
+                    advance: metrics.advance_width,
+                });
+            }
+        }
+    }
+
+    let line_metrics_at = |size: f32| {
+        let lm = font
+            .horizontal_line_metrics(size)
+            .ok_or_else(|| font_error("no horizontal line metrics"))?;
+        Ok(LineMetrics {
+            ascent: lm.ascent,
+            descent: lm.descent,
+            line_gap: lm.line_gap,
+            new_line_size: lm.new_line_size,
+        })
+    };
+    let line_metrics = line_metrics_at(font_size as f32)?;
+    let sized_line_metrics = settings
+        .metric_sizes
+        .iter()
+        .map(|size| Ok((*size, line_metrics_at(*size as f32)?)))
+        .collect::<PackResult<BTreeMap<u32, LineMetrics>>>()?;
+    let sdf_font = SdfFont {
+        font_size,
+        line_metrics,
+        name: font_asset.entry.asset_path.ident().to_string(),
+        glyphs,
+        icons: HashMap::new(),
+        sized_line_metrics,
+        distance_field: font_asset.distance_field,
+    };
+    Ok((sdf_font, image))
+}
+
+/// A character with its metrics, and for visible characters the origin and sdf of its glyph.
+type RenderedGlyph<P> = (char, fontdue::Metrics, Option<(Vec2, TrimmedSdf<P>)>);
+
+/// Copies the rendered glyphs into an atlas in their order and records them in `glyphs`, until
+/// the first one that does not fit. The origins are the bottom left corners of the untrimmed sdfs
+/// relative to the glyph origins.
+fn allocate_glyphs<P: Pixel<Subpixel = u8> + 'static>(
+    rendered: Vec<RenderedGlyph<P>>,
+    max_atlas_size: usize,
+    glyphs: &mut HashMap<char, Glyph>,
+) -> SdfAtlas<P> {
+    let mut atlas = SdfAtlas::new(
+        rendered
+            .iter()
+            .filter_map(|(_, _, sdf)| sdf.as_ref().map(|(_, sdf)| sdf)),
+        max_atlas_size,
+    );
+    for (ch, metrics, sdf) in rendered {
+        let glyph = match sdf {
+            None => Glyph {
                 xmin: metrics.bounds.xmin,
                 ymin: metrics.bounds.ymin,
                 width: metrics.bounds.width,
@@ -72,71 +390,287 @@ pub fn font_to_sdf_font(font_asset: &FontAsset) -> (SdfFont, image::GrayImage) {
                 uv_min: Vec2::ZERO,
                 uv_max: Vec2::ZERO,
                 is_white_space: true,
+                tabular: None,
+            },
+            Some((origin, sdf)) => {
+                // the characters after the first one that does not fit are skipped, so the budget
+                // goes to the characters of the highest priority
+                let Some((uv_min, uv_max)) = atlas.insert(&sdf.image) else {
+                    break;
+                };
+                Glyph {
+                    xmin: origin.x + sdf.left as f32,
+                    ymin: origin.y + sdf.bottom as f32,
+                    width: sdf.image.width() as f32,
+                    height: sdf.image.height() as f32,
+                    advance: metrics.advance_width,
+                    uv_min,
+                    uv_max,
+                    is_white_space: false,
+                    tabular: None,
+                }
             }
-        } else {
-            let gray = image::GrayImage::from_raw(metrics.width as u32, metrics.height as u32, img)
-                .unwrap();
-            let mut gray_for_sdfer: Image2d<Unorm8> = From::from(gray.clone());
-
-            let (generated_sdf, _) = sdfer::esdt::glyph_to_sdf(
-                &mut gray_for_sdfer,
-                sdfer::esdt::Params {
-                    pad: pad as usize,
-                    radius: pad as f32,
-                    cutoff: 0.5,
-                    solidify: true,
-                    preprocess: true,
-                },
-                None,
-            );
-            let sdf = image::GrayImage::from(generated_sdf);
-            let (w, h) = sdf.dimensions();
-            let allocation = atlas_allocator
-                .allocate(size2(w as i32, h as i32))
-                .expect("allocation failed");
-            let uv_min = vec2(
-                allocation.rectangle.min.x as f32,
-                allocation.rectangle.min.y as f32,
-            ) / atlas_size as f32;
-            let uv_max = vec2(
-                allocation.rectangle.min.x as f32 + w as f32,
-                allocation.rectangle.min.y as f32 + h as f32,
-            ) / atlas_size as f32;
-
-            atlas_image
-                .copy_from(
-                    &sdf,
-                    allocation.rectangle.min.x as u32,
-                    allocation.rectangle.min.y as u32,
-                )
-                .expect("copy from sdf_glyph image to atlas_image failed");
+        };
+        glyphs.insert(ch, glyph);
+    }
+    atlas
+}
+
+/// Assembles single-color svg icons into a font, so they can be drawn like text. Each icon gets a
+/// codepoint in the unicode private use area, `SdfFont::icons` maps the icon names to them.
+pub fn icons_to_sdf_font(
+    name: &str,
+    icons: &HashMap<String, IconAsset>,
+    rounding: SizeRounding,
+) -> PackResult<(SdfFont, image::GrayImage)> {
+    let font_size: usize = 64;
+    let pad: usize = 16;
+
+    let cells_per_row = ((icons.len() * 2) as f32).sqrt().ceil() as usize; // icons can be wider than high, so leave some slack
+    let max_atlas_size = next_pow2_number((font_size + 2 * pad) * cells_per_row);
 
+    let rendered: Vec<(&str, char, (u32, u32), TrimmedSdf)> = icon_codepoints(icons)
+        .into_par_iter()
+        .map(|(icon_name, ch)| {
+            let svg = &icons[icon_name].svg;
+            let coverage = svg.rasterize(font_size as f32 / svg.size.y);
+            (
+                icon_name,
+                ch,
+                coverage.dimensions(),
+                trim_sdf(glyph_sdf(coverage, pad)),
+            )
+        })
+        .collect();
+
+    let mut atlas = SdfAtlas::new(rendered.iter().map(|(_, _, _, sdf)| sdf), max_atlas_size);
+    let mut glyphs: HashMap<char, Glyph> = HashMap::new();
+    let mut codepoints: HashMap<String, char> = HashMap::new();
+    for (icon_name, ch, (w, _), sdf) in rendered {
+        let icon = &icons[icon_name];
+        let (uv_min, uv_max) = atlas.insert(&sdf.image).ok_or_else(|| PackError::Svg {
+            path: icon.entry.path.clone(),
+            message: String::from("the icon does not fit into the icon font atlas"),
+        })?;
+        glyphs.insert(
+            ch,
             Glyph {
-                xmin: metrics.bounds.xmin - pad as f32,
-                ymin: metrics.bounds.ymin - pad as f32,
-                width: metrics.bounds.width + (2 * pad) as f32,
-                height: metrics.bounds.height + (2 * pad) as f32,
-                advance: metrics.advance_width,
+                xmin: sdf.left as f32 - pad as f32,
+                ymin: sdf.bottom as f32 - pad as f32,
+                width: sdf.image.width() as f32,
+                height: sdf.image.height() as f32,
+                advance: w as f32,
+                is_white_space: false,
                 uv_min,
                 uv_max,
-                is_white_space: false,
-            }
-        };
-        glyphs.insert(ch, glyph);
+                tabular: None,
+            },
+        );
+        codepoints.insert(icon_name.to_string(), ch);
     }
 
-    let lm = font.horizontal_line_metrics(font_size as f32).unwrap();
-    let line_metrics = LineMetrics {
-        ascent: lm.ascent,
-        descent: lm.descent,
-        line_gap: lm.line_gap,
-        new_line_size: lm.new_line_size,
-    };
+    let image = atlas.finish(&mut glyphs, rounding);
     let sdf_font = SdfFont {
         font_size,
-        line_metrics,
-        name: font_asset.entry.asset_path.ident().to_string(),
+        line_metrics: LineMetrics {
+            ascent: font_size as f32,
+            descent: 0.0,
+            line_gap: 0.0,
+            new_line_size: font_size as f32,
+        },
+        name: name.to_string(),
         glyphs,
+        icons: codepoints,
+        // icons are as high as the font size, their metrics scale exactly
+        sized_line_metrics: BTreeMap::new(),
+        distance_field: DistanceField::Sdf,
     };
-    (sdf_font, atlas_image)
+    Ok((sdf_font, image))
+}
+
+const PRIVATE_USE_AREA_START: u32 = 0xE000;
+
+/// Icon names sorted alphabetically, with the private use area codepoints they are assigned in the icon font.
+pub fn icon_codepoints(icons: &HashMap<String, IconAsset>) -> Vec<(&str, char)> {
+    let mut names: Vec<&String> = icons.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let ch = char::from_u32(PRIVATE_USE_AREA_START + i as u32).expect("too many icons");
+            (name.as_str(), ch)
+        })
+        .collect()
+}
+
+/// Generates the sdf of a glyph coverage image, padded by `pad` on all sides.
+fn glyph_sdf(coverage: image::GrayImage, pad: usize) -> image::GrayImage {
+    let mut gray_for_sdfer: Image2d<Unorm8> = From::from(coverage);
+    let (generated_sdf, _) = sdfer::esdt::glyph_to_sdf(
+        &mut gray_for_sdfer,
+        sdfer::esdt::Params {
+            pad,
+            radius: pad as f32,
+            cutoff: 0.5,
+            solidify: true,
+            preprocess: true,
+        },
+        None,
+    );
+    image::GrayImage::from(generated_sdf)
+}
+
+/// A glyph sdf without the border that is entirely outside of the distance range, gray or rgb for
+/// msdfs.
+struct TrimmedSdf<P: Pixel<Subpixel = u8> + 'static = image::Luma<u8>> {
+    image: ImageBuffer<P, Vec<u8>>,
+    /// columns cut off on the left
+    left: u32,
+    /// rows cut off at the bottom
+    bottom: u32,
+}
+
+/// Crops the rows and columns of an sdf that only contain the minimum value, keeping one of them on
+/// each side, so sampling at the edge of the glyph quad still fades out to nothing.
+fn trim_sdf<P: Pixel<Subpixel = u8> + 'static>(sdf: ImageBuffer<P, Vec<u8>>) -> TrimmedSdf<P> {
+    let (w, h) = sdf.dimensions();
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (w, h, 0, 0);
+    for (x, y, pixel) in sdf.enumerate_pixels() {
+        if pixel.channels().iter().any(|e| *e != 0) {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x + 1);
+            max_y = max_y.max(y + 1);
+        }
+    }
+    if min_x >= max_x {
+        // nothing inside the distance range, keep a single pixel
+        let image = ImageBuffer::new(1, 1);
+        return TrimmedSdf {
+            image,
+            left: 0,
+            bottom: 0,
+        };
+    }
+    let (min_x, min_y) = (min_x.saturating_sub(1), min_y.saturating_sub(1));
+    let (max_x, max_y) = ((max_x + 1).min(w), (max_y + 1).min(h));
+    TrimmedSdf {
+        image: image::imageops::crop_imm(&sdf, min_x, min_y, max_x - min_x, max_y - min_y)
+            .to_image(),
+        left: min_x,
+        bottom: h - max_y,
+    }
+}
+
+/// Gray or rgb atlas that glyph sdfs are allocated in. Starts at the size the sdfs would need if they were
+/// packed perfectly and grows up to `max_size` squared as needed, cropped to the allocated area when finished.
+struct SdfAtlas<P: Pixel<Subpixel = u8> + 'static> {
+    allocator: guillotiere::AtlasAllocator,
+    image: ImageBuffer<P, Vec<u8>>,
+    max_size: u32,
+}
+
+impl<P: Pixel<Subpixel = u8> + 'static> SdfAtlas<P> {
+    fn new<'a>(sdfs: impl Iterator<Item = &'a TrimmedSdf<P>>, max_size: usize) -> Self {
+        let area: u32 = sdfs.map(|sdf| sdf.image.width() * sdf.image.height()).sum();
+        // the smallest power of two size with enough area, twice as wide as high at most
+        let (mut w, mut h) = (2, 2);
+        while w * h < area && w < max_size as u32 {
+            match w > h {
+                true => h *= 2,
+                false => w *= 2,
+            }
+        }
+        SdfAtlas {
+            allocator: guillotiere::AtlasAllocator::new(size2(w as i32, h as i32)),
+            image: ImageBuffer::new(w, h),
+            max_size: max_size as u32,
+        }
+    }
+
+    /// Copies an sdf into the atlas. Returns the rect in pixels, which [`SdfAtlas::finish`] turns
+    /// into uvs, or `None` if the atlas is full.
+    fn insert(&mut self, sdf: &ImageBuffer<P, Vec<u8>>) -> Option<(Vec2, Vec2)> {
+        let (w, h) = sdf.dimensions();
+        let allocation = loop {
+            if let Some(allocation) = self.allocator.allocate(size2(w as i32, h as i32)) {
+                break allocation;
+            }
+            let (mut grown_w, mut grown_h) = self.image.dimensions();
+            match grown_w > grown_h {
+                true => grown_h *= 2,
+                false if grown_w < self.max_size => grown_w *= 2,
+                false => return None,
+            }
+            self.allocator.grow(size2(grown_w as i32, grown_h as i32));
+            let mut grown = ImageBuffer::new(grown_w, grown_h);
+            grown.copy_from(&self.image, 0, 0).unwrap();
+            self.image = grown;
+        };
+        let uv_min = vec2(
+            allocation.rectangle.min.x as f32,
+            allocation.rectangle.min.y as f32,
+        );
+        let uv_max = uv_min + vec2(w as f32, h as f32);
+
+        self.image
+            .copy_from(
+                sdf,
+                allocation.rectangle.min.x as u32,
+                allocation.rectangle.min.y as u32,
+            )
+            .expect("copy from sdf_glyph image to atlas_image failed");
+        Some((uv_min, uv_max))
+    }
+
+    /// Crops the atlas to the rounded size that covers all glyphs and normalizes their uvs.
+    fn finish(
+        self,
+        glyphs: &mut HashMap<char, Glyph>,
+        rounding: SizeRounding,
+    ) -> ImageBuffer<P, Vec<u8>> {
+        let used = glyphs
+            .values()
+            .filter(|glyph| !glyph.is_white_space)
+            .fold(Vec2::ONE, |used, glyph| used.max(glyph.uv_max));
+        let w = rounding.round(used.x as u32);
+        let h = rounding.round(used.y as u32);
+        let size = vec2(w as f32, h as f32);
+        for glyph in glyphs.values_mut().filter(|glyph| !glyph.is_white_space) {
+            glyph.uv_min /= size;
+            glyph.uv_max /= size;
+        }
+        image::imageops::crop_imm(&self.image, 0, 0, w, h).to_image()
+    }
+}
+
+/// Returns the glyph that replaces `glyph` when the `tnum` feature is enabled, if there is one.
+fn tabular_substitute(face: &ttf_parser::Face, glyph: GlyphId) -> Option<GlyphId> {
+    let gsub = face.tables().gsub?;
+    let feature = gsub.features.find(Tag::from_bytes(b"tnum"))?;
+    for lookup_index in feature.lookup_indices {
+        let lookup = gsub.lookups.get(lookup_index)?;
+        for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+            let SubstitutionSubtable::Single(single) = subtable else {
+                continue;
+            };
+            match single {
+                ttf_parser::gsub::SingleSubstitution::Format1 { coverage, delta } => {
+                    if coverage.contains(glyph) {
+                        return Some(GlyphId((glyph.0 as i32 + delta as i32) as u16));
+                    }
+                }
+                ttf_parser::gsub::SingleSubstitution::Format2 {
+                    coverage,
+                    substitutes,
+                } => {
+                    if let Some(index) = coverage.get(glyph) {
+                        return substitutes.get(index);
+                    }
+                }
+            }
+        }
+    }
+    None
 }
@@ -1,24 +1,218 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{btree_map, hash_map::Entry, BTreeMap, HashMap},
     fmt::{Display, Write},
-    path::PathBuf,
+    io::Cursor,
+    path::{Path, PathBuf},
 };
 
+use glam::{vec2, Vec2};
 use heck::ToSnakeCase;
-use image::RgbaImage;
+use image::{
+    codecs::gif::GifDecoder, imageops::FilterType, AnimationDecoder, DynamicImage, Frame,
+    ImageFormat, Rgba, RgbaImage,
+};
+use rayon::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    aseprite::Aseprite,
+    cache::{content_hash, Cache, ContentHasher},
+    error::{PackError, PackResult},
+    font::{render_static_text, DistanceField, FontCharset, StaticText},
+    ignore::{IgnoreRules, DEPRECATED_IGNORE_FILE_NAME, IGNORE_FILE_NAME},
+    pack::{AddressMode, FilterMode, NineSlice, PackSettings, Sampler},
+    psd::Psd,
+    svg::Svg,
+    CONFIG_FILE_NAME,
+};
 
+#[derive(Clone)]
 pub struct ImageAsset {
     pub rgba: RgbaImage,
     pub entry: GatheredEntry,
+    /// hash of the source file, the same for all frames of an aseprite or gif file
+    pub content_hash: u64,
+    /// packed into atlases with at most 256 colors, see [`PackSettings::indexed_colors`]
+    pub indexed: bool,
     pub repeat_x: bool,
     pub repeat_y: bool,
     pub no_pack: bool,
+    /// animation name and frame index, for frames named like `walk_0.anim.png` or `walk_0.12fps.png`,
+    /// or numbered images like `walk_0.png`, `walk_1.png`
+    pub animation: Option<(String, u32)>,
+    /// playback speed of the animation, from a flag like `12fps` on any of its frames
+    pub animation_fps: Option<u32>,
+    /// how long the frame is shown in milliseconds, only known for frames of aseprite and gif files
+    pub frame_duration: Option<u32>,
+    /// from the sidecar or the `streaming_priorities` of the settings, see
+    /// [`crate::pack::PackedAssets::texture_priorities`]
+    pub streaming_priority: Option<u32>,
+    /// linear data like normal maps, named like `normal.linear.png`, packed into atlases without srgb
+    pub linear: bool,
+    /// single channel masks named like `dissolve.mask.png`, the mask value is stored gray and opaque
+    /// in `rgba`, see [`crate::pack::TileKind::Mask`]
+    pub mask: bool,
+    /// the colors of `rgba` are multiplied by its alpha, for sprites named like `smoke.pma.png` or
+    /// all sprites with the `premultiply_alpha` setting, see [`crate::pack::AlphaMode::Premultiplied`]
+    pub premultiplied: bool,
+    /// borders of images named like `panel.9s.png` or with a sidecar, see [`ImageSidecar`]
+    pub nine_slice: Option<NineSlice>,
+    /// origin in pixels of `rgba`, from the `center` or `bottom` flag or a sidecar
+    pub pivot: Option<Vec2>,
+    /// how the texture of the image is sampled, mirrored repeat for images named like
+    /// `water.mirror.png` and nearest filtering for `hero.nearest.png`
+    pub sampler: Sampler,
+    /// from a `bg`, `fg` or `layer=3` flag, the sidecar or the dir config, see
+    /// [`crate::pack::TextureTile::layer`]
+    pub layer: Option<i32>,
+    /// from the sidecar, see [`crate::pack::PackedAssets::tags`]
+    pub tags: Vec<String>,
+    /// from the sidecar, see [`crate::pack::PackedAssets::user_data`]
+    pub user_data: BTreeMap<String, String>,
+}
+
+impl ImageAsset {
+    /// Repeat images that get a texture of their own instead of a strip in an atlas: images repeating
+    /// along both axes, and images whose sampler differs from the one of strip atlases.
+    pub fn has_own_repeat_texture(&self) -> bool {
+        (self.repeat_x && self.repeat_y)
+            || ((self.repeat_x || self.repeat_y)
+                && self.sampler != Sampler::repeat(self.repeat_x, self.repeat_y))
+    }
+}
+
+/// Optional json file next to an image with values that do not fit into its file name, like
+/// `panel.png.meta` or `panel.json` for `panel.png`. The `.meta` sidecar is read if there are both.
+/// The sidecar of an aseprite or gif file like `fire.gif.meta` applies to each of its frames.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImageSidecar {
+    /// explicit borders, for images without the guides of the `9s` flag
+    pub nine_slice: Option<NineSlice>,
+    pub pivot: Option<Pivot>,
+    /// overrides the `streaming_priorities` of the settings for this image
+    pub priority: Option<u32>,
+    /// overrides the `make_tiling` setting for this image
+    pub make_tiling: Option<bool>,
+    /// texture filtering of the image, like `"nearest"` for pixel art
+    pub filter: Option<FilterMode>,
+    /// layer groups of a psd file that are also packed on their own, as images named like `hero_arm`
+    pub layer_groups: Vec<String>,
+    /// draw layer, overrides the flags and dir config
+    pub layer: Option<i32>,
+    /// written to the manifest for games that look up sprites by tag, like `"enemy"`
+    pub tags: Vec<String>,
+    /// any values the game wants to know about the sprite, like `{ "hitbox_radius": 12 }`, written
+    /// to the manifest as strings
+    pub user_data: BTreeMap<String, serde_json::Value>,
+}
+
+/// Optional json file next to a font collection like `noto.ttc.meta`, with the faces to pack.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontSidecar {
+    /// indices of the faces in the collection, all of them if empty. The `faces=0,2` flag of the
+    /// file name overrides it.
+    pub faces: Vec<u32>,
+}
+
+/// Extension of the sidecar files like `player.png.meta`, see [`ImageSidecar`] and [`FontSidecar`].
+pub const SIDECAR_EXTENSION: &str = "meta";
+
+/// File in any dir of the source dir with options for all images in it and its sub dirs.
+pub const DIR_CONFIG_FILE_NAME: &str = "dir.json";
+
+/// Options for all images in a dir and its sub dirs, read from a [`DIR_CONFIG_FILE_NAME`] file like
+/// `{ "relative_padding": 0.125, "atlas_group": "characters", "flags": ["bottom"] }`. The options
+/// of nested dirs override those of their parents, flags add to them. Flags in file names and
+/// sidecars override the options.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirConfig {
+    /// pixels of padding around each sprite in the atlas, 2 by default
+    pub padding: Option<u32>,
+    /// padding as a fraction of the sprite size, like 0.125 for an eighth, for sprites that are
+    /// scaled a lot at runtime and would bleed otherwise
+    pub relative_padding: Option<f32>,
+    /// sprites of different groups never share an atlas, so a game can load the atlases of a group
+    /// on their own
+    pub atlas_group: Option<String>,
+    /// texture filtering of the images, like `"nearest"` for pixel art
+    pub filter: Option<FilterMode>,
+    /// draw layer of the images, like -2 for a dir of far away backgrounds
+    pub layer: Option<i32>,
+    /// flags applied to every image, as if they were in its file name like `linear` or `pal`
+    pub flags: Vec<String>,
+}
+
+impl DirConfig {
+    /// The options of the [`DIR_CONFIG_FILE_NAME`] in `dir` on top of the ones of its parent.
+    fn load(dir: &Path, parent: &DirConfig) -> PackResult<DirConfig> {
+        let path = dir.join(DIR_CONFIG_FILE_NAME);
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(parent.clone()),
+            Err(e) => return Err(PackError::io(path)(e)),
+        };
+        let config: DirConfig = serde_json::from_slice(&bytes).map_err(|e| PackError::Config {
+            path: path.clone(),
+            message: e.to_string(),
+        })?;
+        // a padding in pixels replaces a relative padding of a parent and the other way around
+        let (padding, relative_padding) = match (config.padding, config.relative_padding) {
+            (None, None) => (parent.padding, parent.relative_padding),
+            padding => padding,
+        };
+        Ok(DirConfig {
+            padding,
+            relative_padding,
+            atlas_group: config.atlas_group.or(parent.atlas_group.clone()),
+            filter: config.filter.or(parent.filter),
+            layer: config.layer.or(parent.layer),
+            flags: parent
+                .flags
+                .iter()
+                .chain(config.flags.iter())
+                .cloned()
+                .collect(),
+        })
+    }
+}
+
+/// Origin of a sprite, written like `"center"`, `"bottom_center"` or `{ "pixel": [12, 30] }` in sidecars.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pivot {
+    Center,
+    BottomCenter,
+    /// from the top left of the image
+    Pixel(Vec2),
+}
+
+impl Pivot {
+    fn position(self, (w, h): (u32, u32)) -> Vec2 {
+        match self {
+            Pivot::Center => vec2(w as f32, h as f32) / 2.0,
+            Pivot::BottomCenter => vec2(w as f32 / 2.0, h as f32),
+            Pivot::Pixel(position) => position,
+        }
+    }
 }
 
 pub struct FontAsset {
-    pub bytes: Vec<u8>, // ttf file bytes
+    pub bytes: Vec<u8>, // ttf, otf or ttc file bytes
+    pub content_hash: u64,
+    pub entry: GatheredEntry,
+    pub is_default: bool,              // should only be true for one font asset
+    pub face_index: u32,               // face of a ttc collection, 0 for other fonts
+    pub charset: Option<Vec<char>>,    // generated instead of the ALPHABET, see `font_charset`
+    pub distance_field: DistanceField, // msdf if flagged `msdf` or listed in the font settings
+}
+
+pub struct IconAsset {
+    pub svg: Svg,
+    pub content_hash: u64,
     pub entry: GatheredEntry,
-    pub is_default: bool, // should only be true for one font asset
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -55,159 +249,1506 @@ impl AssetPath {
 }
 
 pub struct GatheredAssets {
+    /// the source dir the assets were gathered from
+    pub dir: PathBuf,
     pub images: HashMap<String, ImageAsset>,
+    /// animation name -> image idents in playback order, for the tags of aseprite files
+    pub animation_tags: BTreeMap<String, Vec<String>>,
     pub fonts: HashMap<String, FontAsset>,
+    pub icons: HashMap<String, IconAsset>, // svgs, assembled into one icon font
+    /// files in the images and fonts dirs that are copied into the pack unchanged, see
+    /// [`DATA_EXTENSION`]
+    pub data_files: BTreeMap<String, GatheredEntry>,
+    /// files in the images dir with an extension that is not an image format
+    pub skipped_files: Vec<PathBuf>,
+    /// files and dirs left out because of the [`IgnoreRules`], like `.DS_Store` or editor backups,
+    /// and symlinks that are not followed
+    pub ignored_files: Vec<PathBuf>,
+    /// names of the bundles split off by [`GatheredAssets::split_bundles`], packed next to these assets
+    pub bundles: Vec<String>,
+}
+
+impl GatheredAssets {
+    /// Splits off the images of each top level dir of the images dir, like `ui` or `characters`, into
+    /// a bundle of its own, packed into its own atlases and manifest. Images at the top of the images
+    /// dir, fonts, icons and data files stay in the returned shared assets.
+    pub fn split_bundles(mut self) -> (GatheredAssets, BTreeMap<String, GatheredAssets>) {
+        let mut bundles: BTreeMap<String, GatheredAssets> = BTreeMap::new();
+        for (ident, asset) in std::mem::take(&mut self.images) {
+            let Some(bundle) = asset.entry.asset_path.path().first().cloned() else {
+                self.images.insert(ident, asset);
+                continue;
+            };
+            let bundle = bundles.entry(bundle).or_insert_with(|| GatheredAssets {
+                dir: self.dir.clone(),
+                images: HashMap::new(),
+                animation_tags: BTreeMap::new(),
+                fonts: HashMap::new(),
+                icons: HashMap::new(),
+                data_files: BTreeMap::new(),
+                skipped_files: vec![],
+                ignored_files: vec![],
+                bundles: vec![],
+            });
+            bundle.images.insert(ident, asset);
+        }
+        // the frames of a tagged animation are all in the file of the animation, so in one bundle
+        for (name, frames) in std::mem::take(&mut self.animation_tags) {
+            let assets = bundles
+                .values_mut()
+                .find(|e| {
+                    frames
+                        .first()
+                        .is_some_and(|frame| e.images.contains_key(frame))
+                })
+                .unwrap_or(&mut self);
+            assets.animation_tags.insert(name, frames);
+        }
+        self.bundles = bundles.keys().cloned().collect();
+        (self, bundles)
+    }
 }
 
-pub fn gather_assets(dir: &str) -> GatheredAssets {
+/// Extensions of the still images, decoded by the `image` crate and converted to rgba.
+pub const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "bmp", "tga", "webp"];
+
+/// Extensions of the fonts, with TrueType or CFF outlines, and of font collections.
+pub const FONT_EXTENSIONS: [&str; 3] = ["ttf", "otf", "ttc"];
+
+/// Extensions of all files the assets are gathered from, besides the json sidecars of images.
+pub const SOURCE_EXTENSIONS: [&str; 14] = [
+    "png", "jpg", "jpeg", "bmp", "tga", "webp", "aseprite", "ase", "gif", "psd", "ttf", "otf",
+    "ttc", "svg",
+];
+
+/// What files with an extension mapped to it in [`PackSettings::extensions`] are read as: they
+/// are copied into the pack unchanged, see [`crate::pack::PackedAssets::data_files`].
+pub const DATA_EXTENSION: &str = "data";
+
+/// Checks that the [`PackSettings::extensions`] map to one of the [`SOURCE_EXTENSIONS`] or the
+/// [`DATA_EXTENSION`].
+fn check_extensions(dir: &str, settings: &PackSettings) -> PackResult<()> {
+    for (extension, read_as) in settings.extensions.iter() {
+        if !SOURCE_EXTENSIONS.contains(&read_as.as_str()) && read_as != DATA_EXTENSION {
+            return Err(PackError::Config {
+                path: PathBuf::from(format!("{dir}/{CONFIG_FILE_NAME}")),
+                message: format!(
+                    "files with the extension {extension} cannot be read as {read_as}, it needs to be one of {} or {DATA_EXTENSION}",
+                    SOURCE_EXTENSIONS.join(", ")
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Restricts an asset file or all assets in a directory to some target platforms.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct PlatformRule {
+    /// platforms the assets are left out for
+    pub exclude: Vec<String>,
+    /// if not empty, the assets are only packed for these platforms
+    pub only: Vec<String>,
+}
+
+impl PlatformRule {
+    pub fn allows(&self, platform: &str) -> bool {
+        !self.exclude.iter().any(|e| e == platform)
+            && (self.only.is_empty() || self.only.iter().any(|e| e == platform))
+    }
+}
+
+/// Whether the file at `path` is left out for the target platform of `settings`. The rule of the
+/// most specific path in `settings.platform_rules` that is `path` or one of its parent directories
+/// decides, paths are relative to `dir`, like `images/portraits`.
+fn excluded_for_platform(settings: &PackSettings, dir: &str, path: &Path) -> bool {
+    let Some(platform) = &settings.platform else {
+        return false;
+    };
+    most_specific_rule(&settings.platform_rules, dir, path)
+        .is_some_and(|rule| !rule.allows(platform))
+}
+
+/// The rule of the most specific path in `rules` that is `path` or one of its parent directories,
+/// paths of the rules are relative to `dir`.
+pub(crate) fn most_specific_rule<'a, T>(
+    rules: &'a BTreeMap<String, T>,
+    dir: &str,
+    path: &Path,
+) -> Option<&'a T> {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    rules
+        .iter()
+        .filter(|(rule_path, _)| relative.starts_with(rule_path))
+        .max_by_key(|(rule_path, _)| Path::new(rule_path).components().count())
+        .map(|(_, rule)| rule)
+}
+
+/// Reads and decodes the assets in `dir`. Assets that fail to load or have the identifier of
+/// another do not stop the others, the errors of all of them are returned together.
+pub fn gather_assets(
+    dir: &str,
+    settings: &PackSettings,
+    cache: &mut Cache,
+) -> PackResult<GatheredAssets> {
+    let included = |entry: &GatheredEntry, extension: &str| {
+        if entry.extension != extension {
+            return false;
+        }
+        let excluded = excluded_for_platform(settings, dir, &entry.path);
+        if excluded {
+            println!("    skip {}: excluded for this platform", entry.asset_path);
+        }
+        !excluded
+    };
+
+    check_extensions(dir, settings)?;
+    let ignore = IgnoreRules::load(dir)?;
+    let mut ignored_files: Vec<PathBuf> = vec![];
+    let mut deprecated_ignore_files: Vec<PathBuf> = vec![];
+    let root_ignore_file = Path::new(dir).join(DEPRECATED_IGNORE_FILE_NAME);
+    if root_ignore_file.is_file() {
+        deprecated_ignore_files.push(root_ignore_file);
+    }
+    let mut report_ignored = |skipped: SkippedEntries| {
+        for path in skipped.ignored {
+            let relative = path.strip_prefix(dir).unwrap_or(&path);
+            println!("    skip {}: ignored", relative.display());
+            ignored_files.push(path);
+        }
+        deprecated_ignore_files.extend(skipped.deprecated_ignore_files);
+    };
+
     // gather images
     let images_dir = format!("{dir}/images");
     let mut images: HashMap<String, ImageAsset> = HashMap::new();
     println!("gather images:");
-    gather_dir_entries(&images_dir, &mut |entry| {
-        if entry.extension != "png" {
-            return;
+    let mut image_entries: Vec<GatheredEntry> = vec![];
+    let mut aseprite_entries: Vec<GatheredEntry> = vec![];
+    let mut gif_entries: Vec<GatheredEntry> = vec![];
+    let mut psd_entries: Vec<GatheredEntry> = vec![];
+    let mut svg_entries: Vec<GatheredEntry> = vec![];
+    let mut data_entries: Vec<GatheredEntry> = vec![];
+    let mut skipped_files: Vec<PathBuf> = vec![];
+    let skipped = gather_dir_entries(&images_dir, &ignore, settings, &mut |entry| {
+        if IMAGE_EXTENSIONS.iter().any(|e| included(&entry, e)) {
+            image_entries.push(entry);
+        } else if included(&entry, "aseprite") || included(&entry, "ase") {
+            aseprite_entries.push(entry);
+        } else if included(&entry, "gif") {
+            gif_entries.push(entry);
+        } else if included(&entry, "psd") {
+            psd_entries.push(entry);
+        } else if included(&entry, "svg") {
+            svg_entries.push(entry);
+        } else if included(&entry, DATA_EXTENSION) {
+            data_entries.push(entry);
+        } else if !SOURCE_EXTENSIONS.contains(&entry.extension.as_str())
+            && entry.extension != "json"
+            && entry.extension != SIDECAR_EXTENSION
+        {
+            skipped_files.push(entry.path);
         }
-        let ident: String = entry.asset_path.ident().to_owned();
-        let asset = load_image_asset(entry);
+        Ok(())
+    })?;
+    report_ignored(skipped);
+    skipped_files.sort();
+    // the errors of all assets are returned together, so all of them can be fixed at once
+    let mut errors: Vec<PackError> = vec![];
+    // decoding dominates the gather time of large projects, so the images are decoded on all cores
+    let decoded: Vec<PackResult<ImageAsset>> = image_entries
+        .into_par_iter()
+        .map(|entry| load_image_asset(entry, settings, cache))
+        .collect();
+    let mut loaded: Vec<ImageAsset> = vec![];
+    for result in decoded {
+        match result {
+            Ok(asset) => loaded.push(asset),
+            Err(e) => errors.push(e),
+        }
+    }
+    let mut animation_tags: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for entry in aseprite_entries {
+        if let Err(e) = load_aseprite_asset(entry, settings, &mut loaded, &mut animation_tags) {
+            errors.push(e);
+        }
+    }
+    for entry in gif_entries {
+        if let Err(e) = load_gif_asset(entry, settings, &mut loaded) {
+            errors.push(e);
+        }
+    }
+    for entry in psd_entries {
+        if let Err(e) = load_psd_asset(entry, settings, &mut loaded) {
+            errors.push(e);
+        }
+    }
+    for entry in svg_entries {
+        if let Err(e) = load_svg_image_asset(entry, settings, &mut loaded) {
+            errors.push(e);
+        }
+    }
+    for mut asset in loaded {
+        cache.record_file(&asset.entry.path, asset.content_hash);
+        if asset.streaming_priority.is_none() {
+            asset.streaming_priority =
+                most_specific_rule(&settings.streaming_priorities, dir, &asset.entry.path).copied();
+        }
+        if !asset.indexed {
+            asset.indexed = most_specific_rule(&settings.indexed_colors, dir, &asset.entry.path)
+                .is_some_and(|indexed| *indexed);
+        }
+        // masks are written as single channel pngs already
+        asset.indexed &= !asset.mask;
+        let ident: String = asset.entry.asset_path.ident().to_owned();
         println!("    image: {ident}");
         match images.entry(ident) {
-            Entry::Occupied(other) => {
-                panic!(
-                    "Duplicate image identifier: {} for {:?} and {:?}",
-                    other.key(),
-                    asset.entry,
-                    other.get().entry
-                )
-            }
+            Entry::Occupied(other) => errors.push(PackError::DuplicateIdentifier {
+                kind: "image",
+                ident: other.key().clone(),
+                paths: (other.get().entry.path.clone(), asset.entry.path),
+            }),
             Entry::Vacant(e) => {
                 e.insert(asset);
             }
         }
-    });
+    }
+    if let Err(e) = detect_animations(&mut images) {
+        errors.push(e);
+    }
 
     // gather fonts
     let fonts_dir = format!("{dir}/fonts");
     let mut fonts: HashMap<String, FontAsset> = HashMap::new();
     println!("gather fonts:");
-    gather_dir_entries(&fonts_dir, &mut |entry| {
-        if entry.extension != "ttf" {
-            return;
+    let skipped = gather_dir_entries(&fonts_dir, &ignore, settings, &mut |entry| {
+        if included(&entry, DATA_EXTENSION) {
+            data_entries.push(entry);
+            return Ok(());
+        }
+        if !FONT_EXTENSIONS.iter().any(|e| included(&entry, e)) {
+            return Ok(());
+        }
+        let assets = match load_font_assets(entry, cache) {
+            Ok(assets) => assets,
+            Err(e) => {
+                errors.push(e);
+                return Ok(());
+            }
+        };
+        for (ident, mut asset) in assets {
+            match font_charset(dir, &ident, &asset.entry, settings) {
+                Ok(charset) => asset.charset = charset,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            }
+            if asset.entry.flags.contains("msdf") || settings.font.msdf_fonts.contains(&ident) {
+                asset.distance_field = DistanceField::Msdf;
+            }
+            println!("    font: {ident}");
+            match fonts.entry(ident) {
+                Entry::Occupied(other) => errors.push(PackError::DuplicateIdentifier {
+                    kind: "font",
+                    ident: other.key().clone(),
+                    paths: (other.get().entry.path.clone(), asset.entry.path),
+                }),
+                Entry::Vacant(e) => {
+                    e.insert(asset);
+                }
+            }
+        }
+        Ok(())
+    })?;
+    report_ignored(skipped);
+    for (name, text) in settings.texts.iter() {
+        match static_text_asset(dir, name, text, &fonts, settings) {
+            Ok(asset) => match images.entry(name.clone()) {
+                Entry::Occupied(other) => errors.push(PackError::DuplicateIdentifier {
+                    kind: "image",
+                    ident: name.clone(),
+                    paths: (other.get().entry.path.clone(), asset.entry.path),
+                }),
+                Entry::Vacant(e) => {
+                    println!("    text: {name}");
+                    e.insert(asset);
+                }
+            },
+            Err(e) => errors.push(e),
+        }
+    }
+
+    // gather icons
+    let icons_dir = format!("{dir}/icons");
+    let mut icons: HashMap<String, IconAsset> = HashMap::new();
+    println!("gather icons:");
+    let skipped = gather_dir_entries(&icons_dir, &ignore, settings, &mut |entry| {
+        if !included(&entry, "svg") {
+            return Ok(());
         }
         let ident: String = entry.asset_path.ident().to_owned();
-        let asset = load_font_asset(entry);
-        println!("    font: {ident}");
-        match fonts.entry(ident) {
-            Entry::Occupied(other) => {
-                panic!(
-                    "Duplicate font identifier: {} for {:?} and {:?}",
-                    other.key(),
-                    asset.entry,
-                    other.get().entry
-                )
+        let asset = match load_icon_asset(entry, cache) {
+            Ok(asset) => asset,
+            Err(e) => {
+                errors.push(e);
+                return Ok(());
             }
+        };
+        println!("    icon: {ident}");
+        match icons.entry(ident) {
+            Entry::Occupied(other) => errors.push(PackError::DuplicateIdentifier {
+                kind: "icon",
+                ident: other.key().clone(),
+                paths: (other.get().entry.path.clone(), asset.entry.path),
+            }),
             Entry::Vacant(e) => {
                 e.insert(asset);
             }
         }
-    });
+        Ok(())
+    })?;
+
+    report_ignored(skipped);
+    ignored_files.sort();
+    for path in deprecated_ignore_files {
+        let relative = path.strip_prefix(dir).unwrap_or(&path);
+        println!(
+            "    {} is deprecated, rename it to {IGNORE_FILE_NAME}",
+            relative.display()
+        );
+    }
+
+    let mut data_files: BTreeMap<String, GatheredEntry> = BTreeMap::new();
+    for entry in data_entries {
+        let ident = entry.asset_path.ident().to_owned();
+        println!("    data: {ident}");
+        match data_files.entry(ident) {
+            btree_map::Entry::Occupied(other) => errors.push(PackError::DuplicateIdentifier {
+                kind: "data file",
+                ident: other.key().clone(),
+                paths: (other.get().path.clone(), entry.path),
+            }),
+            btree_map::Entry::Vacant(e) => {
+                e.insert(entry);
+            }
+        }
+    }
+    PackError::from_errors(errors)?;
+
+    Ok(GatheredAssets {
+        dir: PathBuf::from(dir),
+        images,
+        animation_tags,
+        fonts,
+        icons,
+        data_files,
+        skipped_files,
+        ignored_files,
+        bundles: vec![],
+    })
+}
+
+/// Files that `gather_assets` leaves out because of the `platform_rules` for the target platform.
+pub fn excluded_files(dir: &str, settings: &PackSettings) -> PackResult<Vec<PathBuf>> {
+    let ignore = IgnoreRules::load(dir)?;
+    let mut excluded: Vec<PathBuf> = vec![];
+    for sub_dir in ["images", "fonts", "icons"] {
+        let sub_dir = format!("{dir}/{sub_dir}");
+        gather_dir_entries(&sub_dir, &ignore, settings, &mut |entry| {
+            if (SOURCE_EXTENSIONS.contains(&entry.extension.as_str())
+                || entry.extension == DATA_EXTENSION)
+                && excluded_for_platform(settings, dir, &entry.path)
+            {
+                excluded.push(entry.path);
+            }
+            Ok(())
+        })?;
+    }
+    excluded.sort();
+    Ok(excluded)
+}
+
+/// Hash over the paths, flags and bytes of all files that `gather_assets` would pick up, without decoding anything.
+pub fn input_fingerprint(dir: &str, settings: &PackSettings) -> PackResult<u64> {
+    let ignore = IgnoreRules::load(dir)?;
+    let mut entries: Vec<GatheredEntry> = vec![];
+    for sub_dir in ["images", "fonts", "icons"] {
+        let sub_dir = format!("{dir}/{sub_dir}");
+        gather_dir_entries(&sub_dir, &ignore, settings, &mut |entry| {
+            // json and meta files are the sidecars of images
+            if SOURCE_EXTENSIONS.contains(&entry.extension.as_str())
+                || entry.extension == "json"
+                || entry.extension == SIDECAR_EXTENSION
+                || entry.extension == DATA_EXTENSION
+            {
+                entries.push(entry);
+            }
+            Ok(())
+        })?;
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut hasher = ContentHasher::new();
+    hasher.write(env!("CARGO_PKG_VERSION").as_bytes());
+    // the ignore files change which files are gathered
+    for file_name in [IGNORE_FILE_NAME, DEPRECATED_IGNORE_FILE_NAME] {
+        hasher.write(&std::fs::read(format!("{dir}/{file_name}")).unwrap_or_default());
+    }
+    for file in settings
+        .font
+        .charsets
+        .values()
+        .filter_map(|e| e.file.as_ref())
+    {
+        hasher.write(&std::fs::read(format!("{dir}/{file}")).unwrap_or_default());
+    }
+    for entry in entries {
+        hasher.write(entry.path.to_string_lossy().as_bytes());
+        hasher.write(&std::fs::read(&entry.path).map_err(PackError::io(&entry.path))?);
+    }
+    Ok(hasher.finish())
+}
 
-    GatheredAssets { images, fonts }
+/// Only needs a shared cache, so it can run on several threads at once.
+fn load_image_asset(
+    entry: GatheredEntry,
+    settings: &PackSettings,
+    cache: &Cache,
+) -> PackResult<ImageAsset> {
+    let bytes: Vec<u8> = std::fs::read(&entry.path).map_err(PackError::io(&entry.path))?;
+    let content_hash = content_hash(&bytes);
+    let rgba = match cache.decoded_image(content_hash) {
+        Some(rgba) => rgba,
+        None => {
+            let rgba = decode_image(&entry, &bytes).map_err(PackError::image(&entry.path))?;
+            cache.store_decoded_image(content_hash, &rgba)?;
+            rgba
+        }
+    };
+    image_asset(entry, rgba, content_hash, settings, settings.scale)
 }
 
-fn load_image_asset(entry: GatheredEntry) -> ImageAsset {
-    let bytes: Vec<u8> = std::fs::read(&entry.path).unwrap();
-    let rgba = image::load_from_memory(&bytes).unwrap().to_rgba8();
+fn decode_image(entry: &GatheredEntry, bytes: &[u8]) -> image::ImageResult<RgbaImage> {
+    #[cfg(feature = "fast-png")]
+    if entry.extension == "png" {
+        return crate::fast_png::decode_png(bytes);
+    }
+    // tga files have no magic bytes to guess the format from
+    let image = match ImageFormat::from_extension(&entry.extension) {
+        Some(format) => image::load_from_memory_with_format(bytes, format),
+        None => image::load_from_memory(bytes),
+    }?;
+    Ok(image.to_rgba8())
+}
 
+/// Applies the flags and sidecar of an image to its decoded pixels, and resizes them by `scale` once
+/// the guides of nine slices are removed.
+fn image_asset(
+    entry: GatheredEntry,
+    mut rgba: RgbaImage,
+    content_hash: u64,
+    settings: &PackSettings,
+    scale: f32,
+) -> PackResult<ImageAsset> {
     let mut repeat_x = false;
     let mut repeat_y = false;
+    let mut mirror_x = false;
+    let mut mirror_y = false;
+    let mut filter = entry.dir_config.filter.unwrap_or(FilterMode::Linear);
     let mut no_pack = false;
+    let mut animation: Option<(String, u32)> = None;
+    let mut linear = false;
+    let mut mask = false;
+    let mut premultiplied = settings.premultiply_alpha;
+    let mut nine_slice: Option<NineSlice> = None;
+    let mut pivot: Option<Pivot> = None;
+    let mut animation_fps: Option<u32> = None;
+    let mut indexed = false;
 
-    match entry.flags.as_str() {
-        "rep" => {
-            repeat_x = true;
-            repeat_y = true;
-        }
-        "repx" => {
-            repeat_x = true;
-        }
-        "repy" => {
-            repeat_y = true;
+    let flags = entry.all_flags();
+    for (key, value) in flags.values.iter() {
+        let valid = match key.as_str() {
+            "pad" => value.parse::<u32>().is_ok(),
+            "layer" => value.parse::<i32>().is_ok(),
+            _ => true,
+        };
+        if !valid {
+            return Err(PackError::Config {
+                path: entry.path.clone(),
+                message: format!("invalid value {value} of the {key} flag"),
+            });
         }
-        "no" => {
-            no_pack = true;
+    }
+    for flag in flags.flags.iter().map(String::as_str) {
+        match flag {
+            "rep" => {
+                repeat_x = true;
+                repeat_y = true;
+            }
+            "repx" => {
+                repeat_x = true;
+            }
+            "repy" => {
+                repeat_y = true;
+            }
+            "mirror" => {
+                mirror_x = true;
+                mirror_y = true;
+            }
+            "mirrorx" => {
+                mirror_x = true;
+            }
+            "mirrory" => {
+                mirror_y = true;
+            }
+            "nearest" => {
+                filter = FilterMode::Nearest;
+            }
+            "no" => {
+                no_pack = true;
+            }
+            "linear" => {
+                linear = true;
+            }
+            "mask" => {
+                mask = true;
+                rgba = mask_to_gray(&rgba);
+            }
+            "pma" => {
+                premultiplied = true;
+            }
+            "pal" => {
+                indexed = true;
+            }
+            "center" => {
+                pivot = Some(Pivot::Center);
+            }
+            "bottom" => {
+                pivot = Some(Pivot::BottomCenter);
+            }
+            // fix images exported in the wrong orientation, rotations are clockwise
+            "rot90" => {
+                rgba = image::imageops::rotate90(&rgba);
+            }
+            "rot180" => {
+                rgba = image::imageops::rotate180(&rgba);
+            }
+            "rot270" => {
+                rgba = image::imageops::rotate270(&rgba);
+            }
+            "flipx" => {
+                image::imageops::flip_horizontal_in_place(&mut rgba);
+            }
+            "flipy" => {
+                image::imageops::flip_vertical_in_place(&mut rgba);
+            }
+            "9s" => {
+                let (stripped, borders) = nine_slice_guides(&rgba).ok_or_else(|| PackError::NineSlice {
+                path: entry.path.clone(),
+                message: String::from(
+                    "9s images need a 1 pixel border with black pixels along the top and left edge marking the stretched area",
+                ),
+            })?;
+                rgba = stripped;
+                nine_slice = Some(borders);
+            }
+            flags if flags == "anim" || frames_per_second(flags).is_some() => {
+                animation_fps = frames_per_second(flags);
+                let ident = entry.asset_path.ident();
+                let frame = ident
+                    .rsplit_once('_')
+                    .and_then(|(name, index)| Some((name.to_string(), index.parse().ok()?)));
+                if frame.is_none() {
+                    return Err(PackError::Animation {
+                        name: ident.to_string(),
+                        message: format!(
+                            "{}: frames need to be named like walk_0.anim.png or walk_0.12fps.png",
+                            entry.path.display()
+                        ),
+                    });
+                }
+                animation = frame;
+            }
+            _ => {}
+        };
+    }
+    let sidecar: ImageSidecar = read_sidecar(&entry.path)?;
+    if let Some(borders) = sidecar.nine_slice {
+        nine_slice = Some(borders);
+    }
+    if let Some(sidecar_pivot) = sidecar.pivot {
+        pivot = Some(sidecar_pivot);
+    }
+    let mut pivot = pivot.map(|pivot| pivot.position(rgba.dimensions()));
+    if scale != 1.0 {
+        rgba = scale_image(&rgba, scale);
+        pivot = pivot.map(|pivot| pivot * scale);
+        nine_slice = nine_slice.map(|borders| NineSlice {
+            left: (borders.left as f32 * scale).round() as u32,
+            right: (borders.right as f32 * scale).round() as u32,
+            top: (borders.top as f32 * scale).round() as u32,
+            bottom: (borders.bottom as f32 * scale).round() as u32,
+        });
+    }
+    if let Some(borders) = nine_slice {
+        let (w, h) = rgba.dimensions();
+        if borders.left + borders.right > w || borders.top + borders.bottom > h {
+            return Err(PackError::NineSlice {
+                path: entry.path.clone(),
+                message: format!("the borders are larger than the image ({w}x{h})"),
+            });
         }
-        _ => {}
+    }
+    // mirrored images repeat seamlessly as they are
+    if sidecar.make_tiling.unwrap_or(settings.make_tiling) && (repeat_x || repeat_y) {
+        make_tiling(&mut rgba, repeat_x, repeat_y);
+    }
+    repeat_x |= mirror_x;
+    repeat_y |= mirror_y;
+    let address = |repeat: bool, mirror: bool| match mirror {
+        true => AddressMode::MirrorRepeat,
+        false => AddressMode::repeat(repeat),
+    };
+    let sampler = Sampler {
+        address_x: address(repeat_x, mirror_x),
+        address_y: address(repeat_y, mirror_y),
+        filter: sidecar.filter.unwrap_or(filter),
     };
+    // repeat textures and data that is not a color keep straight alpha
+    premultiplied &= !repeat_x && !repeat_y && !linear && !mask;
+    if premultiplied {
+        premultiply(&mut rgba);
+    }
+    let layer = sidecar.layer.or(draw_layer(&entry));
 
-    ImageAsset {
+    Ok(ImageAsset {
         rgba,
         entry,
+        content_hash,
+        indexed,
         repeat_x,
         repeat_y,
         no_pack,
-    }
+        animation,
+        linear,
+        mask,
+        premultiplied,
+        nine_slice,
+        pivot,
+        animation_fps,
+        frame_duration: None,
+        streaming_priority: sidecar.priority,
+        sampler,
+        layer,
+        tags: sidecar.tags,
+        user_data: sidecar
+            .user_data
+            .into_iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(string) => (key, string),
+                value => (key, value.to_string()),
+            })
+            .collect(),
+    })
 }
 
-fn load_font_asset(entry: GatheredEntry) -> FontAsset {
-    let bytes: Vec<u8> = std::fs::read(&entry.path).unwrap();
-    let is_default = entry.flags == "default";
-    FontAsset {
-        bytes,
+/// Flattens the visible layers of a psd file like `hero.psd` into the image `hero`, and each layer
+/// group listed in its sidecar into an image like `hero_arm`, with the size of the whole document.
+fn load_psd_asset(
+    entry: GatheredEntry,
+    settings: &PackSettings,
+    loaded: &mut Vec<ImageAsset>,
+) -> PackResult<()> {
+    let bytes: Vec<u8> = std::fs::read(&entry.path).map_err(PackError::io(&entry.path))?;
+    let content_hash = content_hash(&bytes);
+    let psd_error = |message| PackError::Psd {
+        path: entry.path.clone(),
+        message,
+    };
+    let psd = Psd::parse(&bytes).map_err(psd_error)?;
+    let name = entry.asset_path.ident().to_string();
+    for group in read_sidecar::<ImageSidecar>(&entry.path)?.layer_groups {
+        let rgba = psd
+            .flatten_group(&group)
+            .ok_or_else(|| psd_error(format!("there is no layer group named {group}")))?;
+        let mut group_entry = entry.clone();
+        *group_entry.asset_path.segments.last_mut().unwrap() =
+            format!("{name}_{}", group.to_snake_case());
+        loaded.push(image_asset(
+            group_entry,
+            rgba,
+            content_hash,
+            settings,
+            settings.scale,
+        )?);
+    }
+    loaded.push(image_asset(
         entry,
-        is_default,
+        psd.flatten(),
+        content_hash,
+        settings,
+        settings.scale,
+    )?);
+    Ok(())
+}
+
+/// Rasterizes an svg in the images dir like `play.svg` in its fill color at each of the `svg_scales`
+/// of the settings, into the image `play` for a single scale and images like `play_1x` and `play_2x`
+/// for several ones.
+fn load_svg_image_asset(
+    entry: GatheredEntry,
+    settings: &PackSettings,
+    loaded: &mut Vec<ImageAsset>,
+) -> PackResult<()> {
+    let text: String = std::fs::read_to_string(&entry.path).map_err(PackError::io(&entry.path))?;
+    let content_hash = content_hash(text.as_bytes());
+    let svg = Svg::parse(&text).map_err(|message| PackError::Svg {
+        path: entry.path.clone(),
+        message,
+    })?;
+    let name = entry.asset_path.ident().to_string();
+    for &scale in settings.svg_scales.iter() {
+        // rasterized at the final size instead of resizing afterwards, which would blur the edges
+        let coverage = svg.rasterize(scale * settings.scale);
+        let [r, g, b] = svg.fill;
+        let rgba = RgbaImage::from_fn(coverage.width(), coverage.height(), |x, y| {
+            Rgba([r, g, b, coverage.get_pixel(x, y)[0]])
+        });
+        let mut scale_entry = entry.clone();
+        if settings.svg_scales.len() > 1 {
+            let suffix = scale.to_string().replace('.', "_");
+            *scale_entry.asset_path.segments.last_mut().unwrap() = format!("{name}_{suffix}x");
+        }
+        loaded.push(image_asset(scale_entry, rgba, content_hash, settings, 1.0)?);
+    }
+    Ok(())
+}
+
+/// Turns the frames of an aseprite file like `hero.aseprite` into images named `hero_0`, `hero_1`, ..
+/// that form the animation `hero`, and each of its tags into an animation like `hero_walk`.
+fn load_aseprite_asset(
+    entry: GatheredEntry,
+    settings: &PackSettings,
+    loaded: &mut Vec<ImageAsset>,
+    animation_tags: &mut BTreeMap<String, Vec<String>>,
+) -> PackResult<()> {
+    let bytes: Vec<u8> = std::fs::read(&entry.path).map_err(PackError::io(&entry.path))?;
+    let content_hash = content_hash(&bytes);
+    let aseprite = Aseprite::parse(&bytes).map_err(|message| PackError::Aseprite {
+        path: entry.path.clone(),
+        message,
+    })?;
+    let frames = aseprite.frames.into_iter().map(|e| (e.rgba, e.duration));
+    push_animation_frames(&entry, settings, content_hash, frames, loaded)?;
+    let name = entry.asset_path.ident();
+    for tag in aseprite.tags {
+        let frames = tag.frame_order().into_iter();
+        let frames = frames.map(|index| format!("{name}_{index}")).collect();
+        animation_tags.insert(format!("{name}_{}", tag.name.to_snake_case()), frames);
+    }
+    Ok(())
+}
+
+/// frame duration in milliseconds of gif frames with a delay of 0 or 10 ms
+const GIF_DEFAULT_DELAY_MS: u32 = 100;
+
+/// Turns the frames of an animated gif like `fire.gif` into images named `fire_0`, `fire_1`, .. that
+/// form the animation `fire`, with the delays of the gif as frame durations.
+fn load_gif_asset(
+    entry: GatheredEntry,
+    settings: &PackSettings,
+    loaded: &mut Vec<ImageAsset>,
+) -> PackResult<()> {
+    let bytes: Vec<u8> = std::fs::read(&entry.path).map_err(PackError::io(&entry.path))?;
+    let content_hash = content_hash(&bytes);
+    let decoder = GifDecoder::new(Cursor::new(&bytes)).map_err(PackError::image(&entry.path))?;
+    let frames: Vec<Frame> = decoder
+        .into_frames()
+        .collect_frames()
+        .map_err(PackError::image(&entry.path))?;
+    let frames = frames.into_iter().map(|frame| {
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        let delay = match numerator / denominator.max(1) {
+            // browsers play gifs without delays, or with the shortest ones, at this speed
+            delay if delay <= 10 => GIF_DEFAULT_DELAY_MS,
+            delay => delay,
+        };
+        (frame.into_buffer(), delay)
+    });
+    push_animation_frames(&entry, settings, content_hash, frames, loaded)
+}
+
+/// Adds the frames of an animation file to `loaded`, as images named after the file and the frame
+/// index that form an animation named after the file. `frames` are the pixels and the durations in
+/// milliseconds, `content_hash` the hash of the file. The flags, dir config and sidecar of the file
+/// apply to every frame.
+fn push_animation_frames(
+    entry: &GatheredEntry,
+    settings: &PackSettings,
+    content_hash: u64,
+    frames: impl Iterator<Item = (RgbaImage, u32)>,
+    loaded: &mut Vec<ImageAsset>,
+) -> PackResult<()> {
+    let name = entry.asset_path.ident().to_string();
+    for (index, (rgba, duration)) in frames.enumerate() {
+        let mut frame_entry = entry.clone();
+        *frame_entry.asset_path.segments.last_mut().unwrap() = format!("{name}_{index}");
+        let mut asset = image_asset(frame_entry, rgba, content_hash, settings, settings.scale)?;
+        asset.animation = Some((name.clone(), index as u32));
+        asset.frame_duration = Some(duration);
+        loaded.push(asset);
+    }
+    Ok(())
+}
+
+/// Makes a repeat image that almost tiles seamless along its repeat axes: it is cross-faded with a copy
+/// of itself shifted by half its size towards the edges, where the copy continues seamlessly into the
+/// opposite edge. The middle half of the image stays untouched.
+fn make_tiling(rgba: &mut RgbaImage, repeat_x: bool, repeat_y: bool) {
+    // weight of the shifted copy at `i` of `len`: 1 at the edges, fading out towards the center
+    let weight = |i: u32, len: u32| {
+        let band = (len as f32 / 4.0).max(1.0);
+        let distance = i.min(len - 1 - i) as f32;
+        let t = (1.0 - distance / band).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    let blend = |a: &Rgba<u8>, b: &Rgba<u8>, t: f32| {
+        Rgba(std::array::from_fn(|c| {
+            (a[c] as f32 * (1.0 - t) + b[c] as f32 * t).round() as u8
+        }))
+    };
+    let (w, h) = rgba.dimensions();
+    if repeat_x && w > 1 {
+        let source = rgba.clone();
+        for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+            let shifted = source.get_pixel((x + w / 2) % w, y);
+            *pixel = blend(source.get_pixel(x, y), shifted, weight(x, w));
+        }
+    }
+    if repeat_y && h > 1 {
+        let source = rgba.clone();
+        for (x, y, pixel) in rgba.enumerate_pixels_mut() {
+            let shifted = source.get_pixel(x, (y + h / 2) % h);
+            *pixel = blend(source.get_pixel(x, y), shifted, weight(y, h));
+        }
+    }
+}
+
+/// Parses flags like `12fps`.
+fn frames_per_second(flags: &str) -> Option<u32> {
+    flags
+        .strip_suffix("fps")?
+        .parse()
+        .ok()
+        .filter(|fps| *fps > 0)
+}
+
+/// Turns images without flags that are numbered like `run_0.png`, `run_1.png`, .. into the frames of
+/// an animation, if the numbers start at 0 and have no gaps. Unflagged images with the name of an
+/// animation flagged on other frames join it. Also gives all frames the fps of their animation.
+fn detect_animations(images: &mut HashMap<String, ImageAsset>) -> PackResult<()> {
+    let mut candidates: HashMap<String, Vec<(u32, String)>> = HashMap::new();
+    let mut flagged: HashMap<String, Option<u32>> = HashMap::new();
+    for (ident, asset) in images.iter() {
+        if let Some((name, _)) = &asset.animation {
+            let fps = flagged.entry(name.clone()).or_default();
+            match (*fps, asset.animation_fps) {
+                (Some(a), Some(b)) if a != b => {
+                    return Err(PackError::Animation {
+                        name: name.clone(),
+                        message: format!("its frames have different fps flags, {a}fps and {b}fps"),
+                    })
+                }
+                (None, b) => *fps = b,
+                _ => {}
+            }
+        } else if asset.entry.flags.flags.is_empty() {
+            let frame = ident
+                .rsplit_once('_')
+                .and_then(|(name, index)| Some((name.to_string(), index.parse().ok()?)));
+            if let Some((name, index)) = frame {
+                candidates
+                    .entry(name)
+                    .or_default()
+                    .push((index, ident.clone()));
+            }
+        }
+    }
+
+    for (name, mut frames) in candidates {
+        frames.sort();
+        let numbered = frames.len() >= 2 && frames.iter().enumerate().all(|(i, e)| e.0 == i as u32);
+        if !numbered && !flagged.contains_key(&name) {
+            continue;
+        }
+        if !flagged.contains_key(&name) {
+            println!(
+                "    animation: {name} from {} numbered images",
+                frames.len()
+            );
+        }
+        for (index, ident) in frames {
+            images.get_mut(&ident).unwrap().animation = Some((name.clone(), index));
+        }
+    }
+    for asset in images.values_mut() {
+        if let Some((name, _)) = &asset.animation {
+            asset.animation_fps = flagged.get(name).copied().flatten();
+        }
+    }
+    Ok(())
+}
+
+/// Draw layer of the images of `entry` from its flags, `bg` is -1, `fg` is 1 and `layer=3` any
+/// layer, or from its dir config.
+fn draw_layer(entry: &GatheredEntry) -> Option<i32> {
+    let flags = entry.all_flags();
+    if let Some(layer) = flags.value("layer").and_then(|e| e.parse().ok()) {
+        return Some(layer);
+    }
+    let mut layer = entry.dir_config.layer;
+    for flag in flags.flags.iter() {
+        match flag.as_str() {
+            "bg" => layer = Some(-1),
+            "fg" => layer = Some(1),
+            _ => {}
+        }
+    }
+    layer
+}
+
+/// Reads the sidecar of the image or font at `path`, the default if there is none.
+fn read_sidecar<T: DeserializeOwned + Default>(path: &Path) -> PackResult<T> {
+    let mut meta_path = path.as_os_str().to_owned();
+    meta_path.push(format!(".{SIDECAR_EXTENSION}"));
+    for sidecar_path in [PathBuf::from(meta_path), path.with_extension("json")] {
+        if let Ok(bytes) = std::fs::read(&sidecar_path) {
+            return serde_json::from_slice(&bytes).map_err(PackError::json(&sidecar_path));
+        }
+    }
+    Ok(T::default())
+}
+
+/// Reads the borders from the guides of a nine patch: black pixels in the outermost row mark the
+/// columns that stretch, black pixels in the outermost column the rows. Returns the image without
+/// the guides, `None` if the guides are missing.
+fn nine_slice_guides(rgba: &RgbaImage) -> Option<(RgbaImage, NineSlice)> {
+    let (w, h) = rgba.dimensions();
+    if w < 3 || h < 3 {
+        return None;
+    }
+    let is_guide = |x: u32, y: u32| {
+        let p = rgba.get_pixel(x, y);
+        p[3] == u8::MAX && p[0] == 0 && p[1] == 0 && p[2] == 0
+    };
+    let columns: Vec<u32> = (1..w - 1).filter(|x| is_guide(*x, 0)).collect();
+    let rows: Vec<u32> = (1..h - 1).filter(|y| is_guide(0, *y)).collect();
+    let borders = NineSlice {
+        left: columns.first()? - 1,
+        right: w - 2 - columns.last()?,
+        top: rows.first()? - 1,
+        bottom: h - 2 - rows.last()?,
+    };
+    let stripped = image::imageops::crop_imm(rgba, 1, 1, w - 2, h - 2).to_image();
+    Some((stripped, borders))
+}
+
+/// Resizes an image by `scale`, integer upscales repeat the pixels so pixel art stays crisp.
+fn scale_image(rgba: &RgbaImage, scale: f32) -> RgbaImage {
+    let (w, h) = rgba.dimensions();
+    let scaled = |size: u32| (size as f32 * scale).round().max(1.0) as u32;
+    let filter = match scale.fract() == 0.0 && scale >= 1.0 {
+        true => FilterType::Nearest,
+        false => FilterType::Lanczos3,
+    };
+    image::imageops::resize(rgba, scaled(w), scaled(h), filter)
+}
+
+/// Multiplies the colors by the alpha, in the encoded srgb values like most renderers blend them.
+fn premultiply(rgba: &mut RgbaImage) {
+    for pixel in rgba.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for c in 0..3 {
+            pixel[c] = ((pixel[c] as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+/// The alpha of an image with transparent pixels, otherwise its luminance, as gray opaque pixels.
+fn mask_to_gray(rgba: &RgbaImage) -> RgbaImage {
+    let use_alpha = rgba.pixels().any(|p| p[3] != u8::MAX);
+    let luma = DynamicImage::ImageRgba8(rgba.clone()).to_luma8();
+    RgbaImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let value = match use_alpha {
+            true => rgba.get_pixel(x, y)[3],
+            false => luma.get_pixel(x, y)[0],
+        };
+        Rgba([value, value, value, u8::MAX])
+    })
+}
+
+/// The sprite `name` of a static text of the settings, rendered with its font or the default font.
+fn static_text_asset(
+    dir: &str,
+    name: &str,
+    text: &StaticText,
+    fonts: &HashMap<String, FontAsset>,
+    settings: &PackSettings,
+) -> PackResult<ImageAsset> {
+    let font = match &text.font {
+        Some(font) => fonts.get(font),
+        None => fonts
+            .values()
+            .find(|e| e.is_default)
+            .or_else(|| match fonts.len() {
+                1 => fonts.values().next(),
+                _ => None,
+            }),
+    };
+    let font = font.ok_or_else(|| PackError::Config {
+        path: PathBuf::from(format!("{dir}/{CONFIG_FILE_NAME}")),
+        message: match &text.font {
+            Some(font) => {
+                format!("the text {name} needs the font {font}, which is not in the fonts dir")
+            }
+            None => format!("the text {name} needs a font, there is no default font"),
+        },
+    })?;
+    let mut rgba = render_static_text(font, text, settings.scale)?;
+    if settings.premultiply_alpha {
+        premultiply(&mut rgba);
     }
+    let mut hasher = ContentHasher::new();
+    hasher.write(&font.content_hash.to_le_bytes());
+    hasher.write(serde_json::to_string(text).unwrap().as_bytes());
+    let mut asset_path = AssetPath::new();
+    asset_path.segments.push(name.to_string());
+    Ok(ImageAsset {
+        rgba,
+        entry: GatheredEntry {
+            asset_path,
+            path: font.entry.path.clone(),
+            flags: FileFlags::default(),
+            extension: font.entry.extension.clone(),
+            dir_config: DirConfig::default(),
+        },
+        content_hash: hasher.finish(),
+        indexed: false,
+        repeat_x: false,
+        repeat_y: false,
+        no_pack: false,
+        animation: None,
+        linear: false,
+        mask: false,
+        premultiplied: settings.premultiply_alpha,
+        nine_slice: None,
+        pivot: None,
+        animation_fps: None,
+        frame_duration: None,
+        streaming_priority: None,
+        sampler: Sampler::default(),
+        layer: None,
+        tags: vec![],
+        user_data: BTreeMap::new(),
+    })
+}
+
+/// The font with its identifier, or the selected faces of a collection, named like `noto_1` if
+/// there are several, see [`FontSidecar`]. Only the first face of a collection flagged as default
+/// is the default font.
+fn load_font_assets(
+    entry: GatheredEntry,
+    cache: &mut Cache,
+) -> PackResult<Vec<(String, FontAsset)>> {
+    let bytes: Vec<u8> = std::fs::read(&entry.path).map_err(PackError::io(&entry.path))?;
+    let content_hash = content_hash(&bytes);
+    cache.record_file(&entry.path, content_hash);
+    let is_default = entry.flags.contains("default");
+    let ident = entry.asset_path.ident().to_owned();
+    if entry.extension != "ttc" {
+        let asset = FontAsset {
+            bytes,
+            content_hash,
+            entry,
+            is_default,
+            face_index: 0,
+            charset: None,
+            distance_field: DistanceField::Sdf,
+        };
+        return Ok(vec![(ident, asset)]);
+    }
+
+    let font_error = |message: String| PackError::Font {
+        path: entry.path.clone(),
+        message,
+    };
+    let face_count = ttf_parser::fonts_in_collection(&bytes)
+        .ok_or_else(|| font_error(String::from("not a font collection")))?;
+    let faces: Vec<u32> = match entry.flags.value("faces") {
+        Some(faces) => faces
+            .split(',')
+            .map(|e| e.trim().parse::<u32>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| {
+                font_error(format!(
+                    "invalid faces flag {faces}, expected like faces=0,2"
+                ))
+            })?,
+        None => read_sidecar::<FontSidecar>(&entry.path)?.faces,
+    };
+    let faces: Vec<u32> = match faces.is_empty() {
+        true => (0..face_count).collect(),
+        false => faces,
+    };
+    if let Some(face) = faces.iter().find(|e| **e >= face_count) {
+        return Err(font_error(format!(
+            "the collection has {face_count} faces, there is no face {face}"
+        )));
+    }
+    let assets = faces
+        .iter()
+        .enumerate()
+        .map(|(i, face_index)| {
+            let name = match faces.len() {
+                1 => ident.clone(),
+                _ => format!("{ident}_{face_index}"),
+            };
+            let asset = FontAsset {
+                bytes: bytes.clone(),
+                content_hash,
+                entry: entry.clone(),
+                is_default: is_default && i == 0,
+                face_index: *face_index,
+                charset: None,
+                distance_field: DistanceField::Sdf,
+            };
+            (name, asset)
+        })
+        .collect();
+    Ok(assets)
+}
+
+/// The characters the font `name` is generated with instead of the [`crate::font::ALPHABET`], from
+/// the `chars=` flag of its file like `chars=ascii,latin1` or its charset in the settings. `None`
+/// for the alphabet.
+fn font_charset(
+    dir: &str,
+    name: &str,
+    entry: &GatheredEntry,
+    settings: &PackSettings,
+) -> PackResult<Option<Vec<char>>> {
+    let flag_charset: FontCharset;
+    let charset = match (entry.flags.value("chars"), settings.font.charsets.get(name)) {
+        (Some(ranges), _) => {
+            flag_charset = FontCharset {
+                ranges: ranges.split(',').map(String::from).collect(),
+                ..Default::default()
+            };
+            &flag_charset
+        }
+        (None, Some(charset)) => charset,
+        (None, None) => return Ok(None),
+    };
+    let chars = charset.chars(dir).map_err(|message| PackError::Font {
+        path: entry.path.clone(),
+        message,
+    })?;
+    Ok(Some(chars))
+}
+
+fn load_icon_asset(entry: GatheredEntry, cache: &mut Cache) -> PackResult<IconAsset> {
+    let text: String = std::fs::read_to_string(&entry.path).map_err(PackError::io(&entry.path))?;
+    let content_hash = content_hash(text.as_bytes());
+    cache.record_file(&entry.path, content_hash);
+    let svg = Svg::parse(&text).map_err(|message| PackError::Svg {
+        path: entry.path.clone(),
+        message,
+    })?;
+    Ok(IconAsset {
+        svg,
+        content_hash,
+        entry,
+    })
 }
 
 // fn collect_images(assets_dir: &str) -> HashMap<HashMap<String, ImageAsset>>{
 
 // }
 
-fn gather_dir_entries(dir: &str, f: &mut dyn FnMut(GatheredEntry)) {
-    _gather_dir_entries(dir, AssetPath::new(), f);
+/// Calls `f` for every file in `dir` and its sub directories that is not ignored, with the
+/// extension it is read as, see [`PackSettings::extensions`]. A missing `dir` is treated as empty.
+fn gather_dir_entries(
+    dir: &str,
+    ignore: &IgnoreRules,
+    settings: &PackSettings,
+    f: &mut dyn FnMut(GatheredEntry) -> PackResult<()>,
+) -> PackResult<SkippedEntries> {
+    let mut walk = DirWalk {
+        follow_symlinks: settings.follow_symlinks,
+        extensions: &settings.extensions,
+        walking: vec![],
+        skipped: SkippedEntries::default(),
+        f,
+    };
+    let config = DirConfig::default();
+    _gather_dir_entries(Path::new(dir), AssetPath::new(), &config, ignore, &mut walk)?;
+    Ok(walk.skipped)
 }
 
-fn _gather_dir_entries(dir: &str, asset_path: AssetPath, f: &mut dyn FnMut(GatheredEntry)) {
-    let Ok(dir) = std::fs::read_dir(dir) else {
-        return;
+/// Files and dirs that [`gather_dir_entries`] does not pass on.
+#[derive(Debug, Default)]
+struct SkippedEntries {
+    /// the ignored files and dirs, which include the symlinks that are not followed, see
+    /// [`PackSettings::follow_symlinks`], and symlinks to a dir they are in
+    ignored: Vec<PathBuf>,
+    /// ignore files under their old name, see [`DEPRECATED_IGNORE_FILE_NAME`]
+    deprecated_ignore_files: Vec<PathBuf>,
+}
+
+/// What [`gather_dir_entries`] passes down into the sub dirs.
+struct DirWalk<'a> {
+    follow_symlinks: bool,
+    /// see [`PackSettings::extensions`]
+    extensions: &'a BTreeMap<String, String>,
+    /// canonical paths of the dir and the dirs it is in, a symlink to one of them would loop forever
+    walking: Vec<PathBuf>,
+    skipped: SkippedEntries,
+    f: &'a mut dyn FnMut(GatheredEntry) -> PackResult<()>,
+}
+
+fn _gather_dir_entries(
+    dir: &Path,
+    asset_path: AssetPath,
+    parent_config: &DirConfig,
+    ignore: &IgnoreRules,
+    walk: &mut DirWalk,
+) -> PackResult<()> {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return Ok(());
     };
-    for entry in dir {
-        let entry = entry.unwrap();
-        let meta = entry.metadata().unwrap();
-        let file_name = entry.file_name().into_string().unwrap();
+    let config = DirConfig::load(dir, parent_config)?;
+    let ignore = ignore.with_dir(dir)?;
+    let canonical = dir.canonicalize().map_err(PackError::io(dir))?;
+    walk.walking.push(canonical);
+    for entry in read_dir {
+        let entry = entry.map_err(PackError::io(dir))?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if IgnoreRules::is_ignore_file(&file_name) {
+            if file_name == DEPRECATED_IGNORE_FILE_NAME {
+                walk.skipped.deprecated_ignore_files.push(entry.path());
+            }
+            continue;
+        }
+        // symlinks and junctions are followed to the file or dir they point to
+        let is_symlink = entry.file_type().is_ok_and(|e| e.is_symlink());
+        if is_symlink && !walk.follow_symlinks {
+            walk.skipped.ignored.push(entry.path());
+            continue;
+        }
+        let meta = std::fs::metadata(entry.path()).map_err(PackError::io(entry.path()))?;
+        if ignore.is_ignored(&entry.path(), meta.is_dir()) {
+            walk.skipped.ignored.push(entry.path());
+            continue;
+        }
         let mut asset_path = asset_path.clone();
         if meta.is_dir() {
+            let target = entry
+                .path()
+                .canonicalize()
+                .map_err(PackError::io(entry.path()))?;
+            if is_symlink && walk.walking.contains(&target) {
+                walk.skipped.ignored.push(entry.path());
+                continue;
+            }
             let file_name = file_name.to_snake_case();
             asset_path.segments.push(file_name);
-            _gather_dir_entries(entry.path().to_str().unwrap(), asset_path, f);
+            _gather_dir_entries(&entry.path(), asset_path, &config, &ignore, walk)?;
         } else {
-            let mut split = file_name.split('.');
-            let file_name = split.next().unwrap().to_snake_case();
-            let (flags, ending) = {
-                let e1 = split.next().unwrap();
-                let e2 = split.next();
-                if let Some(e2) = e2 {
-                    (e1, e2)
-                } else {
-                    ("", e1)
-                }
+            let mut split: Vec<&str> = file_name.split('.').collect();
+            let name = split.remove(0).to_snake_case();
+            let ending = split.pop().unwrap_or_default();
+            let ending = walk.extensions.get(ending).map_or(ending, String::as_str);
+            // sidecars like `hero.bottom.png.meta` belong to the image, without flags of their own
+            let flags = match ending == SIDECAR_EXTENSION {
+                true => FileFlags::default(),
+                false => FileFlags::parse(&split),
             };
-            asset_path.segments.push(file_name);
+            asset_path.segments.push(name);
             let entry = GatheredEntry {
                 asset_path,
                 path: entry.path(),
-                flags: flags.to_owned(),
+                flags,
                 extension: ending.to_owned(),
+                dir_config: config.clone(),
             };
-            f(entry);
+            (walk.f)(entry)?;
         }
     }
+    walk.walking.pop();
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
 pub struct GatheredEntry {
     pub asset_path: AssetPath,
     pub path: PathBuf,
-    pub flags: String, // before extension, e.g. "rep" for background.rep.png
+    pub flags: FileFlags,
     pub extension: String,
+    /// options of the dir of the file and its parents, see [`DirConfig`]
+    pub dir_config: DirConfig,
+}
+
+impl GatheredEntry {
+    /// The flags of the [`DirConfig`] followed by the ones of the file name, which override values
+    /// of the same key.
+    pub fn all_flags(&self) -> FileFlags {
+        let mut flags = FileFlags::default();
+        for flag in self.dir_config.flags.iter() {
+            flags.push(flag);
+        }
+        flags.flags.extend(self.flags.flags.iter().cloned());
+        flags.values.extend(self.flags.values.clone());
+        flags
+    }
+}
+
+/// The flags between the name and the extension of a file, like `repx`, `linear` and `pad=8` of
+/// `bg.repx.linear.pad=8.png`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileFlags {
+    /// flags without a value, in the order of the file name
+    pub flags: Vec<String>,
+    /// flags like `pad=8`, by key
+    pub values: BTreeMap<String, String>,
+}
+
+impl FileFlags {
+    pub fn parse(flags: &[&str]) -> FileFlags {
+        let mut parsed = FileFlags::default();
+        for flag in flags {
+            parsed.push(flag);
+        }
+        parsed
+    }
+
+    /// Adds `flag`, a later value of the same key replaces the earlier one.
+    pub fn push(&mut self, flag: &str) {
+        match flag.split_once('=') {
+            Some((key, value)) => {
+                self.values.insert(key.to_string(), value.to_string());
+            }
+            None if !flag.is_empty() => self.flags.push(flag.to_string()),
+            None => {}
+        }
+    }
+
+    pub fn contains(&self, flag: &str) -> bool {
+        self.flags.iter().any(|e| e == flag)
+    }
+
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flags.is_empty() && self.values.is_empty()
+    }
+}
+
+impl Display for FileFlags {
+    /// The flags joined like in a file name, `repx.linear.pad=8`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let values = self
+            .values
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"));
+        let flags: Vec<String> = self.flags.iter().cloned().chain(values).collect();
+        f.write_str(&flags.join("."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::gather_assets;
+    use crate::{
+        cache::Cache,
+        pack::{FilterMode, PackSettings},
+        testing::TempDir,
+    };
+
+    #[test]
+    fn animation_frames_get_the_flags_and_dir_config_of_their_file() {
+        let dir = TempDir::new("animation_frames_get_the_flags_and_dir_config_of_their_file");
+        dir.write("src/images/pixel/dir.json", r#"{ "filter": "nearest" }"#);
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255]];
+        dir.write_gif("src/images/pixel/fire.linear.gif", (4, 4), &colors, 100);
+        let mut cache = Cache::load(dir.path("cache")).unwrap();
+        let gathered =
+            gather_assets(&dir.path("src"), &PackSettings::default(), &mut cache).unwrap();
+
+        for frame in ["fire_0", "fire_1"] {
+            let asset = &gathered.images[frame];
+            assert_eq!(asset.sampler.filter, FilterMode::Nearest);
+            assert!(asset.linear);
+            assert_eq!(asset.frame_duration, Some(100));
+        }
+    }
+
+    #[test]
+    fn animation_frames_get_the_sidecar_of_their_file() {
+        let dir = TempDir::new("animation_frames_get_the_sidecar_of_their_file");
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255]];
+        dir.write_gif("src/images/fire.gif", (4, 4), &colors, 100);
+        dir.write(
+            "src/images/fire.gif.meta",
+            r#"{ "pivot": "center", "tags": ["hazard"], "user_data": { "damage": 3 }, "priority": 2 }"#,
+        );
+        let mut cache = Cache::load(dir.path("cache")).unwrap();
+        let gathered =
+            gather_assets(&dir.path("src"), &PackSettings::default(), &mut cache).unwrap();
+
+        for frame in ["fire_0", "fire_1"] {
+            let asset = &gathered.images[frame];
+            assert_eq!(asset.pivot, Some(glam::vec2(2.0, 2.0)));
+            assert_eq!(asset.tags, ["hazard"]);
+            assert_eq!(asset.user_data["damage"], "3");
+            assert_eq!(asset.streaming_priority, Some(2));
+        }
+    }
+
+    #[test]
+    fn gif_frames_without_delay_get_the_default_duration() {
+        let dir = TempDir::new("gif_frames_without_delay_get_the_default_duration");
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255]];
+        dir.write_gif("src/images/blink.gif", (4, 4), &colors, 0);
+        let mut cache = Cache::load(dir.path("cache")).unwrap();
+        let gathered =
+            gather_assets(&dir.path("src"), &PackSettings::default(), &mut cache).unwrap();
+        assert_eq!(gathered.images["blink_0"].frame_duration, Some(100));
+    }
+
+    #[test]
+    fn ignore_files_of_sub_dirs_apply_under_both_names() {
+        let dir = TempDir::new("ignore_files_of_sub_dirs_apply_under_both_names");
+        for file in [
+            "ui/button",
+            "ui/wip_button",
+            "props/crate",
+            "props/old_crate",
+        ] {
+            dir.write_png(&format!("src/images/{file}.png"), (4, 4), [255, 0, 0, 255]);
+        }
+        dir.write("src/images/ui/.assetpackerignore", "wip_*");
+        dir.write("src/images/props/.assetignore", "old_*");
+        let mut cache = Cache::load(dir.path("cache")).unwrap();
+        let gathered =
+            gather_assets(&dir.path("src"), &PackSettings::default(), &mut cache).unwrap();
+
+        let mut names: Vec<&str> = gathered.images.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, ["button", "crate"]);
+        assert_eq!(gathered.ignored_files.len(), 2);
+    }
 }
@@ -0,0 +1,214 @@
+use std::{collections::HashMap, fmt::Write, path::Path};
+
+use crate::{
+    error::PackResult,
+    gather::{excluded_files, GatheredAssets},
+    pack::{PackSettings, PackedAssets, ICON_FONT_NAME},
+};
+
+/// file in the output dir that the graph of a run is written to
+pub const PIPELINE_GRAPH_FILE_NAME: &str = "pipeline.dot";
+
+/// Describes a pack run as a graphviz graph: every source file, the step that processed it, the
+/// tiles and fonts it turned into and the files of the pack those ended up in. Files left out for
+/// the target platform are shown without edges. Render it with `dot -Tsvg pipeline.dot`.
+pub fn pipeline_graph(
+    src_dir: &str,
+    gathered: &GatheredAssets,
+    packed: &PackedAssets,
+    settings: &PackSettings,
+) -> PackResult<String> {
+    let source = |path: &Path| {
+        let relative = path.strip_prefix(src_dir).unwrap_or(path);
+        relative.to_string_lossy().into_owned()
+    };
+    let mut out = String::new();
+    out.push_str("// generated by assetpacker\ndigraph pipeline {\n    rankdir=LR;\n");
+    out.push_str("    node [shape=box, fontname=\"monospace\"];\n\n");
+
+    // sources -> tiles -> textures
+    let mut images: Vec<(&String, _)> = gathered.images.iter().collect();
+    images.sort_by_key(|(name, _)| *name);
+    let mut textures_with_tiles: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (name, asset) in images {
+        let src = source(&asset.entry.path);
+        node(&mut out, &format!("src:{src}"), &src, "note");
+        let (step, tiles): (&str, Vec<&String>) = match packed.splits.get(name) {
+            Some(split) => ("split", split.tiles.iter().collect()),
+            None if asset.no_pack => ("own texture", vec![name]),
+            None if asset.has_own_repeat_texture() => ("own repeat texture", vec![name]),
+            None if asset.repeat_x || asset.repeat_y => ("repeat strip", vec![name]),
+            None => ("atlas packing", vec![name]),
+        };
+        for tile_name in tiles {
+            let Some(tile) = packed.tiles.get(tile_name) else {
+                continue;
+            };
+            let mut notes: Vec<&str> = vec![];
+            if tile.trim.is_some() {
+                notes.push("trimmed");
+            }
+            if tile.rotated {
+                notes.push("rotated");
+            }
+            let label = match notes.is_empty() {
+                true => tile_name.to_string(),
+                false => format!("{tile_name} ({})", notes.join(", ")),
+            };
+            node(&mut out, &format!("tile:{tile_name}"), &label, "ellipse");
+            edge(
+                &mut out,
+                &format!("src:{src}"),
+                &format!("tile:{tile_name}"),
+                step,
+            );
+            textures_with_tiles
+                .entry(tile.atlas.as_str())
+                .or_default()
+                .push(tile_name);
+        }
+    }
+    let manifest_file = settings.manifest_format.file_name();
+    let manifest_node = format!("out:{manifest_file}");
+    for (texture, flags) in packed.textures.iter() {
+        let file = format!("{texture}.{}", flags.format.extension());
+        node(
+            &mut out,
+            &format!("out:{file}"),
+            packed.file_name(&file),
+            "box3d",
+        );
+        edge(&mut out, &format!("out:{file}"), &manifest_node, "");
+        let mut tiles = textures_with_tiles
+            .remove(texture.as_str())
+            .unwrap_or_default();
+        tiles.sort();
+        for tile in tiles {
+            edge(
+                &mut out,
+                &format!("tile:{tile}"),
+                &format!("out:{file}"),
+                "",
+            );
+        }
+        for level in 1..=packed.mip_levels.get(texture).copied().unwrap_or(0) {
+            if flags.format.is_image_file() {
+                let mip = format!("{texture}.mip{level}.{}", flags.format.extension());
+                node(
+                    &mut out,
+                    &format!("out:{mip}"),
+                    packed.file_name(&mip),
+                    "box3d",
+                );
+                edge(
+                    &mut out,
+                    &format!("out:{file}"),
+                    &format!("out:{mip}"),
+                    "mip level",
+                );
+            }
+        }
+    }
+
+    // sources -> font files
+    let mut fonts: Vec<(&String, _)> = gathered.fonts.iter().collect();
+    fonts.sort_by_key(|(name, _)| *name);
+    let mut icons: Vec<(&String, _)> = gathered.icons.iter().collect();
+    icons.sort_by_key(|(name, _)| *name);
+    let font_sources = fonts
+        .into_iter()
+        .map(|(name, font)| (name.as_str(), &font.entry.path, "sdf font"))
+        .chain(
+            icons
+                .into_iter()
+                .map(|(_, icon)| (ICON_FONT_NAME, &icon.entry.path, "icon font")),
+        );
+    for (font, path, step) in font_sources {
+        let src = source(path);
+        node(&mut out, &format!("src:{src}"), &src, "note");
+        for file in [
+            format!("{font}.sdf_font.json"),
+            format!("{font}.sdf_font.png"),
+        ] {
+            node(
+                &mut out,
+                &format!("out:{file}"),
+                packed.file_name(&file),
+                "box3d",
+            );
+            edge(
+                &mut out,
+                &format!("src:{src}"),
+                &format!("out:{file}"),
+                step,
+            );
+        }
+    }
+    for font in packed.fonts.iter() {
+        edge(
+            &mut out,
+            &format!("out:{font}.sdf_font.json"),
+            &manifest_node,
+            "",
+        );
+    }
+
+    // the manifest and what is generated from it
+    node(&mut out, &manifest_node, manifest_file, "box3d");
+    if settings.rust_codegen {
+        node(&mut out, "out:assets.rs", "assets.rs", "box3d");
+        edge(&mut out, &manifest_node, "out:assets.rs", "rust codegen");
+    }
+    if let Some(language) = settings.script_export {
+        let file = language.file_name();
+        node(&mut out, &format!("out:{file}"), file, "box3d");
+        edge(
+            &mut out,
+            &manifest_node,
+            &format!("out:{file}"),
+            "script export",
+        );
+    }
+
+    let excluded = excluded_files(src_dir, settings)?;
+    if !excluded.is_empty() {
+        let platform = settings.platform.as_deref().unwrap_or_default();
+        out.push_str("\n    // left out for the target platform\n");
+        for path in excluded {
+            let src = source(&path);
+            let label = format!("{src}\\nexcluded for {platform}");
+            writeln!(
+                out,
+                "    {} [label={}, shape=note, style=dashed, fontcolor=gray];",
+                quoted(&format!("src:{src}")),
+                quoted(&label)
+            )
+            .unwrap();
+        }
+    }
+    out.push_str("}\n");
+    Ok(out)
+}
+
+fn node(out: &mut String, id: &str, label: &str, shape: &str) {
+    writeln!(
+        out,
+        "    {} [label={}, shape={shape}];",
+        quoted(id),
+        quoted(label)
+    )
+    .unwrap();
+}
+
+fn edge(out: &mut String, from: &str, to: &str, label: &str) {
+    write!(out, "    {} -> {}", quoted(from), quoted(to)).unwrap();
+    if !label.is_empty() {
+        write!(out, " [label={}]", quoted(label)).unwrap();
+    }
+    out.push_str(";\n");
+}
+
+/// double quoted dot id, backslashes are kept so labels can contain `\n`
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\\\""))
+}
@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::{PackError, PackResult};
+
+/// File that the source dir and any dir in it can have, with additional patterns of files below
+/// it that the packer ignores, one per line.
+pub const IGNORE_FILE_NAME: &str = ".assetpackerignore";
+
+/// Deprecated name of the [`IGNORE_FILE_NAME`] files, still read after them in the same dir.
+pub const DEPRECATED_IGNORE_FILE_NAME: &str = ".assetignore";
+
+/// Files that are never assets, but end up in asset trees anyway: hidden files and dirs like
+/// `.DS_Store`, the `._*` resource forks macOS writes on foreign file systems or `.git`, files of
+/// the windows explorer and backup, swap and lock files of editors.
+pub const BUILTIN_IGNORE_PATTERNS: [&str; 9] = [
+    ".*",
+    "Thumbs.db",
+    "desktop.ini",
+    "*~",
+    "~$*",
+    "#*#",
+    "*.swp",
+    "*.bak",
+    "*.tmp",
+];
+
+/// Which files and dirs of the source dir the packer skips, the [`BUILTIN_IGNORE_PATTERNS`]
+/// followed by the lines of the [`IGNORE_FILE_NAME`] files of the source dir and the dirs above
+/// the file.
+///
+/// Like in a `.gitignore`, `*` matches any characters but `/`, `?` one of them and `**` any
+/// number of dirs, like in `**/wip` or `sources/**`. The last matching pattern decides, so
+/// `!*.bak` packs backup files again. Patterns with a `/` match the path relative to the dir of
+/// their ignore file like `images/wip/*`, the others the name of any file or dir below it.
+/// Patterns ending in `/` only match dirs. Lines starting with `#` are comments.
+#[derive(Debug, Clone)]
+pub struct IgnoreRules {
+    src_dir: PathBuf,
+    patterns: Vec<IgnorePattern>,
+}
+
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    /// dir of the ignore file relative to the source dir, like `images/ui/`, empty for the source dir
+    base: String,
+    pattern: Vec<char>,
+    /// matched against the relative path instead of the name
+    anchored: bool,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl IgnoreRules {
+    /// Reads the ignore files of `src_dir`, just the built-in patterns if there are none.
+    pub fn load(src_dir: &str) -> PackResult<IgnoreRules> {
+        let mut rules = IgnoreRules {
+            src_dir: PathBuf::from(src_dir),
+            patterns: vec![],
+        };
+        for pattern in BUILTIN_IGNORE_PATTERNS {
+            rules.push(String::new(), pattern);
+        }
+        rules.read_files(Path::new(src_dir))?;
+        Ok(rules)
+    }
+
+    /// These rules with the patterns of the [`IGNORE_FILE_NAME`] file in `dir`, a dir below the
+    /// source dir, for the files in it.
+    pub fn with_dir(&self, dir: &Path) -> PackResult<IgnoreRules> {
+        let mut rules = self.clone();
+        if dir != self.src_dir {
+            rules.read_files(dir)?;
+        }
+        Ok(rules)
+    }
+
+    fn read_files(&mut self, dir: &Path) -> PackResult<()> {
+        for file_name in [IGNORE_FILE_NAME, DEPRECATED_IGNORE_FILE_NAME] {
+            let path = dir.join(file_name);
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(PackError::io(path)(e)),
+            };
+            self.push_lines(dir, &text);
+        }
+        Ok(())
+    }
+
+    fn push_lines(&mut self, dir: &Path, text: &str) {
+        let base = match self.relative(dir) {
+            relative if relative.is_empty() => relative,
+            relative => format!("{relative}/"),
+        };
+        for line in text.lines().map(str::trim) {
+            if !line.is_empty() && !line.starts_with('#') {
+                self.push(base.clone(), line);
+            }
+        }
+    }
+
+    fn push(&mut self, base: String, line: &str) {
+        let (pattern, negated) = match line.strip_prefix('!') {
+            Some(pattern) => (pattern, true),
+            None => (line, false),
+        };
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        self.patterns.push(IgnorePattern {
+            base,
+            pattern: pattern.trim_start_matches('/').chars().collect(),
+            anchored,
+            negated,
+            dir_only,
+        });
+    }
+
+    fn relative(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.src_dir).unwrap_or(path);
+        relative.to_string_lossy().replace('\\', "/")
+    }
+
+    /// Whether the file or dir at `path` in the source dir is ignored, the contents of ignored dirs
+    /// are not visited at all.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let relative = self.relative(path);
+        let name = relative.rsplit('/').next().unwrap_or_default();
+        let mut ignored = false;
+        for pattern in self.patterns.iter() {
+            if pattern.dir_only && !is_dir {
+                continue;
+            }
+            let Some(below_base) = relative.strip_prefix(pattern.base.as_str()) else {
+                continue;
+            };
+            let text = match pattern.anchored {
+                true => below_base,
+                false => name,
+            };
+            let text: Vec<char> = text.chars().collect();
+            if glob_match(&pattern.pattern, &text) {
+                ignored = !pattern.negated;
+            }
+        }
+        ignored
+    }
+
+    /// Whether `file_name` is the name of an ignore file, which is not an asset itself.
+    pub fn is_ignore_file(file_name: &str) -> bool {
+        file_name == IGNORE_FILE_NAME || file_name == DEPRECATED_IGNORE_FILE_NAME
+    }
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match (pattern.first(), text.first()) {
+        (None, None) => true,
+        // `**/` also matches no dir at all
+        (Some('*'), _) if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            (rest.first() == Some(&'/') && glob_match(&rest[1..], text))
+                || glob_match(rest, text)
+                || (!text.is_empty() && glob_match(pattern, &text[1..]))
+        }
+        (Some('*'), _) => {
+            glob_match(&pattern[1..], text)
+                || (text.first().is_some_and(|c| *c != '/') && glob_match(pattern, &text[1..]))
+        }
+        (Some('?'), Some(c)) if *c != '/' => glob_match(&pattern[1..], &text[1..]),
+        (Some(p), Some(c)) if p == c => glob_match(&pattern[1..], &text[1..]),
+        _ => false,
+    }
+}
@@ -0,0 +1,448 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    archive::{with_suffix, write_archive, ArchiveFormat},
+    cache::{Cache, ContentHasher},
+    error::{PackError, PackResult},
+    gather::{gather_assets, input_fingerprint, GatheredAssets},
+    graph::{pipeline_graph, PIPELINE_GRAPH_FILE_NAME},
+    pack::{pack_assets, PackSettings, PackedAssets},
+    stats::{append_to_history, PackStats},
+    warnings::{WarningCode, Warnings},
+};
+
+/// How far a [`PackJob`] got after a step.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    Working {
+        /// what the next step starts with, like `gathering assets` or `packing bundle ui`
+        stage: String,
+        /// estimate of the finished part of the job, between 0 and 1. It can go down once the
+        /// assets are gathered and the number of bundles is known.
+        fraction: f32,
+    },
+    Done,
+    /// stopped by its [`CancelToken`], see [`PackJob::cancel`]
+    Cancelled,
+}
+
+/// Cancels a [`PackJob`] from another thread, like a daemon that shuts down or a watcher that saw a
+/// newer change, see [`PackJob::cancel_token`].
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// The job stops before its next stage.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A pack that runs a few stages at a time, so an editor can pack on its main thread between frames:
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// let mut job = assetpacker::Packer::new("assets").pack_in_steps("packed")?;
+/// while let assetpacker::job::Progress::Working { stage, .. } = job.step(Duration::from_millis(8))? {
+///     println!("{stage}");
+///     // draw a frame of the editor
+/// }
+/// # Ok::<(), assetpacker::error::PackError>(())
+/// ```
+///
+/// The stages are checking the fingerprint of the inputs, gathering the assets, packing them and
+/// each of their bundles, and writing the stats and archive, for the pack and each of its variants.
+/// A stage is never interrupted, so a step can take longer than its budget when a single stage,
+/// like gathering a large source dir, does.
+pub struct PackJob {
+    pipeline: Pipeline,
+    cache: Cache,
+    cancel: CancelToken,
+}
+
+impl PackJob {
+    pub(crate) fn new(
+        src_dir: &str,
+        dest_dir: &str,
+        settings: &PackSettings,
+        cache: Cache,
+    ) -> Self {
+        PackJob {
+            pipeline: Pipeline::new(src_dir, dest_dir, settings),
+            cache,
+            cancel: CancelToken::default(),
+        }
+    }
+
+    /// A token that cancels the job from another thread, before the stage after the one running.
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Stops the job without running further stages. A pack that was partly written is removed,
+    /// the previous files of packs that did not start writing are kept.
+    pub fn cancel(&mut self) {
+        self.cancel.cancel();
+        self.pipeline.cancel(&mut self.cache);
+    }
+
+    /// Runs stages until `budget` is used up, at least one. After an error the job is done and
+    /// further steps return [`Progress::Done`], after it was cancelled [`Progress::Cancelled`].
+    pub fn step(&mut self, budget: Duration) -> PackResult<Progress> {
+        let start = Instant::now();
+        loop {
+            if self.cancel.is_cancelled() {
+                self.pipeline.cancel(&mut self.cache);
+                return Ok(Progress::Cancelled);
+            }
+            if !self.pipeline.run_stage(&mut self.cache)? {
+                return Ok(Progress::Done);
+            }
+            if start.elapsed() >= budget {
+                return Ok(self.pipeline.progress());
+            }
+        }
+    }
+}
+
+/// The stages of packing into one dir.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Fingerprint,
+    Gather,
+    Pack,
+    /// index into the bundles
+    PackBundle(usize),
+    /// the summary of the warnings, pipeline graph, fingerprint and stats history
+    Finish,
+    /// only for destinations that are archives, after the pack was staged next to it
+    Archive,
+    Done,
+}
+
+/// Packing into one dir, the destination or one of its variants.
+struct DirPack {
+    /// the staging dir for archives
+    out_path: String,
+    archive: Option<(String, ArchiveFormat)>,
+    settings: PackSettings,
+    stage: Stage,
+    fingerprint: String,
+    assets: Option<GatheredAssets>,
+    bundles: Vec<(String, GatheredAssets)>,
+    packed: Option<PackedAssets>,
+    warnings: Warnings,
+}
+
+impl DirPack {
+    fn new(src_dir: &str, dest_dir: String, settings: PackSettings) -> Self {
+        let archive = ArchiveFormat::from_path(&dest_dir).map(|format| (dest_dir.clone(), format));
+        let out_path = out_dir(&dest_dir);
+        DirPack {
+            out_path,
+            archive,
+            warnings: Warnings::new(src_dir, &settings.warnings),
+            settings,
+            stage: Stage::Fingerprint,
+            fingerprint: String::new(),
+            assets: None,
+            bundles: vec![],
+            packed: None,
+        }
+    }
+
+    fn stages_left(&self) -> usize {
+        let stages_after_packing = 1 + self.archive.is_some() as usize;
+        match self.stage {
+            Stage::Fingerprint => 3 + stages_after_packing,
+            Stage::Gather => 2 + stages_after_packing,
+            Stage::Pack => 1 + self.bundles.len() + stages_after_packing,
+            Stage::PackBundle(i) => self.bundles.len() - i + stages_after_packing,
+            Stage::Finish => stages_after_packing,
+            Stage::Archive => 1,
+            Stage::Done => 0,
+        }
+    }
+
+    fn description(&self) -> String {
+        let out_path = &self.out_path;
+        match self.stage {
+            Stage::Fingerprint => format!("checking the inputs of {out_path}"),
+            Stage::Gather => format!("gathering assets for {out_path}"),
+            Stage::Pack => format!("packing {out_path}"),
+            Stage::PackBundle(i) => format!("packing bundle {}", self.bundles[i].0),
+            Stage::Finish => format!("finishing {out_path}"),
+            Stage::Archive => format!("writing {}", self.archive.as_ref().unwrap().0),
+            Stage::Done => String::from("done"),
+        }
+    }
+
+    fn run_stage(&mut self, src_dir: &str, cache: &mut Cache) -> PackResult<()> {
+        let out_path = self.out_path.as_str();
+        let settings = &self.settings;
+        self.stage = match self.stage {
+            Stage::Fingerprint => {
+                let mut hasher = ContentHasher::new();
+                hasher.write(&input_fingerprint(src_dir, settings)?.to_le_bytes());
+                hasher.write(serde_json::to_string(settings).unwrap().as_bytes());
+                self.fingerprint = format!("{:016x}", hasher.finish());
+                let fingerprint_path = format!("{out_path}/fingerprint");
+                if std::fs::read_to_string(&fingerprint_path).is_ok_and(|e| e == self.fingerprint) {
+                    println!("inputs unchanged, {out_path} is up to date");
+                    cache.skip_pack();
+                    self.after_finish()
+                } else {
+                    Stage::Gather
+                }
+            }
+            Stage::Gather => {
+                let mut assets = gather_assets(src_dir, settings, cache)?;
+                if settings.bundles {
+                    let bundles: BTreeMap<String, GatheredAssets>;
+                    (assets, bundles) = assets.split_bundles();
+                    self.bundles = bundles.into_iter().collect();
+                }
+                self.assets = Some(assets);
+                Stage::Pack
+            }
+            Stage::Pack => {
+                let assets = self.assets.as_ref().unwrap();
+                let packed = pack_assets(assets, out_path, settings, cache, &mut self.warnings)?;
+                self.packed = Some(packed);
+                self.next_bundle(0)
+            }
+            Stage::PackBundle(i) => {
+                let (name, bundle) = &self.bundles[i];
+                let bundle_path = format!("{out_path}/{name}");
+                println!("bundle {bundle_path}:");
+                pack_assets(bundle, &bundle_path, settings, cache, &mut self.warnings)?;
+                self.next_bundle(i + 1)
+            }
+            Stage::Finish => {
+                let assets = self.assets.as_ref().unwrap();
+                let packed = self.packed.as_ref().unwrap();
+                if settings.record_stats {
+                    let dest = match &self.archive {
+                        Some((path, _)) => path.as_str(),
+                        None => out_path,
+                    };
+                    if let Err(e) = append_to_history(dest, &PackStats::collect(packed, out_path)) {
+                        let message = format!("the stats are not recorded: {e}");
+                        self.warnings.warn(WarningCode::W009, None, message);
+                    }
+                }
+                self.warnings.print_summary();
+                if settings.pipeline_graph {
+                    let graph_path = format!("{out_path}/{PIPELINE_GRAPH_FILE_NAME}");
+                    let graph = pipeline_graph(src_dir, assets, packed, settings)?;
+                    std::fs::write(&graph_path, graph).map_err(PackError::io(&graph_path))?;
+                }
+                let fingerprint_path = format!("{out_path}/fingerprint");
+                std::fs::write(&fingerprint_path, &self.fingerprint)
+                    .map_err(PackError::io(&fingerprint_path))?;
+                self.after_finish()
+            }
+            Stage::Archive => {
+                let (path, format) = self.archive.as_ref().unwrap();
+                println!("writing {path}");
+                write_archive(out_path, path, *format)?;
+                Stage::Done
+            }
+            Stage::Done => Stage::Done,
+        };
+        Ok(())
+    }
+
+    /// Removes the dir of a pack that was partly written: its atlases are written but its bundles,
+    /// stats or fingerprint are not. Packs that did not start writing yet keep their previous files.
+    fn discard(&self) {
+        if matches!(self.stage, Stage::PackBundle(_) | Stage::Finish) {
+            println!("discarding the partly written {}", self.out_path);
+            _ = std::fs::remove_dir_all(&self.out_path);
+        }
+    }
+
+    fn next_bundle(&self, i: usize) -> Stage {
+        match i < self.bundles.len() {
+            true => Stage::PackBundle(i),
+            false => Stage::Finish,
+        }
+    }
+
+    fn after_finish(&self) -> Stage {
+        match self.archive {
+            Some(_) => Stage::Archive,
+            None => Stage::Done,
+        }
+    }
+}
+
+/// The dir a pack is written to: `dest_dir` itself, or for archives the dir next to the archive that
+/// is packed into first, which also keeps the fingerprint of the inputs.
+pub(crate) fn out_dir(dest_dir: &str) -> String {
+    match ArchiveFormat::from_path(dest_dir) {
+        Some(_) => format!("{dest_dir}.staging"),
+        None => dest_dir.to_string(),
+    }
+}
+
+/// The packs of a job, the destination and then each of the `variants` of the settings, run one
+/// stage at a time. Shared by [`PackJob`] and [`crate::pack::gather_and_pack`], which runs all
+/// stages at once with a cache it borrows.
+pub(crate) struct Pipeline {
+    src_dir: String,
+    current: Option<DirPack>,
+    /// destinations and settings of the variants that are not started yet
+    variants: VecDeque<(String, PackSettings)>,
+    /// variants and bundles share the cache in a batch, see [`Cache::start_batch`]
+    batch: bool,
+    started: bool,
+    stages_done: usize,
+}
+
+impl Pipeline {
+    pub(crate) fn new(src_dir: &str, dest_dir: &str, settings: &PackSettings) -> Self {
+        let variants: VecDeque<(String, PackSettings)> = settings
+            .variants
+            .iter()
+            .map(|(suffix, scale)| {
+                let variant_settings = PackSettings {
+                    scale: settings.scale * scale,
+                    variants: BTreeMap::new(),
+                    ..settings.clone()
+                };
+                (with_suffix(dest_dir, suffix), variant_settings)
+            })
+            .collect();
+        Pipeline {
+            src_dir: src_dir.to_string(),
+            current: Some(DirPack::new(
+                src_dir,
+                dest_dir.to_string(),
+                settings.clone(),
+            )),
+            // every bundle is a pack that saves the cache
+            batch: !variants.is_empty() || settings.bundles,
+            variants,
+            started: false,
+            stages_done: 0,
+        }
+    }
+
+    /// Runs the next stage, returns whether there are stages left.
+    pub(crate) fn run_stage(&mut self, cache: &mut Cache) -> PackResult<bool> {
+        let Some(current) = self.current.as_mut() else {
+            return Ok(false);
+        };
+        if !std::mem::replace(&mut self.started, true) && self.batch {
+            cache.start_batch();
+        }
+        if let Err(e) = current.run_stage(&self.src_dir, cache) {
+            self.finish(cache);
+            return Err(e);
+        }
+        self.stages_done += 1;
+        if current.stage == Stage::Done {
+            match self.variants.pop_front() {
+                Some((variant_path, settings)) => {
+                    println!("variant {variant_path}:");
+                    self.current = Some(DirPack::new(&self.src_dir, variant_path, settings));
+                }
+                None => self.finish(cache),
+            }
+        }
+        Ok(self.current.is_some())
+    }
+
+    /// Stops before the next stage. The pack in progress is discarded if it was partly written, and
+    /// the cache keeps the entries that the packs which did not run would have used.
+    pub(crate) fn cancel(&mut self, cache: &mut Cache) {
+        let Some(current) = self.current.as_ref() else {
+            return;
+        };
+        current.discard();
+        if self.started {
+            cache.skip_pack();
+        }
+        self.finish(cache);
+    }
+
+    fn finish(&mut self, cache: &mut Cache) {
+        self.current = None;
+        self.variants.clear();
+        if self.batch && self.started {
+            cache.finish_batch();
+        }
+    }
+
+    fn progress(&self) -> Progress {
+        let Some(current) = self.current.as_ref() else {
+            return Progress::Done;
+        };
+        let stages_left = current.stages_left()
+            + self
+                .variants
+                .iter()
+                .map(|(path, _)| 4 + ArchiveFormat::from_path(path).is_some() as usize)
+                .sum::<usize>();
+        Progress::Working {
+            stage: current.description(),
+            fraction: self.stages_done as f32 / (self.stages_done + stages_left) as f32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{testing::TempDir, Packer};
+
+    #[test]
+    fn bundled_packs_reuse_the_cache() {
+        let dir = TempDir::new("bundled_packs_reuse_the_cache");
+        dir.write_png("src/images/ui/button.png", (8, 8), [255, 0, 0, 255]);
+        dir.write_png("src/images/world/tree.png", (8, 8), [0, 255, 0, 255]);
+        let (src, dest) = (dir.path("src"), dir.path("packed"));
+        let cached_files = || -> Vec<String> {
+            let json = std::fs::read_to_string(dir.path("packed.cache/cache.json")).unwrap();
+            let manifest: serde_json::Value = serde_json::from_str(&json).unwrap();
+            manifest["files"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect()
+        };
+        let cached_images = || -> Vec<String> {
+            let mut names: Vec<String> = std::fs::read_dir(dir.path("packed.cache/images"))
+                .unwrap()
+                .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+
+        Packer::new(&src).bundles(true).pack_to(&dest).unwrap();
+        assert_eq!(cached_files().len(), 2);
+        let first = cached_images();
+        assert_eq!(first.len(), 2);
+
+        dir.write_png("src/images/world/tree.png", (8, 8), [0, 0, 255, 255]);
+        Packer::new(&src).bundles(true).pack_to(&dest).unwrap();
+        assert_eq!(cached_files().len(), 2);
+        let second = cached_images();
+        assert_eq!(second.len(), 2);
+        // the entry of the unchanged button is the one of the first pack
+        assert_eq!(first.iter().filter(|e| second.contains(e)).count(), 1);
+    }
+}
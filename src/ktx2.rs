@@ -0,0 +1,277 @@
+//! Minimal writer and reader for KTX2 files, the container format gpu texture loaders like wgpu and
+//! three.js understand directly. Only what the packer writes is supported: 2d rgba8 or ASTC textures,
+//! srgb or linear, with an optional mip chain and zlib supercompression.
+
+use std::io::{Read, Write};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use image::RgbaImage;
+
+use crate::astc::{compress_astc, decompress_astc, AstcBlockSize};
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const VK_FORMAT_R8G8B8A8_UNORM: u32 = 37;
+const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+const VK_FORMAT_ASTC_4X4_UNORM_BLOCK: u32 = 157;
+const VK_FORMAT_ASTC_4X4_SRGB_BLOCK: u32 = 158;
+const VK_FORMAT_ASTC_6X6_UNORM_BLOCK: u32 = 165;
+const VK_FORMAT_ASTC_6X6_SRGB_BLOCK: u32 = 166;
+const SUPERCOMPRESSION_NONE: u32 = 0;
+const SUPERCOMPRESSION_ZLIB: u32 = 3;
+/// identifier, 9 header fields and the index of the data format descriptor, key/value and
+/// supercompression data
+const HEADER_LENGTH: usize = 12 + 9 * 4 + 4 * 4 + 2 * 8;
+const LEVEL_INDEX_ENTRY_LENGTH: usize = 3 * 8;
+
+/// Encodes `levels` (the texture followed by its mip levels, each half the size of the previous one)
+/// as a KTX2 file.
+pub fn encode_ktx2(levels: &[&RgbaImage], srgb: bool) -> Vec<u8> {
+    let data: Vec<Vec<u8>> = levels.iter().map(|level| level.as_raw().clone()).collect();
+    let dfd = rgba8_data_format_descriptor(srgb);
+    write_ktx2(vk_format(None, srgb), levels[0].dimensions(), &data, &dfd)
+}
+
+/// Encodes `levels` like [`encode_ktx2`], compressed with ASTC.
+pub fn encode_astc_ktx2(levels: &[&RgbaImage], size: AstcBlockSize, srgb: bool) -> Vec<u8> {
+    let data: Vec<Vec<u8>> = levels
+        .iter()
+        .map(|level| compress_astc(level, size))
+        .collect();
+    let dfd = astc_data_format_descriptor(size, srgb);
+    write_ktx2(
+        vk_format(Some(size), srgb),
+        levels[0].dimensions(),
+        &data,
+        &dfd,
+    )
+}
+
+/// Every level is compressed with zlib, supercompressed levels need no alignment.
+fn write_ktx2(vk_format: u32, (w, h): (u32, u32), levels: &[Vec<u8>], dfd: &[u8]) -> Vec<u8> {
+    let compressed: Vec<Vec<u8>> = levels
+        .iter()
+        .map(|level| {
+            let mut encoder = ZlibEncoder::new(vec![], Compression::best());
+            encoder.write_all(level).unwrap();
+            encoder.finish().unwrap()
+        })
+        .collect();
+    let dfd_offset = HEADER_LENGTH + LEVEL_INDEX_ENTRY_LENGTH * levels.len();
+
+    let mut out: Vec<u8> = Vec::new();
+    out.extend(IDENTIFIER);
+    // format, type size, width, height, depth, layers, faces, levels, supercompression
+    let levels_count = levels.len() as u32;
+    for field in [
+        vk_format,
+        1,
+        w,
+        h,
+        0,
+        0,
+        1,
+        levels_count,
+        SUPERCOMPRESSION_ZLIB,
+    ] {
+        out.extend(field.to_le_bytes());
+    }
+    // dfd offset and length, no key/value data
+    for field in [dfd_offset as u32, dfd.len() as u32, 0, 0] {
+        out.extend(field.to_le_bytes());
+    }
+    // no supercompression global data
+    out.extend(0u64.to_le_bytes());
+    out.extend(0u64.to_le_bytes());
+
+    // the level index lists the largest level first, the data is stored smallest level first
+    let mut offset = dfd_offset + dfd.len();
+    let mut level_offsets: Vec<usize> = vec![0; levels.len()];
+    for (i, level) in compressed.iter().enumerate().rev() {
+        level_offsets[i] = offset;
+        offset += level.len();
+    }
+    for ((level, compressed), level_offset) in levels.iter().zip(&compressed).zip(&level_offsets) {
+        out.extend((*level_offset as u64).to_le_bytes());
+        out.extend((compressed.len() as u64).to_le_bytes());
+        out.extend((level.len() as u64).to_le_bytes());
+    }
+    out.extend(dfd);
+    for level in compressed.iter().rev() {
+        out.extend(level);
+    }
+    out
+}
+
+/// Decodes a KTX2 file written by [`encode_ktx2`] into the texture and its mip levels. Returns
+/// `None` for files in any other format.
+pub fn decode_ktx2(bytes: &[u8]) -> Option<Vec<RgbaImage>> {
+    let u32_at = |i: usize| Some(u32::from_le_bytes(bytes.get(i..i + 4)?.try_into().unwrap()));
+    let u64_at = |i: usize| Some(u64::from_le_bytes(bytes.get(i..i + 8)?.try_into().unwrap()));
+    if bytes.get(0..12)? != IDENTIFIER {
+        return None;
+    }
+    let format = u32_at(12)?;
+    let (astc, srgb) = [
+        None,
+        Some(AstcBlockSize::Astc4x4),
+        Some(AstcBlockSize::Astc6x6),
+    ]
+    .into_iter()
+    .flat_map(|astc| [(astc, false), (astc, true)])
+    .find(|(astc, srgb)| vk_format(*astc, *srgb) == format)?;
+    let (w, h) = (u32_at(20)?, u32_at(24)?);
+    let level_count = u32_at(40)?.max(1) as usize;
+    let supercompression = u32_at(44)?;
+    if ![SUPERCOMPRESSION_NONE, SUPERCOMPRESSION_ZLIB].contains(&supercompression) {
+        return None;
+    }
+    (0..level_count)
+        .map(|level| {
+            let entry = HEADER_LENGTH + level * LEVEL_INDEX_ENTRY_LENGTH;
+            let offset = u64_at(entry)? as usize;
+            let length = u64_at(entry + 8)? as usize;
+            let uncompressed_length = u64_at(entry + 16)? as usize;
+            let mut pixels = bytes.get(offset..offset.checked_add(length)?)?.to_vec();
+            if supercompression == SUPERCOMPRESSION_ZLIB {
+                let mut inflated: Vec<u8> = vec![];
+                ZlibDecoder::new(pixels.as_slice())
+                    .take(uncompressed_length as u64)
+                    .read_to_end(&mut inflated)
+                    .ok()?;
+                pixels = inflated;
+            }
+            let (level_w, level_h) = ((w >> level).max(1), (h >> level).max(1));
+            match astc {
+                Some(size) => decompress_astc(&pixels, level_w, level_h, size, srgb),
+                None => RgbaImage::from_raw(level_w, level_h, pixels),
+            }
+        })
+        .collect()
+}
+
+/// Width and height from the header of a KTX2 file.
+pub fn ktx2_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let u32_at = |i: usize| Some(u32::from_le_bytes(bytes.get(i..i + 4)?.try_into().unwrap()));
+    (bytes.get(0..12)? == IDENTIFIER).then_some(())?;
+    Some((u32_at(20)?, u32_at(24)?))
+}
+
+/// Vulkan format of ASTC blocks of the size or rgba8 if `None`.
+fn vk_format(astc: Option<AstcBlockSize>, srgb: bool) -> u32 {
+    match (astc, srgb) {
+        (None, false) => VK_FORMAT_R8G8B8A8_UNORM,
+        (None, true) => VK_FORMAT_R8G8B8A8_SRGB,
+        (Some(AstcBlockSize::Astc4x4), false) => VK_FORMAT_ASTC_4X4_UNORM_BLOCK,
+        (Some(AstcBlockSize::Astc4x4), true) => VK_FORMAT_ASTC_4X4_SRGB_BLOCK,
+        (Some(AstcBlockSize::Astc6x6), false) => VK_FORMAT_ASTC_6X6_UNORM_BLOCK,
+        (Some(AstcBlockSize::Astc6x6), true) => VK_FORMAT_ASTC_6X6_SRGB_BLOCK,
+    }
+}
+
+/// Transfer function of the data format descriptor.
+fn transfer(srgb: bool) -> u8 {
+    match srgb {
+        true => 2,
+        false => 1,
+    }
+}
+
+/// Basic data format descriptor of rgba8 with straight alpha, as the KTX2 spec requires it.
+fn rgba8_data_format_descriptor(srgb: bool) -> Vec<u8> {
+    const SAMPLES: usize = 4;
+    let block_size = 24 + 16 * SAMPLES;
+    let mut dfd: Vec<u8> = Vec::new();
+    dfd.extend(((4 + block_size) as u32).to_le_bytes());
+    // vendor id and descriptor type 0 (khronos basic), version 2
+    dfd.extend(0u32.to_le_bytes());
+    dfd.extend(2u16.to_le_bytes());
+    dfd.extend((block_size as u16).to_le_bytes());
+    // rgbsda color model, bt709 primaries, srgb or linear transfer, straight alpha
+    dfd.extend([1, 1, transfer(srgb), 0]);
+    // texel block dimensions (1x1x1x1, stored minus one)
+    dfd.extend([0, 0, 0, 0]);
+    // bytes per plane, 0 as the levels are supercompressed
+    dfd.extend([0; 8]);
+    // red, green, blue and alpha channel ids, alpha is marked linear in srgb textures
+    let alpha = if srgb { 15 | 0x10 } else { 15 };
+    for (i, channel) in [0u8, 1, 2, alpha].into_iter().enumerate() {
+        dfd.extend(((i * 8) as u16).to_le_bytes());
+        dfd.push(7); // bit length minus one
+        dfd.push(channel);
+        dfd.extend([0, 0, 0, 0]); // sample position
+        dfd.extend(0u32.to_le_bytes()); // lower
+        dfd.extend(255u32.to_le_bytes()); // upper
+    }
+    dfd
+}
+
+/// Basic data format descriptor of ASTC, one sample covering the whole 128 bit block.
+fn astc_data_format_descriptor(size: AstcBlockSize, srgb: bool) -> Vec<u8> {
+    let (block_w, block_h) = size.dimensions();
+    let block_size = 24 + 16;
+    let mut dfd: Vec<u8> = Vec::new();
+    dfd.extend(((4 + block_size) as u32).to_le_bytes());
+    dfd.extend(0u32.to_le_bytes());
+    dfd.extend(2u16.to_le_bytes());
+    dfd.extend((block_size as u16).to_le_bytes());
+    // astc color model, bt709 primaries, srgb or linear transfer, straight alpha
+    dfd.extend([162, 1, transfer(srgb), 0]);
+    dfd.extend([block_w as u8 - 1, block_h as u8 - 1, 0, 0]);
+    dfd.extend([0; 8]);
+    dfd.extend(0u16.to_le_bytes());
+    dfd.push(127); // bit length minus one
+    dfd.push(0); // astc data channel
+    dfd.extend([0, 0, 0, 0]);
+    dfd.extend(0u32.to_le_bytes());
+    dfd.extend(u32::MAX.to_le_bytes());
+    dfd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{gradient, psnr};
+
+    #[test]
+    fn rgba8_textures_round_trip() {
+        let texture = gradient(32, 16, false);
+        let mip = gradient(16, 8, false);
+        let bytes = encode_ktx2(&[&texture, &mip], true);
+        assert_eq!(ktx2_dimensions(&bytes), Some((32, 16)));
+        assert_eq!(decode_ktx2(&bytes), Some(vec![texture, mip]));
+    }
+
+    #[test]
+    fn levels_are_supercompressed() {
+        let texture = RgbaImage::from_pixel(64, 64, image::Rgba([40, 80, 120, 255]));
+        let bytes = encode_ktx2(&[&texture], true);
+        assert!(bytes.len() < texture.len() / 20, "{} bytes", bytes.len());
+        assert_eq!(decode_ktx2(&bytes), Some(vec![texture]));
+    }
+
+    #[test]
+    fn astc_textures_round_trip() {
+        let texture = gradient(60, 60, false);
+        for size in [AstcBlockSize::Astc4x4, AstcBlockSize::Astc6x6] {
+            let bytes = encode_astc_ktx2(&[&texture], size, false);
+            let levels = decode_ktx2(&bytes).unwrap();
+            assert_eq!(levels.len(), 1);
+            let psnr = psnr(&texture, &levels[0]);
+            assert!(psnr > 30.0, "{size:?} has a psnr of {psnr}");
+        }
+    }
+
+    #[test]
+    fn truncated_ktx2_files_are_rejected() {
+        let bytes = encode_ktx2(&[&gradient(8, 8, false)], false);
+        for len in 0..bytes.len() {
+            assert!(decode_ktx2(&bytes[..len]).is_none(), "decoded {len} bytes");
+        }
+        let bytes = encode_astc_ktx2(&[&gradient(8, 8, false)], AstcBlockSize::Astc4x4, false);
+        for len in 0..bytes.len() {
+            assert!(decode_ktx2(&bytes[..len]).is_none(), "decoded {len} bytes");
+        }
+    }
+}
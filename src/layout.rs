@@ -0,0 +1,149 @@
+//! The allocations of the sprite atlases of a pack, kept in the [`Cache`](crate::cache::Cache) so the
+//! next pack leaves the sprites whose size did not change where they are and only allocates the
+//! others, see [`crate::pack::make_texture_atlases`].
+//!
+//! A layout stores the allocations and deallocations of every atlas in the order they happened.
+//! The allocator is deterministic, so replaying them puts every sprite back where it was. Removed
+//! and resized sprites leave holes that later sprites may not fill, `assetpacker defrag` lays the
+//! atlases out from scratch.
+
+use std::collections::BTreeMap;
+
+use glam::UVec2;
+use guillotiere::{size2, Allocation, AllocatorOptions, AtlasAllocator, Size};
+use serde::{Deserialize, Serialize};
+
+use crate::pack::TextureFlags;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct AtlasLayout {
+    /// size of the atlases, a layout of other atlases is not reused
+    pub atlas_size: UVec2,
+    /// the allocations are aligned to the blocks of compressed textures, 1x1 otherwise
+    pub alignment: UVec2,
+    pub pages: Vec<LayoutPage>,
+}
+
+/// An atlas of sprites with the same flags and atlas group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutPage {
+    pub flags: TextureFlags,
+    pub group: Option<String>,
+    pub steps: Vec<LayoutStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LayoutStep {
+    /// a sprite with the size of the allocation, including its padding
+    Allocate {
+        ident: String,
+        size: (i32, i32),
+        rotated: bool,
+    },
+    Deallocate {
+        ident: String,
+    },
+}
+
+/// An atlas while sprites are allocated in it.
+pub(crate) struct Page {
+    pub flags: TextureFlags,
+    pub group: Option<String>,
+    pub allocator: AtlasAllocator,
+    /// sprite ident -> its allocation, the size it was allocated with and whether it is rotated
+    pub sprites: BTreeMap<String, (Allocation, Size, bool)>,
+    steps: Vec<LayoutStep>,
+}
+
+impl Page {
+    pub fn new(
+        flags: TextureFlags,
+        group: Option<String>,
+        atlas_size: UVec2,
+        alignment: UVec2,
+    ) -> Page {
+        let options = AllocatorOptions {
+            alignment: size2(alignment.x as i32, alignment.y as i32),
+            ..guillotiere::DEFAULT_OPTIONS
+        };
+        let size = size2(atlas_size.x as i32, atlas_size.y as i32);
+        Page {
+            flags,
+            group,
+            allocator: AtlasAllocator::with_options(size, &options),
+            sprites: BTreeMap::new(),
+            steps: vec![],
+        }
+    }
+
+    /// Records a sprite that was allocated with `size` by the allocator of the page.
+    pub fn insert(&mut self, ident: &str, size: Size, allocation: Allocation, rotated: bool) {
+        self.steps.push(LayoutStep::Allocate {
+            ident: ident.to_owned(),
+            size: (size.width, size.height),
+            rotated,
+        });
+        self.sprites
+            .insert(ident.to_owned(), (allocation, size, rotated));
+    }
+
+    pub fn remove(&mut self, ident: &str) {
+        if let Some((allocation, _, _)) = self.sprites.remove(ident) {
+            self.allocator.deallocate(allocation.id);
+            self.steps.push(LayoutStep::Deallocate {
+                ident: ident.to_owned(),
+            });
+        }
+    }
+
+    /// `None` if the steps do not allocate like they did before, which only happens if the layout
+    /// was edited.
+    fn replay(page: &LayoutPage, atlas_size: UVec2, alignment: UVec2) -> Option<Page> {
+        let mut replayed = Page::new(page.flags, page.group.clone(), atlas_size, alignment);
+        for step in page.steps.iter() {
+            match step {
+                LayoutStep::Allocate {
+                    ident,
+                    size,
+                    rotated,
+                } => {
+                    let size = size2(size.0, size.1);
+                    let allocation = replayed.allocator.allocate(size)?;
+                    replayed.insert(ident, size, allocation, *rotated);
+                }
+                LayoutStep::Deallocate { ident } => replayed.remove(ident),
+            }
+        }
+        Some(replayed)
+    }
+}
+
+impl AtlasLayout {
+    pub(crate) fn new(atlas_size: UVec2, alignment: UVec2, pages: &[Page]) -> AtlasLayout {
+        let pages = pages
+            .iter()
+            .map(|page| LayoutPage {
+                flags: page.flags,
+                group: page.group.clone(),
+                steps: page.steps.clone(),
+            })
+            .collect();
+        AtlasLayout {
+            atlas_size,
+            alignment,
+            pages,
+        }
+    }
+
+    /// The atlases as the last pack left them, `None` if the layout was made for atlases of another
+    /// size or alignment.
+    pub(crate) fn replay(&self, atlas_size: UVec2, alignment: UVec2) -> Option<Vec<Page>> {
+        if self.atlas_size != atlas_size || self.alignment != alignment {
+            return None;
+        }
+        self.pages
+            .iter()
+            .map(|page| Page::replay(page, atlas_size, alignment))
+            .collect()
+    }
+}
@@ -0,0 +1,536 @@
+//! Packs a directory of images and fonts into texture atlases, sdf fonts and a `packed.json` manifest.
+//!
+//! ```no_run
+//! assetpacker::Packer::new("assets").atlas_size(2048).pack_to("packed")?;
+//! # Ok::<(), assetpacker::error::PackError>(())
+//! ```
+
+use std::path::PathBuf;
+
+use cache::Cache;
+use codegen::IdentCase;
+use compare::{compare_to_baseline, read_baseline, ManifestDiff};
+use error::{PackError, PackResult};
+use job::{out_dir, PackJob};
+use pack::{
+    gather_and_pack, read_manifest, AtlasRounding, ClearColors, ManifestFormat, PackSettings,
+    TextureFormat,
+};
+use script::ScriptLanguage;
+use stats::PackStats;
+
+pub mod archive;
+pub mod aseprite;
+pub mod astc;
+pub mod budget;
+pub mod cache;
+pub mod capabilities;
+pub mod codegen;
+pub mod compare;
+pub mod completions;
+pub mod dds;
+pub mod error;
+#[cfg(feature = "fast-png")]
+pub mod fast_png;
+pub mod font;
+pub mod gather;
+pub mod graph;
+pub mod ignore;
+pub mod job;
+pub mod ktx2;
+pub mod layout;
+pub mod loader;
+pub mod locale;
+pub mod msdf;
+pub mod optimize;
+pub mod pack;
+pub mod pak;
+pub mod palette;
+pub mod postprocess;
+pub mod preview;
+pub mod psd;
+pub mod schema;
+pub mod script;
+pub mod stats;
+pub mod svg;
+#[cfg(test)]
+mod testing;
+pub mod tui;
+pub mod visual_diff;
+pub mod warnings;
+pub mod watch;
+pub mod workspace;
+
+pub const CONFIG_FILE_NAME: &str = "assetpacker.json";
+
+/// Builder for a pack of the assets in a source directory.
+#[derive(Debug, Clone)]
+pub struct Packer {
+    src_dir: String,
+    cache_dir: Option<String>,
+    settings: PackSettings,
+    /// reported by `pack_to` and `watch`, so the builder itself stays infallible
+    config_error: Option<String>,
+}
+
+impl Packer {
+    /// Starts with the settings of the `assetpacker.json` config file in `src_dir` if there is one,
+    /// or the default settings otherwise. The builder methods override the config file.
+    pub fn new(src_dir: impl Into<String>) -> Self {
+        let src_dir: String = src_dir.into();
+        let mut config_error = None;
+        let settings = match std::fs::read(format!("{src_dir}/{CONFIG_FILE_NAME}")) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                config_error = Some(e.to_string());
+                PackSettings::default()
+            }),
+            Err(_) => PackSettings::default(),
+        };
+        Packer {
+            src_dir,
+            cache_dir: None,
+            settings,
+            config_error,
+        }
+    }
+
+    /// Width and height of the texture atlases, 1024 by default.
+    pub fn atlas_size(self, size: u32) -> Self {
+        self.atlas_width(size).atlas_height(size)
+    }
+
+    /// Width of the texture atlases, also the maximum width of packed images.
+    pub fn atlas_width(mut self, width: u32) -> Self {
+        self.settings.atlas_width = width;
+        self
+    }
+
+    /// Height of the texture atlases, also the maximum height of packed images and of the atlases
+    /// that repeat-x strips are stacked in.
+    pub fn atlas_height(mut self, height: u32) -> Self {
+        self.settings.atlas_height = height;
+        self
+    }
+
+    /// Target platform of the pack, assets excluded for it by the `platform_rules` of the config are left out.
+    pub fn platform(mut self, platform: impl Into<String>) -> Self {
+        self.settings.platform = Some(platform.into());
+        self
+    }
+
+    /// Crop the fully transparent border of sprites before packing, the manifest records where the
+    /// tiles sat in their source images.
+    pub fn trim_transparent(mut self, trim_transparent: bool) -> Self {
+        self.settings.trim_transparent = trim_transparent;
+        self
+    }
+
+    /// Rotate sprites by 90° when they only fit into an atlas that way, the manifest marks rotated tiles.
+    pub fn allow_rotation(mut self, allow_rotation: bool) -> Self {
+        self.settings.allow_rotation = allow_rotation;
+        self
+    }
+
+    /// Write a `pipeline.dot` graphviz graph of which source files ended up in which files of the pack.
+    pub fn pipeline_graph(mut self, pipeline_graph: bool) -> Self {
+        self.settings.pipeline_graph = pipeline_graph;
+        self
+    }
+
+    /// Append the stats of every pack to the history next to the destination, on by default. Off for
+    /// throwaway packs like the ones of `compare`.
+    pub fn record_stats(mut self, record_stats: bool) -> Self {
+        self.settings.record_stats = record_stats;
+        self
+    }
+
+    /// File format of the textures, png by default.
+    pub fn texture_format(mut self, format: TextureFormat) -> Self {
+        self.settings.texture_format = format;
+        self
+    }
+
+    /// File format of repeat textures and strips, if it should differ from the other textures.
+    pub fn repeat_texture_format(mut self, format: TextureFormat) -> Self {
+        self.settings.repeat_texture_format = Some(format);
+        self
+    }
+
+    /// Write this many downscaled levels for every atlas, for engines sampling with trilinear filtering.
+    pub fn mip_levels(mut self, levels: u32) -> Self {
+        self.settings.mip_levels = levels;
+        self
+    }
+
+    /// Extrude the border pixels of each sprite this many pixels into its padding, 0 by default.
+    pub fn extrude_edges(mut self, pixels: u32) -> Self {
+        self.settings.extrude_edges = pixels;
+        self
+    }
+
+    /// Write repeat textures without any transparent pixels as rgb instead of rgba pngs.
+    pub fn opaque_rgb(mut self, opaque_rgb: bool) -> Self {
+        self.settings.opaque_rgb = opaque_rgb;
+        self
+    }
+
+    /// Blend repeat images that almost tile into seamless ones, see [`pack::PackSettings::make_tiling`].
+    pub fn make_tiling(mut self, make_tiling: bool) -> Self {
+        self.settings.make_tiling = make_tiling;
+        self
+    }
+
+    /// Record the source path, hash and flags of every tile in the manifest, off by default. Meant
+    /// for debug builds, release packs should leave it off.
+    pub fn provenance(mut self, provenance: bool) -> Self {
+        self.settings.provenance = provenance;
+        self
+    }
+
+    /// Colors the free space of the atlases is filled with, transparent black by default.
+    pub fn clear_colors(mut self, clear_colors: ClearColors) -> Self {
+        self.settings.clear_colors = clear_colors;
+        self
+    }
+
+    /// Factor all images are resized with, 1 by default, see [`Packer::variant`] for several packs at
+    /// different scales.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.settings.scale = scale;
+        self
+    }
+
+    /// Also writes a pack with all images resized by `scale` into the destination dir with `suffix`
+    /// appended, like `packed@2x` for `variant("@2x", 2.0)`.
+    pub fn variant(mut self, suffix: impl Into<String>, scale: f32) -> Self {
+        self.settings.variants.insert(suffix.into(), scale);
+        self
+    }
+
+    /// Read files with `extension` like `texpng` as files with the extension `read_as` like `png`, or
+    /// copy them into the pack unchanged with `read_as` [`gather::DATA_EXTENSION`].
+    pub fn extension(mut self, extension: impl Into<String>, read_as: impl Into<String>) -> Self {
+        self.settings
+            .extensions
+            .insert(extension.into(), read_as.into());
+        self
+    }
+
+    /// Pixels per unit that svgs in the images dir are rasterized with, 1 by default. Svgs are
+    /// packed once per scale, as images named like `play_2x`, if there are several.
+    pub fn svg_scales(mut self, scales: Vec<f32>) -> Self {
+        self.settings.svg_scales = scales;
+        self
+    }
+
+    /// Losslessly shrink all written pngs, for web builds and patches, see [`optimize::optimize_png`].
+    pub fn optimize_pngs(mut self, optimize_pngs: bool) -> Self {
+        self.settings.optimize_pngs = optimize_pngs;
+        self
+    }
+
+    /// Write the atlas pages that compress worst as lossy indexed pngs until all pages together take
+    /// at most `kb` kilobytes, see [`budget::fit_size_budget`]. The minimum quality of lossy pages
+    /// is kept from the config, 35 dB psnr by default.
+    pub fn size_budget(mut self, kb: u64) -> Self {
+        let budget = self.settings.size_budget.unwrap_or_default();
+        self.settings.size_budget = Some(budget::SizeBudget { kb, ..budget });
+        self
+    }
+
+    /// Pack the images of each top level dir of the images dir, like `ui` or `characters`, into a
+    /// bundle with its own atlases and manifest, so games load and unload them independently.
+    pub fn bundles(mut self, bundles: bool) -> Self {
+        self.settings.bundles = bundles;
+        self
+    }
+
+    /// Whether symlinks in the source dir are followed, see [`PackSettings::follow_symlinks`].
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.settings.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Name textures and fonts after their content hash like `atlas_0.1a2b3c4d.png`, for cache
+    /// busting on CDNs. The names are recorded in [`pack::PackedAssets::file_names`].
+    pub fn hashed_file_names(mut self, hashed_file_names: bool) -> Self {
+        self.settings.hashed_file_names = hashed_file_names;
+        self
+    }
+
+    /// Bundle the files a runtime loads into one memory mappable `packed.pak` instead of loose
+    /// files, see [`pak::PackFiles`] for reading it.
+    pub fn pak(mut self, pak: bool) -> Self {
+        self.settings.pak = pak;
+        self
+    }
+
+    /// Deflate the chunks of the `packed.pak` that shrink by it, trading load time for size.
+    pub fn compress_pak(mut self, compress_pak: bool) -> Self {
+        self.settings.compress_pak = compress_pak;
+        self
+    }
+
+    /// Rounding of the atlas sizes that depend on their content, like the strip atlases of repeat
+    /// images, powers of two by default.
+    pub fn atlas_rounding(mut self, atlas_rounding: AtlasRounding) -> Self {
+        self.settings.atlas_rounding = atlas_rounding;
+        self
+    }
+
+    /// Write the manifest as the MessagePack encoded `packed.bin` instead of `packed.json`, which is
+    /// smaller and loads faster for large projects and for script runtimes with fast msgpack parsers.
+    pub fn manifest_format(mut self, format: ManifestFormat) -> Self {
+        self.settings.manifest_format = format;
+        self
+    }
+
+    /// Premultiply the colors of all sprites by their alpha, see [`pack::AlphaMode::Premultiplied`].
+    pub fn premultiply_alpha(mut self, premultiply_alpha: bool) -> Self {
+        self.settings.premultiply_alpha = premultiply_alpha;
+        self
+    }
+
+    /// Split images larger than the atlas into atlas sized tiles instead of giving them their own
+    /// texture, for engines that cannot load textures of arbitrary size.
+    pub fn split_oversized(mut self, split_oversized: bool) -> Self {
+        self.settings.split_oversized = split_oversized;
+        self
+    }
+
+    /// Record tabular figure metrics for the digits of fonts that support `tnum`, off by default.
+    pub fn tabular_figures(mut self, tabular_figures: bool) -> Self {
+        self.settings.font.tabular_figures = tabular_figures;
+        self
+    }
+
+    /// Generate the font `name` with the characters of `charset` instead of the ascii alphabet, see
+    /// [`font::FontCharset`].
+    pub fn font_charset(mut self, name: impl Into<String>, charset: font::FontCharset) -> Self {
+        self.settings.font.charsets.insert(name.into(), charset);
+        self
+    }
+
+    /// Generate the font `name` as a multi-channel sdf, like the `msdf` flag of its file, see
+    /// [`font::DistanceField::Msdf`].
+    pub fn msdf_font(mut self, name: impl Into<String>) -> Self {
+        self.settings.font.msdf_fonts.push(name.into());
+        self
+    }
+
+    /// Additionally record the line metrics of fonts at these pixel sizes, for ui text of fixed sizes.
+    pub fn font_metric_sizes(mut self, sizes: Vec<u32>) -> Self {
+        self.settings.font.metric_sizes = sizes;
+        self
+    }
+
+    /// Generate at most this many glyphs per font, the characters of the lowest priority are
+    /// skipped with a warning instead, see [`font::font_characters`].
+    pub fn font_max_glyphs(mut self, max_glyphs: usize) -> Self {
+        self.settings.font.max_glyphs = Some(max_glyphs);
+        self
+    }
+
+    /// Rare symbols generated for every font after the ascii characters and the locale charsets.
+    pub fn font_extra_characters(mut self, characters: impl Into<String>) -> Self {
+        self.settings.font.extra_characters = characters.into();
+        self
+    }
+
+    /// Fail the pack if the fonts of the locale lack characters of its charset, see
+    /// [`locale::LocaleCharset`].
+    pub fn locale(mut self, name: impl Into<String>, charset: locale::LocaleCharset) -> Self {
+        self.settings.locales.insert(name.into(), charset);
+        self
+    }
+
+    /// Also write an `assets.rs` with constants for all tiles, fonts and icons into the destination dir.
+    pub fn rust_codegen(mut self, rust_codegen: bool) -> Self {
+        self.settings.rust_codegen = rust_codegen;
+        self
+    }
+
+    /// Casing of the identifiers in the generated `assets.rs`, see [`IdentCase`].
+    pub fn rust_ident_case(mut self, case: IdentCase) -> Self {
+        self.settings.rust_ident_case = case;
+        self
+    }
+
+    /// Also write the manifest as an `assets.lua` or `assets.js` for games with embedded scripting.
+    pub fn script_export(mut self, language: ScriptLanguage) -> Self {
+        self.settings.script_export = Some(language);
+        self
+    }
+
+    /// Calls `processor` with the pixels of every tile of the atlas `group`, or of all tiles if
+    /// `None`, after it is blitted into its atlas and before the atlas is encoded, like to watermark
+    /// demo builds, see [`postprocess::TileProcessor`]. Processors run in the order they are added.
+    /// `name` is part of the fingerprint of the inputs, so a pack made with other processors is not
+    /// taken as up to date: change it when the processor draws something else.
+    pub fn tile_processor(
+        mut self,
+        name: impl Into<String>,
+        group: Option<&str>,
+        processor: impl Fn(&mut image::RgbaImage, &postprocess::TileMeta) + Send + Sync + 'static,
+    ) -> Self {
+        self.settings.tile_processors.add(
+            name.into(),
+            group.map(String::from),
+            std::sync::Arc::new(processor),
+        );
+        self
+    }
+
+    /// Directory for the incremental build cache, defaults to `<dest_dir>.cache`.
+    pub fn cache_dir(mut self, cache_dir: impl Into<String>) -> Self {
+        self.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    /// Overrides the settings with the fields of a json object, like a profile of a workspace file,
+    /// see [`workspace`].
+    pub fn profile(mut self, overrides: &serde_json::Map<String, serde_json::Value>) -> Self {
+        let mut settings = serde_json::to_value(&self.settings).unwrap();
+        if let serde_json::Value::Object(fields) = &mut settings {
+            fields.extend(overrides.clone());
+        }
+        match serde_json::from_value(settings) {
+            Ok(settings) => {
+                let tile_processors = std::mem::take(&mut self.settings.tile_processors);
+                self.settings = PackSettings {
+                    tile_processors,
+                    ..settings
+                };
+            }
+            Err(e) => self.config_error = Some(format!("invalid profile: {e}")),
+        }
+        self
+    }
+
+    pub fn settings(&self) -> &PackSettings {
+        &self.settings
+    }
+
+    /// Packs the assets into `dest_dir`, replacing its previous contents. A `dest_dir` ending in
+    /// `.zip`, `.tar` or `.tar.gz` is written as that archive instead, see [`archive::ArchiveFormat`].
+    pub fn pack_to(&self, dest_dir: &str) -> PackResult<()> {
+        let mut cache = self.load_cache(dest_dir)?;
+        gather_and_pack(&self.src_dir, dest_dir, &self.settings, &mut cache)
+    }
+
+    /// Like [`Packer::pack_to`], with a cache shared by several packers.
+    pub fn pack_with_cache(&self, dest_dir: &str, cache: &mut Cache) -> PackResult<()> {
+        self.check_config()?;
+        gather_and_pack(&self.src_dir, dest_dir, &self.settings, cache)
+    }
+
+    /// Like [`Packer::pack_to`], but returns a job that packs a few stages at a time with
+    /// [`PackJob::step`], for editors that pack on their main thread between frames.
+    pub fn pack_in_steps(&self, dest_dir: &str) -> PackResult<PackJob> {
+        let cache = self.load_cache(dest_dir)?;
+        Ok(PackJob::new(&self.src_dir, dest_dir, &self.settings, cache))
+    }
+
+    /// Packs the assets into `dest_dir` and compares the result to the manifest of a `baseline`
+    /// pack, a url, manifest file or pack dir, see [`compare::read_baseline`]. Shows what a release
+    /// of the pack would change when the previous one is only on a CDN.
+    pub fn compare(&self, dest_dir: &str, baseline: &str) -> PackResult<ManifestDiff> {
+        let baseline = read_baseline(baseline)?;
+        self.pack_to(dest_dir)?;
+        compare_to_baseline(&baseline, &out_dir(dest_dir))
+    }
+
+    /// Repacks the assets into `dest_dir` even if the inputs did not change, with a fresh layout of all
+    /// atlases, and prints how the number and size of the textures changed.
+    ///
+    /// Packs keep unchanged sprites where the previous pack put them, so removed and resized sprites
+    /// leave holes in the atlases over time, see [`layout`]. Defrag forgets the cached layouts and packs
+    /// the atlases tightly again. Tile names stay the same, so this never changes how assets are
+    /// referenced, only where they are in the atlases.
+    pub fn defrag(&self, dest_dir: &str) -> PackResult<()> {
+        // archives are read from the dir they are packed into first
+        let out_dir = out_dir(dest_dir);
+        let before = read_manifest(&out_dir).map(|packed| PackStats::collect(&packed, &out_dir));
+        let mut cache = self.load_cache(dest_dir)?;
+        cache.discard_atlas_layouts();
+        _ = std::fs::remove_file(format!("{out_dir}/fingerprint"));
+        self.pack_with_cache(dest_dir, &mut cache)?;
+        let after = PackStats::collect(&read_manifest(&out_dir)?, &out_dir);
+        if let Ok(before) = before {
+            println!(
+                "defrag: {} textures with {} pixels -> {} textures with {} pixels",
+                before.textures, before.texture_pixels, after.textures, after.texture_pixels
+            );
+        }
+        Ok(())
+    }
+
+    /// Packs the assets into `dest_dir` and repacks them whenever a source file changes. Only returns
+    /// if the packer could not be set up, errors while packing are printed and the watcher keeps going.
+    pub fn watch(&self, dest_dir: &str) -> PackResult<()> {
+        let mut cache = self.load_cache(dest_dir)?;
+        watch::watch(&self.src_dir, dest_dir, &self.settings, &mut cache);
+        Ok(())
+    }
+
+    fn check_config(&self) -> PackResult<()> {
+        match &self.config_error {
+            Some(message) => Err(PackError::Config {
+                path: PathBuf::from(format!("{}/{CONFIG_FILE_NAME}", self.src_dir)),
+                message: message.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn load_cache(&self, dest_dir: &str) -> PackResult<Cache> {
+        self.check_config()?;
+        // the cache lives next to the destination dir, so it is not shipped together with the packed assets
+        let cache_dir = self
+            .cache_dir
+            .clone()
+            .unwrap_or_else(|| format!("{}.cache", dest_dir.trim_end_matches('/')));
+        Cache::load(cache_dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{pack::read_manifest, testing::TempDir, Packer};
+
+    #[test]
+    fn defrag_repacks_archives() {
+        let dir = TempDir::new("defrag_repacks_archives");
+        dir.write_png("src/images/a.png", (8, 8), [255, 0, 0, 255]);
+        let dest = dir.path("packed.zip");
+        let packer = Packer::new(dir.path("src"));
+        packer.pack_to(&dest).unwrap();
+        packer.defrag(&dest).unwrap();
+    }
+
+    #[test]
+    fn defrag_fills_the_holes_of_removed_sprites() {
+        let dir = TempDir::new("defrag_fills_the_holes_of_removed_sprites");
+        dir.write_png("src/images/big.png", (32, 32), [255, 0, 0, 255]);
+        dir.write_png("src/images/small.png", (8, 8), [0, 0, 255, 255]);
+        let dest = dir.path("packed");
+        let packer = Packer::new(dir.path("src"));
+        packer.pack_to(&dest).unwrap();
+        let first = read_manifest(&dest).unwrap();
+
+        // small keeps its place next to the hole big leaves behind
+        std::fs::remove_file(dir.path("src/images/big.png")).unwrap();
+        packer.pack_to(&dest).unwrap();
+        let kept = read_manifest(&dest).unwrap();
+        assert_eq!(kept.tiles["small"].min, first.tiles["small"].min);
+
+        // laid out like a pack without a cache
+        packer.defrag(&dest).unwrap();
+        let defragged = read_manifest(&dest).unwrap();
+        let fresh_dest = dir.path("fresh");
+        packer.pack_to(&fresh_dest).unwrap();
+        let fresh = read_manifest(&fresh_dest).unwrap();
+        assert_eq!(defragged.tiles["small"].min, fresh.tiles["small"].min);
+        assert_ne!(defragged.tiles["small"].min, kept.tiles["small"].min);
+    }
+}
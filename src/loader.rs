@@ -0,0 +1,310 @@
+use std::{borrow::Cow, collections::HashMap, path::PathBuf};
+
+use glam::Vec2;
+use image::{GrayImage, RgbImage, RgbaImage};
+
+use crate::{
+    cache::content_hash,
+    dds::decode_dds,
+    error::{PackError, PackResult},
+    font::{DistanceField, Glyph, SdfFont},
+    ktx2::decode_ktx2,
+    pack::{
+        font_checksum, read_pack_manifest, NineSlice, PackedAssets, TextureFlags, TextureFormat,
+        TileKind, Trim,
+    },
+    pak::PackFiles,
+};
+
+/// Index of a tile in [`LoadedAssets`], cheap to copy around instead of looking tiles up by name each frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileHandle(u32);
+
+/// Index of a font in [`LoadedAssets`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FontHandle(u32);
+
+/// Index of a texture in [`LoadedAssets`], the unit a renderer would upload to the gpu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(u32);
+
+pub struct LoadedTexture {
+    pub name: String,
+    pub flags: TextureFlags,
+    pub rgba: RgbaImage,
+    /// downscaled levels, starting with half the size of `rgba`, empty if none were packed
+    pub mips: Vec<RgbaImage>,
+}
+
+pub struct LoadedTile {
+    pub name: String,
+    pub texture: TextureHandle,
+    /// normalized uv coordinates of the tile in its texture
+    pub uv_min: Vec2,
+    pub uv_max: Vec2,
+    /// where the tile sits in its source image, if its transparent border was trimmed
+    pub trim: Option<Trim>,
+    /// the source image is rotated 90° clockwise in the texture, so its top left corner is at
+    /// `(uv_max.x, uv_min.y)`
+    pub rotated: bool,
+    /// masks sit in single channel textures, only the red channel of their texture is meaningful
+    pub kind: TileKind,
+    /// borders in pixels of the source image, for tiles drawn as scalable panels
+    pub nine_slice: Option<NineSlice>,
+    /// origin in pixels of the source image, if the sprite has one
+    pub pivot: Option<Vec2>,
+}
+
+pub struct LoadedFont {
+    pub font: SdfFont,
+    /// the distance field, for msdf fonts the median of their channels
+    pub sdf: GrayImage,
+    /// the channels of msdf fonts, see [`DistanceField::Msdf`]
+    pub msdf: Option<RgbImage>,
+}
+
+/// The contents of a directory written by [`crate::pack::pack_assets`], with all textures and fonts loaded.
+pub struct LoadedAssets {
+    pub manifest: PackedAssets,
+    pub textures: Vec<LoadedTexture>,
+    pub tiles: Vec<LoadedTile>,
+    pub fonts: Vec<LoadedFont>,
+    tile_handles: HashMap<String, TileHandle>,
+    font_handles: HashMap<String, FontHandle>,
+    /// `None` for packs without fonts and partial loads where the default font failed to load
+    default_font: Option<FontHandle>,
+}
+
+impl LoadedAssets {
+    /// Reads `packed.json` (or `packed.bin`) in `dir`, or in its `packed.pak`, and loads all
+    /// textures and fonts it references. Fails if any of them is missing or does not match its
+    /// checksum.
+    pub fn load(dir: &str) -> PackResult<LoadedAssets> {
+        let (assets, failed) = LoadedAssets::load_partial(dir)?;
+        PackError::from_errors(failed)?;
+        Ok(assets)
+    }
+
+    /// Like [`LoadedAssets::load`], but textures and fonts that are missing, corrupted or do not match
+    /// their checksum are left out instead of failing the whole load, together with the tiles on them.
+    /// Returns what failed, e.g. to report damaged installs. Only fails if the manifest itself cannot
+    /// be read.
+    pub fn load_partial(dir: &str) -> PackResult<(LoadedAssets, Vec<PackError>)> {
+        let files = PackFiles::open(dir)?;
+        let manifest = read_pack_manifest(&files)?;
+        let mut failed: Vec<PackError> = vec![];
+
+        let mut textures: Vec<LoadedTexture> = vec![];
+        let mut texture_handles: HashMap<&str, TextureHandle> = HashMap::new();
+        for (name, flags) in manifest.textures.iter() {
+            let file_name = format!("{name}.{}", flags.format.extension());
+            let file_name = manifest.file_name(&file_name);
+            let path = files.path(file_name);
+            let checksum = manifest.checksums.textures.get(name).copied();
+            let levels = match flags.format.extension() {
+                "png" | "webp" => load_image_levels(&files, name, flags.format, &manifest),
+                "ktx2" => read_checked(&files, file_name, checksum).and_then(|bytes| {
+                    decode_ktx2(&bytes).ok_or_else(|| PackError::InvalidManifest {
+                        message: format!("{path} is not a ktx2 file written by the packer"),
+                    })
+                }),
+                "dds" => read_checked(&files, file_name, checksum).and_then(|bytes| {
+                    decode_dds(&bytes).ok_or_else(|| PackError::InvalidManifest {
+                        message: format!("{path} is not a dds file written by the packer"),
+                    })
+                }),
+                _ => Err(PackError::InvalidManifest {
+                    message: format!("texture {name} has a format this loader does not know"),
+                }),
+            };
+            let (rgba, mips) = match levels {
+                Ok(mut levels) => (levels.remove(0), levels),
+                Err(e) => {
+                    failed.push(e);
+                    continue;
+                }
+            };
+            texture_handles.insert(name, TextureHandle(textures.len() as u32));
+            textures.push(LoadedTexture {
+                name: name.clone(),
+                flags: *flags,
+                rgba,
+                mips,
+            });
+        }
+
+        let mut tiles: Vec<LoadedTile> = vec![];
+        let mut tile_handles: HashMap<String, TileHandle> = HashMap::new();
+        let mut tile_names: Vec<&String> = manifest.tiles.keys().collect();
+        tile_names.sort();
+        for name in tile_names {
+            let tile = &manifest.tiles[name];
+            let Some(texture) = texture_handles.get(tile.atlas.as_str()).copied() else {
+                // tiles on textures that failed to load were already reported with their texture
+                if !manifest.textures.iter().any(|(e, _)| *e == tile.atlas) {
+                    failed.push(PackError::InvalidManifest {
+                        message: format!("tile {name} references unknown texture {}", tile.atlas),
+                    });
+                }
+                continue;
+            };
+            let rgba = &textures[texture.0 as usize].rgba;
+            let size = Vec2::new(rgba.width() as f32, rgba.height() as f32);
+            tile_handles.insert(name.clone(), TileHandle(tiles.len() as u32));
+            tiles.push(LoadedTile {
+                name: name.clone(),
+                texture,
+                uv_min: tile.min.as_vec2() / size,
+                uv_max: tile.max.as_vec2() / size,
+                trim: tile.trim,
+                rotated: tile.rotated,
+                kind: tile.kind,
+                nine_slice: tile.nine_slice,
+                pivot: tile.pivot,
+            });
+        }
+
+        let mut fonts: Vec<LoadedFont> = vec![];
+        let mut font_handles: HashMap<String, FontHandle> = HashMap::new();
+        for name in manifest.fonts.iter() {
+            let font = match load_font(&files, &manifest, name) {
+                Ok(font) => font,
+                Err(e) => {
+                    failed.push(e);
+                    continue;
+                }
+            };
+            font_handles.insert(name.clone(), FontHandle(fonts.len() as u32));
+            fonts.push(font);
+        }
+        let mut default_font: Option<FontHandle> = None;
+        if let Some(name) = &manifest.default_font {
+            default_font = font_handles.get(name).copied();
+            if default_font.is_none() && !manifest.fonts.contains(name) {
+                failed.push(PackError::InvalidManifest {
+                    message: format!("unknown default font {name}"),
+                });
+            }
+        }
+
+        let assets = LoadedAssets {
+            manifest,
+            textures,
+            tiles,
+            fonts,
+            tile_handles,
+            font_handles,
+            default_font,
+        };
+        Ok((assets, failed))
+    }
+
+    pub fn tile_handle(&self, name: &str) -> Option<TileHandle> {
+        self.tile_handles.get(name).copied()
+    }
+
+    pub fn tile(&self, handle: TileHandle) -> &LoadedTile {
+        &self.tiles[handle.0 as usize]
+    }
+
+    pub fn texture(&self, handle: TextureHandle) -> &LoadedTexture {
+        &self.textures[handle.0 as usize]
+    }
+
+    /// Returns the texture and the normalized uv rect of a tile.
+    pub fn tile_uv(&self, handle: TileHandle) -> (TextureHandle, Vec2, Vec2) {
+        let tile = self.tile(handle);
+        (tile.texture, tile.uv_min, tile.uv_max)
+    }
+
+    pub fn font_handle(&self, name: &str) -> Option<FontHandle> {
+        self.font_handles.get(name).copied()
+    }
+
+    pub fn default_font(&self) -> Option<FontHandle> {
+        self.default_font
+    }
+
+    pub fn font(&self, handle: FontHandle) -> &LoadedFont {
+        &self.fonts[handle.0 as usize]
+    }
+
+    /// Returns the glyph of a character, its uvs point into the sdf image of the font.
+    pub fn glyph(&self, handle: FontHandle, ch: char) -> Option<&Glyph> {
+        self.font(handle).font.glyphs.get(&ch)
+    }
+}
+
+/// Reads the png or webp of a texture and the files of its mip levels, checking them against their
+/// checksums.
+fn load_image_levels(
+    files: &PackFiles,
+    name: &str,
+    format: TextureFormat,
+    manifest: &PackedAssets,
+) -> PackResult<Vec<RgbaImage>> {
+    let levels = manifest.mip_levels.get(name).copied().unwrap_or(0);
+    (0..=levels)
+        .map(|level| {
+            let file_name = match level {
+                0 => name.to_string(),
+                level => format!("{name}.mip{level}"),
+            };
+            let checksum = manifest.checksums.textures.get(&file_name).copied();
+            let file_name = format!("{file_name}.{}", format.extension());
+            let file_name = manifest.file_name(&file_name);
+            read_checked(files, file_name, checksum).and_then(|bytes| {
+                image::load_from_memory(&bytes)
+                    .map(|image| image.to_rgba8())
+                    .map_err(PackError::image(files.path(file_name)))
+            })
+        })
+        .collect()
+}
+
+/// Reads a file of the pack and compares it with its checksum from the manifest, if there is one.
+fn read_checked<'a>(
+    files: &'a PackFiles,
+    file_name: &str,
+    checksum: Option<u64>,
+) -> PackResult<Cow<'a, [u8]>> {
+    let bytes = files.read(file_name)?;
+    match checksum {
+        Some(checksum) if content_hash(&bytes) != checksum => Err(PackError::ChecksumMismatch {
+            path: PathBuf::from(files.path(file_name)),
+        }),
+        _ => Ok(bytes),
+    }
+}
+
+fn load_font(files: &PackFiles, manifest: &PackedAssets, name: &str) -> PackResult<LoadedFont> {
+    let json_name = format!("{name}.sdf_font.json");
+    let png_name = format!("{name}.sdf_font.png");
+    let (json_name, png_name) = (
+        manifest.file_name(&json_name),
+        manifest.file_name(&png_name),
+    );
+    let checksum = manifest.checksums.fonts.get(name).copied();
+    let (json_path, png_path) = (files.path(json_name), files.path(png_name));
+    let json = files.read(json_name)?;
+    let png = files.read(png_name)?;
+    if checksum.is_some_and(|checksum| font_checksum(&json, &png) != checksum) {
+        return Err(PackError::ChecksumMismatch {
+            path: PathBuf::from(json_path),
+        });
+    }
+    let font: SdfFont = serde_json::from_slice(&json).map_err(PackError::json(&json_path))?;
+    let image = image::load_from_memory(&png).map_err(PackError::image(&png_path))?;
+    let (sdf, msdf) = match font.distance_field {
+        DistanceField::Sdf => (image.to_luma8(), None),
+        DistanceField::Msdf => {
+            let msdf = image.to_rgb8();
+            let median = |[r, g, b]: [u8; 3]| r.min(g).max(r.max(g).min(b));
+            let sdf = GrayImage::from_fn(msdf.width(), msdf.height(), |x, y| {
+                image::Luma([median(msdf.get_pixel(x, y).0)])
+            });
+            (sdf, Some(msdf))
+        }
+    };
+    Ok(LoadedFont { font, sdf, msdf })
+}
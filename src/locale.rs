@@ -0,0 +1,115 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{PackError, PackResult},
+    font::{charset_range, SdfFont},
+    pack::PackedAssets,
+};
+
+/// Characters that the texts of a language need. If locales are configured in the [`PackSettings`],
+/// the pack fails when one of their fonts lacks a character, instead of the game showing boxes
+/// (tofu) for it.
+///
+/// [`PackSettings`]: crate::pack::PackSettings
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocaleCharset {
+    /// fonts the texts of the locale are rendered with, each of them has to cover the whole charset.
+    /// The default font if empty.
+    pub fonts: Vec<String>,
+    /// characters the locale needs, including its punctuation like `„“` or `«»`
+    pub characters: String,
+    /// inclusive codepoint ranges like `U+0400-U+04FF`, single codepoints like `U+00A0`, or named
+    /// ranges like `cyrillic`, see [`crate::font::NAMED_RANGES`]
+    pub ranges: Vec<String>,
+}
+
+impl LocaleCharset {
+    /// All characters of the locale, whitespace except for the space is not rendered and left out.
+    pub fn chars(&self) -> Result<BTreeSet<char>, String> {
+        let mut chars: BTreeSet<char> = self
+            .characters
+            .chars()
+            .filter(|ch| *ch == ' ' || !ch.is_whitespace())
+            .collect();
+        for range in self.ranges.iter() {
+            chars.extend(
+                charset_range(range)?
+                    .into_iter()
+                    .filter(|ch| *ch == ' ' || !ch.is_whitespace()),
+            );
+        }
+        Ok(chars)
+    }
+}
+
+pub(crate) fn parse_range(range: &str) -> Option<(char, char)> {
+    let codepoint = |s: &str| {
+        let hex = s.trim().trim_start_matches("U+").trim_start_matches("u+");
+        char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+    };
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (codepoint(start)?, codepoint(end)?),
+        None => (codepoint(range)?, codepoint(range)?),
+    };
+    (start <= end).then_some((start, end))
+}
+
+/// Checks that the packed fonts of every locale cover its charset, reporting the missing codepoints
+/// of each locale and font.
+pub fn check_locales(
+    locales: &BTreeMap<String, LocaleCharset>,
+    out_path: &str,
+    packed: &PackedAssets,
+) -> PackResult<()> {
+    let mut fonts: BTreeMap<&str, SdfFont> = BTreeMap::new();
+    let mut errors: Vec<PackError> = vec![];
+    for (locale, charset) in locales.iter() {
+        let coverage_error = |message: String| PackError::LocaleCoverage {
+            locale: locale.clone(),
+            message,
+        };
+        let chars = match charset.chars() {
+            Ok(chars) => chars,
+            Err(message) => {
+                errors.push(coverage_error(message));
+                continue;
+            }
+        };
+        let font_names: Vec<&str> = match (charset.fonts.is_empty(), &packed.default_font) {
+            (true, Some(default_font)) => vec![default_font],
+            (true, None) => {
+                errors.push(coverage_error(String::from("the pack has no default font")));
+                continue;
+            }
+            (false, _) => charset.fonts.iter().map(String::as_str).collect(),
+        };
+        for name in font_names {
+            if !packed.fonts.iter().any(|e| e == name) {
+                errors.push(coverage_error(format!("the pack has no font {name}")));
+                continue;
+            }
+            if !fonts.contains_key(name) {
+                let json_path = format!("{out_path}/{name}.sdf_font.json");
+                let json = std::fs::read(&json_path).map_err(PackError::io(&json_path))?;
+                let font = serde_json::from_slice(&json).map_err(PackError::json(&json_path))?;
+                fonts.insert(name, font);
+            }
+            let missing: Vec<String> = chars
+                .iter()
+                .filter(|ch| !fonts[name].glyphs.contains_key(ch))
+                .map(|ch| format!("U+{:04X} {ch}", *ch as u32))
+                .collect();
+            if !missing.is_empty() {
+                errors.push(coverage_error(format!(
+                    "font {name} lacks {} characters: {}",
+                    missing.len(),
+                    missing.join(", ")
+                )));
+            }
+        }
+    }
+    PackError::from_errors(errors)
+}
@@ -1,18 +1,460 @@
 use std::env::args;
 
-use gather::gather_assets;
-use pack::pack_assets;
+use assetpacker::{
+    compare::format_manifest_diff,
+    completions::{completion_script, CliHelp, Shell},
+    error::PackError,
+    loader::LoadedAssets,
+    pack::{AtlasRounding, ClearColors, ManifestFormat, SizeRounding, TextureFormat},
+    preview::render_text,
+    schema::manifest_schema,
+    script::ScriptLanguage,
+    stats::{format_history, format_tile_compression, read_history, tile_compression},
+    tui::run_tui,
+    visual_diff::{format_visual_diff, visual_diff, DiffImageMode},
+    workspace::pack_workspace,
+    Packer,
+};
 
-mod font;
-mod gather;
-mod pack;
+const USAGE: &str = "Use like this: assetpacker [options] path/to/srcdir path/to/destination
+           or this: assetpacker watch [options] path/to/srcdir path/to/destination
+           or this: assetpacker defrag [options] path/to/srcdir path/to/destination
+           or this: assetpacker stats path/to/packed
+           or this: assetpacker compression [path/to/packed]
+           or this: assetpacker batch path/to/workspace.json
+           or this: assetpacker text-preview [--font NAME] --text TEXT [path/to/packed] out.png
+           or this: assetpacker tui [path/to/packed]
+           or this: assetpacker schema [path/to/packed.schema.json]
+           or this: assetpacker visual-diff [--highlight] old/packed new/packed [path/to/diffs]
+           or this: assetpacker compare --baseline=URL|PATH [options] path/to/srcdir [path/to/destination]
+           or this: assetpacker completions bash|zsh|fish|powershell
+           or this: assetpacker --help-json
+
+A destination ending in .zip, .tar or .tar.gz is written as that archive, the pack is staged in
+a directory next to it. Settings are read from an assetpacker.json in the srcdir, options
+override them.
+Hidden files, editor backups and the patterns of a .assetpackerignore in the srcdir or any of
+its dirs are skipped.
+A dir.json in any dir of the images sets the padding, atlas group, filter, draw layer and flags of
+the images below it. File names can combine flags and values like bg.repx.linear.pad=8.png, the
+bg, fg and layer=N flags set the draw layer. A json sidecar like player.png.meta sets the pivot,
+nine slice borders, layer, tags and user data of one image. The extensions setting maps unusual
+extensions to formats like texpng: png, or to data to copy those files into the pack as is.
+Fonts are ttf, otf or ttc collections, whose faces=0,2 flag or .meta sidecar selects the faces.
+Fonts get the ascii characters, or those of their chars=latin,cyrillic,U+2190-U+21FF flag or of
+their charset in the font settings. The msdf flag generates a font as a multi-channel sdf in an
+rgb png, whose corners stay sharp when drawn large.
+
+options:
+    --out=PATH           destination, instead of the second path, like --out=assets.zip
+    --atlas-width=N      width of the texture atlases in pixels, 1024 by default
+    --atlas-height=N     height of the texture atlases in pixels, 1024 by default
+    --platform=NAME      target platform, assets excluded for it in the config are left out
+    --trim               crop fully transparent borders of sprites before packing
+    --rotate             rotate sprites by 90° if that lets them fit into an atlas
+    --extrude=N          extrude the border pixels of sprites N pixels into their padding
+    --texture-format=F   png (default), webp for smaller lossless textures for browsers, ktx2
+                         for zlib supercompressed textures that need no image decoding,
+                         astc4x4 or astc6x6 for mobile, bc1, bc3 or bc7 for desktop
+    --repeat-format=F    texture format of repeat textures and strips, if it should differ
+    --rounding=R         rounding of atlas sizes that fit their content: pow2 (default),
+                         multiple-of-4 or exact
+    --mips=N             write N downscaled mip levels for every atlas
+    --split-oversized    split images larger than the atlas into several tiles
+    --opaque-rgb         write opaque repeat textures as rgb pngs without alpha
+    --optimize           losslessly shrink all written pngs, slow for the first pack
+    --size-budget=KB     write the atlas pages that compress worst as lossy indexed pngs until
+                         all pages fit into KB kilobytes, printing the stats of each page
+    --bundles            pack each top level dir of images into a bundle of its own
+    --no-follow-symlinks skip symlinks in the srcdir instead of gathering what they point to
+    --hashed-names       name textures and fonts after their content hash, for web caching
+    --pak                bundle the manifest, textures and fonts into one packed.pak file
+    --compress-pak       deflate the chunks of the packed.pak that shrink by it
+    --premultiply        premultiply the colors of sprites by their alpha
+    --clear-color=RGBA   hex color of the free space in atlases, like ff00ffff to spot uv errors
+    --make-tiling        blend repeat images that almost tile into seamless ones
+    --tabular-figures    record tabular figure metrics for digits of fonts supporting `tnum`
+    --metric-sizes=N,..  also record the line metrics of fonts at these pixel sizes
+    --max-glyphs=N       generate at most N glyphs per font, skipping rare characters
+    --msdf=NAME,..       generate these fonts as multi-channel sdfs, like the msdf flag
+    --scale=F            resize all images by this factor
+    --variant=SUFFIX:F   also write a pack with images resized by F into destination+SUFFIX,
+                         like --variant=@2x:2 for packed@2x, can be given several times
+    --svg-scales=S,..    pixels per unit of svg images, packed once per scale, 1 by default
+    --manifest-format=F  json (default) or msgpack (alias binary) for a smaller MessagePack
+                         packed.bin that loads faster, also from lua or js msgpack libraries
+    --provenance         record the source file of every tile in the manifest, for debugging
+    --graph              write a pipeline.dot graph of which source files ended up where
+    --rust-codegen       write an assets.rs with constants for all asset identifiers
+    --lua                write an assets.lua with the manifest as a lua table
+    --js                 write an assets.js with the manifest as a js module";
 
 fn main() {
-    let args: Vec<String> = args().collect();
-    let src_dir = args
-        .get(1)
-        .expect("Use like this: assetpacker path/to/srcdir path/to/destination");
-    let dest_dir = args.get(2).cloned().unwrap_or(String::from("packed"));
-    let assets = gather_assets(&src_dir);
-    pack_assets(&assets, &dest_dir);
+    let mut args: Vec<String> = args().skip(1).collect();
+    if args.first().is_some_and(|e| e == "--help-json") {
+        let help = CliHelp::parse(USAGE);
+        println!("{}", serde_json::to_string_pretty(&help).unwrap());
+        return;
+    }
+    if args.first().is_some_and(|e| e == "completions") {
+        let shell = args
+            .get(1)
+            .and_then(|e| Shell::from_name(e))
+            .unwrap_or_else(|| usage_error("completions needs bash, zsh, fish or powershell"));
+        print!("{}", completion_script(&CliHelp::parse(USAGE), shell));
+        return;
+    }
+    if args.first().is_some_and(|e| e == "stats") {
+        let dest = args
+            .get(1)
+            .unwrap_or_else(|| usage_error("stats needs the destination of a pack"));
+        print!("{}", format_history(&read_history(dest), 20));
+        return;
+    }
+    if args.first().is_some_and(|e| e == "batch") {
+        let workspace_path = args
+            .get(1)
+            .unwrap_or_else(|| usage_error("batch needs a workspace file"));
+        if let Err(e) = pack_workspace(workspace_path) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.first().is_some_and(|e| e == "compression") {
+        let packed_dir = args.get(1).map(String::as_str).unwrap_or("packed");
+        match tile_compression(packed_dir) {
+            Ok(tiles) => print!("{}", format_tile_compression(&tiles, 30)),
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if args.first().is_some_and(|e| e == "text-preview") {
+        text_preview(&args[1..]);
+        return;
+    }
+    if args.first().is_some_and(|e| e == "tui") {
+        let packed_dir = args.get(1).map(String::as_str).unwrap_or("packed");
+        if let Err(e) = run_tui(packed_dir) {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.first().is_some_and(|e| e == "visual-diff") {
+        // exits with 1 if the packs differ, so scripts can check that a change kept all pixels
+        let (options, positional): (Vec<&String>, Vec<&String>) =
+            args[1..].iter().partition(|e| e.starts_with("--"));
+        let mode = match options.as_slice() {
+            [] => DiffImageMode::SideBySide,
+            [option] if *option == "--highlight" => DiffImageMode::Highlight,
+            _ => usage_error("visual-diff only knows the option --highlight"),
+        };
+        let (Some(old_dir), Some(new_dir)) = (positional.first(), positional.get(1)) else {
+            usage_error("visual-diff needs the old and the new pack dir");
+        };
+        let out_dir = positional
+            .get(2)
+            .map(|e| e.as_str())
+            .unwrap_or("visual_diff");
+        match visual_diff(old_dir, new_dir, Some(out_dir), mode) {
+            Ok(diff) => {
+                print!("{}", format_visual_diff(&diff));
+                if !diff.is_empty() {
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+    if args.first().is_some_and(|e| e == "schema") {
+        let schema = serde_json::to_string_pretty(&manifest_schema()).unwrap();
+        match args.get(1) {
+            Some(path) => {
+                if let Err(e) = std::fs::write(path, schema) {
+                    eprintln!("error: {}", PackError::io(path)(e));
+                    std::process::exit(1);
+                }
+            }
+            None => println!("{schema}"),
+        }
+        return;
+    }
+    let command = match args.first().map(|e| e.as_str()) {
+        Some("watch") | Some("defrag") | Some("compare") => args.remove(0),
+        _ => String::from("pack"),
+    };
+
+    // `--baseline URL` is also accepted with a space, like curl takes urls
+    if let Some(i) = args.iter().position(|e| e == "--baseline") {
+        if i + 1 < args.len() {
+            let url = args.remove(i + 1);
+            args[i] = format!("--baseline={url}");
+        }
+    }
+    let (options, positional): (Vec<String>, Vec<String>) =
+        args.into_iter().partition(|e| e.starts_with("--"));
+    let src_dir = positional
+        .first()
+        .unwrap_or_else(|| usage_error("missing the source dir"));
+    let dest_dir = options
+        .iter()
+        .find_map(|e| e.strip_prefix("--out="))
+        .or(positional.get(1).map(String::as_str))
+        .map(String::from)
+        .unwrap_or_else(|| match command.as_str() {
+            // packs that are only compared are not kept
+            "compare" => std::env::temp_dir()
+                .join(format!("assetpacker-compare-{}", std::process::id()))
+                .to_string_lossy()
+                .into_owned(),
+            _ => String::from("packed"),
+        });
+    let baseline = options
+        .iter()
+        .find_map(|e| e.strip_prefix("--baseline="))
+        .map(String::from);
+    let temporary_dest = !options.iter().any(|e| e.starts_with("--out=")) && positional.len() < 2;
+
+    // the throwaway packs of compare are not recorded in a stats history
+    let mut packer = Packer::new(src_dir).record_stats(!(command == "compare" && temporary_dest));
+    for option in options {
+        let (option, value) = match option.split_once('=') {
+            Some((option, value)) => (option.to_string(), Some(value.to_string())),
+            None => (option, None),
+        };
+        let number = || -> u32 {
+            value
+                .as_deref()
+                .and_then(|e| e.parse().ok())
+                .unwrap_or_else(|| {
+                    usage_error(&format!("{option} needs a number like {option}=2048"))
+                })
+        };
+        let texture_format = || {
+            match value.as_deref() {
+            Some("png") => TextureFormat::Png,
+            Some("webp") => TextureFormat::Webp,
+            Some("ktx2") => TextureFormat::Ktx2,
+            Some("astc4x4") => TextureFormat::Astc4x4,
+            Some("astc6x6") => TextureFormat::Astc6x6,
+            Some("bc1") => TextureFormat::Bc1,
+            Some("bc3") => TextureFormat::Bc3,
+            Some("bc7") => TextureFormat::Bc7,
+            _ => usage_error(&format!("{option} needs png, webp, ktx2, astc4x4, astc6x6, bc1, bc3 or bc7 like {option}=ktx2")),
+        }
+        };
+        packer = match option.as_str() {
+            "--atlas-width" => packer.atlas_width(number()),
+            "--atlas-height" => packer.atlas_height(number()),
+            "--extrude" => packer.extrude_edges(number()),
+            "--size-budget" => packer.size_budget(number() as u64),
+            "--platform" => match &value {
+                Some(platform) => packer.platform(platform),
+                None => usage_error("--platform needs a name like --platform=web"),
+            },
+            "--split-oversized" => packer.split_oversized(true),
+            "--trim" => packer.trim_transparent(true),
+            "--rotate" => packer.allow_rotation(true),
+            "--rounding" => packer.atlas_rounding(AtlasRounding::all(match value.as_deref() {
+                Some("pow2") => SizeRounding::Pow2,
+                Some("multiple-of-4") => SizeRounding::MultipleOf4,
+                Some("exact") => SizeRounding::Exact,
+                _ => usage_error(&format!(
+                    "{option} needs pow2, multiple-of-4 or exact like {option}=exact"
+                )),
+            })),
+            "--mips" => packer.mip_levels(number()),
+            "--texture-format" => packer.texture_format(texture_format()),
+            "--repeat-format" => packer.repeat_texture_format(texture_format()),
+            "--opaque-rgb" => packer.opaque_rgb(true),
+            "--optimize" => packer.optimize_pngs(true),
+            "--bundles" => packer.bundles(true),
+            "--no-follow-symlinks" => packer.follow_symlinks(false),
+            "--hashed-names" => packer.hashed_file_names(true),
+            "--pak" => packer.pak(true),
+            "--compress-pak" => packer.pak(true).compress_pak(true),
+            "--premultiply" => packer.premultiply_alpha(true),
+            "--clear-color" => packer.clear_colors(ClearColors::all(
+                value
+                    .as_deref()
+                    .filter(|e| e.len() == 8)
+                    .and_then(|e| u32::from_str_radix(e, 16).ok())
+                    .map(u32::to_be_bytes)
+                    .unwrap_or_else(|| {
+                        usage_error(&format!(
+                            "{option} needs a hex color like {option}=ff00ffff"
+                        ))
+                    }),
+            )),
+            "--make-tiling" => packer.make_tiling(true),
+            "--tabular-figures" => packer.tabular_figures(true),
+            "--metric-sizes" => packer.font_metric_sizes(
+                value
+                    .as_deref()
+                    .and_then(|e| e.split(',').map(|size| size.parse().ok()).collect())
+                    .unwrap_or_else(|| {
+                        usage_error(&format!(
+                            "{option} needs pixel sizes like {option}=12,16,24"
+                        ))
+                    }),
+            ),
+            "--msdf" => value
+                .as_deref()
+                .unwrap_or_else(|| {
+                    usage_error(&format!("{option} needs font names like {option}=title"))
+                })
+                .split(',')
+                .fold(packer, |packer, name| packer.msdf_font(name)),
+            "--max-glyphs" => packer.font_max_glyphs(
+                value
+                    .as_deref()
+                    .and_then(|e| e.parse().ok())
+                    .unwrap_or_else(|| {
+                        usage_error(&format!("{option} needs a glyph count like {option}=200"))
+                    }),
+            ),
+            "--scale" => packer.scale(
+                value
+                    .as_deref()
+                    .and_then(|e| e.parse().ok())
+                    .unwrap_or_else(|| {
+                        usage_error(&format!("{option} needs a factor like {option}=0.5"))
+                    }),
+            ),
+            "--variant" => match value
+                .as_deref()
+                .and_then(|e| e.split_once(':'))
+                .and_then(|(suffix, scale)| Some((suffix, scale.parse().ok()?)))
+            {
+                Some((suffix, scale)) => packer.variant(suffix, scale),
+                None => usage_error(&format!(
+                    "{option} needs a suffix and factor like {option}=@2x:2"
+                )),
+            },
+            "--svg-scales" => packer.svg_scales(
+                value
+                    .as_deref()
+                    .and_then(|e| e.split(',').map(|scale| scale.parse().ok()).collect())
+                    .unwrap_or_else(|| {
+                        usage_error(&format!("{option} needs scales like {option}=1,2"))
+                    }),
+            ),
+            "--manifest-format" => packer.manifest_format(match value.as_deref() {
+                Some("json") => ManifestFormat::Json,
+                Some("msgpack") | Some("binary") => ManifestFormat::Binary,
+                _ => usage_error(&format!(
+                    "{option} needs json or msgpack like {option}=msgpack"
+                )),
+            }),
+            "--out" | "--baseline" => packer, // read above
+            "--provenance" => packer.provenance(true),
+            "--graph" => packer.pipeline_graph(true),
+            "--rust-codegen" => packer.rust_codegen(true),
+            "--lua" => packer.script_export(ScriptLanguage::Lua),
+            "--js" => packer.script_export(ScriptLanguage::Js),
+            _ => usage_error(&format!("unknown option {option}")),
+        };
+    }
+
+    if command == "compare" {
+        let Some(baseline) = baseline else {
+            usage_error("compare needs a baseline manifest like --baseline=https://cdn.example.com/packed.json");
+        };
+        // exits with 1 if the pack differs from the baseline, like visual-diff
+        let result = packer.compare(&dest_dir, &baseline);
+        if temporary_dest {
+            _ = std::fs::remove_dir_all(&dest_dir);
+            _ = std::fs::remove_dir_all(format!("{dest_dir}.cache"));
+        }
+        match result {
+            Ok(diff) => {
+                print!("{}", format_manifest_diff(&diff));
+                if !diff.is_empty() {
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+    let result = match command.as_str() {
+        "watch" => packer.watch(&dest_dir),
+        "defrag" => packer.defrag(&dest_dir),
+        _ => packer.pack_to(&dest_dir),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Renders a text with a font of a pack into a png, to debug fonts without starting the game.
+fn text_preview(args: &[String]) {
+    let mut font: Option<String> = None;
+    let mut text: Option<String> = None;
+    let mut positional: Vec<&String> = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let (option, value) = match arg.split_once('=') {
+            Some((option, value)) if arg.starts_with("--") => (option, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let value = || {
+            value
+                .or_else(|| args.next().cloned())
+                .unwrap_or_else(|| usage_error(&format!("{option} needs a value")))
+        };
+        match option {
+            "--font" => font = Some(value()),
+            "--text" => text = Some(value()),
+            _ if option.starts_with("--") => usage_error(&format!("unknown option {option}")),
+            _ => positional.push(arg),
+        }
+    }
+    let text = text.unwrap_or_else(|| usage_error("text-preview needs a text like --text=Hello"));
+    let (packed_dir, out_path) = match positional.as_slice() {
+        [out_path] => ("packed", out_path.as_str()),
+        [packed_dir, out_path] => (packed_dir.as_str(), out_path.as_str()),
+        _ => usage_error("text-preview needs the png to render into"),
+    };
+
+    let result = LoadedAssets::load(packed_dir).and_then(|assets| {
+        let handle = match &font {
+            Some(name) => assets.font_handle(name),
+            None => assets.default_font(),
+        }
+        .ok_or_else(|| PackError::Unsupported {
+            message: format!(
+                "the pack has no font {}",
+                font.as_deref().unwrap_or("default")
+            ),
+        })?;
+        let (image, missing) = render_text(&assets, handle, &text);
+        if !missing.is_empty() {
+            let missing: String = missing.into_iter().collect();
+            println!("characters not in the font, drawn as boxes: {missing}");
+        }
+        image.save(out_path).map_err(PackError::image(out_path))
+    });
+    if let Err(e) = result {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+
+/// Exits with 2 for mistakes in the command line, like the other commands exit for failed packs.
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {message}\n\n{USAGE}");
+    std::process::exit(2);
 }
@@ -0,0 +1,353 @@
+use glam::{vec2, Vec2};
+use image::{Rgb, RgbImage};
+use ttf_parser::{GlyphId, OutlineBuilder};
+
+/// line segments per bezier curve of a glyph outline
+const CURVE_SEGMENTS: usize = 8;
+/// the outline turns by more than about 8° at a corner, smaller kinks get no channel switch
+const CORNER_CROSS: f32 = 0.14;
+
+const RED: u8 = 1;
+const GREEN: u8 = 2;
+const BLUE: u8 = 4;
+const WHITE: u8 = RED | GREEN | BLUE;
+/// colors of the edges between corners, neighbors share exactly one channel
+const CORNER_COLORS: [u8; 3] = [GREEN | BLUE, RED | BLUE, RED | GREEN];
+
+/// A line of a flattened glyph outline, with the channels it contributes to.
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    a: Vec2,
+    b: Vec2,
+    /// the outline has a corner at `a`
+    corner: bool,
+    color: u8,
+}
+
+/// Distance of `p` to an edge, signed by the side of the edge it is on, with the parameter of the
+/// closest point on the edge's line (outside `0..1` past its ends), and how much the direction
+/// from the closest end is aligned with the edge, to pick the edge that is more orthogonal on ties.
+#[derive(Debug, Clone, Copy)]
+struct EdgeDistance {
+    distance: f32,
+    alignment: f32,
+    t: f32,
+}
+
+impl EdgeDistance {
+    fn closer_than(&self, other: &EdgeDistance) -> bool {
+        let (a, b) = (self.distance.abs(), other.distance.abs());
+        a < b || (a == b && self.alignment < other.alignment)
+    }
+}
+
+impl Edge {
+    fn distance(&self, p: Vec2) -> EdgeDistance {
+        let ab = self.b - self.a;
+        let ap = p - self.a;
+        let t = ap.dot(ab) / ab.dot(ab);
+        let closest_end = match t > 0.5 {
+            true => self.b,
+            false => self.a,
+        };
+        let end_distance = closest_end.distance(p);
+        if t > 0.0 && t < 1.0 {
+            let orthogonal = ap.perp_dot(ab) / ab.length();
+            if orthogonal.abs() < end_distance {
+                return EdgeDistance {
+                    distance: orthogonal,
+                    alignment: 0.0,
+                    t,
+                };
+            }
+        }
+        let sign = match ap.perp_dot(ab) < 0.0 {
+            true => -1.0,
+            false => 1.0,
+        };
+        EdgeDistance {
+            distance: sign * end_distance,
+            alignment: ab
+                .normalize()
+                .dot((closest_end - p).normalize_or_zero())
+                .abs(),
+            t,
+        }
+    }
+
+    /// The distance to the line of the edge extended past its ends, if `p` is beside the end it is
+    /// closest to, which keeps the channels of two edges meeting at a corner from rounding it.
+    fn pseudo_distance(&self, p: Vec2, distance: EdgeDistance) -> f32 {
+        let dir = (self.b - self.a).normalize();
+        let end = match distance.t {
+            t if t < 0.0 => Some((self.a, -1.0)),
+            t if t > 1.0 => Some((self.b, 1.0)),
+            _ => None,
+        };
+        if let Some((end, side)) = end {
+            let ep = p - end;
+            if ep.dot(dir) * side > 0.0 {
+                let pseudo = ep.perp_dot(dir);
+                if pseudo.abs() <= distance.distance.abs() {
+                    return pseudo;
+                }
+            }
+        }
+        distance.distance
+    }
+
+    /// How often and in which direction a ray from `p` to the right crosses the edge.
+    fn winding(&self, p: Vec2) -> i32 {
+        let (a, b) = (self.a, self.b);
+        let crosses = (a.y <= p.y) != (b.y <= p.y);
+        if !crosses {
+            return 0;
+        }
+        let x = a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x);
+        match (x > p.x, b.y > a.y) {
+            (false, _) => 0,
+            (true, true) => 1,
+            (true, false) => -1,
+        }
+    }
+}
+
+/// A curve of an outline with its directions at both ends, flattened to lines.
+struct Curve {
+    lines: Vec<(Vec2, Vec2)>,
+    start_dir: Vec2,
+    end_dir: Vec2,
+}
+
+/// Collects the outline of a glyph as contours of curves, in pixels.
+struct Outline {
+    scale: f32,
+    contours: Vec<Vec<Curve>>,
+    contour: Vec<Curve>,
+    start: Vec2,
+    last: Vec2,
+}
+
+impl Outline {
+    fn push(&mut self, points: &[Vec2]) {
+        let first = self.last;
+        let end = *points.last().unwrap();
+        // directions at the ends, the next control point that is not on top of the end
+        let start_dir = points
+            .iter()
+            .map(|p| *p - first)
+            .find(|d| *d != Vec2::ZERO)
+            .unwrap_or(Vec2::ZERO);
+        let end_dir = std::iter::once(first)
+            .chain(points.iter().copied())
+            .rev()
+            .map(|p| end - p)
+            .find(|d| *d != Vec2::ZERO)
+            .unwrap_or(Vec2::ZERO);
+        self.last = end;
+        if start_dir == Vec2::ZERO {
+            return;
+        }
+        let point_at = |t: f32| -> Vec2 {
+            match points {
+                [p1, p2] => first.lerp(*p1, t).lerp(p1.lerp(*p2, t), t),
+                [p1, p2, p3] => {
+                    let (a, b, c) = (first.lerp(*p1, t), p1.lerp(*p2, t), p2.lerp(*p3, t));
+                    a.lerp(b, t).lerp(b.lerp(c, t), t)
+                }
+                _ => first.lerp(end, t),
+            }
+        };
+        let segments = match points.len() {
+            1 => 1,
+            _ => CURVE_SEGMENTS,
+        };
+        let mut lines = vec![];
+        let mut a = first;
+        for i in 1..=segments {
+            let b = point_at(i as f32 / segments as f32);
+            if b != a {
+                lines.push((a, b));
+                a = b;
+            }
+        }
+        self.contour.push(Curve {
+            lines,
+            start_dir,
+            end_dir,
+        });
+    }
+}
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = vec2(x, y) * self.scale;
+        self.last = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(&[vec2(x, y) * self.scale]);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.push(&[vec2(x1, y1) * self.scale, vec2(x, y) * self.scale]);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.push(&[
+            vec2(x1, y1) * self.scale,
+            vec2(x2, y2) * self.scale,
+            vec2(x, y) * self.scale,
+        ]);
+    }
+
+    fn close(&mut self) {
+        if self.last != self.start {
+            self.push(&[self.start]);
+        }
+        let contour = std::mem::take(&mut self.contour);
+        if !contour.is_empty() {
+            self.contours.push(contour);
+        }
+    }
+}
+
+fn is_corner(a: Vec2, b: Vec2) -> bool {
+    let (a, b) = (a.normalize(), b.normalize());
+    a.dot(b) <= 0.0 || a.perp_dot(b).abs() > CORNER_CROSS
+}
+
+/// Flattens the curves of a contour into edges, colored so that the two sides of every corner
+/// are in different channels: contours without corners are white, the edges between corners cycle
+/// through cyan, magenta and yellow, and a contour with a single corner is split into thirds.
+fn colored_edges(contour: &[Curve]) -> Vec<Edge> {
+    let mut edges: Vec<Edge> = vec![];
+    for (i, curve) in contour.iter().enumerate() {
+        let previous = &contour[(i + contour.len() - 1) % contour.len()];
+        let corner = is_corner(previous.end_dir, curve.start_dir);
+        for (j, (a, b)) in curve.lines.iter().enumerate() {
+            edges.push(Edge {
+                a: *a,
+                b: *b,
+                corner: corner && j == 0,
+                color: WHITE,
+            });
+        }
+    }
+    let corners: Vec<usize> = (0..edges.len()).filter(|i| edges[*i].corner).collect();
+    match corners.len() {
+        0 => {}
+        1 if edges.len() < 3 => {}
+        1 => {
+            let colors = [CORNER_COLORS[1], WHITE, CORNER_COLORS[2]];
+            let n = edges.len();
+            for i in 0..n {
+                edges[(corners[0] + i) % n].color = colors[i * 3 / n];
+            }
+        }
+        n => {
+            let mut spline = n - 1;
+            for edge in edges.iter_mut() {
+                if edge.corner {
+                    spline = (spline + 1) % n;
+                }
+                // the last spline also touches the first one, which is cyan
+                edge.color = match spline == n - 1 && spline % 3 == 0 {
+                    true => CORNER_COLORS[1],
+                    false => CORNER_COLORS[spline % 3],
+                };
+            }
+        }
+    }
+    edges
+}
+
+/// Generates the multi-channel signed distance field of a glyph, where one font unit is `scale`
+/// pixels, padded by `pad` on all sides. Returns the image and the position of its bottom left
+/// corner relative to the glyph origin, in pixels with y up.
+///
+/// Each channel holds the distance to the edges of its color, the median of the three is the
+/// distance to the outline with its corners kept sharp. Pixels where the median is on the wrong
+/// side of the outline get the plain distance in all channels.
+pub fn glyph_msdf(
+    face: &ttf_parser::Face,
+    glyph: GlyphId,
+    scale: f32,
+    pad: usize,
+) -> (RgbImage, Vec2) {
+    let mut outline = Outline {
+        scale,
+        contours: vec![],
+        contour: vec![],
+        start: Vec2::ZERO,
+        last: Vec2::ZERO,
+    };
+    face.outline_glyph(glyph, &mut outline);
+    let edges: Vec<Edge> = outline
+        .contours
+        .iter()
+        .flat_map(|contour| colored_edges(contour))
+        .collect();
+    if edges.is_empty() {
+        return (RgbImage::new(1, 1), Vec2::ZERO);
+    }
+
+    let (min, max) = edges.iter().fold((Vec2::MAX, Vec2::MIN), |(min, max), e| {
+        (min.min(e.a.min(e.b)), max.max(e.a.max(e.b)))
+    });
+    let pad = pad as f32;
+    let origin = min.floor() - pad;
+    let size = max.ceil() + pad - origin;
+    let (w, h) = (size.x as u32, size.y as u32);
+    // truetype outlines are clockwise and cff outlines counter clockwise, inside is positive
+    let area: f32 = edges.iter().map(|e| e.a.perp_dot(e.b)).sum();
+    let orientation = match area > 0.0 {
+        true => -1.0,
+        false => 1.0,
+    };
+    let encode = |distance: f32| -> u8 {
+        ((0.5 + distance / (2.0 * pad)) * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+
+    let mut image = RgbImage::new(w, h);
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let p = origin + vec2(x as f32 + 0.5, (h - 1 - y) as f32 + 0.5);
+        let mut closest: [Option<(usize, EdgeDistance)>; 3] = [None, None, None];
+        let mut true_distance = f32::MAX;
+        let mut winding = 0;
+        for (i, edge) in edges.iter().enumerate() {
+            winding += edge.winding(p);
+            let distance = edge.distance(p);
+            true_distance = true_distance.min(distance.distance.abs());
+            for (channel, closest) in closest.iter_mut().enumerate() {
+                if edge.color & (1 << channel) == 0 {
+                    continue;
+                }
+                if closest
+                    .as_ref()
+                    .is_none_or(|(_, c)| distance.closer_than(c))
+                {
+                    *closest = Some((i, distance));
+                }
+            }
+        }
+        let inside = winding != 0;
+        let true_distance = match inside {
+            true => true_distance,
+            false => -true_distance,
+        };
+        let mut channels = closest.map(|closest| match closest {
+            Some((i, distance)) => orientation * edges[i].pseudo_distance(p, distance),
+            None => true_distance,
+        });
+        let [r, g, b] = channels;
+        let median = r.min(g).max(r.max(g).min(b));
+        if (median > 0.0) != inside {
+            channels = [true_distance; 3];
+        }
+        *pixel = Rgb(channels.map(encode));
+    }
+    (image, origin)
+}
@@ -0,0 +1,160 @@
+use std::borrow::Cow;
+
+use image::{
+    error::{DecodingError, EncodingError, ImageFormatHint},
+    ImageError, ImageFormat, ImageResult,
+};
+use png::{AdaptiveFilterType, BitDepth, ColorType, Compression, Decoder, FilterType};
+use rayon::prelude::*;
+
+use crate::{
+    cache::{content_hash, Cache},
+    error::{PackError, PackResult},
+};
+
+/// Row filters tried for every png, the adaptive filter picks one per row.
+const FILTERS: [(FilterType, AdaptiveFilterType); 6] = [
+    (FilterType::NoFilter, AdaptiveFilterType::NonAdaptive),
+    (FilterType::Sub, AdaptiveFilterType::NonAdaptive),
+    (FilterType::Up, AdaptiveFilterType::NonAdaptive),
+    (FilterType::Avg, AdaptiveFilterType::NonAdaptive),
+    (FilterType::Paeth, AdaptiveFilterType::NonAdaptive),
+    (FilterType::Paeth, AdaptiveFilterType::Adaptive),
+];
+
+/// Decoded pixels of a png together with everything needed to encode them again.
+struct RawPng {
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    bit_depth: BitDepth,
+    palette: Option<Vec<u8>>,
+    trns: Option<Vec<u8>>,
+    pixels: Vec<u8>,
+}
+
+/// Re-encodes a png losslessly with the smallest of several filter strategies at the best
+/// compression, without ancillary chunks like text or gamma. 8 bit rgba pngs that are opaque lose
+/// their alpha channel and gray rgb(a) pngs become gray pngs. Returns `None` if the png is already
+/// smaller than all alternatives.
+pub fn optimize_png(bytes: &[u8]) -> ImageResult<Option<Vec<u8>>> {
+    let mut png = decode_raw(bytes)?;
+    reduce_channels(&mut png);
+    let encoded: Vec<Vec<u8>> = FILTERS
+        .par_iter()
+        .map(|(filter, adaptive)| encode_raw(&png, *filter, *adaptive))
+        .collect::<ImageResult<_>>()?;
+    Ok(encoded
+        .into_iter()
+        .min_by_key(|e| e.len())
+        .filter(|e| e.len() < bytes.len()))
+}
+
+/// Optimizes all pngs in `out_path` with [`optimize_png`], reusing the results of previous runs
+/// from the cache. Returns the number of bytes saved.
+pub fn optimize_pngs(out_path: &str, cache: &Cache) -> PackResult<u64> {
+    let dir = std::fs::read_dir(out_path).map_err(PackError::io(out_path))?;
+    let mut paths: Vec<_> = dir
+        .flatten()
+        .map(|e| e.path())
+        .filter(|e| e.extension().is_some_and(|e| e == "png"))
+        .collect();
+    paths.sort();
+    let saved = paths
+        .par_iter()
+        .map(|path| -> PackResult<u64> {
+            let bytes = std::fs::read(path).map_err(PackError::io(path))?;
+            let hash = content_hash(&bytes);
+            if !cache.restore_optimized_png(hash, path) {
+                if let Some(optimized) = optimize_png(&bytes).map_err(PackError::image(path))? {
+                    std::fs::write(path, optimized).map_err(PackError::io(path))?;
+                }
+                cache.store_optimized_png(hash, path)?;
+            }
+            let size = std::fs::metadata(path).map_err(PackError::io(path))?.len();
+            Ok((bytes.len() as u64).saturating_sub(size))
+        })
+        .collect::<PackResult<Vec<u64>>>()?;
+    Ok(saved.into_iter().sum())
+}
+
+fn decode_raw(bytes: &[u8]) -> ImageResult<RawPng> {
+    let png_error = |e: png::DecodingError| {
+        ImageError::Decoding(DecodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Png),
+            e,
+        ))
+    };
+    let mut reader = Decoder::new(bytes).read_info().map_err(png_error)?;
+    let mut pixels: Vec<u8> = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut pixels).map_err(png_error)?;
+    pixels.truncate(frame.buffer_size());
+    let info = reader.info();
+    Ok(RawPng {
+        width: info.width,
+        height: info.height,
+        color_type: info.color_type,
+        bit_depth: info.bit_depth,
+        palette: info.palette.as_deref().map(<[u8]>::to_vec),
+        trns: info.trns.as_deref().map(<[u8]>::to_vec),
+        pixels,
+    })
+}
+
+fn reduce_channels(png: &mut RawPng) {
+    if png.bit_depth != BitDepth::Eight || png.trns.is_some() {
+        return;
+    }
+    let samples = match png.color_type {
+        ColorType::Rgb => 3,
+        ColorType::Rgba => 4,
+        _ => return,
+    };
+    let opaque = samples == 3 || png.pixels.chunks_exact(4).all(|p| p[3] == u8::MAX);
+    let gray = png
+        .pixels
+        .chunks_exact(samples)
+        .all(|p| p[0] == p[1] && p[1] == p[2]);
+    let (color_type, channels): (ColorType, &[usize]) = match (gray, opaque) {
+        (true, true) => (ColorType::Grayscale, &[0]),
+        (true, false) => (ColorType::GrayscaleAlpha, &[0, 3]),
+        (false, true) => (ColorType::Rgb, &[0, 1, 2]),
+        (false, false) => return,
+    };
+    png.pixels = png
+        .pixels
+        .chunks_exact(samples)
+        .flat_map(|p| channels.iter().map(|c| p[*c]))
+        .collect();
+    png.color_type = color_type;
+}
+
+fn encode_raw(
+    png: &RawPng,
+    filter: FilterType,
+    adaptive: AdaptiveFilterType,
+) -> ImageResult<Vec<u8>> {
+    let png_error = |e: png::EncodingError| {
+        ImageError::Encoding(EncodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Png),
+            e,
+        ))
+    };
+    let mut bytes: Vec<u8> = vec![];
+    let mut encoder = png::Encoder::new(&mut bytes, png.width, png.height);
+    encoder.set_color(png.color_type);
+    encoder.set_depth(png.bit_depth);
+    encoder.set_compression(Compression::Best);
+    encoder.set_filter(filter);
+    encoder.set_adaptive_filter(adaptive);
+    if let Some(palette) = &png.palette {
+        encoder.set_palette(Cow::Borrowed(palette.as_slice()));
+    }
+    if let Some(trns) = &png.trns {
+        encoder.set_trns(Cow::Borrowed(trns.as_slice()));
+    }
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(&png.pixels).map_err(png_error)?;
+    writer.finish().map_err(png_error)?;
+    Ok(bytes)
+}
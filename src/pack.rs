@@ -1,139 +1,1300 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::File,
+    io::BufWriter,
+};
 
 use crate::{
-    font::font_to_sdf_font,
-    gather::{FontAsset, GatheredAssets, ImageAsset},
+    astc::astc_block_size,
+    budget::{fit_size_budget, SizeBudget},
+    cache::{content_hash, texture_hash, Cache, ContentHasher, PngChannels},
+    capabilities::EngineCapabilities,
+    codegen::{generate_rust, IdentCase},
+    dds::{block_compression, encode_dds, pad_to_blocks},
+    error::{PackError, PackResult},
+    font::{
+        font_characters, font_to_sdf_font, icons_to_sdf_font, FontSettings, SdfFont, StaticText,
+    },
+    gather::{most_specific_rule, FontAsset, GatheredAssets, ImageAsset, PlatformRule},
+    job::Pipeline,
+    ktx2::{encode_astc_ktx2, encode_ktx2},
+    layout::{AtlasLayout, Page},
+    locale::{check_locales, LocaleCharset},
+    optimize::optimize_pngs,
+    pak::{bundle_pak, PackFiles},
+    postprocess::{TileMeta, TileProcessors},
+    script::{generate_script, ScriptLanguage},
+    warnings::{WarningCode, WarningSettings, Warnings},
+};
+pub use assetpacker_manifest::{
+    binary, AddressMode, AlphaMode, Animation, FilterMode, ManifestVersion, NineSlice,
+    PackedAssets, Playback, Sampler, SectionChecksums, SplitImage, TextureFlags, TextureFormat,
+    TextureTile, TileKind, TileProvenance, Trim, FORMAT_REVISION,
 };
 use glam::{uvec2, UVec2};
-use image::{GenericImage, RgbaImage};
+use image::{
+    codecs::webp::WebPEncoder, ColorType, DynamicImage, GenericImage, GenericImageView, Rgba,
+    RgbaImage,
+};
 use serde::{Deserialize, Serialize};
 
+/// Settings that influence the packed output, part of the fingerprint of a pack.
+///
+/// Can be loaded from an `assetpacker.json` config file in the source dir, see [`crate::Packer::new`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TextureTile {
-    pub atlas: String,
-    pub min: UVec2,
-    pub max: UVec2,
+#[serde(default)]
+pub struct PackSettings {
+    pub atlas_width: u32,
+    pub atlas_height: u32,
+    pub font: FontSettings,
+    /// write an `assets.rs` with constants for all asset identifiers
+    pub rust_codegen: bool,
+    /// identifiers of the generated rust code, constants in SCREAMING_SNAKE_CASE by default
+    pub rust_ident_case: IdentCase,
+    /// also export the manifest as a lua table or js module for scripts
+    pub script_export: Option<ScriptLanguage>,
+    /// keys of the script export, dotted strings like `tiles.button` by default
+    pub script_ident_case: IdentCase,
+    /// crop the fully transparent border of sprites before packing, see [`Trim`]
+    pub trim_transparent: bool,
+    /// pixels that the border of each sprite is extruded into its padding, against bleeding with bilinear
+    /// filtering, at most the padding of the sprite
+    pub extrude_edges: u32,
+    /// rotate sprites by 90° if they only fit into the current atlas that way, see [`TextureTile::rotated`]
+    pub allow_rotation: bool,
+    /// write opaque repeat textures as rgb pngs without alpha channel
+    pub opaque_rgb: bool,
+    /// blend repeat images that almost tile into seamless ones, against the seams W003 warns about.
+    /// Sidecars can turn it on or off for single images.
+    pub make_tiling: bool,
+    /// record the source file of every tile in the manifest, for debug builds, see
+    /// [`PackedAssets::provenance`]
+    pub provenance: bool,
+    /// losslessly shrink all written pngs by trying several filter strategies at the best compression,
+    /// see [`crate::optimize::optimize_png`]. Slow for the first pack, the results are cached.
+    pub optimize_pngs: bool,
+    /// pack the images of each top level dir of the images dir, like `ui` or `characters`, into a
+    /// bundle with its own atlases and manifest in a dir of the same name, see
+    /// [`GatheredAssets::split_bundles`]
+    pub bundles: bool,
+    /// gather the files and dirs that symlinks and junctions in the source dir point to, like a
+    /// shared asset library linked into several projects. Links to a dir they are in are skipped.
+    pub follow_symlinks: bool,
+    /// name the textures and fonts after their content hash like `atlas_0.1a2b3c4d.png`, so web
+    /// games can cache them forever, see [`PackedAssets::file_names`]
+    pub hashed_file_names: bool,
+    /// bundle the manifest, textures and sdf fonts into one `packed.pak`, see
+    /// [`crate::pak::bundle_pak`]
+    pub pak: bool,
+    /// deflate the chunks of the `packed.pak` that get smaller by it, like the manifest, fonts and
+    /// uncompressed gpu textures
+    pub compress_pak: bool,
+    /// premultiply the colors of all sprites by their alpha, see [`AlphaMode::Premultiplied`]. Single
+    /// sprites can opt in with the `pma` flag instead.
+    pub premultiply_alpha: bool,
+    /// factor all images are resized with after loading, fonts stay the same as sdf fonts scale on
+    /// their own. Set for each pack of the `variants`.
+    pub scale: f32,
+    /// additional packs of the same assets at other scales, written next to the destination dir with
+    /// their suffix, like `"@2x": 2.0` for a `packed@2x` dir with all images twice as large
+    pub variants: BTreeMap<String, f32>,
+    /// pixels per unit of the svgs in the images dir, which are rasterized once for each scale, see
+    /// [`crate::svg::Svg`] for the supported subset
+    pub svg_scales: Vec<f32>,
+    /// rounding of the atlas sizes that depend on their content, powers of two by default
+    pub atlas_rounding: AtlasRounding,
+    /// colors the atlases are filled with before the tiles are copied in, transparent black by default
+    pub clear_colors: ClearColors,
+    /// format of all textures except sdf fonts, png by default, `opaque_rgb` only applies to pngs.
+    /// With a block compressed format, sprites are padded to whole blocks that their borders are
+    /// extruded into
+    pub texture_format: TextureFormat,
+    /// format of repeat textures and strips, `texture_format` if `None`, e.g. bc1 for opaque backgrounds
+    /// next to bc7 sprite atlases
+    pub repeat_texture_format: Option<TextureFormat>,
+    /// downscaled levels written for every atlas, each half the size of the previous one, 0 by default
+    pub mip_levels: u32,
+    /// split images larger than the atlas into atlas sized tiles, instead of giving them their own texture
+    pub split_oversized: bool,
+    /// target platform of the pack, assets excluded for it by `platform_rules` are left out
+    pub platform: Option<String>,
+    /// asset file or directory relative to the source dir, e.g. `images/portraits` -> the platforms
+    /// it is packed for. The rule of the most specific path applies.
+    pub platform_rules: BTreeMap<String, PlatformRule>,
+    /// image file or directory relative to the source dir -> whether its sprites are packed into
+    /// atlases with at most 256 colors, written as indexed pngs, for pixel art. The most specific path
+    /// applies, the `pal` flag opts in single images.
+    pub indexed_colors: BTreeMap<String, bool>,
+    /// frame file or directory relative to the source dir -> what animations do after their last
+    /// frame, like `"images/effects": "once"`. The most specific path of the first frame applies,
+    /// animations loop by default.
+    pub animation_playback: BTreeMap<String, Playback>,
+    /// image file or directory relative to the source dir -> streaming priority of its tiles, see
+    /// [`PackedAssets::texture_priorities`]. The most specific path applies, sidecars override it.
+    pub streaming_priorities: BTreeMap<String, u32>,
+    /// locale name -> characters its texts need, checked against the packed fonts, see [`LocaleCharset`]
+    pub locales: BTreeMap<String, LocaleCharset>,
+    /// sprite name -> static string rendered into it with one of the fonts, like a logo or level title
+    pub texts: BTreeMap<String, StaticText>,
+    /// extension of unusual files -> extension of the format they are read as, like
+    /// `"texpng": "png"` or `"fnt2": "ttf"`, or `"data"` to copy them into the pack unchanged, see
+    /// [`PackedAssets::data_files`]
+    pub extensions: BTreeMap<String, String>,
+    /// whether the manifest is written as `packed.json` or as the smaller and faster to load
+    /// `packed.bin`, see [`assetpacker_manifest::binary`]
+    pub manifest_format: ManifestFormat,
+    /// warnings that are not shown
+    pub warnings: WarningSettings,
+    /// write a graphviz graph of the run to the output dir, see [`pipeline_graph`]
+    pub pipeline_graph: bool,
+    /// append the stats of every pack to the history next to the destination, see
+    /// [`crate::stats::history_path`]
+    pub record_stats: bool,
+    /// limits of the engine the pack is made for, not checked if `None`
+    pub capabilities: Option<EngineCapabilities>,
+    /// size the atlas pages should fit into by writing some of them lossy, see [`fit_size_budget`]
+    pub size_budget: Option<SizeBudget>,
+    /// hooks of the library that change the pixels of tiles, see [`crate::Packer::tile_processor`]
+    #[serde(skip_deserializing, skip_serializing_if = "TileProcessors::is_empty")]
+    pub tile_processors: TileProcessors,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
-pub struct TextureFlags {
-    pub repeat_x: bool,
-    pub repeat_y: bool,
+impl Default for PackSettings {
+    fn default() -> Self {
+        PackSettings {
+            atlas_width: 1024,
+            atlas_height: 1024,
+            font: FontSettings::default(),
+            rust_codegen: false,
+            rust_ident_case: IdentCase::default(),
+            script_export: None,
+            script_ident_case: IdentCase::Dotted,
+            split_oversized: false,
+            opaque_rgb: false,
+            optimize_pngs: false,
+            bundles: false,
+            follow_symlinks: true,
+            hashed_file_names: false,
+            pak: false,
+            compress_pak: false,
+            premultiply_alpha: false,
+            provenance: false,
+            make_tiling: false,
+            atlas_rounding: AtlasRounding::default(),
+            clear_colors: ClearColors::default(),
+            svg_scales: vec![1.0],
+            scale: 1.0,
+            variants: BTreeMap::new(),
+            texture_format: TextureFormat::Png,
+            repeat_texture_format: None,
+            trim_transparent: false,
+            extrude_edges: 0,
+            allow_rotation: false,
+            mip_levels: 0,
+            platform: None,
+            locales: BTreeMap::new(),
+            texts: BTreeMap::new(),
+            extensions: BTreeMap::new(),
+            animation_playback: BTreeMap::new(),
+            manifest_format: ManifestFormat::default(),
+            warnings: WarningSettings::default(),
+            pipeline_graph: false,
+            record_stats: true,
+            platform_rules: BTreeMap::new(),
+            streaming_priorities: BTreeMap::new(),
+            indexed_colors: BTreeMap::new(),
+            capabilities: None,
+            size_budget: None,
+            tile_processors: TileProcessors::default(),
+        }
+    }
 }
 
-impl TextureFlags {
-    pub const REPEAT: TextureFlags = TextureFlags {
-        repeat_x: true,
-        repeat_y: true,
-    };
-    pub const REPEAT_X: TextureFlags = TextureFlags {
-        repeat_x: true,
-        repeat_y: false,
-    };
-    pub const NO_REPEAT: TextureFlags = TextureFlags {
-        repeat_x: false,
-        repeat_y: false,
+impl PackSettings {
+    /// Format a texture with these flags is written in. Single channel and indexed textures are
+    /// always pngs.
+    pub fn texture_format_for(&self, flags: &TextureFlags) -> TextureFormat {
+        if flags.single_channel || flags.indexed {
+            return TextureFormat::Png;
+        }
+        match flags.repeat_x || flags.repeat_y {
+            true => self.repeat_texture_format.unwrap_or(self.texture_format),
+            false => self.texture_format,
+        }
+    }
+}
+
+/// Straight alpha rgba colors of the free space in the atlases of each group, like opaque magenta in
+/// debug packs to spot uv errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ClearColors {
+    /// atlases of sprites
+    pub sprites: [u8; 4],
+    /// atlases of repeat strips
+    pub repeat: [u8; 4],
+    /// atlases of masks, only the red channel is written
+    pub masks: [u8; 4],
+}
+
+impl ClearColors {
+    /// The same color for all groups.
+    pub fn all(color: [u8; 4]) -> Self {
+        ClearColors {
+            sprites: color,
+            repeat: color,
+            masks: color,
+        }
+    }
+
+    /// The color of an atlas with these flags, premultiplied for premultiplied atlases.
+    pub fn for_flags(&self, flags: &TextureFlags) -> Rgba<u8> {
+        let [r, g, b, a] = if flags.single_channel {
+            self.masks
+        } else if flags.repeat_x || flags.repeat_y {
+            self.repeat
+        } else {
+            self.sprites
+        };
+        match flags.alpha {
+            AlphaMode::Straight => Rgba([r, g, b, a]),
+            AlphaMode::Premultiplied => {
+                let premultiply = |c: u8| ((c as u32 * a as u32 + 127) / 255) as u8;
+                Rgba([premultiply(r), premultiply(g), premultiply(b), a])
+            }
+        }
+    }
+}
+
+/// File format of the manifest of a pack. Sdf fonts are always json.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestFormat {
+    #[default]
+    Json,
+    /// MessagePack, see [`assetpacker_manifest::binary`]. Readable with any msgpack library, like
+    /// the ones of lua or js runtimes whose json parsing is slow. `"msgpack"` in the config.
+    #[serde(alias = "msgpack", alias = "message_pack")]
+    Binary,
+}
+
+impl ManifestFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ManifestFormat::Json => "packed.json",
+            ManifestFormat::Binary => "packed.bin",
+        }
+    }
+}
+
+/// Reads the manifest of the pack in `dir`, `packed.bin` if the pack was written with the binary
+/// manifest format, `packed.json` otherwise, from the `packed.pak` if the pack was bundled.
+/// Manifests of newer format revisions are rejected before they are misread, those of older
+/// revisions are migrated.
+pub fn read_manifest(dir: &str) -> PackResult<PackedAssets> {
+    read_pack_manifest(&PackFiles::open(dir)?)
+}
+
+pub(crate) fn read_pack_manifest(files: &PackFiles) -> PackResult<PackedAssets> {
+    match files.read(ManifestFormat::Binary.file_name()) {
+        Ok(bytes) => {
+            let path = files.path(ManifestFormat::Binary.file_name());
+            parse_manifest(&path, &bytes, ManifestFormat::Binary)
+        }
+        Err(_) => {
+            let path = files.path(ManifestFormat::Json.file_name());
+            let bytes = files.read(ManifestFormat::Json.file_name())?;
+            parse_manifest(&path, &bytes, ManifestFormat::Json)
+        }
+    }
+}
+
+/// Reads a manifest from its bytes, checking its version and migrating it to the current format
+/// revision. `path` is only used in errors.
+pub(crate) fn parse_manifest(
+    path: &str,
+    bytes: &[u8],
+    format: ManifestFormat,
+) -> PackResult<PackedAssets> {
+    let mut manifest = match format {
+        ManifestFormat::Binary => {
+            let binary_error = |e: binary::BinaryError| PackError::InvalidManifest {
+                message: format!("{path}: {e}"),
+            };
+            check_manifest_version(path, binary::from_slice(bytes).map_err(binary_error)?)?;
+            binary::from_slice(bytes).map_err(binary_error)?
+        }
+        ManifestFormat::Json => {
+            check_manifest_version(
+                path,
+                serde_json::from_slice(bytes).map_err(PackError::json(path))?,
+            )?;
+            serde_json::from_slice(bytes).map_err(PackError::json(path))?
+        }
     };
+    migrate_manifest(&mut manifest);
+    Ok(manifest)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct PackedAssets {
-    pub textures: Vec<(String, TextureFlags)>, // file names
-    pub tiles: HashMap<String, TextureTile>,
-    pub fonts: Vec<String>, // font names
-    pub default_font: String,
+fn check_manifest_version(path: &str, version: ManifestVersion) -> PackResult<()> {
+    match version.format_revision > FORMAT_REVISION {
+        true => Err(PackError::InvalidManifest {
+            message: format!(
+                "{path} was written by assetpacker {} with format revision {}, this version reads up to revision {FORMAT_REVISION}, update it",
+                version.version, version.format_revision
+            ),
+        }),
+        false => Ok(()),
+    }
 }
 
-pub fn pack_assets(gathered: &GatheredAssets, out_path: &str) {
-    _ = std::fs::remove_dir_all(out_path);
-    std::fs::create_dir(out_path).unwrap();
+/// Brings a manifest of an older format revision up to the current one.
+fn migrate_manifest(manifest: &mut PackedAssets) {
+    // the other fields added since revision 0 have serde defaults
+    if manifest.format_revision == 0 {
+        for (_, flags) in manifest.textures.iter_mut() {
+            flags.sampler = Some(flags.sampler());
+        }
+    }
+    manifest.format_revision = FORMAT_REVISION;
+}
 
-    let mut packed = PackedAssets::default();
+/// How the size of atlases that shrink to fit their content is rounded up, in each group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct AtlasRounding {
+    /// atlases of repeat strips, along the axis the strips are stacked on
+    pub strips: SizeRounding,
+    /// sdf font and icon atlases
+    pub fonts: SizeRounding,
+}
 
-    let (textures, tiles) = make_texture_atlases(&gathered.images);
-    packed.tiles = tiles;
-    for (i, (rgba, flags)) in textures.iter().enumerate() {
-        let texture_name = atlas_name(i);
-        rgba.save(format!("{out_path}/{texture_name}.png")).unwrap();
-        packed.textures.push((texture_name, *flags));
+impl AtlasRounding {
+    /// The same rounding for all groups.
+    pub fn all(rounding: SizeRounding) -> Self {
+        AtlasRounding {
+            strips: rounding,
+            fonts: rounding,
+        }
     }
-    let mut default_font: Option<String> = None;
-    for (name, font) in gathered.fonts.iter() {
-        if font.is_default {
-            default_font = Some(name.clone());
+}
+
+/// Rounding of an atlas size. Modern GPUs do not need power of two sizes, but block compressed
+/// formats need multiples of 4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SizeRounding {
+    /// the next power of two
+    #[default]
+    Pow2,
+    /// the next multiple of 4
+    #[serde(rename = "multiple_of_4")]
+    MultipleOf4,
+    /// no rounding
+    Exact,
+}
+
+impl SizeRounding {
+    pub fn round(self, size: u32) -> u32 {
+        match self {
+            SizeRounding::Pow2 => next_pow2_number(size as usize) as u32,
+            SizeRounding::MultipleOf4 => size.next_multiple_of(4),
+            SizeRounding::Exact => size,
+        }
+    }
+}
+
+pub fn font_checksum(json: &[u8], png: &[u8]) -> u64 {
+    let mut hasher = ContentHasher::new();
+    hasher.write(&(json.len() as u64).to_le_bytes());
+    hasher.write(json);
+    hasher.write(png);
+    hasher.finish()
+}
+
+/// Gathers the assets in `src_dir` and packs them into `out_path`, and into a dir next to it for each
+/// of the `variants` of the settings.
+///
+/// Skips all work for packs whose inputs have the same fingerprint as the ones the existing pack was made from.
+pub fn gather_and_pack(
+    src_dir: &str,
+    out_path: &str,
+    settings: &PackSettings,
+    cache: &mut Cache,
+) -> PackResult<()> {
+    let mut pipeline = Pipeline::new(src_dir, out_path, settings);
+    while pipeline.run_stage(cache)? {}
+    Ok(())
+}
+
+/// name of the font that the svgs in the `icons` dir are assembled into
+pub const ICON_FONT_NAME: &str = "icons";
+
+pub fn pack_assets(
+    gathered: &GatheredAssets,
+    out_path: &str,
+    settings: &PackSettings,
+    cache: &mut Cache,
+    warnings: &mut Warnings,
+) -> PackResult<PackedAssets> {
+    let adjusted_settings;
+    let settings = match &settings.capabilities {
+        Some(capabilities) => {
+            adjusted_settings = capabilities.adjust_settings(gathered, settings, warnings)?;
+            &adjusted_settings
         }
+        None => settings,
+    };
+    let check_texture = |name: &str, size: (u32, u32)| -> PackResult<()> {
+        match &settings.capabilities {
+            Some(capabilities) => capabilities.check_texture(name, size),
+            None => Ok(()),
+        }
+    };
 
-        let (sdf_font, sdf_image) = font_to_sdf_font(font);
+    check_repeat_seams(gathered, warnings);
+    for path in gathered.skipped_files.iter() {
+        warnings.warn(
+            WarningCode::W006,
+            Some(path),
+            format!(
+                "skipped {}, images need to be png, jpg, bmp, tga, webp, gif, aseprite, psd or svg files",
+                path.display()
+            ),
+        );
+    }
 
-        std::fs::write(
-            format!("{out_path}/{}.sdf_font.json", name),
-            serde_json::to_string(&sdf_font).unwrap(),
-        )
-        .unwrap();
-        sdf_image
-            .save(format!("{out_path}/{}.sdf_font.png", name))
-            .unwrap();
+    _ = std::fs::remove_dir_all(out_path);
+    std::fs::create_dir(out_path).map_err(PackError::io(out_path))?;
 
-        packed.fonts.push(name.clone());
+    let mut packed = PackedAssets {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        format_revision: FORMAT_REVISION,
+        scale: settings.scale,
+        bundles: gathered.bundles.clone(),
+        ..Default::default()
+    };
+
+    // textures with the same pixels and flags are only written once, tiles of duplicates reference the first
+    let mut written: HashMap<(u64, TextureFlags), String> = HashMap::new();
+    let mut write_texture = |packed: &mut PackedAssets,
+                             cache: &mut Cache,
+                             name: &str,
+                             rgba: &RgbaImage,
+                             mut flags: TextureFlags|
+     -> PackResult<String> {
+        if flags.repeat_x || flags.repeat_y {
+            flags.is_opaque = rgba.pixels().all(|p| p[3] == u8::MAX);
+        }
+        flags.format = settings.texture_format_for(&flags);
+        let rgba = &*block_aligned(name, rgba, flags)?;
+        let key = (texture_hash(rgba), flags);
+        if let Some(existing) = written.get(&key) {
+            println!("texture {name} is identical to {existing}, writing it only once");
+            return Ok(existing.clone());
+        }
+        check_texture(name, rgba.dimensions())?;
+        match flags.format {
+            TextureFormat::Png => {
+                let channels = png_channels(flags, settings);
+                cache.save_texture(rgba, &format!("{out_path}/{name}.png"), channels)?;
+            }
+            TextureFormat::Webp => write_webp(rgba, &format!("{out_path}/{name}.webp"))?,
+            _ => write_gpu_texture(&[rgba], flags, &format!("{out_path}/{name}"))?,
+        }
+        packed.textures.push((name.to_string(), flags));
+        written.insert(key, name.to_string());
+        Ok(name.to_string())
+    };
+
+    let (trimmed, trims) = match settings.trim_transparent {
+        true => trim_images(&gathered.images),
+        false => (HashMap::new(), HashMap::new()),
+    };
+    let images = match settings.trim_transparent {
+        true => &trimmed,
+        false => &gathered.images,
+    };
+    let previous = cache.atlas_layout(out_path);
+    let (mut textures, mut tiles, splits, layout) =
+        make_texture_atlases(images, settings, previous.as_ref(), warnings)?;
+    cache.store_atlas_layout(out_path, layout);
+    for (name, trim) in trims {
+        if let Some(tile) = tiles.get_mut(&name) {
+            tile.trim = Some(trim);
+        }
+    }
+    if !settings.tile_processors.is_empty() {
+        process_tiles(&mut textures, &tiles, images, &settings.tile_processors);
+    }
+    packed.splits = splits;
+    for (i, (rgba, flags)) in textures.iter().enumerate() {
+        let name = atlas_name(i);
+        let format = settings.texture_format_for(flags);
+        let rgba = &*block_aligned(&name, rgba, TextureFlags { format, ..*flags })?;
+        let texture_name = write_texture(&mut packed, cache, &name, rgba, *flags)?;
+        if texture_name != name {
+            for tile in tiles.values_mut().filter(|tile| tile.atlas == name) {
+                tile.atlas = texture_name.clone();
+            }
+        } else if settings.mip_levels > 0 {
+            let regions: Vec<(UVec2, UVec2)> = tiles
+                .values()
+                .filter(|tile| tile.atlas == name)
+                .map(|tile| (tile.min, tile.max))
+                .collect();
+            let flags = packed.textures.last().unwrap().1;
+            let levels = write_mips(rgba, &regions, flags, &name, settings, out_path, cache)?;
+            packed.mip_levels.insert(name, levels);
+        }
     }
-    packed.default_font = default_font.expect("there should be one default font");
 
-    for (name, asset) in gathered.images.iter() {
+    // no_pack images get textures of their own, before the tiles are complete for the metadata below
+    let mut no_pack_names: Vec<&String> = gathered.images.keys().collect();
+    no_pack_names.sort(); // which duplicate is written should not depend on hash map order
+    for name in no_pack_names {
+        let asset = &gathered.images[name];
         if asset.no_pack {
             let (w, h) = asset.rgba.dimensions();
-            packed.textures.push((
-                name.clone(),
-                TextureFlags {
-                    repeat_x: asset.repeat_x,
-                    repeat_y: asset.repeat_y,
-                },
-            ));
-            packed.tiles.insert(
+            let flags = TextureFlags {
+                repeat_x: asset.repeat_x,
+                repeat_y: asset.repeat_y,
+                is_opaque: false,
+                format: settings.texture_format,
+                srgb: !asset.linear && !asset.mask,
+                single_channel: asset.mask,
+                alpha: alpha_mode(asset),
+                indexed: asset.indexed,
+                sampler: Some(asset.sampler),
+            };
+            let format = settings.texture_format_for(&flags);
+            let rgba = &*block_aligned(name, &asset.rgba, TextureFlags { format, ..flags })?;
+            let atlas = write_texture(&mut packed, cache, name, rgba, flags)?;
+            if atlas == *name && settings.mip_levels > 0 {
+                let regions = [(uvec2(0, 0), uvec2(w, h))];
+                let flags = packed.textures.last().unwrap().1;
+                let levels = write_mips(rgba, &regions, flags, name, settings, out_path, cache)?;
+                packed.mip_levels.insert(name.clone(), levels);
+            }
+            tiles.insert(
                 name.clone(),
                 TextureTile {
-                    atlas: name.clone(),
+                    atlas,
                     min: uvec2(0, 0),
                     max: uvec2(w, h),
+                    trim: None,
+                    rotated: false,
+                    kind: tile_kind(asset),
+                    nine_slice: asset.nine_slice,
+                    pivot: asset.pivot,
+                    layer: asset.layer,
                 },
             );
-            asset.rgba.save(format!("{out_path}/{name}.png")).unwrap();
         }
     }
+    if let Some(budget) = &settings.size_budget {
+        let pages: Vec<(String, &RgbaImage, PngChannels)> = textures
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (rgba, _))| {
+                let name = atlas_name(i);
+                let (_, flags) = packed.textures.iter().find(|e| e.0 == name)?;
+                let channels = png_channels(*flags, settings);
+                let lossless = flags.format == TextureFormat::Png
+                    && matches!(channels, PngChannels::Rgba | PngChannels::Rgb);
+                lossless.then_some((name, rgba, channels))
+            })
+            .collect();
+        fit_size_budget(&pages, &mut packed, out_path, budget, cache, warnings)?;
+    }
+    packed.tiles = tiles;
+    packed.animations = collect_animations(gathered, &packed.tiles, settings)?;
+    packed.texture_priorities = texture_priorities(&gathered.images, &packed);
+    if settings.provenance {
+        packed.provenance = tile_provenance(gathered, &packed);
+    }
+    add_tile_metadata(gathered, &mut packed);
+    let mut default_font: Option<String> = None;
+    for (name, font) in gathered.fonts.iter() {
+        if font.is_default {
+            default_font = Some(name.clone());
+        }
+
+        let json_path = format!("{out_path}/{}.sdf_font.json", name);
+        let png_path = format!("{out_path}/{}.sdf_font.png", name);
+        let characters = font_characters(
+            &settings.font,
+            font,
+            locale_characters(settings, name, font),
+        );
+        let mut hasher = ContentHasher::new();
+        hasher.write(&font.content_hash.to_le_bytes());
+        hasher.write(&font.face_index.to_le_bytes());
+        hasher.write(format!("{:?}", font.distance_field).as_bytes());
+        hasher.write(name.as_bytes());
+        hasher.write(serde_json::to_string(&settings.font).unwrap().as_bytes());
+        hasher.write(format!("{:?}", settings.atlas_rounding.fonts).as_bytes());
+        hasher.write(String::from_iter(&characters).as_bytes());
+        let font_hash = hasher.finish();
+        if !cache.restore_sdf_font(font_hash, &json_path, &png_path) {
+            let (sdf_font, sdf_image) = font_to_sdf_font(
+                font,
+                &settings.font,
+                settings.atlas_rounding.fonts,
+                &characters,
+            )?;
+            write_sdf_font(&sdf_font, &sdf_image, &json_path, &png_path)?;
+            cache.store_sdf_font(font_hash, &json_path, &png_path)?;
+        }
+        check_texture(name, image_dimensions(&png_path)?)?;
+        warn_skipped_glyphs(font, &characters, &json_path, warnings)?;
+
+        packed.fonts.push(name.clone());
+    }
+    // packs without fonts are fine, but if there are fonts, one of them has to be the default
+    if default_font.is_none() && !gathered.fonts.is_empty() {
+        return Err(PackError::MissingDefaultFont);
+    }
+    packed.default_font = default_font;
+
+    if !gathered.icons.is_empty() {
+        let name = ICON_FONT_NAME;
+        if let Some(font) = gathered.fonts.get(name) {
+            return Err(PackError::Font {
+                path: font.entry.path.clone(),
+                message: format!("the font name {name} is reserved for the icon font"),
+            });
+        }
+        let json_path = format!("{out_path}/{name}.sdf_font.json");
+        let png_path = format!("{out_path}/{name}.sdf_font.png");
+        let mut icon_names: Vec<&String> = gathered.icons.keys().collect();
+        icon_names.sort();
+        let mut hasher = ContentHasher::new();
+        for icon_name in icon_names {
+            hasher.write(icon_name.as_bytes());
+            hasher.write(&gathered.icons[icon_name].content_hash.to_le_bytes());
+        }
+        hasher.write(format!("{:?}", settings.atlas_rounding.fonts).as_bytes());
+        let font_hash = hasher.finish();
+        if !cache.restore_sdf_font(font_hash, &json_path, &png_path) {
+            let (sdf_font, sdf_image) =
+                icons_to_sdf_font(name, &gathered.icons, settings.atlas_rounding.fonts)?;
+            write_sdf_font(&sdf_font, &sdf_image.into(), &json_path, &png_path)?;
+            cache.store_sdf_font(font_hash, &json_path, &png_path)?;
+        }
+        check_texture(name, image_dimensions(&png_path)?)?;
+        packed.fonts.push(name.to_string());
+    }
+
+    check_locales(&settings.locales, out_path, &packed)?;
+    packed.data_files = copy_data_files(gathered, out_path)?;
+
+    if settings.optimize_pngs {
+        let saved = optimize_pngs(out_path, cache)?;
+        println!("optimizing pngs saved {} KB", saved / 1024);
+    }
+    packed.checksums = section_checksums(&packed, out_path)?;
+    if settings.hashed_file_names {
+        packed.file_names = hash_file_names(&packed, out_path)?;
+    }
+    let manifest_path = format!("{out_path}/{}", settings.manifest_format.file_name());
+    let manifest = match settings.manifest_format {
+        ManifestFormat::Json => {
+            serde_json::to_vec(&packed).map_err(PackError::json(&manifest_path))?
+        }
+        ManifestFormat::Binary => {
+            binary::to_vec(&packed).map_err(|e| PackError::InvalidManifest {
+                message: format!("{manifest_path}: {e}"),
+            })?
+        }
+    };
+    std::fs::write(&manifest_path, manifest).map_err(PackError::io(&manifest_path))?;
+    if settings.pak {
+        bundle_pak(out_path, &packed, settings)?;
+    }
+    if settings.rust_codegen {
+        let codegen_path = format!("{out_path}/assets.rs");
+        let code = generate_rust(&packed, gathered, settings.rust_ident_case, warnings)?;
+        std::fs::write(&codegen_path, code).map_err(PackError::io(&codegen_path))?;
+    }
+    if let Some(language) = settings.script_export {
+        let script_path = format!("{out_path}/{}", language.file_name());
+        let script = generate_script(
+            &packed,
+            gathered,
+            language,
+            settings.script_ident_case,
+            warnings,
+        );
+        std::fs::write(&script_path, script).map_err(PackError::io(&script_path))?;
+    }
+    cache.save()?;
+    Ok(packed)
+}
+
+/// The files a runtime loads besides the manifest, the textures with their mip levels and the sdf
+/// fonts, as the name of the texture or font and the rest of the file name, like `mip1.png`.
+pub(crate) fn pack_file_names(packed: &PackedAssets) -> Vec<(&str, String)> {
+    let mut file_names: Vec<(&str, String)> = vec![];
+    for (name, flags) in packed.textures.iter() {
+        let extension = flags.format.extension();
+        file_names.push((name, extension.to_string()));
+        if flags.format.is_image_file() {
+            let levels = packed.mip_levels.get(name).copied().unwrap_or(0);
+            file_names.extend(
+                (1..=levels).map(|level| (name.as_str(), format!("mip{level}.{extension}"))),
+            );
+        }
+    }
+    for name in packed.fonts.iter() {
+        file_names.push((name, String::from("sdf_font.json")));
+        file_names.push((name, String::from("sdf_font.png")));
+    }
+    file_names
+}
+
+/// Renames the files of the pack to names with their content hash before the extension, like
+/// `atlas_0.1a2b3c4d.png`, and returns the new names by the old ones.
+fn hash_file_names(packed: &PackedAssets, out_path: &str) -> PackResult<BTreeMap<String, String>> {
+    let mut file_names: BTreeMap<String, String> = BTreeMap::new();
+    for (name, extension) in pack_file_names(packed) {
+        let file_name = format!("{name}.{extension}");
+        let path = format!("{out_path}/{file_name}");
+        let hash = content_hash(&std::fs::read(&path).map_err(PackError::io(&path))?);
+        let hashed = format!("{name}.{:08x}.{extension}", hash as u32);
+        let hashed_path = format!("{out_path}/{hashed}");
+        std::fs::rename(&path, &hashed_path).map_err(PackError::io(&path))?;
+        file_names.insert(file_name, hashed);
+    }
+    Ok(file_names)
+}
+
+fn section_checksums(packed: &PackedAssets, out_path: &str) -> PackResult<SectionChecksums> {
+    let read = |path: String| std::fs::read(&path).map_err(PackError::io(&path));
+    let mut checksums = SectionChecksums::default();
+    for (name, flags) in packed.textures.iter() {
+        let file = read(format!("{out_path}/{name}.{}", flags.format.extension()))?;
+        checksums.textures.insert(name.clone(), content_hash(&file));
+    }
+    let mip_files = packed.mip_levels.iter().filter_map(|(name, levels)| {
+        let (_, flags) = packed.textures.iter().find(|(e, _)| e == name)?;
+        flags
+            .format
+            .is_image_file()
+            .then_some((name, levels, flags.format))
+    });
+    for (name, levels, format) in mip_files {
+        for level in 1..=*levels {
+            let mip = read(format!(
+                "{out_path}/{name}.mip{level}.{}",
+                format.extension()
+            ))?;
+            checksums
+                .textures
+                .insert(format!("{name}.mip{level}"), content_hash(&mip));
+        }
+    }
+    for name in packed.fonts.iter() {
+        let json = read(format!("{out_path}/{name}.sdf_font.json"))?;
+        let png = read(format!("{out_path}/{name}.sdf_font.png"))?;
+        checksums
+            .fonts
+            .insert(name.clone(), font_checksum(&json, &png));
+    }
+    Ok(checksums)
+}
+
+fn write_sdf_font(
+    sdf_font: &SdfFont,
+    sdf_image: &DynamicImage,
+    json_path: &str,
+    png_path: &str,
+) -> PackResult<()> {
+    let json = serde_json::to_string(sdf_font).map_err(PackError::json(json_path))?;
+    std::fs::write(json_path, json).map_err(PackError::io(json_path))?;
+    sdf_image.save(png_path).map_err(PackError::image(png_path))
+}
+
+fn png_channels(flags: TextureFlags, settings: &PackSettings) -> PngChannels {
+    if flags.single_channel {
+        PngChannels::Gray
+    } else if flags.indexed {
+        PngChannels::Indexed
+    } else if flags.is_opaque && settings.opaque_rgb {
+        PngChannels::Rgb
+    } else {
+        PngChannels::Rgba
+    }
+}
+
+/// Writes a texture as a lossless webp. Lossy webps would need libwebp, which is not a dependency.
+fn write_webp(rgba: &RgbaImage, path: &str) -> PackResult<()> {
+    let file = File::create(path).map_err(PackError::io(path))?;
+    WebPEncoder::new_lossless(BufWriter::new(file))
+        .encode(rgba, rgba.width(), rgba.height(), ColorType::Rgba8)
+        .map_err(PackError::image(path))
+}
+
+/// Writes a texture with its mip levels into one file, for the formats other than png and webp. `path` is
+/// without extension.
+fn write_gpu_texture(levels: &[&RgbaImage], flags: TextureFlags, path: &str) -> PackResult<()> {
+    let path = format!("{path}.{}", flags.format.extension());
+    let bytes = match (
+        block_compression(flags.format),
+        astc_block_size(flags.format),
+    ) {
+        (Some(compression), _) => encode_dds(levels, compression, flags.srgb),
+        (None, Some(size)) => encode_astc_ktx2(levels, size, flags.srgb),
+        (None, None) => encode_ktx2(levels, flags.srgb),
+    };
+    std::fs::write(&path, bytes).map_err(PackError::io(&path))
+}
+
+/// Block compressed textures need sizes that are multiples of 4. Other textures are padded with
+/// transparent pixels, tiles keep their pixel positions. Repeat textures would not repeat seamlessly
+/// anymore, so they fail.
+fn block_aligned<'a>(
+    name: &str,
+    rgba: &'a RgbaImage,
+    flags: TextureFlags,
+) -> PackResult<Cow<'a, RgbaImage>> {
+    let (w, h) = rgba.dimensions();
+    if block_compression(flags.format).is_none() || (w % 4 == 0 && h % 4 == 0) {
+        return Ok(Cow::Borrowed(rgba));
+    }
+    if (flags.repeat_x && w % 4 != 0) || (flags.repeat_y && h % 4 != 0) {
+        return Err(PackError::Unsupported {
+            message: format!(
+                "repeat texture {name} is {w}x{h}, {:?} compression needs sizes that are multiples of 4",
+                flags.format
+            ),
+        });
+    }
+    Ok(Cow::Owned(pad_to_blocks(rgba)))
+}
+
+/// Size of the blocks of a compressed format, `None` for formats that compress pixels independently.
+fn compression_block_size(format: TextureFormat) -> Option<UVec2> {
+    match astc_block_size(format) {
+        Some(size) => Some(size.dimensions().into()),
+        None => block_compression(format).map(|_| uvec2(4, 4)),
+    }
+}
+
+fn image_dimensions(path: &str) -> PackResult<(u32, u32)> {
+    image::image_dimensions(path).map_err(PackError::image(path))
+}
+
+fn collect_animations(
+    gathered: &GatheredAssets,
+    tiles: &HashMap<String, TextureTile>,
+    settings: &PackSettings,
+) -> PackResult<BTreeMap<String, Animation>> {
+    let images = &gathered.images;
+    let playback = |first_frame: &str| {
+        let path = &images[first_frame].entry.path;
+        let dir = gathered.dir.to_string_lossy();
+        most_specific_rule(&settings.animation_playback, &dir, path)
+            .copied()
+            .unwrap_or_default()
+    };
+    let mut frames: BTreeMap<&str, Vec<(u32, &str)>> = BTreeMap::new();
+    for (ident, asset) in images.iter() {
+        if let Some((name, index)) = &asset.animation {
+            frames.entry(name).or_default().push((*index, ident));
+        }
+    }
+    // durations are only known if all frames have one
+    let durations = |frames: &[String]| -> Vec<u32> {
+        frames
+            .iter()
+            .map(|e| images[e].frame_duration)
+            .collect::<Option<Vec<u32>>>()
+            .unwrap_or_default()
+    };
+    let mut animations: BTreeMap<String, Animation> = frames
+        .into_iter()
+        .map(|(name, mut frames)| {
+            frames.sort();
+            let frames: Vec<String> = frames.into_iter().map(|(_, e)| e.to_string()).collect();
+            let animation = Animation {
+                texture: tiles[&frames[0]].atlas.clone(),
+                fps: images[&frames[0]].animation_fps,
+                durations: durations(&frames),
+                playback: playback(&frames[0]),
+                frames,
+            };
+            (name.to_string(), animation)
+        })
+        .collect();
+    // the frames of tags are part of the animation of their whole file, so they are on one texture
+    for (name, frames) in gathered.animation_tags.iter() {
+        if animations.contains_key(name) {
+            return Err(PackError::Animation {
+                name: name.clone(),
+                message: String::from("an aseprite tag has the same name as another animation"),
+            });
+        }
+        let animation = Animation {
+            texture: tiles[&frames[0]].atlas.clone(),
+            fps: None,
+            durations: durations(frames),
+            playback: playback(&frames[0]),
+            frames: frames.clone(),
+        };
+        animations.insert(name.clone(), animation);
+    }
+    Ok(animations)
+}
+
+/// Source file of every tile packed from an image.
+fn tile_provenance(
+    gathered: &GatheredAssets,
+    packed: &PackedAssets,
+) -> BTreeMap<String, TileProvenance> {
+    let mut provenance: BTreeMap<String, TileProvenance> = BTreeMap::new();
+    for (ident, asset) in gathered.images.iter() {
+        let path = &asset.entry.path;
+        let relative = path.strip_prefix(&gathered.dir).unwrap_or(path);
+        let source: Vec<String> = relative
+            .components()
+            .map(|e| e.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let tile_provenance = TileProvenance {
+            source: source.join("/"),
+            hash: asset.content_hash,
+            flags: asset.entry.flags.to_string(),
+        };
+        let tile_names = match packed.splits.get(ident) {
+            Some(split) => split.tiles.clone(),
+            None => vec![ident.clone()],
+        };
+        for tile_name in tile_names {
+            if packed.tiles.contains_key(&tile_name) {
+                provenance.insert(tile_name, tile_provenance.clone());
+            }
+        }
+    }
+    provenance
+}
+
+/// Runs the tile processors on the pixels of every tile in the atlases, once for tiles that share
+/// their pixels.
+fn process_tiles(
+    textures: &mut [(RgbaImage, TextureFlags)],
+    tiles: &HashMap<String, TextureTile>,
+    images: &HashMap<String, ImageAsset>,
+    processors: &TileProcessors,
+) {
+    let mut names: Vec<&String> = tiles.keys().collect();
+    names.sort();
+    let mut processed: HashSet<(&str, UVec2, UVec2)> = HashSet::new();
+    for name in names {
+        let tile = &tiles[name];
+        if !processed.insert((&tile.atlas, tile.min, tile.max)) {
+            continue;
+        }
+        let Some(i) = (0..textures.len()).find(|i| atlas_name(*i) == tile.atlas) else {
+            continue;
+        };
+        let asset = images.get(name);
+        let meta = TileMeta {
+            name,
+            atlas: &tile.atlas,
+            group: asset.and_then(|e| e.entry.dir_config.atlas_group.as_deref()),
+            source: asset.map(|e| e.entry.path.as_path()),
+            min: tile.min,
+            max: tile.max,
+            rotated: tile.rotated,
+        };
+        let mut group_processors = processors.for_group(meta.group).peekable();
+        if group_processors.peek().is_none() {
+            continue;
+        }
+        let atlas = &mut textures[i].0;
+        let size = tile.max - tile.min;
+        let mut pixels = atlas
+            .view(tile.min.x, tile.min.y, size.x, size.y)
+            .to_image();
+        for processor in group_processors {
+            processor(&mut pixels, &meta);
+        }
+        let pixels = image::imageops::crop_imm(&pixels, 0, 0, size.x, size.y).to_image();
+        image::imageops::replace(atlas, &pixels, tile.min.x as i64, tile.min.y as i64);
+    }
+}
+
+/// Copies the data files into the `data` dir of the pack, keeping their names.
+fn copy_data_files(
+    gathered: &GatheredAssets,
+    out_path: &str,
+) -> PackResult<BTreeMap<String, String>> {
+    let mut data_files: BTreeMap<String, String> = BTreeMap::new();
+    if gathered.data_files.is_empty() {
+        return Ok(data_files);
+    }
+    let data_dir = format!("{out_path}/data");
+    std::fs::create_dir_all(&data_dir).map_err(PackError::io(&data_dir))?;
+    for (name, entry) in gathered.data_files.iter() {
+        let file_name = entry.path.file_name().unwrap().to_string_lossy();
+        let path = format!("{data_dir}/{file_name}");
+        std::fs::copy(&entry.path, &path).map_err(PackError::io(&entry.path))?;
+        data_files.insert(name.clone(), format!("data/{file_name}"));
+    }
+    Ok(data_files)
+}
+
+/// Writes the tags and user data of every tile packed from an image with a sidecar that has some.
+fn add_tile_metadata(gathered: &GatheredAssets, packed: &mut PackedAssets) {
+    for (ident, asset) in gathered.images.iter() {
+        if asset.tags.is_empty() && asset.user_data.is_empty() {
+            continue;
+        }
+        let tile_names = match packed.splits.get(ident) {
+            Some(split) => split.tiles.clone(),
+            None => vec![ident.clone()],
+        };
+        for tile_name in tile_names {
+            if !packed.tiles.contains_key(&tile_name) {
+                continue;
+            }
+            if !asset.tags.is_empty() {
+                packed.tags.insert(tile_name.clone(), asset.tags.clone());
+            }
+            if !asset.user_data.is_empty() {
+                packed.user_data.insert(tile_name, asset.user_data.clone());
+            }
+        }
+    }
+}
+
+/// The highest streaming priority of the tiles on each texture, textures without prioritized tiles are left out.
+fn texture_priorities(
+    images: &HashMap<String, ImageAsset>,
+    packed: &PackedAssets,
+) -> BTreeMap<String, u32> {
+    let mut priorities: BTreeMap<String, u32> = BTreeMap::new();
+    for (ident, asset) in images.iter() {
+        let Some(priority) = asset.streaming_priority else {
+            continue;
+        };
+        let tile_names = match packed.splits.get(ident) {
+            Some(split) => split.tiles.clone(),
+            None => vec![ident.clone()],
+        };
+        for tile_name in tile_names {
+            if let Some(tile) = packed.tiles.get(&tile_name) {
+                let max = priorities.entry(tile.atlas.clone()).or_default();
+                *max = (*max).max(priority);
+            }
+        }
+    }
+    priorities
+}
+
+/// Characters of the locales whose texts are rendered with the font, see [`LocaleCharset::fonts`].
+/// Invalid charsets are reported later by [`check_locales`].
+fn locale_characters(settings: &PackSettings, name: &str, font: &FontAsset) -> BTreeSet<char> {
+    settings
+        .locales
+        .values()
+        .filter(|e| match e.fonts.is_empty() {
+            true => font.is_default,
+            false => e.fonts.iter().any(|e| e == name),
+        })
+        .filter_map(|e| e.chars().ok())
+        .flatten()
+        .collect()
+}
+
+/// Warns about the characters that were left out of a generated font because of the glyph budget.
+fn warn_skipped_glyphs(
+    font: &FontAsset,
+    characters: &[char],
+    json_path: &str,
+    warnings: &mut Warnings,
+) -> PackResult<()> {
+    let json = std::fs::read(json_path).map_err(PackError::io(json_path))?;
+    let sdf_font: SdfFont = serde_json::from_slice(&json).map_err(PackError::json(json_path))?;
+    let skipped: Vec<String> = characters
+        .iter()
+        .filter(|ch| !sdf_font.glyphs.contains_key(ch))
+        .map(|ch| format!("U+{:04X} {}", *ch as u32, ch.escape_debug()))
+        .collect();
+    if !skipped.is_empty() {
+        warnings.warn(
+            WarningCode::W007,
+            Some(&font.entry.path),
+            format!(
+                "the font exceeds its glyph budget, {} characters are skipped: {}",
+                skipped.len(),
+                skipped.join(", ")
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// Warns about repeat images whose opposite edges differ so much that tiling them shows seams.
+fn check_repeat_seams(gathered: &GatheredAssets, warnings: &mut Warnings) {
+    // mean difference per channel, out of 255
+    const MAX_EDGE_DIFFERENCE: f32 = 24.0;
+    let edge_difference = |a: &mut dyn Iterator<Item = &Rgba<u8>>,
+                           b: &mut dyn Iterator<Item = &Rgba<u8>>| {
+        let (mut sum, mut count) = (0u64, 0u64);
+        for (a, b) in a.zip(b) {
+            for c in 0..4 {
+                sum += a[c].abs_diff(b[c]) as u64;
+                count += 1;
+            }
+        }
+        sum as f32 / count.max(1) as f32
+    };
+
+    let mut names: Vec<&String> = gathered.images.keys().collect();
+    names.sort();
+    for name in names {
+        let asset = &gathered.images[name];
+        let rgba = &asset.rgba;
+        let (w, h) = rgba.dimensions();
+        if w == 0 || h == 0 {
+            continue;
+        }
+        let mut seams: Vec<&str> = vec![];
+        if asset.repeat_x {
+            let mut left = (0..h).map(|y| rgba.get_pixel(0, y));
+            let mut right = (0..h).map(|y| rgba.get_pixel(w - 1, y));
+            if edge_difference(&mut left, &mut right) > MAX_EDGE_DIFFERENCE {
+                seams.push("left/right");
+            }
+        }
+        if asset.repeat_y {
+            let mut top = (0..w).map(|x| rgba.get_pixel(x, 0));
+            let mut bottom = (0..w).map(|x| rgba.get_pixel(x, h - 1));
+            if edge_difference(&mut top, &mut bottom) > MAX_EDGE_DIFFERENCE {
+                seams.push("top/bottom");
+            }
+        }
+        if !seams.is_empty() {
+            warnings.warn(
+                WarningCode::W003,
+                Some(&asset.entry.path),
+                format!(
+                    "the {} edges of repeat image {name} do not match, it will show seams when tiled, the make_tiling setting can blend them",
+                    seams.join(" and ")
+                ),
+            );
+        }
+    }
+}
 
-    std::fs::write(
-        format!("{out_path}/packed.json"),
-        serde_json::to_string(&packed).unwrap(),
-    )
-    .unwrap();
+/// Crops the fully transparent border of all images that are packed into atlases, and moves their
+/// pivots and nine slice borders along. Returns the images with the trimmed ones replaced, and where
+/// the trimmed images were in their source image.
+fn trim_images(
+    images: &HashMap<String, ImageAsset>,
+) -> (HashMap<String, ImageAsset>, HashMap<String, Trim>) {
+    let mut trimmed: HashMap<String, ImageAsset> = HashMap::new();
+    let mut trims: HashMap<String, Trim> = HashMap::new();
+    for (name, asset) in images.iter() {
+        let mut asset = asset.clone();
+        if is_plain_sprite(&asset) {
+            let (w, h) = asset.rgba.dimensions();
+            let (min, max) = opaque_bounds(&asset.rgba);
+            if (min, max) != (uvec2(0, 0), uvec2(w, h)) {
+                let size = max - min;
+                asset.rgba =
+                    image::imageops::crop_imm(&asset.rgba, min.x, min.y, size.x, size.y).to_image();
+                // borders that reached into the trimmed margin shrink by it, and never overlap
+                asset.nine_slice = asset.nine_slice.map(|slice| {
+                    let left = slice.left.saturating_sub(min.x).min(size.x);
+                    let top = slice.top.saturating_sub(min.y).min(size.y);
+                    NineSlice {
+                        left,
+                        right: slice.right.saturating_sub(w - max.x).min(size.x - left),
+                        top,
+                        bottom: slice.bottom.saturating_sub(h - max.y).min(size.y - top),
+                    }
+                });
+                asset.pivot = asset.pivot.map(|pivot| pivot - min.as_vec2());
+                trims.insert(
+                    name.clone(),
+                    Trim {
+                        original_size: uvec2(w, h),
+                        offset: min,
+                    },
+                );
+            }
+        }
+        trimmed.insert(name.clone(), asset);
+    }
+    (trimmed, trims)
 }
 
-//  returns pad_x and pad_y
+/// Bounding rect of the pixels that are not fully transparent, at least one pixel large.
+fn opaque_bounds(rgba: &RgbaImage) -> (UVec2, UVec2) {
+    let (mut min, mut max) = (uvec2(u32::MAX, u32::MAX), uvec2(0, 0));
+    for (x, y, p) in rgba.enumerate_pixels() {
+        if p[3] != 0 {
+            min = min.min(uvec2(x, y));
+            max = max.max(uvec2(x + 1, y + 1));
+        }
+    }
+    if max == uvec2(0, 0) {
+        return (
+            uvec2(0, 0),
+            uvec2(1, 1).min(uvec2(rgba.width(), rgba.height())),
+        );
+    }
+    (min, max)
+}
+
+/// Padding of a sprite in the atlas, 2 pixels unless the [`crate::gather::DirConfig`] of its dir
+/// sets another.
 fn pad_for_image_asset(asset: &ImageAsset) -> (u32, u32) {
-    let pad_x: u32;
-    let pad_y: u32;
-    if asset
+    // a `pad=8` flag overrides the padding of the dir config
+    if let Some(padding) = asset
         .entry
-        .asset_path
-        .path()
-        .last()
-        .is_some_and(|e| e == "characters")
+        .all_flags()
+        .value("pad")
+        .and_then(|e| e.parse().ok())
     {
-        let (w, h) = asset.rgba.dimensions();
-        pad_x = w / 8;
-        pad_y = h / 8;
-    } else {
-        pad_x = 2;
-        pad_y = 2;
+        return (padding, padding);
+    }
+    let config = &asset.entry.dir_config;
+    match (config.padding, config.relative_padding) {
+        (_, Some(fraction)) => {
+            let (w, h) = asset.rgba.dimensions();
+            ((w as f32 * fraction) as u32, (h as f32 * fraction) as u32)
+        }
+        (Some(padding), None) => (padding, padding),
+        (None, None) => (2, 2),
     }
-
-    (pad_x, pad_y)
 }
 
+/// pixel hash, animation, atlas flags and atlas group of a sprite, sprites with the same key share a tile
+type DuplicateKey<'a> = (u64, Option<&'a str>, TextureFlags, Option<&'a str>);
+
+/// atlas images with their flags, the tile of every image that was packed into one of them and the
+/// layout of the sprite atlases
+type TextureAtlases = (
+    Vec<(RgbaImage, TextureFlags)>,
+    HashMap<String, TextureTile>,
+    HashMap<String, SplitImage>,
+    AtlasLayout,
+);
+
+/// Packs the images into atlases. Sprites keep their place in the `previous` layout of the pack if
+/// their size did not change, see [`crate::layout`].
 pub fn make_texture_atlases(
     images: &HashMap<String, ImageAsset>,
-) -> (Vec<(RgbaImage, TextureFlags)>, HashMap<String, TextureTile>) {
-    let atlas_w: u32 = 1024; // todo! incorporate things like max_width and min_width here...
-    let atlas_h: u32 = 1024;
+    settings: &PackSettings,
+    previous: Option<&AtlasLayout>,
+    warnings: &mut Warnings,
+) -> PackResult<TextureAtlases> {
+    let atlas_w: u32 = settings.atlas_width;
+    let atlas_h: u32 = settings.atlas_height;
 
     let mut atlases: Vec<(RgbaImage, TextureFlags)> = vec![];
 
@@ -146,50 +1307,100 @@ pub fn make_texture_atlases(
         .map(|e| (e, false))
         .collect();
     sorted.sort_by(|a, b| {
-        match a
-            .0
-            .entry
-            .asset_path
-            .path()
-            .cmp(&b.0.entry.asset_path.path())
-        {
+        match a.0.entry.asset_path.path().cmp(b.0.entry.asset_path.path()) {
             std::cmp::Ordering::Equal => {
                 let h1 = a.0.rgba.height();
                 let h2 = b.0.rgba.height();
-                h2.cmp(&h1)
+                // ident as tie breaker keeps the layout stable between runs, so cached atlases can be reused
+                h2.cmp(&h1).then_with(|| {
+                    a.0.entry
+                        .asset_path
+                        .ident()
+                        .cmp(b.0.entry.asset_path.ident())
+                })
             }
             e => e,
         }
     });
 
+    // sprites with the same pixels as an earlier sprite share its tile, recolored directories often
+    // contain identical frames
+    // frames are only shared within their animation, so it stays on one atlas, and sprites only
+    // within their atlas group
+    let mut first_with_pixels: HashMap<DuplicateKey, &str> = HashMap::new();
+    let mut duplicates: Vec<(&str, &str)> = vec![];
+    for (asset, allocated) in sorted.iter_mut() {
+        if !is_plain_sprite(asset) {
+            continue;
+        }
+        let ident = asset.entry.asset_path.ident();
+        let animation = asset.animation.as_ref().map(|(name, _)| name.as_str());
+        let key = (
+            texture_hash(&asset.rgba),
+            animation,
+            sprite_atlas_flags(asset),
+            asset.entry.dir_config.atlas_group.as_deref(),
+        );
+        match first_with_pixels.entry(key) {
+            Entry::Occupied(first) => {
+                warnings.warn(
+                    WarningCode::W001,
+                    Some(&asset.entry.path),
+                    format!(
+                        "image {ident} is identical to {}, sharing its tile",
+                        first.get()
+                    ),
+                );
+                duplicates.push((ident, first.get()));
+                *allocated = true;
+            }
+            Entry::Vacant(e) => {
+                e.insert(ident);
+            }
+        }
+    }
+
     // first handle the images that need some sort of tiling:
 
     let mut min_w: u32 = u32::MAX; // all of these min max only across non-repeat images
     let mut min_h: u32 = u32::MAX;
     let mut max_w: u32 = 0;
     let mut max_h: u32 = 0;
-    let mut rep_x_buckets: HashMap<u32, Vec<(usize, u32)>> = HashMap::new(); // maps width to indices and thierheight
-    let mut rep_y_buckets: HashMap<u32, Vec<(usize, u32)>> = HashMap::new(); // maps height to indices and their and width
+    let mut rep_x_buckets: HashMap<StripKey, Vec<(usize, u32)>> = HashMap::new(); // maps width to indices and thierheight
+    let mut rep_y_buckets: HashMap<StripKey, Vec<(usize, u32)>> = HashMap::new(); // maps height to indices and their and width
 
     for (i, (e, allocated)) in sorted.iter_mut().enumerate() {
         let (w, h) = e.rgba.dimensions();
 
-        if e.repeat_x && e.repeat_y {
-            // if repx and repy give it its own texture
+        if e.has_own_repeat_texture() {
             tiles.insert(
                 e.entry.asset_path.ident().to_owned(),
                 TextureTile {
-                    atlas: e.entry.asset_path.ident().to_string(),
+                    atlas: atlas_name(atlases.len()),
                     min: uvec2(0, 0),
                     max: uvec2(w, h),
+                    trim: None,
+                    rotated: false,
+                    kind: tile_kind(e),
+                    nine_slice: e.nine_slice,
+                    pivot: e.pivot,
+                    layer: e.layer,
                 },
             );
-            atlases.push((e.rgba.clone(), TextureFlags::REPEAT));
+            let flags = TextureFlags {
+                repeat_x: e.repeat_x,
+                repeat_y: e.repeat_y,
+                sampler: Some(e.sampler),
+                ..with_color_flags(TextureFlags::REPEAT, e.linear, e.mask)
+            };
+            atlases.push((e.rgba.clone(), flags));
             *allocated = true;
         } else if e.repeat_x {
-            rep_x_buckets.entry(w).or_default().push((i, h));
+            let key = (w, e.linear, e.mask);
+            rep_x_buckets.entry(key).or_default().push((i, h));
         } else if e.repeat_y {
-            rep_y_buckets.entry(h).or_default().push((i, w));
+            let key = (h, e.linear, e.mask);
+            rep_y_buckets.entry(key).or_default().push((i, w));
         } else {
             if h < min_h {
                 min_h = h;
@@ -206,14 +1417,36 @@ pub fn make_texture_atlases(
         }
     }
 
-    for (width, entries) in rep_x_buckets.iter() {
-        let pad = 2;
+    let pad = 2;
+    let extrusion = |pad_x: u32, pad_y: u32| {
+        (
+            settings.extrude_edges.min(pad_x),
+            settings.extrude_edges.min(pad_y),
+        )
+    };
+    // buckets whose strips do not fit below each other into the atlas height are split into several atlases
+    let strip_too_large = |i: usize, size: (u32, u32)| {
+        let asset = sorted[i].0;
+        PackError::ImageTooLarge {
+            ident: asset.entry.asset_path.ident().to_owned(),
+            size,
+            max: (atlas_w, atlas_h),
+        }
+    };
+    let rep_x_strips = strips_per_atlas(rep_x_buckets, pad, atlas_h)
+        .map_err(|(i, width, h)| strip_too_large(i, (width, h + pad)))?;
+    let rep_y_strips = strips_per_atlas(rep_y_buckets, pad, atlas_w)
+        .map_err(|(i, height, w)| strip_too_large(i, (w + pad, height)))?;
+
+    for ((width, linear, mask), entries) in rep_x_strips.iter() {
+        let strip_flags = with_color_flags(TextureFlags::REPEAT_X, *linear, *mask);
         let entries_height: u32 = entries.iter().map(|e| e.1 + pad).sum::<u32>();
 
         let mut asset_paths_of_bucket: HashSet<Vec<String>> = HashSet::new();
 
-        let height = next_pow2_number(entries_height as usize).max(256) as u32;
-        let mut atlas: RgbaImage = RgbaImage::new(*width, height);
+        let height = strip_atlas_size(entries_height, settings).min(atlas_h);
+        let clear_color = settings.clear_colors.for_flags(&strip_flags);
+        let mut atlas: RgbaImage = RgbaImage::from_pixel(*width, height, clear_color);
 
         let mut y: u32 = 0;
 
@@ -227,6 +1460,12 @@ pub fn make_texture_atlases(
                 atlas: atlas_name(atlases.len()),
                 min: uvec2(0, y),
                 max: uvec2(atlas.width(), y + *h),
+                trim: None,
+                rotated: false,
+                kind: tile_kind(asset),
+                nine_slice: asset.nine_slice,
+                pivot: asset.pivot,
+                layer: asset.layer,
             };
 
             y += *h + pad;
@@ -242,8 +1481,13 @@ pub fn make_texture_atlases(
                 AtlasAllocator::new(size2(*width as i32, remaining_height as i32));
 
             for (asset, allocated) in sorted.iter_mut() {
-                if asset_paths_of_bucket.contains(asset.entry.asset_path.path()) {
-                    let (pad_x, pad_y) = pad_for_image_asset(*asset);
+                if !*allocated
+                    && is_plain_sprite(asset)
+                    && asset.animation.is_none()
+                    && sprite_atlas_flags(asset) == no_repeat_flags(*linear, *mask)
+                    && asset_paths_of_bucket.contains(asset.entry.asset_path.path())
+                {
+                    let (pad_x, pad_y) = pad_for_image_asset(asset);
                     let (w, h) = asset.rgba.dimensions();
                     let alloc_size = size2((w + 2 * pad_x) as i32, (h + 2 * pad_y) as i32);
                     if let Some(allocation) = remaining_size_allocator.allocate(alloc_size) {
@@ -251,16 +1495,24 @@ pub fn make_texture_atlases(
                             allocation.rectangle.min.x as u32,
                             allocation.rectangle.min.y as u32,
                         );
-                        y += entries_height + pad_x;
-                        x += pad_y;
+                        y += entries_height + pad_y;
+                        x += pad_x;
 
                         // copy the image over and set allocated to true:
                         *allocated = true;
-                        atlas.copy_from(&asset.rgba, x, y).unwrap();
+                        let extrude =
+                            extruded_area(uvec2(x, y), uvec2(w, h), extrusion(pad_x, pad_y));
+                        copy_sprite(&mut atlas, &asset.rgba, x, y, extrude);
                         let tile = TextureTile {
                             atlas: atlas_name(atlases.len()),
                             min: uvec2(x, y),
                             max: uvec2(x + w, y + h),
+                            trim: None,
+                            rotated: false,
+                            kind: TileKind::Sprite,
+                            nine_slice: asset.nine_slice,
+                            pivot: asset.pivot,
+                            layer: asset.layer,
                         };
                         tiles.insert(asset.entry.asset_path.ident().to_owned(), tile);
                     }
@@ -268,60 +1520,669 @@ pub fn make_texture_atlases(
             }
         }
 
-        atlases.push((atlas, TextureFlags::REPEAT_X));
+        atlases.push((atlas, strip_flags));
     }
 
-    for (height, entries) in rep_y_buckets.iter() {
-        todo!("do the same as above for the rep_x_buckets. Was not really needed yet, so I saved the 5 min.");
+    for ((height, linear, mask), entries) in rep_y_strips.iter() {
+        let strip_flags = with_color_flags(TextureFlags::REPEAT_Y, *linear, *mask);
+        let entries_width: u32 = entries.iter().map(|e| e.1 + pad).sum::<u32>();
+
+        let mut asset_paths_of_bucket: HashSet<Vec<String>> = HashSet::new();
+
+        let width = strip_atlas_size(entries_width, settings).min(atlas_w);
+        let clear_color = settings.clear_colors.for_flags(&strip_flags);
+        let mut atlas: RgbaImage = RgbaImage::from_pixel(width, *height, clear_color);
+
+        let mut x: u32 = 0;
+
+        // allocate the horizontal strips:
+        for (i, w) in entries.iter() {
+            let (asset, allocated) = &mut sorted[*i];
+            *allocated = true;
+            atlas.copy_from(&asset.rgba, x, 0).unwrap();
+
+            let tile = TextureTile {
+                atlas: atlas_name(atlases.len()),
+                min: uvec2(x, 0),
+                max: uvec2(x + *w, atlas.height()),
+                trim: None,
+                rotated: false,
+                kind: tile_kind(asset),
+                nine_slice: asset.nine_slice,
+                pivot: asset.pivot,
+                layer: asset.layer,
+            };
+
+            x += *w + pad;
+            tiles.insert(asset.entry.asset_path.ident().to_owned(), tile);
+
+            asset_paths_of_bucket.insert(asset.entry.asset_path.path().to_vec());
+        }
+
+        // try to put some images around in the remaining width:
+        let remaining_width = width - entries_width;
+        if remaining_width >= min_w {
+            let mut remaining_size_allocator =
+                AtlasAllocator::new(size2(remaining_width as i32, *height as i32));
+
+            for (asset, allocated) in sorted.iter_mut() {
+                if !*allocated
+                    && is_plain_sprite(asset)
+                    && asset.animation.is_none()
+                    && sprite_atlas_flags(asset) == no_repeat_flags(*linear, *mask)
+                    && asset_paths_of_bucket.contains(asset.entry.asset_path.path())
+                {
+                    let (pad_x, pad_y) = pad_for_image_asset(asset);
+                    let (w, h) = asset.rgba.dimensions();
+                    let alloc_size = size2((w + 2 * pad_x) as i32, (h + 2 * pad_y) as i32);
+                    if let Some(allocation) = remaining_size_allocator.allocate(alloc_size) {
+                        let (mut x, mut y) = (
+                            allocation.rectangle.min.x as u32,
+                            allocation.rectangle.min.y as u32,
+                        );
+                        x += entries_width + pad_x;
+                        y += pad_y;
+
+                        // copy the image over and set allocated to true:
+                        *allocated = true;
+                        let extrude =
+                            extruded_area(uvec2(x, y), uvec2(w, h), extrusion(pad_x, pad_y));
+                        copy_sprite(&mut atlas, &asset.rgba, x, y, extrude);
+                        let tile = TextureTile {
+                            atlas: atlas_name(atlases.len()),
+                            min: uvec2(x, y),
+                            max: uvec2(x + w, y + h),
+                            trim: None,
+                            rotated: false,
+                            kind: TileKind::Sprite,
+                            nine_slice: asset.nine_slice,
+                            pivot: asset.pivot,
+                            layer: asset.layer,
+                        };
+                        tiles.insert(asset.entry.asset_path.ident().to_owned(), tile);
+                    }
+                }
+            }
+        }
+
+        atlases.push((atlas, strip_flags));
     }
 
     use guillotiere::{size2, AtlasAllocator};
-    let mut allocator = AtlasAllocator::new(size2(atlas_w as i32, atlas_h as i32));
+    // with block compression, the padded sprites take whole blocks that their borders are extruded
+    // into, so no block mixes the pixels of two sprites and compression artifacts do not bleed
+    let block = compression_block_size(settings.texture_format);
+    let alignment = block.unwrap_or(UVec2::ONE);
+    let atlas_size = uvec2(atlas_w, atlas_h);
 
-    // let mut allocator = AtlasAllocator::new(Size::new(atlas_w as i32, atlas_h as i32));
-    let mut atlas = RgbaImage::new(atlas_w, atlas_h);
+    let mut oversized: Vec<&ImageAsset> = vec![];
+    // sprites are placed in units, all frames of an animation together at the position of its first
+    // frame, so they end up on the same atlas
+    let mut units: Vec<Vec<&ImageAsset>> = vec![];
+    let mut animation_units: HashMap<&str, usize> = HashMap::new();
     for (asset, allocated) in sorted.iter_mut() {
         if *allocated {
             continue;
         }
-        let (pad_x, pad_y) = pad_for_image_asset(*asset);
-        let (w, h) = asset.rgba.dimensions();
+        *allocated = true;
+        match &asset.animation {
+            Some((name, _)) => match animation_units.entry(name) {
+                Entry::Occupied(unit) => units[*unit.get()].push(*asset),
+                Entry::Vacant(e) => {
+                    e.insert(units.len());
+                    units.push(vec![*asset]);
+                }
+            },
+            None => units.push(vec![*asset]),
+        }
+    }
+    // premultiplied, linear, indexed and nearest filtered sprites and masks come last and get atlases
+    // of their own, as do the atlas groups of dirs
+    units.sort_by_key(|sprites| {
+        let sprite = sprites[0];
+        (
+            sprite.entry.dir_config.atlas_group.clone(),
+            sprite.premultiplied,
+            sprite.linear,
+            sprite.indexed,
+            sprite.mask,
+            sprite.sampler.filter == FilterMode::Nearest,
+        )
+    });
 
-        if w > atlas_w || h > atlas_h {
-            panic!("Only textures up to 1024x1024 supported! Just increase the allocator size if really necessary");
+    // sprites of the previous layout keep their place if their size and atlas did not change, the
+    // frames of an animation only all together
+    let mut pages: Vec<Page> = previous
+        .and_then(|layout| layout.replay(atlas_size, alignment))
+        .unwrap_or_default();
+    let placed: HashMap<String, usize> = pages
+        .iter()
+        .enumerate()
+        .flat_map(|(i, page)| page.sprites.keys().map(move |ident| (ident.clone(), i)))
+        .collect();
+    let mut kept: HashSet<&str> = HashSet::new();
+    let mut unplaced: Vec<Vec<&ImageAsset>> = vec![];
+    for sprites in units {
+        if let Some(too_large) = sprites
+            .iter()
+            .find(|asset| sprite_orientations(asset, settings).is_empty())
+        {
+            if let Some((name, _)) = &too_large.animation {
+                let (w, h) = too_large.rgba.dimensions();
+                return Err(PackError::Animation {
+                    name: name.clone(),
+                    message: format!(
+                        "frame {} ({w}x{h}) does not fit into the atlas",
+                        too_large.entry.asset_path.ident()
+                    ),
+                });
+            }
+            // textures are added after the last atlas, so the names of its tiles stay valid
+            oversized.push(*too_large);
+            continue;
+        }
+        let ident = sprites[0].entry.asset_path.ident();
+        let in_place = placed.get(ident).is_some_and(|i| {
+            let page = &pages[*i];
+            sprites.iter().all(|asset| {
+                let ident = asset.entry.asset_path.ident();
+                page.flags == sprite_atlas_flags(asset)
+                    && page.group == asset.entry.dir_config.atlas_group
+                    && page
+                        .sprites
+                        .get(ident)
+                        .is_some_and(|(_, size, rotated)| *size == allocation_size(asset, *rotated))
+            })
+        });
+        match in_place {
+            true => kept.extend(sprites.iter().map(|e| e.entry.asset_path.ident())),
+            false => unplaced.push(sprites),
+        }
+    }
+    if !pages.is_empty() {
+        println!(
+            "atlas layout: {} sprites kept their place, {} are placed again",
+            kept.len(),
+            unplaced.iter().map(Vec::len).sum::<usize>()
+        );
+    }
+    for page in pages.iter_mut() {
+        let removed: Vec<String> = page
+            .sprites
+            .keys()
+            .filter(|ident| !kept.contains(ident.as_str()))
+            .cloned()
+            .collect();
+        for ident in removed {
+            page.remove(&ident);
         }
+    }
 
-        let alloc_size = size2((w + pad_x * 2) as i32, (h + pad_y * 2) as i32);
-        let allocation = if let Some(alloc) = allocator.allocate(alloc_size) {
-            alloc
-        } else {
-            // allocator is full, put in new allocator, flush atlas
-            let last_atlas = std::mem::replace(&mut atlas, RgbaImage::new(atlas_w, atlas_h));
-            atlases.push((last_atlas, TextureFlags::NO_REPEAT));
-            allocator = AtlasAllocator::new(size2(atlas_w as i32, atlas_h as i32));
-            allocator
-                .allocate(alloc_size)
-                .expect("The new allocator should be big enough now")
+    for sprites in unplaced {
+        let flags = sprite_atlas_flags(sprites[0]);
+        let group = &sprites[0].entry.dir_config.atlas_group;
+        // the first atlas of the flags and group with room for the sprites
+        let fitting = pages
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, page)| page.flags == flags && page.group == *group)
+            .find_map(|(i, page)| {
+                allocate_sprites(&mut page.allocator, &sprites, settings).map(|e| (i, e))
+            });
+        let (i, allocations) = match fitting {
+            Some(fitting) => fitting,
+            None => {
+                let mut page = Page::new(flags, group.clone(), atlas_size, alignment);
+                let allocations = allocate_sprites(&mut page.allocator, &sprites, settings)
+                    .ok_or_else(|| {
+                        let (name, _) = sprites[0]
+                            .animation
+                            .as_ref()
+                            .expect("The new allocator should be big enough for a single sprite");
+                        PackError::Animation {
+                            name: name.clone(),
+                            message: format!(
+                                "its {} frames do not fit into one atlas of {atlas_w}x{atlas_h}, increase the atlas size or split it into several animations",
+                                sprites.len()
+                            ),
+                        }
+                    })?;
+                pages.push(page);
+                (pages.len() - 1, allocations)
+            }
         };
-        let (mut x, mut y) = (
-            allocation.rectangle.min.x as u32,
-            allocation.rectangle.min.y as u32,
+        for (asset, (allocation, rotated)) in sprites.iter().zip(allocations) {
+            let ident = asset.entry.asset_path.ident();
+            pages[i].insert(ident, allocation_size(asset, rotated), allocation, rotated);
+        }
+    }
+
+    // atlases whose sprites were all removed are dropped
+    pages.retain(|page| !page.sprites.is_empty());
+    let sprites_by_ident: HashMap<&str, &ImageAsset> = sorted
+        .iter()
+        .map(|(asset, _)| (asset.entry.asset_path.ident(), *asset))
+        .collect();
+    for page in pages.iter() {
+        let clear_color = settings.clear_colors.for_flags(&page.flags);
+        let mut atlas = RgbaImage::from_pixel(atlas_w, atlas_h, clear_color);
+        for (ident, (allocation, _, rotated)) in page.sprites.iter() {
+            let asset = sprites_by_ident[ident.as_str()];
+            let rotated = *rotated;
+            let (pad_x, pad_y) = pad_for_image_asset(asset);
+            let rotated_rgba;
+            let (rgba, pad_x, pad_y) = if rotated {
+                rotated_rgba = image::imageops::rotate90(&asset.rgba);
+                (&rotated_rgba, pad_y, pad_x)
+            } else {
+                (&asset.rgba, pad_x, pad_y)
+            };
+            let (w, h) = rgba.dimensions();
+            let x = allocation.rectangle.min.x as u32 + pad_x;
+            let y = allocation.rectangle.min.y as u32 + pad_y;
+            let extrude = match block {
+                Some(_) => {
+                    let max = allocation.rectangle.max;
+                    (
+                        uvec2(x - pad_x, y - pad_y),
+                        uvec2(max.x as u32, max.y as u32),
+                    )
+                }
+                None => extruded_area(uvec2(x, y), uvec2(w, h), extrusion(pad_x, pad_y)),
+            };
+            copy_sprite(&mut atlas, rgba, x, y, extrude);
+            let tile = TextureTile {
+                atlas: atlas_name(atlases.len()),
+                min: uvec2(x, y),
+                max: uvec2(x + w, y + h),
+                trim: None,
+                rotated,
+                kind: tile_kind(asset),
+                nine_slice: asset.nine_slice,
+                pivot: asset.pivot,
+                layer: asset.layer,
+            };
+            tiles.insert(ident.clone(), tile);
+        }
+        atlases.push((atlas, page.flags));
+    }
+    let layout = AtlasLayout::new(atlas_size, alignment, &pages);
+
+    let mut splits: HashMap<String, SplitImage> = HashMap::new();
+    for asset in oversized {
+        let ident = asset.entry.asset_path.ident().to_owned();
+        let (w, h) = asset.rgba.dimensions();
+        let columns = w.div_ceil(atlas_w);
+        let rows = h.div_ceil(atlas_h);
+        // images that only do not fit because of their padding are not worth splitting
+        if !settings.split_oversized || columns * rows == 1 {
+            warnings.warn(
+                WarningCode::W002,
+                Some(&asset.entry.path),
+                format!("image {ident} ({w}x{h}) does not fit into the atlas, giving it its own texture"),
+            );
+            tiles.insert(
+                ident,
+                TextureTile {
+                    atlas: atlas_name(atlases.len()),
+                    min: uvec2(0, 0),
+                    max: uvec2(w, h),
+                    trim: None,
+                    rotated: false,
+                    kind: tile_kind(asset),
+                    nine_slice: asset.nine_slice,
+                    pivot: asset.pivot,
+                    layer: asset.layer,
+                },
+            );
+            atlases.push((asset.rgba.clone(), sprite_atlas_flags(asset)));
+            continue;
+        }
+
+        warnings.warn(
+            WarningCode::W002,
+            Some(&asset.entry.path),
+            format!("image {ident} ({w}x{h}) is larger than the atlas, splitting it into {columns}x{rows} tiles"),
         );
-        y += pad_x;
-        x += pad_y;
-        // copy the image over and set allocated to true:
-        *allocated = true;
-        atlas.copy_from(&asset.rgba, x, y).unwrap();
-        let tile = TextureTile {
-            atlas: atlas_name(atlases.len()),
-            min: uvec2(x, y),
-            max: uvec2(x + w, y + h),
+        let mut split = SplitImage {
+            columns,
+            rows,
+            tiles: vec![],
         };
-        tiles.insert(asset.entry.asset_path.ident().to_owned(), tile);
+        for row in 0..rows {
+            for column in 0..columns {
+                let (x, y) = (column * atlas_w, row * atlas_h);
+                let piece = image::imageops::crop_imm(
+                    &asset.rgba,
+                    x,
+                    y,
+                    atlas_w.min(w - x),
+                    atlas_h.min(h - y),
+                )
+                .to_image();
+                let tile_name = format!("{ident}_{column}_{row}");
+                tiles.insert(
+                    tile_name.clone(),
+                    TextureTile {
+                        atlas: atlas_name(atlases.len()),
+                        min: uvec2(0, 0),
+                        max: uvec2(piece.width(), piece.height()),
+                        trim: None,
+                        rotated: false,
+                        kind: tile_kind(asset),
+                        nine_slice: None,
+                        pivot: None,
+                        layer: asset.layer,
+                    },
+                );
+                atlases.push((piece, sprite_atlas_flags(asset)));
+                split.tiles.push(tile_name);
+            }
+        }
+        splits.insert(ident, split);
+    }
+
+    for (ident, first) in duplicates {
+        if let Some(tile) = tiles.get(first) {
+            // duplicates share the pixels, but keep their own layer
+            let layer = images.get(ident).and_then(|e| e.layer);
+            let tile = TextureTile {
+                layer,
+                ..tile.clone()
+            };
+            tiles.insert(ident.to_owned(), tile);
+        } else if let Some(split) = splits.get(first) {
+            splits.insert(ident.to_owned(), split.clone());
+        }
+    }
+
+    Ok((atlases, tiles, splits, layout))
+}
+
+/// Size of the allocation of a sprite including its padding, rotated sprites also swap their padding.
+fn allocation_size(asset: &ImageAsset, rotated: bool) -> guillotiere::Size {
+    let (pad_x, pad_y) = pad_for_image_asset(asset);
+    let (w, h) = asset.rgba.dimensions();
+    match rotated {
+        false => guillotiere::size2((w + pad_x * 2) as i32, (h + pad_y * 2) as i32),
+        true => guillotiere::size2((h + pad_y * 2) as i32, (w + pad_x * 2) as i32),
+    }
+}
+
+/// The orientations to try for a sprite (`true` is rotated), the first one is used if the sprite
+/// starts a new atlas. Empty if the sprite does not fit into the atlas at all.
+fn sprite_orientations(asset: &ImageAsset, settings: &PackSettings) -> &'static [bool] {
+    let block = compression_block_size(settings.texture_format).unwrap_or(UVec2::ONE);
+    let fits = |rotated: bool| {
+        let size = allocation_size(asset, rotated);
+        let size = uvec2(size.width as u32, size.height as u32);
+        let size = uvec2(
+            size.x.next_multiple_of(block.x),
+            size.y.next_multiple_of(block.y),
+        );
+        size.x <= settings.atlas_width && size.y <= settings.atlas_height
+    };
+    let (w, h) = asset.rgba.dimensions();
+    match (fits(false), settings.allow_rotation && w != h && fits(true)) {
+        (true, true) => &[false, true],
+        (true, false) => &[false],
+        (false, true) => &[true],
+        (false, false) => &[],
+    }
+}
+
+/// Allocates either all of the sprites, with whether they are rotated, or none of them.
+fn allocate_sprites(
+    allocator: &mut guillotiere::AtlasAllocator,
+    sprites: &[&ImageAsset],
+    settings: &PackSettings,
+) -> Option<Vec<(guillotiere::Allocation, bool)>> {
+    let mut allocations: Vec<(guillotiere::Allocation, bool)> = vec![];
+    for asset in sprites {
+        let placed = sprite_orientations(asset, settings)
+            .iter()
+            .find_map(|&rotated| {
+                allocator
+                    .allocate(allocation_size(asset, rotated))
+                    .map(|alloc| (alloc, rotated))
+            });
+        match placed {
+            Some(placed) => allocations.push(placed),
+            None => {
+                for (allocation, _) in allocations {
+                    allocator.deallocate(allocation.id);
+                }
+                return None;
+            }
+        }
     }
-    atlases.push((atlas, TextureFlags::NO_REPEAT));
+    Some(allocations)
+}
 
-    (atlases, tiles)
+/// The area of a sprite at `min` of `size` with its border extruded by `extrude` pixels.
+fn extruded_area(min: UVec2, size: UVec2, extrude: (u32, u32)) -> (UVec2, UVec2) {
+    let extrude = UVec2::from(extrude);
+    (min - extrude, min + size + extrude)
+}
+
+/// Copies a sprite into the atlas at `(x, y)` and repeats its outermost pixels into the padding
+/// around it up to the `extrude` area (min and max), so bilinear sampling at the border of the sprite
+/// does not blend in the transparent padding.
+fn copy_sprite(atlas: &mut RgbaImage, rgba: &RgbaImage, x: u32, y: u32, extrude: (UVec2, UVec2)) {
+    atlas.copy_from(rgba, x, y).unwrap();
+    let (w, h) = rgba.dimensions();
+    if w == 0 || h == 0 {
+        return;
+    }
+    let (min, max) = extrude;
+    for ty in min.y..max.y {
+        for tx in min.x..max.x {
+            let inside = (x..x + w).contains(&tx) && (y..y + h).contains(&ty);
+            if !inside {
+                let sx = tx.clamp(x, x + w - 1) - x;
+                let sy = ty.clamp(y, y + h - 1) - y;
+                atlas.put_pixel(tx, ty, *rgba.get_pixel(sx, sy));
+            }
+        }
+    }
+}
+
+/// Flags of the atlases a sprite can be packed into, sprites are never mixed with others of different flags.
+fn sprite_atlas_flags(asset: &ImageAsset) -> TextureFlags {
+    TextureFlags {
+        alpha: alpha_mode(asset),
+        indexed: asset.indexed,
+        sampler: Some(asset.sampler),
+        ..no_repeat_flags(asset.linear, asset.mask)
+    }
+}
+
+/// Flags of plain sprite atlases with the color space and channels of linear images or masks.
+fn no_repeat_flags(linear: bool, mask: bool) -> TextureFlags {
+    match (linear, mask) {
+        (_, true) => TextureFlags::NO_REPEAT_MASK,
+        (true, false) => TextureFlags::NO_REPEAT_LINEAR,
+        (false, false) => TextureFlags::NO_REPEAT,
+    }
+}
+
+/// `flags` of a repeat texture or strip atlas, with the color space and channels of linear images or
+/// masks, which are never mixed into the strips of srgb images.
+fn with_color_flags(flags: TextureFlags, linear: bool, mask: bool) -> TextureFlags {
+    let color = no_repeat_flags(linear, mask);
+    TextureFlags {
+        srgb: color.srgb,
+        single_channel: color.single_channel,
+        ..flags
+    }
+}
+
+fn alpha_mode(asset: &ImageAsset) -> AlphaMode {
+    match asset.premultiplied {
+        true => AlphaMode::Premultiplied,
+        false => AlphaMode::Straight,
+    }
+}
+
+fn tile_kind(asset: &ImageAsset) -> TileKind {
+    match asset.mask {
+        true => TileKind::Mask,
+        false => TileKind::Sprite,
+    }
+}
+
+/// Sprites without repeat flags that are packed into atlases, only these fill the leftover space of strip atlases.
+fn is_plain_sprite(asset: &ImageAsset) -> bool {
+    !asset.no_pack && !asset.repeat_x && !asset.repeat_y
+}
+
+/// size along the repeat axis, and whether the strips are linear or masks, which are not mixed with
+/// srgb strips
+type StripKey = (u32, bool, bool);
+
+/// key of the strips, and the indices of the strips with their extent across the repeat axis
+type StripBucket = (StripKey, Vec<(usize, u32)>);
+
+/// Groups the strips of each bucket (key -> indices of the strips with their extent across the
+/// repeat axis) so that the stacked strips of a group, including padding, fit into `limit`.
+/// Fails with the bucket size and index and extent of a strip that does not fit on its own.
+fn strips_per_atlas(
+    buckets: HashMap<StripKey, Vec<(usize, u32)>>,
+    pad: u32,
+    limit: u32,
+) -> Result<Vec<StripBucket>, (usize, u32, u32)> {
+    let mut buckets: Vec<StripBucket> = buckets.into_iter().collect();
+    buckets.sort_by_key(|(key, _)| *key);
+    let mut strips: Vec<StripBucket> = vec![];
+    for (key, entries) in buckets {
+        let mut group: Vec<(usize, u32)> = vec![];
+        let mut extent: u32 = 0;
+        for (i, e) in entries {
+            if e + pad > limit {
+                return Err((i, key.0, e));
+            }
+            if extent + e + pad > limit {
+                strips.push((key, std::mem::take(&mut group)));
+                extent = 0;
+            }
+            group.push((i, e));
+            extent += e + pad;
+        }
+        strips.push((key, group));
+    }
+    Ok(strips)
+}
+
+/// Writes the mip levels of the texture `name` next to it, or into its file for the formats other
+/// than png and webp. Returns the number of levels.
+fn write_mips(
+    rgba: &RgbaImage,
+    regions: &[(UVec2, UVec2)],
+    flags: TextureFlags,
+    name: &str,
+    settings: &PackSettings,
+    out_path: &str,
+    cache: &mut Cache,
+) -> PackResult<u32> {
+    let mips = mip_chain(rgba, regions, settings.mip_levels);
+    match flags.format {
+        TextureFormat::Png => {
+            let channels = png_channels(flags, settings);
+            for (level, mip) in mips.iter().enumerate() {
+                let path = format!("{out_path}/{name}.mip{}.png", level + 1);
+                cache.save_texture(mip, &path, channels)?;
+            }
+        }
+        TextureFormat::Webp => {
+            for (level, mip) in mips.iter().enumerate() {
+                write_webp(mip, &format!("{out_path}/{name}.mip{}.webp", level + 1))?;
+            }
+        }
+        _ => {
+            // replaces the file written without mips
+            let levels: Vec<&RgbaImage> = std::iter::once(rgba).chain(&mips).collect();
+            write_gpu_texture(&levels, flags, &format!("{out_path}/{name}"))?;
+        }
+    }
+    Ok(mips.len() as u32)
+}
+
+/// Downscales an atlas `levels` times (or until it is 1x1) by averaging 2x2 blocks. The pixels of a
+/// block that belong to different `regions` (the tiles of the atlas) are never mixed, every pixel of a
+/// level is averaged only from the pixels of the tile covering most of its block, so sprites do not
+/// bleed into each other with trilinear filtering.
+fn mip_chain(rgba: &RgbaImage, regions: &[(UVec2, UVec2)], levels: u32) -> Vec<RgbaImage> {
+    let (mut w, mut h) = rgba.dimensions();
+    // index of the region + 1 for every pixel of the current level, 0 for padding
+    let mut owners: Vec<u32> = vec![0; (w * h) as usize];
+    for (i, (min, max)) in regions.iter().enumerate() {
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                owners[(y * w + x) as usize] = i as u32 + 1;
+            }
+        }
+    }
+
+    let mut chain: Vec<RgbaImage> = vec![];
+    let mut previous = rgba;
+    while (chain.len() as u32) < levels && (w > 1 || h > 1) {
+        let (next_w, next_h) = ((w / 2).max(1), (h / 2).max(1));
+        let mut next = RgbaImage::new(next_w, next_h);
+        let mut next_owners: Vec<u32> = vec![0; (next_w * next_h) as usize];
+        for y in 0..next_h {
+            for x in 0..next_w {
+                let block: Vec<(u32, u32)> = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .into_iter()
+                    .map(|(dx, dy)| ((x * 2 + dx).min(w - 1), (y * 2 + dy).min(h - 1)))
+                    .collect();
+                let owner_of = |(px, py): (u32, u32)| owners[(py * w + px) as usize];
+                // tiles win over padding, so sprites keep their size at small levels
+                let owner = block
+                    .iter()
+                    .map(|p| owner_of(*p))
+                    .max_by_key(|owner| {
+                        let count = block.iter().filter(|p| owner_of(**p) == *owner).count();
+                        (*owner != 0, count, *owner)
+                    })
+                    .unwrap();
+                let (mut rgb, mut alpha, mut count) = ([0u32; 3], 0u32, 0u32);
+                for p in block.iter().filter(|p| owner_of(**p) == owner) {
+                    let pixel = previous.get_pixel(p.0, p.1);
+                    let a = pixel[3] as u32;
+                    for c in 0..3 {
+                        rgb[c] += pixel[c] as u32 * a;
+                    }
+                    alpha += a;
+                    count += 1;
+                }
+                // colors are weighted by alpha, so transparent pixels do not darken the edges
+                let color = |c: usize| match alpha {
+                    0 => 0,
+                    alpha => (rgb[c] / alpha) as u8,
+                };
+                next.put_pixel(
+                    x,
+                    y,
+                    Rgba([color(0), color(1), color(2), (alpha / count) as u8]),
+                );
+                next_owners[(y * next_w + x) as usize] = owner;
+            }
+        }
+        chain.push(next);
+        previous = chain.last().unwrap();
+        (w, h, owners) = (next_w, next_h, next_owners);
+    }
+    chain
+}
+
+/// Size of a strip atlas along the axis its strips are stacked on, power of two atlases are at least
+/// 256 pixels large to leave some space for sprites.
+fn strip_atlas_size(strips_size: u32, settings: &PackSettings) -> u32 {
+    let size = settings.atlas_rounding.strips.round(strips_size);
+    match settings.atlas_rounding.strips {
+        SizeRounding::Pow2 => size.max(256),
+        _ => size,
+    }
 }
 
 fn atlas_name(i: usize) -> String {
@@ -337,3 +2198,130 @@ pub fn next_pow2_number(n: usize) -> usize {
         e *= 2;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::read_manifest;
+    use crate::{testing::TempDir, Packer};
+
+    #[test]
+    fn linear_repeat_images_keep_their_color_space() {
+        let dir = TempDir::new("linear_repeat_images_keep_their_color_space");
+        dir.write_png(
+            "src/images/normal.rep.linear.png",
+            (16, 16),
+            [128, 128, 255, 255],
+        );
+        dir.write_png(
+            "src/images/nrm.repx.linear.png",
+            (16, 8),
+            [128, 128, 255, 255],
+        );
+        dir.write_png("src/images/sky.repx.png", (16, 8), [100, 150, 255, 255]);
+        let dest = dir.path("packed");
+        Packer::new(dir.path("src")).pack_to(&dest).unwrap();
+
+        let packed = read_manifest(&dest).unwrap();
+        let srgb = |tile: &str| {
+            let atlas = &packed.tiles[tile].atlas;
+            let (_, flags) = packed
+                .textures
+                .iter()
+                .find(|(name, _)| name == atlas)
+                .unwrap();
+            flags.srgb
+        };
+        assert!(!srgb("normal"));
+        assert!(!srgb("nrm"));
+        assert!(srgb("sky"));
+        assert_ne!(packed.tiles["nrm"].atlas, packed.tiles["sky"].atlas);
+    }
+
+    #[test]
+    fn no_pack_images_keep_their_metadata() {
+        let dir = TempDir::new("no_pack_images_keep_their_metadata");
+        dir.write_png("src/images/splash.no.png", (16, 16), [200, 40, 40, 255]);
+        dir.write(
+            "src/images/splash.no.png.meta",
+            r#"{ "tags": ["menu"], "user_data": { "fade": 2 }, "priority": 3 }"#,
+        );
+        dir.write_png("src/images/button.png", (8, 8), [40, 200, 40, 255]);
+        let dest = dir.path("packed");
+        Packer::new(dir.path("src"))
+            .provenance(true)
+            .mip_levels(2)
+            .pack_to(&dest)
+            .unwrap();
+
+        let packed = read_manifest(&dest).unwrap();
+        assert_eq!(packed.tiles["splash"].atlas, "splash");
+        assert_eq!(packed.tags["splash"], ["menu"]);
+        assert_eq!(packed.user_data["splash"]["fade"], "2");
+        assert_eq!(packed.texture_priorities["splash"], 3);
+        assert_eq!(packed.provenance["splash"].source, "images/splash.no.png");
+        assert_eq!(packed.mip_levels["splash"], 2);
+    }
+
+    #[test]
+    fn unchanged_sprites_keep_their_place() {
+        let dir = TempDir::new("unchanged_sprites_keep_their_place");
+        dir.write_png("src/images/big.png", (32, 32), [200, 40, 40, 255]);
+        dir.write_png("src/images/mid.png", (16, 16), [40, 200, 40, 255]);
+        dir.write_png("src/images/small.png", (8, 8), [40, 40, 200, 255]);
+        let dest = dir.path("packed");
+        let packer = Packer::new(dir.path("src"));
+        packer.pack_to(&dest).unwrap();
+        let before = read_manifest(&dest).unwrap();
+
+        // grown past the size of mid, so laying the atlas out from scratch would place it before mid
+        dir.write_png("src/images/small.png", (24, 24), [40, 40, 200, 255]);
+        packer.pack_to(&dest).unwrap();
+        let after = read_manifest(&dest).unwrap();
+        for ident in ["big", "mid"] {
+            assert_eq!(after.tiles[ident].atlas, before.tiles[ident].atlas);
+            assert_eq!(after.tiles[ident].min, before.tiles[ident].min);
+        }
+        assert_eq!(
+            after.tiles["small"].max - after.tiles["small"].min,
+            (24, 24).into()
+        );
+    }
+
+    #[test]
+    fn trimmed_nine_slices_keep_their_borders_and_pivot() {
+        let dir = TempDir::new("trimmed_nine_slices_keep_their_borders_and_pivot");
+        // opaque from 4,2 to 16,18 in a 20x20 image
+        let opaque = |x, y| (4..16).contains(&x) && (2..18).contains(&y);
+        let panel = RgbaImage::from_fn(20, 20, |x, y| match opaque(x, y) {
+            true => Rgba([90, 90, 200, 255]),
+            false => Rgba([0, 0, 0, 0]),
+        });
+        dir.write_png("src/images/button.png", (8, 8), [40, 200, 40, 255]);
+        panel.save(dir.path("src/images/panel.png")).unwrap();
+        dir.write(
+            "src/images/panel.png.meta",
+            r#"{
+                "nine_slice": { "left": 6, "right": 3, "top": 5, "bottom": 2 },
+                "pivot": { "pixel": [10, 18] }
+            }"#,
+        );
+        let dest = dir.path("packed");
+        Packer::new(dir.path("src"))
+            .trim_transparent(true)
+            .pack_to(&dest)
+            .unwrap();
+
+        let tile = &read_manifest(&dest).unwrap().tiles["panel"];
+        assert_eq!(tile.max - tile.min, (12, 16).into());
+        assert_eq!(tile.trim.unwrap().offset, (4, 2).into());
+        // the right and bottom borders were all margin
+        let slice = tile.nine_slice.unwrap();
+        assert_eq!(
+            (slice.left, slice.right, slice.top, slice.bottom),
+            (2, 0, 3, 0)
+        );
+        assert_eq!(tile.pivot, Some((6.0, 16.0).into()));
+    }
+}
@@ -0,0 +1,105 @@
+use std::borrow::Cow;
+
+pub use assetpacker_manifest::pak::{Compression, PakEntry, PakError, PakIndex};
+
+use crate::{
+    error::{PackError, PackResult},
+    pack::{pack_file_names, PackSettings, PackedAssets},
+};
+
+/// File name of the bundle written with the `pak` setting.
+pub const PAK_FILE_NAME: &str = "packed.pak";
+
+/// The files of a pack, loose in its dir or bundled into a [`PAK_FILE_NAME`] file.
+pub struct PackFiles {
+    dir: String,
+    pak: Option<(Vec<u8>, PakIndex)>,
+}
+
+impl PackFiles {
+    /// Reads the pak of the pack in `dir` if there is one.
+    pub fn open(dir: &str) -> PackResult<PackFiles> {
+        let pak_path = format!("{dir}/{PAK_FILE_NAME}");
+        let pak = match std::fs::read(&pak_path) {
+            Ok(bytes) => {
+                let index = PakIndex::parse(&bytes).map_err(|e| PackError::InvalidManifest {
+                    message: format!("{pak_path}: {e}"),
+                })?;
+                Some((bytes, index))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(PackError::io(pak_path)(e)),
+        };
+        Ok(PackFiles {
+            dir: dir.to_string(),
+            pak,
+        })
+    }
+
+    /// Path of a file of the pack for error messages, inside the dir even if it is in the pak.
+    pub fn path(&self, file_name: &str) -> String {
+        format!("{}/{file_name}", self.dir)
+    }
+
+    pub fn read(&self, file_name: &str) -> PackResult<Cow<'_, [u8]>> {
+        match &self.pak {
+            Some((bytes, index)) => {
+                if !index.entries.contains_key(file_name) {
+                    return Err(PackError::io(self.path(file_name))(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("not in {PAK_FILE_NAME}"),
+                    )));
+                }
+                index
+                    .read(bytes, file_name)
+                    .map_err(|e| PackError::InvalidManifest {
+                        message: format!("{}/{PAK_FILE_NAME}: {e}", self.dir),
+                    })
+            }
+            None => {
+                let path = self.path(file_name);
+                std::fs::read(&path)
+                    .map(Cow::Owned)
+                    .map_err(PackError::io(path))
+            }
+        }
+    }
+}
+
+/// Moves the files a runtime loads, the manifest, textures with their mip levels and sdf fonts,
+/// from `out_path` into one [`PAK_FILE_NAME`] file, see [`assetpacker_manifest::pak`] for the format.
+/// Generated code and other files stay loose.
+pub fn bundle_pak(
+    out_path: &str,
+    packed: &PackedAssets,
+    settings: &PackSettings,
+) -> PackResult<()> {
+    let mut file_names: Vec<String> = vec![settings.manifest_format.file_name().to_string()];
+    for (name, extension) in pack_file_names(packed) {
+        let file_name = format!("{name}.{extension}");
+        file_names.push(packed.file_name(&file_name).to_string());
+    }
+    let files: Vec<(String, Vec<u8>)> = file_names
+        .into_iter()
+        .map(|name| {
+            let path = format!("{out_path}/{name}");
+            let bytes = std::fs::read(&path).map_err(PackError::io(&path))?;
+            Ok((name, bytes))
+        })
+        .collect::<PackResult<_>>()?;
+    let pak_path = format!("{out_path}/{PAK_FILE_NAME}");
+    let compression = match settings.compress_pak {
+        true => Compression::Deflate,
+        false => Compression::None,
+    };
+    std::fs::write(
+        &pak_path,
+        assetpacker_manifest::pak::to_vec(&files, compression),
+    )
+    .map_err(PackError::io(&pak_path))?;
+    for (name, _) in files {
+        let path = format!("{out_path}/{name}");
+        std::fs::remove_file(&path).map_err(PackError::io(&path))?;
+    }
+    Ok(())
+}
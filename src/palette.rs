@@ -0,0 +1,73 @@
+use std::{collections::HashMap, fs::File, io::BufWriter};
+
+use color_quant::NeuQuant;
+use image::{
+    error::{EncodingError, ImageFormatHint},
+    ImageError, ImageFormat, RgbaImage,
+};
+
+use crate::error::{PackError, PackResult};
+
+/// speed of the quantizer for images with more than 256 colors, 1 is the best and slowest, 30 the worst
+const NEUQUANT_SAMPLE_FACTOR: i32 = 10;
+
+/// The palette of at most 256 colors and the palette index of every pixel. Exact if the image has no
+/// more colors, like most pixel art, otherwise the colors are quantized with NeuQuant.
+pub fn quantize(rgba: &RgbaImage) -> (Vec<[u8; 4]>, Vec<u8>) {
+    let mut palette: Vec<[u8; 4]> = vec![];
+    let mut lookup: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut indices: Vec<u8> = Vec::with_capacity(rgba.len() / 4);
+    for pixel in rgba.pixels() {
+        let index = match lookup.get(&pixel.0) {
+            Some(index) => *index,
+            None if palette.len() < 256 => {
+                let index = palette.len() as u8;
+                lookup.insert(pixel.0, index);
+                palette.push(pixel.0);
+                index
+            }
+            None => return quantize_lossy(rgba),
+        };
+        indices.push(index);
+    }
+    (palette, indices)
+}
+
+fn quantize_lossy(rgba: &RgbaImage) -> (Vec<[u8; 4]>, Vec<u8>) {
+    let quant = NeuQuant::new(NEUQUANT_SAMPLE_FACTOR, 256, rgba.as_raw());
+    let palette: Vec<[u8; 4]> = quant
+        .color_map_rgba()
+        .chunks_exact(4)
+        .map(|e| [e[0], e[1], e[2], e[3]])
+        .collect();
+    let indices: Vec<u8> = rgba.pixels().map(|p| quant.index_of(&p.0) as u8).collect();
+    (palette, indices)
+}
+
+/// Writes the image as an indexed png with at most 256 colors, see [`quantize`].
+pub fn save_indexed_png(rgba: &RgbaImage, path: &str) -> PackResult<()> {
+    let png_error = |e: png::EncodingError| PackError::Image {
+        path: path.into(),
+        source: ImageError::Encoding(EncodingError::new(
+            ImageFormatHint::Exact(ImageFormat::Png),
+            e,
+        )),
+    };
+    let (palette, indices) = quantize(rgba);
+    let file = File::create(path).map_err(PackError::io(path))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), rgba.width(), rgba.height());
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(
+        palette
+            .iter()
+            .flat_map(|e| [e[0], e[1], e[2]])
+            .collect::<Vec<u8>>(),
+    );
+    if palette.iter().any(|e| e[3] != u8::MAX) {
+        encoder.set_trns(palette.iter().map(|e| e[3]).collect::<Vec<u8>>());
+    }
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(&indices).map_err(png_error)?;
+    writer.finish().map_err(png_error)
+}
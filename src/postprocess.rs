@@ -0,0 +1,82 @@
+use std::{fmt::Debug, path::Path, sync::Arc};
+
+use glam::UVec2;
+use image::RgbaImage;
+use serde::{Serialize, Serializer};
+
+/// What a [`TileProcessor`] gets to know about the tile it processes.
+#[derive(Debug, Clone, Copy)]
+pub struct TileMeta<'a> {
+    pub name: &'a str,
+    /// the atlas the tile is in, like `atlas_0`
+    pub atlas: &'a str,
+    /// atlas group from the dir config of the image, `None` for the default group
+    pub group: Option<&'a str>,
+    /// file the tile was packed from, `None` for generated tiles like the parts of split images
+    pub source: Option<&'a Path>,
+    /// position of the tile in its atlas
+    pub min: UVec2,
+    pub max: UVec2,
+    /// the image is rotated by 90° in the atlas, and so are the pixels the processor gets
+    pub rotated: bool,
+}
+
+/// Changes the pixels of a tile after it is blitted into its atlas and before the atlas is encoded,
+/// like a watermark in demo builds or the name of the tile stamped into debug builds. Pixels the
+/// processor draws beyond the size of the tile are dropped.
+pub type TileProcessor = Arc<dyn Fn(&mut RgbaImage, &TileMeta) + Send + Sync>;
+
+/// The tile processors of the [`crate::pack::PackSettings`], registered with
+/// [`crate::Packer::tile_processor`]. They are not read from config files, and serialized as
+/// their names, so the fingerprint of a pack changes with its processors.
+#[derive(Clone, Default)]
+pub struct TileProcessors {
+    /// name, atlas group or `None` for all tiles, processor
+    processors: Vec<(String, Option<String>, TileProcessor)>,
+}
+
+impl TileProcessors {
+    pub fn add(&mut self, name: String, group: Option<String>, processor: TileProcessor) {
+        self.processors.push((name, group, processor));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    /// The processors of the tiles in `group`, in the order they were added.
+    pub fn for_group<'a>(
+        &'a self,
+        group: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a TileProcessor> + 'a {
+        self.processors
+            .iter()
+            .filter(move |(_, processor_group, _)| {
+                processor_group.is_none() || processor_group.as_deref() == group
+            })
+            .map(|(_, _, processor)| processor)
+    }
+
+    /// Like `watermark` or `watermark@ui` for a processor of the atlas group `ui`.
+    fn names(&self) -> Vec<String> {
+        self.processors
+            .iter()
+            .map(|(name, group, _)| match group {
+                Some(group) => format!("{name}@{group}"),
+                None => name.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Debug for TileProcessors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+impl Serialize for TileProcessors {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.names().serialize(serializer)
+    }
+}
@@ -0,0 +1,96 @@
+use glam::{ivec2, IVec2};
+use image::{Rgba, RgbaImage};
+
+use crate::loader::{FontHandle, LoadedAssets};
+
+/// Empty space around the rendered text, in pixels.
+const MARGIN: i32 = 16;
+
+/// Renders `text` with a packed font on the cpu, black on white at the size the sdf font was
+/// generated at. Meant for debugging fonts and packing issues without a renderer. Characters that
+/// are not in the font are drawn as empty boxes and returned, in the order they first appear.
+pub fn render_text(assets: &LoadedAssets, font: FontHandle, text: &str) -> (RgbaImage, Vec<char>) {
+    let loaded = assets.font(font);
+    let metrics = &loaded.font.line_metrics;
+    let line_height = metrics.new_line_size.round() as i32;
+    let box_advance = (loaded.font.font_size / 2) as f32;
+
+    // top left corner of every glyph quad (or missing glyph box) with its size
+    let mut quads: Vec<(char, IVec2, IVec2)> = vec![];
+    let mut missing: Vec<char> = vec![];
+    let mut width: i32 = 0;
+    let mut lines: i32 = 1;
+    let mut pen_x: f32 = 0.0;
+    for ch in text.chars() {
+        if ch == '\n' {
+            pen_x = 0.0;
+            lines += 1;
+            continue;
+        }
+        let baseline = metrics.ascent + ((lines - 1) * line_height) as f32;
+        match loaded.font.glyphs.get(&ch) {
+            Some(glyph) => {
+                if !glyph.is_white_space {
+                    let min = ivec2(
+                        (pen_x + glyph.xmin).round() as i32,
+                        (baseline - glyph.ymin - glyph.height).round() as i32,
+                    );
+                    quads.push((ch, min, ivec2(glyph.width as i32, glyph.height as i32)));
+                }
+                pen_x += glyph.advance;
+            }
+            None => {
+                if !missing.contains(&ch) {
+                    missing.push(ch);
+                }
+                let height = metrics.ascent.round() as i32;
+                let min = ivec2(pen_x.round() as i32 + 2, (baseline as i32) - height);
+                quads.push((ch, min, ivec2(box_advance as i32 - 4, height)));
+                pen_x += box_advance;
+            }
+        }
+        width = width.max(pen_x.ceil() as i32);
+    }
+
+    let size = ivec2(width, lines * line_height) + IVec2::splat(2 * MARGIN);
+    let mut image = RgbaImage::from_pixel(size.x as u32, size.y as u32, Rgba([255, 255, 255, 255]));
+    let mut draw = |x: i32, y: i32, coverage: f32| {
+        let (x, y) = (x + MARGIN, y + MARGIN);
+        if x < 0 || y < 0 || x >= size.x || y >= size.y || coverage <= 0.0 {
+            return;
+        }
+        let pixel = image.get_pixel_mut(x as u32, y as u32);
+        for c in 0..3 {
+            pixel[c] = (pixel[c] as f32 * (1.0 - coverage)) as u8;
+        }
+    };
+
+    let sdf = &loaded.sdf;
+    let sdf_size = glam::vec2(sdf.width() as f32, sdf.height() as f32);
+    for (ch, min, quad_size) in quads {
+        let Some(glyph) = loaded.font.glyphs.get(&ch) else {
+            // outline of a missing glyph
+            for x in 0..quad_size.x {
+                draw(min.x + x, min.y, 1.0);
+                draw(min.x + x, min.y + quad_size.y - 1, 1.0);
+            }
+            for y in 0..quad_size.y {
+                draw(min.x, min.y + y, 1.0);
+                draw(min.x + quad_size.x - 1, min.y + y, 1.0);
+            }
+            continue;
+        };
+        // the sdf is stored at the size of the quad, so every pixel maps to one texel
+        let texel_min = (glyph.uv_min * sdf_size).round();
+        for y in 0..quad_size.y {
+            for x in 0..quad_size.x {
+                let value =
+                    sdf.get_pixel(texel_min.x as u32 + x as u32, texel_min.y as u32 + y as u32)[0];
+                // 0.5 is the edge of the glyph, antialiased over about one pixel
+                let coverage = ((value as f32 / 255.0 - 0.5) * 16.0 + 0.5).clamp(0.0, 1.0);
+                draw(min.x + x, min.y + y, coverage);
+            }
+        }
+    }
+    (image, missing)
+}
@@ -0,0 +1,414 @@
+use std::io::Read;
+
+use image::RgbaImage;
+
+use crate::aseprite::draw_image;
+
+/// The layers of a Photoshop file with 8 bit rgb or grayscale colors. Blend modes other than normal
+/// are drawn as normal, clipping masks, layer masks and adjustment layers are ignored.
+#[derive(Debug, Clone)]
+pub struct Psd {
+    pub width: u32,
+    pub height: u32,
+    /// from the bottom to the top
+    layers: Vec<PsdLayer>,
+    /// the composite Photoshop stores next to the layers, used for files without layers
+    merged: Option<RgbaImage>,
+}
+
+#[derive(Debug, Clone)]
+struct PsdLayer {
+    visible: bool,
+    opacity: u8,
+    x: i32,
+    y: i32,
+    image: RgbaImage,
+    /// names and visibility of the groups the layer is in, the outermost first
+    groups: Vec<(String, bool)>,
+}
+
+const COLOR_MODE_GRAYSCALE: u16 = 1;
+const COLOR_MODE_RGB: u16 = 3;
+const SECTION_OPEN_FOLDER: u32 = 1;
+const SECTION_CLOSED_FOLDER: u32 = 2;
+const SECTION_DIVIDER: u32 = 3;
+
+/// A layer record with the still compressed data of its channels.
+struct LayerRecord<'a> {
+    top: i32,
+    left: i32,
+    bottom: i32,
+    right: i32,
+    /// channel id (0 red or gray, 1 green, 2 blue, -1 alpha, negative ids are masks) -> data
+    channels: Vec<(i16, &'a [u8])>,
+    opacity: u8,
+    hidden: bool,
+    name: String,
+    section: u32,
+}
+
+impl Psd {
+    pub fn parse(bytes: &[u8]) -> Result<Psd, String> {
+        let mut reader = Reader::new(bytes);
+        if reader.bytes(4)? != b"8BPS" {
+            return Err(String::from("not a psd file"));
+        }
+        if reader.u16()? != 1 {
+            return Err(String::from("large documents (psb) are not supported"));
+        }
+        reader.skip(6)?;
+        let channel_count = reader.u16()? as usize;
+        let height = reader.u32()?;
+        let width = reader.u32()?;
+        let depth = reader.u16()?;
+        let color_mode = reader.u16()?;
+        if depth != 8 {
+            return Err(format!(
+                "{depth} bits per channel are not supported, only 8"
+            ));
+        }
+        if ![COLOR_MODE_GRAYSCALE, COLOR_MODE_RGB].contains(&color_mode) {
+            return Err(format!(
+                "color mode {color_mode} is not supported, only rgb and grayscale"
+            ));
+        }
+        let color_mode_data = reader.u32()? as usize;
+        reader.skip(color_mode_data)?;
+        let image_resources = reader.u32()? as usize;
+        reader.skip(image_resources)?;
+
+        let layer_and_mask_info = reader.u32()? as usize;
+        let mut layer_and_mask_info = Reader::new(reader.bytes(layer_and_mask_info)?);
+        let mut records: Vec<LayerRecord> = vec![];
+        if !layer_and_mask_info.bytes.is_empty() {
+            let layer_info = layer_and_mask_info.u32()? as usize;
+            let mut layer_info = Reader::new(layer_and_mask_info.bytes(layer_info)?);
+            if !layer_info.bytes.is_empty() {
+                // negative if the first alpha channel is the transparency of the merged image
+                let count = (layer_info.u16()? as i16).unsigned_abs();
+                let mut channel_lengths: Vec<Vec<(i16, usize)>> = vec![];
+                for _ in 0..count {
+                    let (record, lengths) = layer_record(&mut layer_info)?;
+                    records.push(record);
+                    channel_lengths.push(lengths);
+                }
+                for (record, lengths) in records.iter_mut().zip(channel_lengths) {
+                    for (id, len) in lengths {
+                        record.channels.push((id, layer_info.bytes(len)?));
+                    }
+                }
+            }
+        }
+
+        // the records of a group come after its content, so the groups are tracked from the top
+        let mut groups: Vec<(String, bool)> = vec![];
+        let mut layers: Vec<PsdLayer> = vec![];
+        for record in records.iter().rev() {
+            match record.section {
+                SECTION_OPEN_FOLDER | SECTION_CLOSED_FOLDER => {
+                    groups.push((record.name.clone(), !record.hidden));
+                }
+                SECTION_DIVIDER => {
+                    groups.pop();
+                }
+                _ => {
+                    let w = (record.right - record.left).max(0) as u32;
+                    let h = (record.bottom - record.top).max(0) as u32;
+                    if w == 0 || h == 0 {
+                        continue;
+                    }
+                    let image = layer_image(record, w, h, color_mode)
+                        .map_err(|e| format!("layer {}: {e}", record.name))?;
+                    layers.push(PsdLayer {
+                        visible: !record.hidden,
+                        opacity: record.opacity,
+                        x: record.left,
+                        y: record.top,
+                        image,
+                        groups: groups.clone(),
+                    });
+                }
+            }
+        }
+        layers.reverse();
+
+        let merged = match layers.is_empty() {
+            true => Some(merged_image(
+                &mut reader,
+                width,
+                height,
+                channel_count,
+                color_mode,
+            )?),
+            false => None,
+        };
+        Ok(Psd {
+            width,
+            height,
+            layers,
+            merged,
+        })
+    }
+
+    /// All visible layers drawn over each other.
+    pub fn flatten(&self) -> RgbaImage {
+        if let Some(merged) = &self.merged {
+            return merged.clone();
+        }
+        self.draw_layers(|layer| layer.groups.iter().all(|(_, visible)| *visible))
+    }
+
+    /// The visible layers of the group `name`, also if the group itself is hidden, `None` if there is
+    /// no such group.
+    pub fn flatten_group(&self, name: &str) -> Option<RgbaImage> {
+        let in_group = |layer: &PsdLayer| layer.groups.iter().position(|(group, _)| group == name);
+        self.layers.iter().find_map(in_group)?;
+        Some(self.draw_layers(|layer| {
+            in_group(layer).is_some_and(|index| {
+                let nested = &layer.groups[index + 1..];
+                nested.iter().all(|(_, visible)| *visible)
+            })
+        }))
+    }
+
+    fn draw_layers(&self, include: impl Fn(&PsdLayer) -> bool) -> RgbaImage {
+        let mut rgba = RgbaImage::new(self.width, self.height);
+        for layer in self.layers.iter().filter(|e| e.visible && include(e)) {
+            draw_image(
+                &mut rgba,
+                &layer.image,
+                layer.x,
+                layer.y,
+                layer.opacity as u32,
+            );
+        }
+        rgba
+    }
+}
+
+/// Reads a layer record, with the lengths of its channels, whose data comes after all records.
+fn layer_record<'a>(
+    reader: &mut Reader<'a>,
+) -> Result<(LayerRecord<'a>, Vec<(i16, usize)>), String> {
+    let top = reader.u32()? as i32;
+    let left = reader.u32()? as i32;
+    let bottom = reader.u32()? as i32;
+    let right = reader.u32()? as i32;
+    let channel_count = reader.u16()?;
+    let mut lengths: Vec<(i16, usize)> = vec![];
+    for _ in 0..channel_count {
+        lengths.push((reader.u16()? as i16, reader.u32()? as usize));
+    }
+    if reader.bytes(4)? != b"8BIM" {
+        return Err(String::from("corrupted layer record"));
+    }
+    reader.skip(4)?; // blend mode
+    let opacity = reader.u8()?;
+    reader.skip(1)?; // clipping
+    let flags = reader.u8()?;
+    reader.skip(1)?;
+    let extra = reader.u32()? as usize;
+    let mut extra = Reader::new(reader.bytes(extra)?);
+    let mask = extra.u32()? as usize;
+    extra.skip(mask)?;
+    let blending_ranges = extra.u32()? as usize;
+    extra.skip(blending_ranges)?;
+    // pascal string, padded to a multiple of 4 bytes
+    let name_len = extra.u8()? as usize;
+    let mut name = String::from_utf8_lossy(extra.bytes(name_len)?).into_owned();
+    extra.skip((4 - (name_len + 1) % 4) % 4)?;
+    let mut section = 0;
+    while extra.bytes.len() >= 12 {
+        let signature = extra.bytes(4)?;
+        if signature != b"8BIM" && signature != b"8B64" {
+            break;
+        }
+        let key = extra.bytes(4)?;
+        let len = extra.u32()? as usize;
+        let mut data = Reader::new(extra.bytes(len)?);
+        match key {
+            b"lsct" | b"lsdk" => section = data.u32()?,
+            b"luni" => {
+                let len = data.u32()? as usize;
+                let units: Vec<u16> = (0..len).map(|_| data.u16()).collect::<Result<_, _>>()?;
+                name = String::from_utf16_lossy(&units);
+            }
+            _ => {}
+        }
+    }
+    let record = LayerRecord {
+        top,
+        left,
+        bottom,
+        right,
+        channels: vec![],
+        opacity,
+        hidden: flags & 2 != 0,
+        name,
+        section,
+    };
+    Ok((record, lengths))
+}
+
+/// Decodes the channels of a layer into rgba.
+fn layer_image(record: &LayerRecord, w: u32, h: u32, color_mode: u16) -> Result<RgbaImage, String> {
+    let mut rgba = RgbaImage::from_pixel(w, h, image::Rgba([0, 0, 0, 255]));
+    for (id, data) in record.channels.iter() {
+        let mut data = Reader::new(data);
+        let compression = data.u16()?;
+        let Some(channel) = rgba_channel(*id, color_mode) else {
+            continue;
+        };
+        let plane = channel_plane(compression, data.remaining(), w, h, 1)?;
+        write_plane(&mut rgba, &plane, channel, color_mode);
+    }
+    Ok(rgba)
+}
+
+/// The composite image at the end of the file, all channels compressed together.
+fn merged_image(
+    reader: &mut Reader,
+    w: u32,
+    h: u32,
+    channel_count: usize,
+    color_mode: u16,
+) -> Result<RgbaImage, String> {
+    let compression = reader.u16()?;
+    let data = channel_plane(compression, reader.remaining(), w, h, channel_count)?;
+    let mut rgba = RgbaImage::from_pixel(w, h, image::Rgba([0, 0, 0, 255]));
+    let plane_size = (w * h) as usize;
+    let color_channels = match color_mode {
+        COLOR_MODE_GRAYSCALE => 1,
+        _ => 3,
+    };
+    for index in 0..channel_count.min(color_channels + 1) {
+        // the channel after the colors is the transparency
+        let id = match index == color_channels {
+            true => -1,
+            false => index as i16,
+        };
+        let channel = rgba_channel(id, color_mode).unwrap();
+        let plane = &data[index * plane_size..(index + 1) * plane_size];
+        write_plane(&mut rgba, plane, channel, color_mode);
+    }
+    Ok(rgba)
+}
+
+/// The rgba channel a psd channel id is written to, `None` for masks.
+fn rgba_channel(id: i16, color_mode: u16) -> Option<usize> {
+    match (id, color_mode) {
+        (-1, _) => Some(3),
+        (0, COLOR_MODE_GRAYSCALE) => Some(0),
+        (0..=2, COLOR_MODE_RGB) => Some(id as usize),
+        _ => None,
+    }
+}
+
+fn write_plane(rgba: &mut RgbaImage, plane: &[u8], channel: usize, color_mode: u16) {
+    for (pixel, value) in rgba.pixels_mut().zip(plane) {
+        pixel[channel] = *value;
+        // gray is written to all color channels
+        if color_mode == COLOR_MODE_GRAYSCALE && channel == 0 {
+            pixel[1] = *value;
+            pixel[2] = *value;
+        }
+    }
+}
+
+/// Decompresses `planes` channels of `w` x `h` pixels that are stored one after another.
+fn channel_plane(
+    compression: u16,
+    data: &[u8],
+    w: u32,
+    h: u32,
+    planes: usize,
+) -> Result<Vec<u8>, String> {
+    let rows = h as usize * planes;
+    let size = w as usize * rows;
+    let plane = match compression {
+        0 => data.get(..size).ok_or("truncated channel")?.to_vec(),
+        // packbits, after the byte counts of all rows
+        1 => {
+            let mut counts = Reader::new(data);
+            let mut rest = Reader::new(data.get(rows * 2..).ok_or("truncated channel")?);
+            let mut plane: Vec<u8> = Vec::with_capacity(size);
+            for _ in 0..rows {
+                let count = counts.u16()? as usize;
+                let mut row = Reader::new(rest.bytes(count)?);
+                while !row.bytes.is_empty() {
+                    let header = row.u8()? as i8;
+                    match header {
+                        -128 => {}
+                        0.. => plane.extend_from_slice(row.bytes(header as usize + 1)?),
+                        _ => {
+                            let value = row.u8()?;
+                            plane
+                                .extend(std::iter::repeat_n(value, (1 - header as isize) as usize));
+                        }
+                    }
+                }
+            }
+            plane
+        }
+        // zip without and with prediction, each byte stored as the difference to the one on its left
+        2 | 3 => {
+            let mut plane: Vec<u8> = vec![];
+            flate2::read::ZlibDecoder::new(data)
+                .read_to_end(&mut plane)
+                .map_err(|e| e.to_string())?;
+            if compression == 3 {
+                for row in plane.chunks_mut(w.max(1) as usize) {
+                    for x in 1..row.len() {
+                        row[x] = row[x].wrapping_add(row[x - 1]);
+                    }
+                }
+            }
+            plane
+        }
+        _ => return Err(format!("unknown compression {compression}")),
+    };
+    if plane.len() < size {
+        return Err(String::from("truncated channel"));
+    }
+    Ok(plane)
+}
+
+/// Big endian reads that fail with a message instead of panicking on truncated files.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < len {
+            return Err(String::from("unexpected end of file"));
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    fn remaining(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), String> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+}
@@ -0,0 +1,248 @@
+use serde_json::{json, Value};
+
+/// JSON Schema (draft 2020-12) of `packed.json`, with the `TextureTile` and `SdfFont` of the
+/// `*.sdf_font.json` files under `$defs`, for tooling that is not written in rust.
+///
+/// The schema is written by hand and has to follow the manifest types, fields with serde defaults
+/// are optional and unknown fields are allowed like serde allows them.
+pub fn manifest_schema() -> Value {
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "PackedAssets",
+        "description": "the packed.json manifest written by assetpacker",
+        "type": "object",
+        "required": ["textures", "tiles", "fonts"],
+        "properties": {
+            "version": {
+                "type": "string",
+                "description": "version of the packer that wrote the manifest"
+            },
+            "format_revision": {
+                "type": "integer",
+                "minimum": 0,
+                "description": "revision of the manifest layout, newer revisions are rejected by older runtimes"
+            },
+            "textures": {
+                "type": "array",
+                "description": "texture file names in the pack dir with their flags",
+                "items": {
+                    "type": "array",
+                    "prefixItems": [{ "type": "string" }, { "$ref": "#/$defs/TextureFlags" }],
+                    "items": false,
+                    "minItems": 2
+                }
+            },
+            "tiles": map_of(json!({ "$ref": "#/$defs/TextureTile" })),
+            "fonts": {
+                "type": "array",
+                "description": "font names, each with a <name>.sdf_font.json and <name>.sdf_font.png",
+                "items": { "type": "string" }
+            },
+            "default_font": { "type": "string" },
+            "splits": map_of(json!({ "$ref": "#/$defs/SplitImage" })),
+            "animations": map_of(json!({ "$ref": "#/$defs/Animation" })),
+            "mip_levels": map_of(uint()),
+            "texture_priorities": map_of(uint()),
+            "provenance": map_of(json!({ "$ref": "#/$defs/TileProvenance" })),
+            "tags": map_of(json!({ "type": "array", "items": { "type": "string" } })),
+            "user_data": map_of(map_of(json!({ "type": "string" }))),
+            "data_files": {
+                "type": "object",
+                "description": "files copied into the pack unchanged, name -> path relative to the pack dir",
+                "additionalProperties": { "type": "string" }
+            },
+            "scale": { "type": "number", "default": 1.0 },
+            "checksums": { "$ref": "#/$defs/SectionChecksums" },
+            "file_names": {
+                "type": "object",
+                "description": "content hashed names of the files, like atlas_0.png -> atlas_0.1a2b3c4d.png",
+                "additionalProperties": { "type": "string" }
+            },
+            "bundles": {
+                "type": "array",
+                "description": "bundles packed next to the manifest, each a pack in the dir of its name",
+                "items": { "type": "string" }
+            }
+        },
+        "$defs": {
+            "UVec2": {
+                "type": "array",
+                "items": uint(),
+                "minItems": 2,
+                "maxItems": 2
+            },
+            "Vec2": {
+                "type": "array",
+                "items": { "type": "number" },
+                "minItems": 2,
+                "maxItems": 2
+            },
+            "TextureTile": {
+                "type": "object",
+                "description": "a rect of a texture, in pixels",
+                "required": ["atlas", "min", "max"],
+                "properties": {
+                    "atlas": { "type": "string" },
+                    "min": { "$ref": "#/$defs/UVec2" },
+                    "max": { "$ref": "#/$defs/UVec2" },
+                    "trim": { "$ref": "#/$defs/Trim" },
+                    "rotated": { "type": "boolean", "default": false },
+                    "kind": string_enum(&["sprite", "mask"], "sprite"),
+                    "nine_slice": { "$ref": "#/$defs/NineSlice" },
+                    "pivot": { "$ref": "#/$defs/Vec2" },
+                    "layer": { "type": "integer" }
+                }
+            },
+            "Trim": {
+                "type": "object",
+                "required": ["original_size", "offset"],
+                "properties": {
+                    "original_size": { "$ref": "#/$defs/UVec2" },
+                    "offset": { "$ref": "#/$defs/UVec2" }
+                }
+            },
+            "NineSlice": {
+                "type": "object",
+                "required": ["left", "right", "top", "bottom"],
+                "properties": {
+                    "left": uint(),
+                    "right": uint(),
+                    "top": uint(),
+                    "bottom": uint()
+                }
+            },
+            "TextureFlags": {
+                "type": "object",
+                "required": ["repeat_x", "repeat_y"],
+                "properties": {
+                    "repeat_x": { "type": "boolean" },
+                    "repeat_y": { "type": "boolean" },
+                    "is_opaque": { "type": "boolean", "default": false },
+                    "format": string_enum(
+                        &["png", "webp", "ktx2", "astc4x4", "astc6x6", "bc1", "bc3", "bc7"],
+                        "png"
+                    ),
+                    "srgb": { "type": "boolean", "default": true },
+                    "single_channel": { "type": "boolean", "default": false },
+                    "alpha": string_enum(&["straight", "premultiplied"], "straight"),
+                    "indexed": { "type": "boolean", "default": false },
+                    "sampler": { "$ref": "#/$defs/Sampler" }
+                }
+            },
+            "Sampler": {
+                "type": "object",
+                "properties": {
+                    "address_x": { "$ref": "#/$defs/AddressMode" },
+                    "address_y": { "$ref": "#/$defs/AddressMode" },
+                    "filter": string_enum(&["linear", "nearest"], "linear")
+                }
+            },
+            "AddressMode": string_enum(&["clamp_to_edge", "repeat", "mirror_repeat"], "clamp_to_edge"),
+            "SplitImage": {
+                "type": "object",
+                "description": "an image larger than the atlas, split into rows of tiles",
+                "required": ["columns", "rows", "tiles"],
+                "properties": {
+                    "columns": uint(),
+                    "rows": uint(),
+                    "tiles": { "type": "array", "items": { "type": "string" } }
+                }
+            },
+            "Animation": {
+                "type": "object",
+                "required": ["texture", "frames"],
+                "properties": {
+                    "texture": { "type": "string" },
+                    "frames": { "type": "array", "items": { "type": "string" } },
+                    "fps": uint(),
+                    "durations": { "type": "array", "items": uint() },
+                    "playback": string_enum(&["loop", "once", "ping_pong"], "loop")
+                }
+            },
+            "TileProvenance": {
+                "type": "object",
+                "required": ["source", "hash"],
+                "properties": {
+                    "source": { "type": "string" },
+                    "hash": uint(),
+                    "flags": { "type": "string" }
+                }
+            },
+            "SectionChecksums": {
+                "type": "object",
+                "properties": {
+                    "textures": map_of(uint()),
+                    "fonts": map_of(uint())
+                }
+            },
+            "SdfFont": {
+                "type": "object",
+                "description": "a <name>.sdf_font.json file, the uvs of the glyphs are in <name>.sdf_font.png",
+                "required": ["font_size", "line_metrics", "name", "glyphs"],
+                "properties": {
+                    "font_size": uint(),
+                    "line_metrics": { "$ref": "#/$defs/LineMetrics" },
+                    "name": { "type": "string" },
+                    "glyphs": {
+                        "type": "object",
+                        "propertyNames": { "minLength": 1, "maxLength": 1 },
+                        "additionalProperties": { "$ref": "#/$defs/Glyph" }
+                    },
+                    "sized_line_metrics": {
+                        "type": "object",
+                        "propertyNames": { "pattern": "^[0-9]+$" },
+                        "additionalProperties": { "$ref": "#/$defs/LineMetrics" }
+                    },
+                    "icons": map_of(json!({ "type": "string", "minLength": 1, "maxLength": 1 })),
+                    "distance_field": string_enum(&["sdf", "msdf"], "sdf")
+                }
+            },
+            "LineMetrics": {
+                "type": "object",
+                "required": ["ascent", "descent", "line_gap", "new_line_size"],
+                "properties": {
+                    "ascent": { "type": "number" },
+                    "descent": { "type": "number" },
+                    "line_gap": { "type": "number" },
+                    "new_line_size": { "type": "number" }
+                }
+            },
+            "Glyph": {
+                "type": "object",
+                "required": ["xmin", "ymin", "width", "height", "advance", "is_white_space", "uv_min", "uv_max"],
+                "properties": {
+                    "xmin": { "type": "number" },
+                    "ymin": { "type": "number" },
+                    "width": { "type": "number" },
+                    "height": { "type": "number" },
+                    "advance": { "type": "number" },
+                    "is_white_space": { "type": "boolean" },
+                    "uv_min": { "$ref": "#/$defs/Vec2" },
+                    "uv_max": { "$ref": "#/$defs/Vec2" },
+                    "tabular": { "$ref": "#/$defs/TabularMetrics" }
+                }
+            },
+            "TabularMetrics": {
+                "type": "object",
+                "required": ["xmin", "advance"],
+                "properties": {
+                    "xmin": { "type": "number" },
+                    "advance": { "type": "number" }
+                }
+            }
+        }
+    })
+}
+
+fn uint() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+/// A map with string keys, like the `HashMap<String, _>` and `BTreeMap<String, _>` of the manifest.
+fn map_of(value: Value) -> Value {
+    json!({ "type": "object", "additionalProperties": value })
+}
+
+fn string_enum(variants: &[&str], default: &str) -> Value {
+    json!({ "type": "string", "enum": variants, "default": default })
+}
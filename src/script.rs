@@ -0,0 +1,295 @@
+use std::{collections::HashMap, fmt::Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    codegen::{unique_idents, IdentCase},
+    font::icon_codepoints,
+    gather::GatheredAssets,
+    pack::{PackedAssets, TileKind},
+    warnings::Warnings,
+};
+
+/// Scripting languages the manifest can be exported to, for games that resolve assets from scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptLanguage {
+    /// `assets.lua`, a chunk returning the manifest as a table
+    Lua,
+    /// `assets.js`, an es module exporting the manifest as its default export
+    Js,
+}
+
+impl ScriptLanguage {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ScriptLanguage::Lua => "assets.lua",
+            ScriptLanguage::Js => "assets.js",
+        }
+    }
+}
+
+/// Minimal value tree that both lua tables and js objects can be written from.
+enum Value {
+    Str(String),
+    Num(u32),
+    Float(f32),
+    Bool(bool),
+    List(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+/// Generates the manifest as a script, with tiles, fonts and icons keyed by their identifiers.
+///
+/// With [`IdentCase::Dotted`] all assets are keys of the top level table (`["tiles.button"]`),
+/// otherwise every asset kind gets its own nested table (`tiles.BUTTON`).
+pub fn generate_script(
+    packed: &PackedAssets,
+    gathered: &GatheredAssets,
+    language: ScriptLanguage,
+    case: IdentCase,
+    warnings: &mut Warnings,
+) -> String {
+    let textures = packed
+        .textures
+        .iter()
+        .map(|(name, flags)| {
+            Value::Table(vec![
+                (String::from("name"), Value::Str(name.clone())),
+                (String::from("repeat_x"), Value::Bool(flags.repeat_x)),
+                (String::from("repeat_y"), Value::Bool(flags.repeat_y)),
+                (String::from("is_opaque"), Value::Bool(flags.is_opaque)),
+            ])
+        })
+        .collect();
+
+    let mut tile_names: Vec<&str> = packed.tiles.keys().map(|e| e.as_str()).collect();
+    tile_names.sort();
+    let tiles: Vec<(String, Value)> = unique_idents("tiles", &tile_names, case, &[], warnings)
+        .into_iter()
+        .map(|(name, ident)| {
+            let tile = &packed.tiles[name];
+            let mut fields = vec![
+                (String::from("name"), Value::Str(name.to_string())),
+                (String::from("texture"), Value::Str(tile.atlas.clone())),
+                (
+                    String::from("min"),
+                    Value::List(vec![Value::Num(tile.min.x), Value::Num(tile.min.y)]),
+                ),
+                (
+                    String::from("max"),
+                    Value::List(vec![Value::Num(tile.max.x), Value::Num(tile.max.y)]),
+                ),
+            ];
+            if let Some(trim) = tile.trim {
+                fields.push((
+                    String::from("original_size"),
+                    Value::List(vec![
+                        Value::Num(trim.original_size.x),
+                        Value::Num(trim.original_size.y),
+                    ]),
+                ));
+                fields.push((
+                    String::from("trim_offset"),
+                    Value::List(vec![Value::Num(trim.offset.x), Value::Num(trim.offset.y)]),
+                ));
+            }
+            if tile.rotated {
+                fields.push((String::from("rotated"), Value::Bool(true)));
+            }
+            if tile.kind == TileKind::Mask {
+                fields.push((String::from("kind"), Value::Str(String::from("mask"))));
+            }
+            if let Some(pivot) = tile.pivot {
+                fields.push((
+                    String::from("pivot"),
+                    Value::List(vec![Value::Float(pivot.x), Value::Float(pivot.y)]),
+                ));
+            }
+            if let Some(nine_slice) = tile.nine_slice {
+                // left, right, top, bottom
+                fields.push((
+                    String::from("nine_slice"),
+                    Value::List(vec![
+                        Value::Num(nine_slice.left),
+                        Value::Num(nine_slice.right),
+                        Value::Num(nine_slice.top),
+                        Value::Num(nine_slice.bottom),
+                    ]),
+                ));
+            }
+            (ident, Value::Table(fields))
+        })
+        .collect();
+
+    let mut font_names: Vec<&str> = packed.fonts.iter().map(|e| e.as_str()).collect();
+    font_names.sort();
+    let fonts: Vec<(String, Value)> = unique_idents("fonts", &font_names, case, &[], warnings)
+        .into_iter()
+        .map(|(name, ident)| (ident, Value::Str(name.to_string())))
+        .collect();
+
+    let icon_chars: HashMap<&str, char> = icon_codepoints(&gathered.icons).into_iter().collect();
+    let mut icon_names: Vec<&str> = icon_chars.keys().copied().collect();
+    icon_names.sort();
+    let icons: Vec<(String, Value)> = unique_idents("icons", &icon_names, case, &[], warnings)
+        .into_iter()
+        .map(|(name, ident)| (ident, Value::Num(icon_chars[name] as u32)))
+        .collect();
+
+    let mut root: Vec<(String, Value)> = vec![(String::from("textures"), Value::List(textures))];
+    if let Some(default_font) = &packed.default_font {
+        root.push((
+            String::from("default_font"),
+            Value::Str(default_font.clone()),
+        ));
+    }
+    for (namespace, entries) in [("tiles", tiles), ("fonts", fonts), ("icons", icons)] {
+        if case == IdentCase::Dotted {
+            root.extend(entries);
+        } else {
+            root.push((namespace.to_string(), Value::Table(entries)));
+        }
+    }
+
+    let mut out = String::new();
+    match language {
+        ScriptLanguage::Lua => {
+            out.push_str("-- generated by assetpacker, do not edit\nreturn ");
+            write_lua(&mut out, &Value::Table(root), 0);
+        }
+        ScriptLanguage::Js => {
+            out.push_str("// generated by assetpacker, do not edit\nexport default ");
+            write_js(&mut out, &Value::Table(root), 0);
+            out.push(';');
+        }
+    }
+    out.push('\n');
+    out
+}
+
+fn write_lua(out: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Str(s) => write_quoted(out, s),
+        Value::Num(n) => write!(out, "{n}").unwrap(),
+        Value::Float(n) => write!(out, "{n}").unwrap(),
+        Value::Bool(b) => write!(out, "{b}").unwrap(),
+        Value::List(items) if items.iter().all(is_scalar) => {
+            out.push_str("{ ");
+            for (i, item) in items.iter().enumerate() {
+                if i != 0 {
+                    out.push_str(", ");
+                }
+                write_lua(out, item, indent);
+            }
+            out.push_str(" }");
+        }
+        Value::List(items) => {
+            out.push_str("{\n");
+            for item in items {
+                push_indent(out, indent + 1);
+                write_lua(out, item, indent + 1);
+                out.push_str(",\n");
+            }
+            push_indent(out, indent);
+            out.push('}');
+        }
+        Value::Table(entries) => {
+            out.push_str("{\n");
+            for (key, value) in entries {
+                push_indent(out, indent + 1);
+                if is_plain_ident(key) && !LUA_KEYWORDS.contains(&key.as_str()) {
+                    out.push_str(key);
+                } else {
+                    out.push('[');
+                    write_quoted(out, key);
+                    out.push(']');
+                }
+                out.push_str(" = ");
+                write_lua(out, value, indent + 1);
+                out.push_str(",\n");
+            }
+            push_indent(out, indent);
+            out.push('}');
+        }
+    }
+}
+
+fn write_js(out: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Str(s) => write_quoted(out, s),
+        Value::Num(n) => write!(out, "{n}").unwrap(),
+        Value::Float(n) => write!(out, "{n}").unwrap(),
+        Value::Bool(b) => write!(out, "{b}").unwrap(),
+        Value::List(items) if items.iter().all(is_scalar) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i != 0 {
+                    out.push_str(", ");
+                }
+                write_js(out, item, indent);
+            }
+            out.push(']');
+        }
+        Value::List(items) => {
+            out.push_str("[\n");
+            for item in items {
+                push_indent(out, indent + 1);
+                write_js(out, item, indent + 1);
+                out.push_str(",\n");
+            }
+            push_indent(out, indent);
+            out.push(']');
+        }
+        Value::Table(entries) => {
+            out.push_str("{\n");
+            for (key, value) in entries {
+                push_indent(out, indent + 1);
+                if is_plain_ident(key) {
+                    out.push_str(key);
+                } else {
+                    write_quoted(out, key);
+                }
+                out.push_str(": ");
+                write_js(out, value, indent + 1);
+                out.push_str(",\n");
+            }
+            push_indent(out, indent);
+            out.push('}');
+        }
+    }
+}
+
+fn is_scalar(value: &Value) -> bool {
+    !matches!(value, Value::List(_) | Value::Table(_))
+}
+
+fn is_plain_ident(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// double quoted string literal, valid in both lua and js
+fn write_quoted(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
+
+const LUA_KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
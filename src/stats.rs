@@ -0,0 +1,331 @@
+use std::{
+    fmt::Write as _,
+    io::{Cursor, Write as _},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::{DynamicImage, ImageOutputFormat};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dds::dds_dimensions,
+    error::{PackError, PackResult},
+    ktx2::ktx2_dimensions,
+    loader::LoadedAssets,
+    pack::PackedAssets,
+    pak::PackFiles,
+};
+
+/// suffix of the file next to the destination that the stats of every pack are appended to, like
+/// `packed.stats_history.jsonl`
+pub const STATS_HISTORY_FILE_NAME: &str = "stats_history.jsonl";
+
+/// Key numbers of one pack, one line in the stats history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackStats {
+    /// unix timestamp in seconds
+    pub time: u64,
+    pub tiles: usize,
+    pub textures: usize,
+    pub fonts: usize,
+    /// width * height summed over all textures
+    pub texture_pixels: u64,
+    /// size of all files in the output dir
+    pub output_bytes: u64,
+}
+
+impl PackStats {
+    pub fn collect(packed: &PackedAssets, out_path: &str) -> PackStats {
+        let files = PackFiles::open(out_path).ok();
+        let texture_pixels = packed
+            .textures
+            .iter()
+            .filter_map(|(name, flags)| {
+                let bytes = files
+                    .as_ref()?
+                    .read(packed.file_name(&format!("{name}.{}", flags.format.extension())))
+                    .ok()?;
+                match flags.format.extension() {
+                    "png" | "webp" => image::io::Reader::new(Cursor::new(bytes))
+                        .with_guessed_format()
+                        .ok()?
+                        .into_dimensions()
+                        .ok(),
+                    "ktx2" => ktx2_dimensions(&bytes),
+                    "dds" => dds_dimensions(&bytes),
+                    _ => None,
+                }
+            })
+            .map(|(w, h)| w as u64 * h as u64)
+            .sum();
+        let output_bytes = std::fs::read_dir(out_path)
+            .map(|dir| {
+                dir.flatten()
+                    .filter_map(|e| e.metadata().ok())
+                    .map(|e| e.len())
+                    .sum()
+            })
+            .unwrap_or(0);
+        PackStats {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|e| e.as_secs())
+                .unwrap_or(0),
+            tiles: packed.tiles.len(),
+            textures: packed.textures.len(),
+            fonts: packed.fonts.len(),
+            texture_pixels,
+            output_bytes,
+        }
+    }
+}
+
+/// The history of the pack written to `dest`, a dir or archive, next to it so packing never
+/// changes the source dir.
+pub fn history_path(dest: &str) -> String {
+    format!("{}.{STATS_HISTORY_FILE_NAME}", dest.trim_end_matches('/'))
+}
+
+pub fn append_to_history(dest: &str, stats: &PackStats) -> PackResult<()> {
+    let path = history_path(dest);
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(PackError::io(&path))?;
+    let json = serde_json::to_string(stats).map_err(PackError::json(&path))?;
+    writeln!(file, "{json}").map_err(PackError::io(&path))
+}
+
+pub fn read_history(dest: &str) -> Vec<PackStats> {
+    let Ok(text) = std::fs::read_to_string(history_path(dest)) else {
+        return vec![];
+    };
+    text.lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Renders the last `count` entries of the history as a table, followed by a sparkline per metric
+/// over the whole history.
+pub fn format_history(history: &[PackStats], count: usize) -> String {
+    let mut out = String::new();
+    if history.is_empty() {
+        out.push_str("no stats recorded yet, they are appended on every pack\n");
+        return out;
+    }
+    writeln!(
+        out,
+        "{:<17} {:>7} {:>9} {:>6} {:>14} {:>12}",
+        "time (utc)", "tiles", "textures", "fonts", "texture pixels", "output size"
+    )
+    .unwrap();
+    for stats in history.iter().skip(history.len().saturating_sub(count)) {
+        writeln!(
+            out,
+            "{:<17} {:>7} {:>9} {:>6} {:>14} {:>12}",
+            format_time(stats.time),
+            stats.tiles,
+            stats.textures,
+            stats.fonts,
+            stats.texture_pixels,
+            format_bytes(stats.output_bytes)
+        )
+        .unwrap();
+    }
+
+    out.push('\n');
+    let metrics: [Metric; 5] = [
+        ("tiles", |e| e.tiles as u64),
+        ("textures", |e| e.textures as u64),
+        ("fonts", |e| e.fonts as u64),
+        ("texture pixels", |e| e.texture_pixels),
+        ("output bytes", |e| e.output_bytes),
+    ];
+    for (name, metric) in metrics {
+        let values: Vec<u64> = history.iter().map(metric).collect();
+        writeln!(
+            out,
+            "{name:<15} {} {} -> {}",
+            sparkline(&values),
+            values[0],
+            values[values.len() - 1]
+        )
+        .unwrap();
+    }
+    out
+}
+
+type Metric = (&'static str, fn(&PackStats) -> u64);
+
+fn sparkline(values: &[u64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = *values.iter().min().unwrap();
+    let max = *values.iter().max().unwrap();
+    values
+        .iter()
+        .map(|v| {
+            if max == min {
+                BARS[0]
+            } else {
+                BARS[((v - min) * 7 / (max - min)) as usize]
+            }
+        })
+        .collect()
+}
+
+/// Approximate share of one tile in the compressed size of its texture.
+#[derive(Debug, Clone)]
+pub struct TileCompression {
+    pub name: String,
+    pub pixels: u64,
+    /// size of the tile on its own as png
+    pub bytes: u64,
+}
+
+impl TileCompression {
+    pub fn bytes_per_pixel(&self) -> f64 {
+        self.bytes as f64 / self.pixels.max(1) as f64
+    }
+}
+
+/// Encodes the region of every tile of the pack in `dir` as a png of its own, largest first.
+///
+/// The sizes do not add up to the texture sizes, but noise and gradients that compress badly stand
+/// out the same way. Block compressed formats have a fixed size per pixel, the png sizes still show
+/// which sprites lose the most detail in them.
+pub fn tile_compression(dir: &str) -> PackResult<Vec<TileCompression>> {
+    let assets = LoadedAssets::load(dir)?;
+    let mut tiles: Vec<TileCompression> = assets
+        .tiles
+        .par_iter()
+        .map(|tile| {
+            let texture = assets.texture(tile.texture);
+            let packed_tile = &assets.manifest.tiles[&tile.name];
+            let size = packed_tile.max - packed_tile.min;
+            let region = image::imageops::crop_imm(
+                &texture.rgba,
+                packed_tile.min.x,
+                packed_tile.min.y,
+                size.x,
+                size.y,
+            )
+            .to_image();
+            let region = match texture.flags.single_channel {
+                true => DynamicImage::ImageLuma8(DynamicImage::ImageRgba8(region).to_luma8()),
+                false => DynamicImage::ImageRgba8(region),
+            };
+            let mut png: Vec<u8> = vec![];
+            region
+                .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+                .map_err(PackError::image(format!("{dir}/{}", texture.name)))?;
+            Ok(TileCompression {
+                name: tile.name.clone(),
+                pixels: size.x as u64 * size.y as u64,
+                bytes: png.len() as u64,
+            })
+        })
+        .collect::<PackResult<_>>()?;
+    tiles.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    Ok(tiles)
+}
+
+/// Renders the `count` largest tiles as a table, marking the ones with more than twice the median
+/// bytes per pixel as outliers.
+pub fn format_tile_compression(tiles: &[TileCompression], count: usize) -> String {
+    let mut out = String::new();
+    if tiles.is_empty() {
+        out.push_str("the pack has no tiles\n");
+        return out;
+    }
+    let mut bytes_per_pixel: Vec<f64> = tiles.iter().map(|e| e.bytes_per_pixel()).collect();
+    bytes_per_pixel.sort_by(f64::total_cmp);
+    let median = bytes_per_pixel[bytes_per_pixel.len() / 2];
+    let total: u64 = tiles.iter().map(|e| e.bytes).sum();
+    writeln!(
+        out,
+        "{:<32} {:>10} {:>9} {:>11} {:>6}",
+        "tile", "size", "pixels", "bytes/pixel", "share"
+    )
+    .unwrap();
+    for tile in tiles.iter().take(count) {
+        let outlier = match tile.bytes_per_pixel() > 2.0 * median {
+            true => "  outlier",
+            false => "",
+        };
+        writeln!(
+            out,
+            "{:<32} {:>10} {:>9} {:>11.2} {:>5.1}%{outlier}",
+            tile.name,
+            format_bytes(tile.bytes),
+            tile.pixels,
+            tile.bytes_per_pixel(),
+            tile.bytes as f64 / total.max(1) as f64 * 100.0
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "\n{} tiles, {} in total, median {median:.2} bytes per pixel",
+        tiles.len(),
+        format_bytes(total)
+    )
+    .unwrap();
+    out
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1 << 10) as f64),
+        b => format!("{b} B"),
+    }
+}
+
+/// `YYYY-MM-DD HH:MM` in utc, without pulling in a date crate
+fn format_time(unix_secs: u64) -> String {
+    let days = (unix_secs / 86400) as i64;
+    let secs_of_day = unix_secs % 86400;
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{history_path, read_history};
+    use crate::{testing::TempDir, Packer};
+
+    #[test]
+    fn history_is_recorded_next_to_the_destination() {
+        let dir = TempDir::new("history_is_recorded_next_to_the_destination");
+        dir.write_png("src/images/a.png", (8, 8), [255, 0, 0, 255]);
+        let (src, dest) = (dir.path("src"), dir.path("packed"));
+        Packer::new(&src).pack_to(&dest).unwrap();
+        assert_eq!(read_history(&dest).len(), 1);
+        assert_eq!(std::fs::read_dir(&src).unwrap().count(), 1);
+
+        // a history that can not be written is a warning, not an error
+        let blocked = dir.path("blocked");
+        std::fs::create_dir_all(history_path(&blocked)).unwrap();
+        Packer::new(&src).pack_to(&blocked).unwrap();
+        Packer::new(&src)
+            .record_stats(false)
+            .pack_to(&dir.path("throwaway"))
+            .unwrap();
+        assert!(read_history(&dir.path("throwaway")).is_empty());
+    }
+}
@@ -0,0 +1,513 @@
+use glam::{vec2, Vec2};
+use image::GrayImage;
+
+/// A very small subset of SVG: the filled shapes (`path`, `rect`, `circle`, `ellipse`, `polygon`) of
+/// single-color icons, flattened to closed polygons. Strokes, transforms, gradients and css are ignored.
+#[derive(Debug, Clone)]
+pub struct Svg {
+    /// size of the view box, which the polygons are relative to
+    pub size: Vec2,
+    pub polygons: Vec<Vec<Vec2>>,
+    /// rgb color of the first filled shape, black if it has none, as the shapes are drawn when the svg
+    /// is rasterized as an image
+    pub fill: [u8; 3],
+}
+
+/// line segments per bezier curve or arc when flattening
+const CURVE_SEGMENTS: usize = 16;
+/// vertical samples per pixel when rasterizing
+const SUBSAMPLES: usize = 4;
+
+impl Svg {
+    /// Fails if the svg has neither a `viewBox` nor a `width` and `height`.
+    pub fn parse(text: &str) -> Result<Svg, String> {
+        let mut size: Option<Vec2> = None;
+        let mut origin = Vec2::ZERO;
+        let mut polygons: Vec<Vec<Vec2>> = vec![];
+        let mut fill: Option<[u8; 3]> = None;
+        for (tag, attrs) in xml_elements(text) {
+            let attr = |name: &str| -> Option<&str> {
+                attrs.iter().find(|(k, _)| *k == name).map(|(_, v)| *v)
+            };
+            let num = |name: &str| -> f32 { attr(name).map(parse_length).unwrap_or(0.0) };
+            if tag == "svg" {
+                let view_box = attr("viewBox").map(parse_numbers);
+                if let Some([x, y, w, h]) = view_box.as_deref() {
+                    origin = vec2(*x, *y);
+                    size = Some(vec2(*w, *h));
+                } else {
+                    size = Some(vec2(num("width"), num("height")));
+                }
+                continue;
+            }
+            if attr("fill") == Some("none") {
+                continue;
+            }
+            if fill.is_none() && ["path", "rect", "circle", "ellipse", "polygon"].contains(&tag) {
+                fill = Some(attr("fill").and_then(parse_color).unwrap_or([0, 0, 0]));
+            }
+            match tag {
+                "path" => {
+                    if let Some(d) = attr("d") {
+                        polygons.extend(parse_path(d));
+                    }
+                }
+                "rect" => {
+                    let (x, y, w, h) = (num("x"), num("y"), num("width"), num("height"));
+                    polygons.push(vec![
+                        vec2(x, y),
+                        vec2(x + w, y),
+                        vec2(x + w, y + h),
+                        vec2(x, y + h),
+                    ]);
+                }
+                "circle" | "ellipse" => {
+                    let center = vec2(num("cx"), num("cy"));
+                    let radius = match tag {
+                        "circle" => Vec2::splat(num("r")),
+                        _ => vec2(num("rx"), num("ry")),
+                    };
+                    let segments = CURVE_SEGMENTS * 4;
+                    polygons.push(
+                        (0..segments)
+                            .map(|i| {
+                                let a = i as f32 / segments as f32 * std::f32::consts::TAU;
+                                center + radius * vec2(a.cos(), a.sin())
+                            })
+                            .collect(),
+                    );
+                }
+                "polygon" => {
+                    if let Some(points) = attr("points") {
+                        let n = parse_numbers(points);
+                        polygons.push(n.chunks_exact(2).map(|e| vec2(e[0], e[1])).collect());
+                    }
+                }
+                _ => {}
+            }
+        }
+        for p in polygons.iter_mut().flatten() {
+            *p -= origin;
+        }
+        match size {
+            Some(size) if size.x > 0.0 && size.y > 0.0 => Ok(Svg {
+                size,
+                polygons,
+                fill: fill.unwrap_or([0, 0, 0]),
+            }),
+            _ => Err(String::from(
+                "the svg element needs a viewBox or a width and height",
+            )),
+        }
+    }
+
+    /// Rasterizes the filled area into a coverage image where one view box unit is `scale` pixels.
+    ///
+    /// Uses the non-zero winding rule with exact horizontal coverage and a few vertical subsamples per pixel.
+    pub fn rasterize(&self, scale: f32) -> GrayImage {
+        let w = (self.size.x * scale).ceil().max(1.0) as usize;
+        let h = (self.size.y * scale).ceil().max(1.0) as usize;
+        let mut coverage = vec![0.0f32; w * h];
+
+        let mut edges: Vec<(Vec2, Vec2)> = vec![];
+        for polygon in self.polygons.iter() {
+            for i in 0..polygon.len() {
+                let a = polygon[i] * scale;
+                let b = polygon[(i + 1) % polygon.len()] * scale;
+                if a.y != b.y {
+                    edges.push((a, b));
+                }
+            }
+        }
+
+        let mut crossings: Vec<(f32, i32)> = vec![];
+        for py in 0..h {
+            let row = &mut coverage[py * w..(py + 1) * w];
+            for s in 0..SUBSAMPLES {
+                let y = py as f32 + (s as f32 + 0.5) / SUBSAMPLES as f32;
+                crossings.clear();
+                for (a, b) in edges.iter() {
+                    let (top, bottom, winding) = if a.y < b.y { (a, b, 1) } else { (b, a, -1) };
+                    if y >= top.y && y < bottom.y {
+                        let t = (y - top.y) / (bottom.y - top.y);
+                        crossings.push((top.x + (bottom.x - top.x) * t, winding));
+                    }
+                }
+                crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+                let mut winding = 0;
+                let mut span_start = 0.0;
+                for (x, dir) in crossings.iter() {
+                    if winding == 0 {
+                        span_start = *x;
+                    }
+                    winding += dir;
+                    if winding == 0 {
+                        fill_span(row, span_start, *x, 1.0 / SUBSAMPLES as f32);
+                    }
+                }
+            }
+        }
+
+        let pixels = coverage
+            .iter()
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+        GrayImage::from_raw(w as u32, h as u32, pixels).unwrap()
+    }
+}
+
+fn fill_span(row: &mut [f32], x0: f32, x1: f32, weight: f32) {
+    let x0 = x0.max(0.0);
+    let x1 = x1.min(row.len() as f32);
+    if x1 <= x0 {
+        return;
+    }
+    let first = x0.floor() as usize;
+    for (ix, coverage) in row[first..x1.ceil() as usize].iter_mut().enumerate() {
+        let ix = (first + ix) as f32;
+        let overlap = x1.min(ix + 1.0) - x0.max(ix);
+        *coverage += overlap * weight;
+    }
+}
+
+/// Returns the tag name and attributes of all opening (or self closing) elements.
+fn xml_elements(text: &str) -> Vec<(&str, Vec<(&str, &str)>)> {
+    let mut elements = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let element = &rest[..end];
+        rest = &rest[end + 1..];
+        if element.starts_with(['/', '?', '!']) {
+            continue;
+        }
+        let element = element.trim_end_matches('/');
+        let tag_end = element
+            .find(|c: char| c.is_whitespace())
+            .unwrap_or(element.len());
+        let tag = &element[..tag_end];
+
+        let mut attrs = vec![];
+        let mut attr_rest = &element[tag_end..];
+        while let Some(eq) = attr_rest.find('=') {
+            let name = attr_rest[..eq].trim();
+            let after = attr_rest[eq + 1..].trim_start();
+            let Some(quote) = after.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+                break;
+            };
+            let Some(value_end) = after[1..].find(quote) else {
+                break;
+            };
+            attrs.push((name, &after[1..value_end + 1]));
+            attr_rest = &after[value_end + 2..];
+        }
+        elements.push((tag, attrs));
+    }
+    elements
+}
+
+/// Colors like `#f80`, `#ff8800`, `white` or `black`, `None` for anything else.
+fn parse_color(s: &str) -> Option<[u8; 3]> {
+    match s.trim() {
+        "white" => return Some([255, 255, 255]),
+        "black" => return Some([0, 0, 0]),
+        _ => {}
+    }
+    let hex = s.trim().strip_prefix('#')?;
+    let digit = |i: usize| u8::from_str_radix(hex.get(i..i + 1)?, 16).ok();
+    match hex.len() {
+        3 => Some([digit(0)? * 17, digit(1)? * 17, digit(2)? * 17]),
+        6 => Some([
+            digit(0)? * 16 + digit(1)?,
+            digit(2)? * 16 + digit(3)?,
+            digit(4)? * 16 + digit(5)?,
+        ]),
+        _ => None,
+    }
+}
+
+fn parse_length(s: &str) -> f32 {
+    s.trim_end_matches("px").trim().parse().unwrap_or(0.0)
+}
+
+fn parse_numbers(s: &str) -> Vec<f32> {
+    let mut tokens = PathTokens::new(s);
+    let mut numbers = vec![];
+    while let Some(n) = tokens.number() {
+        numbers.push(n);
+    }
+    numbers
+}
+
+/// Flattens the path data of a `d` attribute into closed polygons.
+fn parse_path(d: &str) -> Vec<Vec<Vec2>> {
+    let mut polygons: Vec<Vec<Vec2>> = vec![];
+    let mut current: Vec<Vec2> = vec![];
+    let mut tokens = PathTokens::new(d);
+
+    let mut pos = Vec2::ZERO;
+    let mut start = Vec2::ZERO;
+    // second control point of the last curve, for the smooth curve commands
+    let mut last_control: Option<Vec2> = None;
+    let mut command = 'M';
+
+    loop {
+        if let Some(c) = tokens.command() {
+            command = c;
+        } else if tokens.is_at_end() || command.eq_ignore_ascii_case(&'Z') {
+            break;
+        }
+        let relative = command.is_ascii_lowercase();
+        let offset = if relative { pos } else { Vec2::ZERO };
+        let point = |tokens: &mut PathTokens| -> Option<Vec2> {
+            Some(vec2(tokens.number()?, tokens.number()?) + offset)
+        };
+
+        match command.to_ascii_uppercase() {
+            'M' => {
+                let Some(p) = point(&mut tokens) else { break };
+                if current.len() > 2 {
+                    polygons.push(std::mem::take(&mut current));
+                }
+                current.clear();
+                current.push(p);
+                pos = p;
+                start = p;
+                last_control = None;
+                // further coordinate pairs after a move are implicit line commands
+                command = if relative { 'l' } else { 'L' };
+            }
+            'L' => {
+                let Some(p) = point(&mut tokens) else { break };
+                current.push(p);
+                pos = p;
+                last_control = None;
+            }
+            'H' => {
+                let Some(x) = tokens.number() else { break };
+                pos.x = if relative { pos.x + x } else { x };
+                current.push(pos);
+                last_control = None;
+            }
+            'V' => {
+                let Some(y) = tokens.number() else { break };
+                pos.y = if relative { pos.y + y } else { y };
+                current.push(pos);
+                last_control = None;
+            }
+            'C' | 'S' => {
+                let c1 = if command.eq_ignore_ascii_case(&'C') {
+                    let Some(c1) = point(&mut tokens) else { break };
+                    c1
+                } else {
+                    last_control.map(|c| 2.0 * pos - c).unwrap_or(pos)
+                };
+                let (Some(c2), Some(p)) = (point(&mut tokens), point(&mut tokens)) else {
+                    break;
+                };
+                for i in 1..=CURVE_SEGMENTS {
+                    let t = i as f32 / CURVE_SEGMENTS as f32;
+                    let mt = 1.0 - t;
+                    current.push(
+                        pos * mt * mt * mt
+                            + c1 * 3.0 * mt * mt * t
+                            + c2 * 3.0 * mt * t * t
+                            + p * t * t * t,
+                    );
+                }
+                pos = p;
+                last_control = Some(c2);
+            }
+            'Q' | 'T' => {
+                let c = if command.eq_ignore_ascii_case(&'Q') {
+                    let Some(c) = point(&mut tokens) else { break };
+                    c
+                } else {
+                    last_control.map(|c| 2.0 * pos - c).unwrap_or(pos)
+                };
+                let Some(p) = point(&mut tokens) else { break };
+                for i in 1..=CURVE_SEGMENTS {
+                    let t = i as f32 / CURVE_SEGMENTS as f32;
+                    let mt = 1.0 - t;
+                    current.push(pos * mt * mt + c * 2.0 * mt * t + p * t * t);
+                }
+                pos = p;
+                last_control = Some(c);
+            }
+            'A' => {
+                let (Some(rx), Some(ry), Some(rotation), Some(large_arc), Some(sweep)) = (
+                    tokens.number(),
+                    tokens.number(),
+                    tokens.number(),
+                    tokens.flag(),
+                    tokens.flag(),
+                ) else {
+                    break;
+                };
+                let Some(p) = point(&mut tokens) else { break };
+                current.extend(flatten_arc(
+                    pos,
+                    p,
+                    vec2(rx, ry),
+                    rotation.to_radians(),
+                    large_arc,
+                    sweep,
+                ));
+                pos = p;
+                last_control = None;
+            }
+            'Z' => {
+                if current.len() > 2 {
+                    polygons.push(std::mem::take(&mut current));
+                }
+                current.clear();
+                current.push(start);
+                pos = start;
+                last_control = None;
+            }
+            _ => break,
+        }
+    }
+    if current.len() > 2 {
+        polygons.push(current);
+    }
+    polygons
+}
+
+/// Flattens an elliptical arc into points (excluding `from`), see the SVG spec appendix on arc implementation.
+fn flatten_arc(
+    from: Vec2,
+    to: Vec2,
+    radius: Vec2,
+    rotation: f32,
+    large_arc: bool,
+    sweep: bool,
+) -> Vec<Vec2> {
+    let mut r = radius.abs();
+    if r.x == 0.0 || r.y == 0.0 || from == to {
+        return vec![to];
+    }
+    let (sin, cos) = rotation.sin_cos();
+    let half = (from - to) / 2.0;
+    let p = vec2(cos * half.x + sin * half.y, -sin * half.x + cos * half.y);
+
+    let lambda = (p.x * p.x) / (r.x * r.x) + (p.y * p.y) / (r.y * r.y);
+    if lambda > 1.0 {
+        r *= lambda.sqrt();
+    }
+    let num = r.x * r.x * r.y * r.y - r.x * r.x * p.y * p.y - r.y * r.y * p.x * p.x;
+    let den = r.x * r.x * p.y * p.y + r.y * r.y * p.x * p.x;
+    let mut coef = (num / den).max(0.0).sqrt();
+    if large_arc == sweep {
+        coef = -coef;
+    }
+    let c = coef * vec2(r.x * p.y / r.y, -r.y * p.x / r.x);
+    let center = vec2(cos * c.x - sin * c.y, sin * c.x + cos * c.y) + (from + to) / 2.0;
+
+    let angle = |v: Vec2| v.y.atan2(v.x);
+    let start_angle = angle((p - c) / r);
+    let mut delta = angle((-p - c) / r) - start_angle;
+    if sweep && delta < 0.0 {
+        delta += std::f32::consts::TAU;
+    } else if !sweep && delta > 0.0 {
+        delta -= std::f32::consts::TAU;
+    }
+
+    (1..=CURVE_SEGMENTS)
+        .map(|i| {
+            let a = start_angle + delta * i as f32 / CURVE_SEGMENTS as f32;
+            let e = vec2(r.x * a.cos(), r.y * a.sin());
+            vec2(cos * e.x - sin * e.y, sin * e.x + cos * e.y) + center
+        })
+        .collect()
+}
+
+/// Tokenizer for path data and number lists, which allow omitting separators in many places (e.g. `M1-2.5.5z`).
+struct PathTokens<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl<'a> PathTokens<'a> {
+    fn new(s: &'a str) -> Self {
+        PathTokens {
+            s: s.as_bytes(),
+            i: 0,
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while self.i < self.s.len()
+            && (self.s[self.i].is_ascii_whitespace() || self.s[self.i] == b',')
+        {
+            self.i += 1;
+        }
+    }
+
+    fn is_at_end(&mut self) -> bool {
+        self.skip_separators();
+        self.i >= self.s.len()
+    }
+
+    fn command(&mut self) -> Option<char> {
+        self.skip_separators();
+        let c = *self.s.get(self.i)? as char;
+        if c.is_ascii_alphabetic() && c != 'e' && c != 'E' {
+            self.i += 1;
+            Some(c)
+        } else {
+            None
+        }
+    }
+
+    /// arc flags are single digits that may not be separated from the following number
+    fn flag(&mut self) -> Option<bool> {
+        self.skip_separators();
+        let c = *self.s.get(self.i)?;
+        self.i += 1;
+        match c {
+            b'0' => Some(false),
+            b'1' => Some(true),
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Option<f32> {
+        self.skip_separators();
+        let start = self.i;
+        let mut seen_dot = false;
+        let mut seen_exp = false;
+        while self.i < self.s.len() {
+            let c = self.s[self.i];
+            let is_part = match c {
+                b'0'..=b'9' => true,
+                b'+' | b'-' => self.i == start || matches!(self.s[self.i - 1], b'e' | b'E'),
+                b'.' if !seen_dot && !seen_exp => {
+                    seen_dot = true;
+                    true
+                }
+                b'e' | b'E' if !seen_exp && self.i > start => {
+                    seen_exp = true;
+                    true
+                }
+                _ => false,
+            };
+            if !is_part {
+                break;
+            }
+            self.i += 1;
+        }
+        let n = std::str::from_utf8(&self.s[start..self.i])
+            .ok()?
+            .parse()
+            .ok();
+        if n.is_none() {
+            self.i = start;
+        }
+        n
+    }
+}
@@ -0,0 +1,87 @@
+//! Helpers for the tests that pack asset trees on disk or encode textures.
+
+use std::path::PathBuf;
+
+use image::{codecs::gif::GifEncoder, Delay, Frame, Rgba, RgbaImage};
+
+/// A dir in the system temp dir, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Named after the test, so tests running in parallel do not share dirs.
+    pub fn new(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("assetpacker_{name}_{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    /// Path of a file or dir relative to the temp dir.
+    pub fn path(&self, relative: &str) -> String {
+        self.0.join(relative).to_string_lossy().into_owned()
+    }
+
+    /// Writes a png of a single color, creating its parent dirs.
+    pub fn write_png(&self, relative: &str, size: (u32, u32), color: [u8; 4]) {
+        let path = self.0.join(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        RgbaImage::from_pixel(size.0, size.1, Rgba(color))
+            .save(path)
+            .unwrap();
+    }
+}
+
+impl TempDir {
+    /// Writes an animated gif with a frame of a single color per color, each shown for `delay_ms`.
+    pub fn write_gif(&self, relative: &str, size: (u32, u32), colors: &[[u8; 4]], delay_ms: u32) {
+        let path = self.0.join(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = std::fs::File::create(path).unwrap();
+        let frames = colors.iter().map(|color| {
+            let rgba = RgbaImage::from_pixel(size.0, size.1, Rgba(*color));
+            Frame::from_parts(rgba, 0, 0, Delay::from_numer_denom_ms(delay_ms, 1))
+        });
+        GifEncoder::new(file).encode_frames(frames).unwrap();
+    }
+
+    /// Writes a text file like a sidecar or dir config, creating its parent dirs.
+    pub fn write(&self, relative: &str, text: &str) {
+        let path = self.0.join(relative);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A smooth gradient over all channels, with the alpha fading out to the right unless `opaque`.
+pub fn gradient(w: u32, h: u32, opaque: bool) -> RgbaImage {
+    RgbaImage::from_fn(w, h, |x, y| {
+        let (u, v) = (
+            x * 255 / w.max(2).saturating_sub(1),
+            y * 255 / h.max(2).saturating_sub(1),
+        );
+        let alpha = match opaque {
+            true => 255,
+            false => 255 - u / 2,
+        };
+        Rgba([u as u8, v as u8, ((u + v) / 2) as u8, alpha as u8])
+    })
+}
+
+/// Peak signal to noise ratio of `decoded` compared to `original` over all channels, in dB.
+pub fn psnr(original: &RgbaImage, decoded: &RgbaImage) -> f64 {
+    assert_eq!(original.dimensions(), decoded.dimensions());
+    let squared_error: f64 = original
+        .as_raw()
+        .iter()
+        .zip(decoded.as_raw())
+        .map(|(a, b)| (*a as f64 - *b as f64).powi(2))
+        .sum();
+    let mse = squared_error / original.as_raw().len() as f64;
+    10.0 * (255.0 * 255.0 / mse.max(1e-9)).log10()
+}
@@ -0,0 +1,507 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{IsTerminal, Read, Write},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    error::{PackError, PackResult},
+    loader::{LoadedAssets, LoadedFont, LoadedTexture},
+    pack::{AlphaMode, TextureTile, TileKind},
+};
+use glam::UVec2;
+
+/// Width of the list on the left of every view, the preview gets the rest of the terminal.
+const LIST_WIDTH: usize = 44;
+
+/// Unicode blocks shown in the glyph coverage of fonts, blocks without any glyph are left out.
+const UNICODE_BLOCKS: &[(&str, u32, u32)] = &[
+    ("Basic Latin", 0x20, 0x7E),
+    ("Latin-1 Supplement", 0xA0, 0xFF),
+    ("Latin Extended-A", 0x100, 0x17F),
+    ("Latin Extended-B", 0x180, 0x24F),
+    ("Greek and Coptic", 0x370, 0x3FF),
+    ("Cyrillic", 0x400, 0x4FF),
+    ("Hebrew", 0x590, 0x5FF),
+    ("Arabic", 0x600, 0x6FF),
+    ("General Punctuation", 0x2000, 0x206F),
+    ("Currency Symbols", 0x20A0, 0x20CF),
+    ("Letterlike Symbols", 0x2100, 0x214F),
+    ("Arrows", 0x2190, 0x21FF),
+    ("Mathematical Operators", 0x2200, 0x22FF),
+    ("Box Drawing", 0x2500, 0x257F),
+    ("Block Elements", 0x2580, 0x259F),
+    ("Geometric Shapes", 0x25A0, 0x25FF),
+    ("Miscellaneous Symbols", 0x2600, 0x26FF),
+    ("Dingbats", 0x2700, 0x27BF),
+    ("Hiragana", 0x3040, 0x309F),
+    ("Katakana", 0x30A0, 0x30FF),
+    ("CJK Unified Ideographs", 0x4E00, 0x9FFF),
+    ("Hangul Syllables", 0xAC00, 0xD7AF),
+    ("Private Use Area", 0xE000, 0xF8FF),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    Textures,
+    Tiles,
+    Fonts,
+}
+
+struct Tui<'a> {
+    assets: &'a LoadedAssets,
+    /// tile names sorted, the tiles view shows the ones matching `search`
+    tile_names: Vec<&'a String>,
+    view: View,
+    selected: usize,
+    search: String,
+    searching: bool,
+}
+
+enum Key {
+    Char(char),
+    Up,
+    Down,
+    Enter,
+    Escape,
+    Backspace,
+    Tab,
+}
+
+/// Browses a pack in the terminal: textures with a preview and how much of them tiles cover, tiles
+/// with a search, and fonts with their glyph coverage per unicode block. Meant for inspecting packs
+/// over ssh or from ci artifacts without an image viewer. If stdout is not a terminal, the overview
+/// is printed once instead.
+pub fn run_tui(dir: &str) -> PackResult<()> {
+    let assets = LoadedAssets::load(dir)?;
+    let mut tile_names: Vec<&String> = assets.manifest.tiles.keys().collect();
+    tile_names.sort();
+    let mut tui = Tui {
+        assets: &assets,
+        tile_names,
+        view: View::Textures,
+        selected: 0,
+        search: String::new(),
+        searching: false,
+    };
+
+    if !std::io::stdout().is_terminal() {
+        print!("{}", overview(&assets));
+        return Ok(());
+    }
+
+    let _terminal = RawTerminal::enter()?;
+    tui.run()
+}
+
+/// The terminal in raw mode on the alternate screen, switched back on drop so that errors and
+/// panics while browsing do not leave the shell without echo.
+struct RawTerminal {
+    saved_mode: String,
+}
+
+impl RawTerminal {
+    fn enter() -> PackResult<RawTerminal> {
+        let terminal = RawTerminal {
+            saved_mode: stty(&["-g"])?.trim().to_owned(),
+        };
+        stty(&["raw", "-echo"])?;
+        print!("\x1b[?1049h\x1b[?25l"); // alternate screen, hidden cursor
+        Ok(terminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        _ = std::io::stdout().flush();
+        _ = stty(&[&self.saved_mode]);
+    }
+}
+
+impl Tui<'_> {
+    fn run(&mut self) -> PackResult<()> {
+        let mut stdin = std::io::stdin();
+        loop {
+            let (rows, cols) = terminal_size();
+            let frame = self.render(rows, cols);
+            let mut stdout = std::io::stdout();
+            _ = stdout.write_all(frame.as_bytes());
+            _ = stdout.flush();
+
+            let Some(key) = read_key(&mut stdin) else {
+                return Ok(());
+            };
+            if self.searching {
+                match key {
+                    Key::Char(ch) if !ch.is_control() => self.search.push(ch),
+                    Key::Backspace => _ = self.search.pop(),
+                    Key::Enter | Key::Escape => self.searching = false,
+                    _ => {}
+                }
+                self.selected = 0;
+                continue;
+            }
+            let count = self.entries().len();
+            match key {
+                Key::Char('q') | Key::Char('\x03') => return Ok(()),
+                Key::Char('1') => self.show(View::Textures),
+                Key::Char('2') => self.show(View::Tiles),
+                Key::Char('3') => self.show(View::Fonts),
+                Key::Tab => self.show(match self.view {
+                    View::Textures => View::Tiles,
+                    View::Tiles => View::Fonts,
+                    View::Fonts => View::Textures,
+                }),
+                Key::Char('/') if self.view == View::Tiles => self.searching = true,
+                Key::Char('j') | Key::Down => {
+                    self.selected = (self.selected + 1).min(count.saturating_sub(1))
+                }
+                Key::Char('k') | Key::Up => self.selected = self.selected.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+
+    fn show(&mut self, view: View) {
+        self.view = view;
+        self.selected = 0;
+    }
+
+    /// Lines of the list on the left of the current view.
+    fn entries(&self) -> Vec<String> {
+        match self.view {
+            View::Textures => self
+                .assets
+                .textures
+                .iter()
+                .map(|texture| {
+                    let (w, h) = texture.rgba.dimensions();
+                    let fill = tile_coverage(self.assets, texture) * 100.0;
+                    format!("{}  {w}x{h}  {fill:.0}%", texture.name)
+                })
+                .collect(),
+            View::Tiles => self
+                .matching_tiles()
+                .into_iter()
+                .map(|(name, tile)| {
+                    let size = tile.max - tile.min;
+                    format!("{name}  {}  {}x{}", tile.atlas, size.x, size.y)
+                })
+                .collect(),
+            View::Fonts => self
+                .assets
+                .fonts
+                .iter()
+                .map(|font| format!("{}  {} glyphs", font.font.name, font.font.glyphs.len()))
+                .collect(),
+        }
+    }
+
+    fn matching_tiles(&self) -> Vec<(&String, &TextureTile)> {
+        let search = self.search.to_lowercase();
+        self.tile_names
+            .iter()
+            .filter(|name| name.to_lowercase().contains(&search))
+            .map(|name| (*name, &self.assets.manifest.tiles[*name]))
+            .collect()
+    }
+
+    fn render(&self, rows: usize, cols: usize) -> String {
+        let mut out = String::from("\x1b[H\x1b[2J");
+        let tabs = [
+            (View::Textures, "1 textures"),
+            (View::Tiles, "2 tiles"),
+            (View::Fonts, "3 fonts"),
+        ];
+        for (view, label) in tabs {
+            match view == self.view {
+                true => out.push_str(&format!("\x1b[7m {label} \x1b[0m ")),
+                false => out.push_str(&format!(" {label}  ")),
+            }
+        }
+        out.push_str("  tab/1-3 switch  j/k move");
+        if self.view == View::Tiles {
+            out.push_str("  / search");
+        }
+        out.push_str("  q quit\r\n");
+        if self.view == View::Tiles {
+            let cursor = if self.searching { "_" } else { "" };
+            out.push_str(&format!("search: {}{cursor}\r\n", self.search));
+        } else {
+            out.push_str("\r\n");
+        }
+
+        let body_rows = rows.saturating_sub(3);
+        let entries = self.entries();
+        // keep the selected entry in view
+        let first = self.selected.saturating_sub(body_rows.saturating_sub(1));
+        let list: Vec<String> = entries
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(body_rows)
+            .map(|(i, entry)| {
+                let entry = fit(entry, LIST_WIDTH - 1);
+                match i == self.selected {
+                    true => format!("\x1b[7m{entry:<width$}\x1b[0m ", width = LIST_WIDTH - 1),
+                    false => format!("{entry:<width$} ", width = LIST_WIDTH - 1),
+                }
+            })
+            .collect();
+        let detail_width = cols.saturating_sub(LIST_WIDTH);
+        let detail = self.detail(detail_width, body_rows);
+        for row in 0..body_rows {
+            let left = list
+                .get(row)
+                .cloned()
+                .unwrap_or_else(|| " ".repeat(LIST_WIDTH));
+            let right = detail.get(row).map(String::as_str).unwrap_or_default();
+            out.push_str(&format!("{left}{right}\x1b[0m\r\n"));
+        }
+        out
+    }
+
+    /// Lines right of the list, for the selected entry.
+    fn detail(&self, width: usize, rows: usize) -> Vec<String> {
+        match self.view {
+            View::Textures => {
+                let Some(texture) = self.assets.textures.get(self.selected) else {
+                    return vec![];
+                };
+                let flags = texture.flags;
+                let mut lines = vec![format!(
+                    "{}  {:?}{}{}{}  {} mip levels",
+                    texture.name,
+                    flags.format,
+                    if flags.srgb { "" } else { " linear" },
+                    if flags.single_channel {
+                        " single channel"
+                    } else {
+                        ""
+                    },
+                    match flags.alpha {
+                        AlphaMode::Straight => "",
+                        AlphaMode::Premultiplied => " premultiplied",
+                    },
+                    texture.mips.len(),
+                )];
+                let (w, h) = texture.rgba.dimensions();
+                lines.extend(half_blocks(texture, (0, 0, w, h), width, rows - 1));
+                lines
+            }
+            View::Tiles => {
+                let tiles = self.matching_tiles();
+                let Some((name, tile)) = tiles.get(self.selected) else {
+                    return vec![String::from("no tile matches the search")];
+                };
+                let mut notes: Vec<&str> = vec![];
+                if tile.rotated {
+                    notes.push("rotated");
+                }
+                if tile.trim.is_some() {
+                    notes.push("trimmed");
+                }
+                if tile.kind == TileKind::Mask {
+                    notes.push("mask");
+                }
+                let mut lines = vec![format!(
+                    "{name}  in {} at {},{}  {}",
+                    tile.atlas,
+                    tile.min.x,
+                    tile.min.y,
+                    notes.join(" ")
+                )];
+                let texture = self.assets.textures.iter().find(|e| e.name == tile.atlas);
+                if let Some(texture) = texture {
+                    let size = tile.max - tile.min;
+                    let region = (tile.min.x, tile.min.y, size.x, size.y);
+                    lines.extend(half_blocks(texture, region, width, rows - 1));
+                }
+                lines
+            }
+            View::Fonts => match self.assets.fonts.get(self.selected) {
+                Some(font) => font_coverage(font, width),
+                None => vec![],
+            },
+        }
+    }
+}
+
+/// Everything the views show at a glance, for when there is no terminal to browse in.
+fn overview(assets: &LoadedAssets) -> String {
+    let mut out = String::new();
+    out.push_str("textures (tile coverage):\n");
+    for texture in assets.textures.iter() {
+        let (w, h) = texture.rgba.dimensions();
+        let fill = tile_coverage(assets, texture) * 100.0;
+        out.push_str(&format!("    {}  {w}x{h}  {fill:.0}%\n", texture.name));
+    }
+    out.push_str(&format!("{} tiles\n", assets.manifest.tiles.len()));
+    for font in assets.fonts.iter() {
+        out.push_str(&format!("font {}:\n", font.font.name));
+        for line in font_coverage(font, 60).into_iter().skip(1) {
+            out.push_str(&format!("    {line}\n"));
+        }
+    }
+    out
+}
+
+/// Share of the texture covered by tiles, tiles shared by several names are counted once.
+fn tile_coverage(assets: &LoadedAssets, texture: &LoadedTexture) -> f32 {
+    let rects: HashSet<(UVec2, UVec2)> = assets
+        .manifest
+        .tiles
+        .values()
+        .filter(|tile| tile.atlas == texture.name)
+        .map(|tile| (tile.min, tile.max))
+        .collect();
+    let covered: u64 = rects
+        .iter()
+        .map(|(min, max)| ((max.x - min.x) * (max.y - min.y)) as u64)
+        .sum();
+    let (w, h) = texture.rgba.dimensions();
+    covered as f32 / (w as u64 * h as u64).max(1) as f32
+}
+
+/// Glyphs of the font per unicode block, with a bar of how much of the block they cover.
+fn font_coverage(font: &LoadedFont, width: usize) -> Vec<String> {
+    let sdf_font = &font.font;
+    let mut lines = vec![format!(
+        "{}  size {}  line height {:.1}",
+        sdf_font.name, sdf_font.font_size, sdf_font.line_metrics.new_line_size
+    )];
+    let mut blocks: BTreeMap<usize, u32> = BTreeMap::new();
+    let mut other: u32 = 0;
+    for ch in sdf_font.glyphs.keys() {
+        let code = *ch as u32;
+        match UNICODE_BLOCKS
+            .iter()
+            .position(|(_, start, end)| (*start..=*end).contains(&code))
+        {
+            Some(block) => *blocks.entry(block).or_default() += 1,
+            None => other += 1,
+        }
+    }
+    let bar_width = width.saturating_sub(48).clamp(4, 30);
+    for (block, count) in blocks {
+        let (name, start, end) = UNICODE_BLOCKS[block];
+        let share = count as f32 / (end - start + 1) as f32;
+        let filled = (share * bar_width as f32).round() as usize;
+        lines.push(format!(
+            "{name:<24} {count:>5}/{:<5} {}{}",
+            end - start + 1,
+            "█".repeat(filled),
+            "░".repeat(bar_width - filled)
+        ));
+    }
+    if other > 0 {
+        lines.push(format!("{:<24} {other:>5}", "other"));
+    }
+    lines
+}
+
+/// Renders a region of the image with two pixels per character cell using `▀` with 24 bit colors,
+/// scaled down to fit and composited over a checkerboard so transparency is visible.
+fn half_blocks(
+    texture: &LoadedTexture,
+    (x, y, w, h): (u32, u32, u32, u32),
+    cols: usize,
+    rows: usize,
+) -> Vec<String> {
+    if w == 0 || h == 0 || cols == 0 || rows == 0 {
+        return vec![];
+    }
+    let scale = (w as f32 / cols as f32)
+        .max(h as f32 / (rows * 2) as f32)
+        .max(1.0);
+    let rgba = &texture.rgba;
+    let premultiplied = texture.flags.alpha == AlphaMode::Premultiplied;
+    let out_w = (w as f32 / scale) as u32;
+    let out_h = (h as f32 / scale) as u32;
+    let pixel = |px: u32, py: u32| -> [u8; 3] {
+        let sx = x + ((px as f32 * scale) as u32).min(w - 1);
+        let sy = y + ((py as f32 * scale) as u32).min(h - 1);
+        let p = rgba.get_pixel(sx, sy);
+        let checker = if (px / 4 + py / 4).is_multiple_of(2) {
+            90.0
+        } else {
+            60.0
+        };
+        let alpha = p[3] as f32 / 255.0;
+        let color_alpha = if premultiplied { 1.0 } else { alpha };
+        std::array::from_fn(|c| (p[c] as f32 * color_alpha + checker * (1.0 - alpha)) as u8)
+    };
+    (0..out_h.div_ceil(2))
+        .map(|row| {
+            let mut line = String::new();
+            for px in 0..out_w {
+                let top = pixel(px, row * 2);
+                let bottom = match row * 2 + 1 < out_h {
+                    true => pixel(px, row * 2 + 1),
+                    false => [0, 0, 0],
+                };
+                line.push_str(&format!(
+                    "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
+                    top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+                ));
+            }
+            line
+        })
+        .collect()
+}
+
+/// Truncates to `width` characters.
+fn fit(s: &str, width: usize) -> String {
+    match s.chars().count() > width {
+        true => s
+            .chars()
+            .take(width.saturating_sub(1))
+            .chain(['…'])
+            .collect(),
+        false => s.to_string(),
+    }
+}
+
+fn read_key(stdin: &mut std::io::Stdin) -> Option<Key> {
+    let mut byte = [0u8; 1];
+    stdin.read_exact(&mut byte).ok()?;
+    Some(match byte[0] {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7F | 0x08 => Key::Backspace,
+        0x1B => {
+            // arrow keys arrive as escape, '[' and a letter, a lone escape is followed by nothing
+            let mut sequence = [0u8; 2];
+            match stdin.read_exact(&mut sequence) {
+                Ok(()) if sequence == *b"[A" => Key::Up,
+                Ok(()) if sequence == *b"[B" => Key::Down,
+                _ => Key::Escape,
+            }
+        }
+        byte => Key::Char(byte as char),
+    })
+}
+
+/// Rows and columns of the terminal, 24x80 if they cannot be determined.
+fn terminal_size() -> (usize, usize) {
+    stty(&["size"])
+        .ok()
+        .and_then(|size| {
+            let (rows, cols) = size.trim().split_once(' ')?;
+            Some((rows.parse().ok()?, cols.parse().ok()?))
+        })
+        .unwrap_or((24, 80))
+}
+
+/// Runs `stty` on the terminal, which switches it into raw mode without depending on a terminal crate.
+fn stty(args: &[&str]) -> PackResult<String> {
+    let tty = std::fs::File::open("/dev/tty").map_err(PackError::io("/dev/tty"))?;
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(tty)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(PackError::io("stty"))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
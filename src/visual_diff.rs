@@ -0,0 +1,231 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use glam::{uvec2, UVec2};
+use image::{imageops, GenericImage, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+use crate::{
+    error::{PackError, PackResult},
+    loader::{LoadedAssets, LoadedTexture},
+};
+
+/// How the images of atlases that changed between two packs are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiffImageMode {
+    /// the old atlas, the new atlas and the highlighted differences next to each other
+    #[default]
+    SideBySide,
+    /// only the new atlas, dimmed to gray, with the differing pixels in magenta
+    Highlight,
+}
+
+/// What happened to a tile between two packs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TileChange {
+    Added,
+    Removed,
+    /// the size of the tile changed, so its pixels cannot be compared
+    Resized {
+        old: UVec2,
+        new: UVec2,
+    },
+    /// `changed` of `total` pixels differ, by up to `max_delta` in one channel
+    Pixels {
+        changed: u64,
+        total: u64,
+        max_delta: u8,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct TileDiff {
+    pub name: String,
+    pub change: TileChange,
+}
+
+/// Differences between the shipped pixels of two packs.
+#[derive(Debug, Clone, Default)]
+pub struct VisualDiff {
+    /// changed tiles, sorted by name
+    pub tiles: Vec<TileDiff>,
+    pub unchanged_tiles: usize,
+    /// diff images written for the atlases whose pixels differ
+    pub images: Vec<String>,
+}
+
+impl VisualDiff {
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty() && self.images.is_empty()
+    }
+}
+
+/// Compares the tiles of the packs in `old_dir` and `new_dir` pixel by pixel, and writes an image for
+/// every atlas that differs into `out_dir`, named like `{atlas}.diff.png`. Neither pack is modified.
+///
+/// Tiles are compared by their own pixels, so tiles that only moved to another place or atlas count
+/// as unchanged. Rotated tiles are compared in the orientation of their source image.
+pub fn visual_diff(
+    old_dir: &str,
+    new_dir: &str,
+    out_dir: Option<&str>,
+    mode: DiffImageMode,
+) -> PackResult<VisualDiff> {
+    let (old, new) = rayon::join(
+        || LoadedAssets::load(old_dir),
+        || LoadedAssets::load(new_dir),
+    );
+    let (old, new) = (old?, new?);
+
+    let names: BTreeSet<&String> = old
+        .manifest
+        .tiles
+        .keys()
+        .chain(new.manifest.tiles.keys())
+        .collect();
+    let changes: Vec<Option<TileDiff>> = names
+        .par_iter()
+        .map(|name| {
+            let change = match (old.tile_handle(name), new.tile_handle(name)) {
+                (Some(_), None) => Some(TileChange::Removed),
+                (None, Some(_)) => Some(TileChange::Added),
+                _ => compare_tiles(&tile_pixels(&old, name), &tile_pixels(&new, name)),
+            };
+            change.map(|change| TileDiff {
+                name: name.to_string(),
+                change,
+            })
+        })
+        .collect();
+    let tiles: Vec<TileDiff> = changes.into_iter().flatten().collect();
+    let unchanged_tiles = names.len() - tiles.len();
+
+    let mut images: Vec<String> = vec![];
+    if let Some(out_dir) = out_dir {
+        std::fs::create_dir_all(out_dir).map_err(PackError::io(out_dir))?;
+        for old_texture in old.textures.iter() {
+            let Some(new_texture) = new.textures.iter().find(|e| e.name == old_texture.name) else {
+                continue;
+            };
+            if old_texture.rgba == new_texture.rgba {
+                continue;
+            }
+            let path = format!("{out_dir}/{}.diff.png", old_texture.name);
+            diff_image(old_texture, new_texture, mode)
+                .save(&path)
+                .map_err(PackError::image(&path))?;
+            images.push(path);
+        }
+    }
+    Ok(VisualDiff {
+        tiles,
+        unchanged_tiles,
+        images,
+    })
+}
+
+/// The pixels of a tile in the orientation of its source image.
+fn tile_pixels(assets: &LoadedAssets, name: &str) -> RgbaImage {
+    let tile = &assets.manifest.tiles[name];
+    let texture = assets.texture(assets.tile(assets.tile_handle(name).unwrap()).texture);
+    let size = tile.max - tile.min;
+    let region = imageops::crop_imm(&texture.rgba, tile.min.x, tile.min.y, size.x, size.y);
+    match tile.rotated {
+        true => imageops::rotate270(&*region),
+        false => region.to_image(),
+    }
+}
+
+fn compare_tiles(old: &RgbaImage, new: &RgbaImage) -> Option<TileChange> {
+    if old.dimensions() != new.dimensions() {
+        return Some(TileChange::Resized {
+            old: old.dimensions().into(),
+            new: new.dimensions().into(),
+        });
+    }
+    let mut changed: u64 = 0;
+    let mut max_delta: u8 = 0;
+    for (a, b) in old.pixels().zip(new.pixels()) {
+        let delta = (0..4).map(|c| a[c].abs_diff(b[c])).max().unwrap();
+        if delta > 0 {
+            changed += 1;
+            max_delta = max_delta.max(delta);
+        }
+    }
+    (changed > 0).then_some(TileChange::Pixels {
+        changed,
+        total: old.width() as u64 * old.height() as u64,
+        max_delta,
+    })
+}
+
+/// The new atlas dimmed to gray with the pixels that differ from the old one in magenta, including
+/// the area only one of them covers if their sizes differ.
+fn highlight(old: &RgbaImage, new: &RgbaImage) -> RgbaImage {
+    let size = uvec2(old.width().max(new.width()), old.height().max(new.height()));
+    RgbaImage::from_fn(size.x, size.y, |x, y| {
+        let a = old.get_pixel_checked(x, y);
+        let b = new.get_pixel_checked(x, y);
+        match (a, b) {
+            (Some(a), Some(b)) if a == b => {
+                let gray = (b[0] as u32 + b[1] as u32 + b[2] as u32) / 3;
+                let gray = (gray * b[3] as u32 / 255 / 3 + 40) as u8;
+                Rgba([gray, gray, gray, 255])
+            }
+            _ => Rgba([255, 0, 255, 255]),
+        }
+    })
+}
+
+fn diff_image(old: &LoadedTexture, new: &LoadedTexture, mode: DiffImageMode) -> RgbaImage {
+    let highlighted = highlight(&old.rgba, &new.rgba);
+    match mode {
+        DiffImageMode::Highlight => highlighted,
+        DiffImageMode::SideBySide => {
+            const GAP: u32 = 8;
+            let parts = [&old.rgba, &new.rgba, &highlighted];
+            let width = parts.iter().map(|e| e.width() + GAP).sum::<u32>() - GAP;
+            let height = parts.iter().map(|e| e.height()).max().unwrap();
+            let mut image = RgbaImage::new(width, height);
+            let mut x = 0;
+            for part in parts {
+                image.copy_from(part, x, 0).unwrap();
+                x += part.width() + GAP;
+            }
+            image
+        }
+    }
+}
+
+/// Renders the changed tiles as a table, followed by the written images and a summary line.
+pub fn format_visual_diff(diff: &VisualDiff) -> String {
+    let mut out = String::new();
+    for tile in diff.tiles.iter() {
+        let change = match &tile.change {
+            TileChange::Added => String::from("added"),
+            TileChange::Removed => String::from("removed"),
+            TileChange::Resized { old, new } => {
+                format!("resized {}x{} -> {}x{}", old.x, old.y, new.x, new.y)
+            }
+            TileChange::Pixels {
+                changed,
+                total,
+                max_delta,
+            } => format!(
+                "{changed} of {total} pixels changed ({:.1}%), by up to {max_delta}",
+                *changed as f64 / *total as f64 * 100.0
+            ),
+        };
+        writeln!(out, "{:<32} {change}", tile.name).unwrap();
+    }
+    for image in diff.images.iter() {
+        writeln!(out, "wrote {image}").unwrap();
+    }
+    writeln!(
+        out,
+        "{} tiles changed, {} unchanged",
+        diff.tiles.len(),
+        diff.unchanged_tiles
+    )
+    .unwrap();
+    out
+}
@@ -0,0 +1,127 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Problems that do not stop a pack, but are likely mistakes in the asset tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum WarningCode {
+    /// an image has the same pixels as another one
+    W001,
+    /// an image does not fit into the atlas
+    W002,
+    /// the opposite edges of a repeat image do not match, so it shows seams when tiled
+    W003,
+    /// asset names collide after converting them to identifiers of generated code
+    W004,
+    /// the atlas size was reduced to meet the engine capabilities
+    W005,
+    /// a file in the images dir is skipped because its extension is no known image format
+    W006,
+    /// a font exceeds its glyph budget, so its characters of the lowest priority are skipped
+    W007,
+    /// the atlas pages are larger than the size budget even with the lossy pages
+    W008,
+    /// the stats of the pack could not be appended to the stats history
+    W009,
+}
+
+impl WarningCode {
+    pub const ALL: [WarningCode; 9] = [
+        WarningCode::W001,
+        WarningCode::W002,
+        WarningCode::W003,
+        WarningCode::W004,
+        WarningCode::W005,
+        WarningCode::W006,
+        WarningCode::W007,
+        WarningCode::W008,
+        WarningCode::W009,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningCode::W001 => "duplicate-looking asset",
+            WarningCode::W002 => "oversized image",
+            WarningCode::W003 => "non-seamless repeat",
+            WarningCode::W004 => "identifier collision",
+            WarningCode::W005 => "atlas size reduced",
+            WarningCode::W006 => "unknown file type",
+            WarningCode::W007 => "glyphs skipped",
+            WarningCode::W008 => "size budget exceeded",
+            WarningCode::W009 => "stats history not written",
+        }
+    }
+}
+
+impl Display for WarningCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Which warnings to suppress, part of the [`crate::pack::PackSettings`].
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct WarningSettings {
+    /// suppressed for all assets
+    pub suppress: Vec<WarningCode>,
+    /// asset file or directory relative to the source dir, e.g. `images/tiles/water.rep.png` -> the
+    /// warnings suppressed for it
+    pub suppress_for: BTreeMap<String, Vec<WarningCode>>,
+}
+
+/// Collects the warnings of one pack, prints them as they occur and a summary at the end.
+pub struct Warnings {
+    src_dir: PathBuf,
+    suppress: HashSet<WarningCode>,
+    suppress_for: BTreeMap<String, Vec<WarningCode>>,
+    /// code -> (shown, suppressed)
+    counts: BTreeMap<WarningCode, (usize, usize)>,
+}
+
+impl Warnings {
+    pub fn new(src_dir: &str, settings: &WarningSettings) -> Self {
+        Warnings {
+            src_dir: PathBuf::from(src_dir),
+            suppress: settings.suppress.iter().copied().collect(),
+            suppress_for: settings.suppress_for.clone(),
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Reports a warning, `path` is the source file it is about, if there is one.
+    pub fn warn(&mut self, code: WarningCode, path: Option<&Path>, message: impl Display) {
+        let counts = self.counts.entry(code).or_default();
+        let suppressed = self.suppress.contains(&code)
+            || path.is_some_and(|path| {
+                let relative = path.strip_prefix(&self.src_dir).unwrap_or(path);
+                self.suppress_for.iter().any(|(rule_path, codes)| {
+                    relative.starts_with(rule_path) && codes.contains(&code)
+                })
+            });
+        if suppressed {
+            counts.1 += 1;
+        } else {
+            counts.0 += 1;
+            println!("warning[{code}]: {message}");
+        }
+    }
+
+    /// Prints a table with the number of warnings per code, if there were any.
+    pub fn print_summary(&self) {
+        if self.counts.is_empty() {
+            return;
+        }
+        println!("warnings:");
+        println!("    code  shown  suppressed");
+        for code in WarningCode::ALL {
+            if let Some((shown, suppressed)) = self.counts.get(&code) {
+                println!("    {code}  {shown:>5}  {suppressed:>10}  {}", code.name());
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    cache::Cache,
+    gather::{SIDECAR_EXTENSION, SOURCE_EXTENSIONS},
+    ignore::IgnoreRules,
+    job::Pipeline,
+    pack::PackSettings,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// modification time and length of every relevant file in the source dir
+type Snapshot = BTreeMap<PathBuf, (SystemTime, u64)>;
+
+/// Packs once and then repacks whenever an image, font, svg, sidecar, data file or the ignore file in
+/// `src_dir` is added, removed or modified.
+///
+/// Polls the file system instead of relying on platform specific notifications, which is cheap enough
+/// for the asset trees of small games.
+pub fn watch(src_dir: &str, dest_dir: &str, settings: &PackSettings, cache: &mut Cache) {
+    let mut last = snapshot(src_dir, settings);
+    repack(src_dir, dest_dir, settings, cache, &mut last);
+    println!("watching {src_dir} for changes...");
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let current = snapshot(src_dir, settings);
+        if current != last {
+            last = current;
+            println!("change detected, repacking:");
+            repack(src_dir, dest_dir, settings, cache, &mut last);
+        }
+    }
+}
+
+/// Packs one stage at a time and also polls for changes between the stages. A change cancels the
+/// pack and starts a new one, so fast successive saves do not queue up full packs of outdated files.
+///
+/// an error (e.g. a half written png) should not stop the watcher, the next change will trigger another pack anyway.
+fn repack(
+    src_dir: &str,
+    dest_dir: &str,
+    settings: &PackSettings,
+    cache: &mut Cache,
+    last: &mut Snapshot,
+) {
+    'restart: loop {
+        let mut pipeline = Pipeline::new(src_dir, dest_dir, settings);
+        let mut last_poll = Instant::now();
+        loop {
+            match pipeline.run_stage(cache) {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(e) => {
+                    eprintln!("error: {e}");
+                    println!("packing failed, waiting for further changes...");
+                    return;
+                }
+            }
+            if last_poll.elapsed() < POLL_INTERVAL {
+                continue;
+            }
+            last_poll = Instant::now();
+            let current = snapshot(src_dir, settings);
+            if current != *last {
+                *last = current;
+                pipeline.cancel(cache);
+                println!("change detected while packing, restarting:");
+                continue 'restart;
+            }
+        }
+    }
+}
+
+fn snapshot(dir: &str, settings: &PackSettings) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    _snapshot(
+        Path::new(dir),
+        settings,
+        &mut snapshot,
+        &mut BTreeSet::new(),
+    );
+    snapshot
+}
+
+/// Follows symlinks, so the files of linked dirs are watched too. `visited` are the canonical
+/// paths of the dirs already in the snapshot, which stops symlinks that loop.
+fn _snapshot(
+    dir: &Path,
+    settings: &PackSettings,
+    snapshot: &mut Snapshot,
+    visited: &mut BTreeSet<PathBuf>,
+) {
+    if !dir.canonicalize().is_ok_and(|e| visited.insert(e)) {
+        return;
+    }
+    let Ok(dir) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in dir.flatten() {
+        let path = entry.path();
+        let Ok(meta) = std::fs::metadata(&path) else {
+            continue;
+        };
+        if meta.is_dir() {
+            _snapshot(&path, settings, snapshot, visited);
+        } else if path.extension().is_some_and(|ext| {
+            SOURCE_EXTENSIONS.iter().any(|e| ext == *e)
+                || ext == "json"
+                || ext == SIDECAR_EXTENSION
+                || settings.extensions.contains_key(&*ext.to_string_lossy())
+        }) || path
+            .file_name()
+            .is_some_and(|e| IgnoreRules::is_ignore_file(&e.to_string_lossy()))
+        {
+            let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            snapshot.insert(path, (modified, meta.len()));
+        }
+    }
+}
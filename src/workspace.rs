@@ -0,0 +1,87 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    cache::Cache,
+    error::{PackError, PackResult},
+    Packer,
+};
+
+/// A json file listing several projects of an asset monorepo that are packed together with one
+/// cache, like this:
+///
+/// ```json
+/// {
+///     "profiles": { "release": { "texture_format": "bc7", "mip_levels": 2 } },
+///     "projects": [
+///         { "src": "game_a/assets", "dest": "game_a/packed", "profile": "release" },
+///         { "src": "tools/editor_assets", "dest": "tools/packed" }
+///     ]
+/// }
+/// ```
+///
+/// Paths are relative to the workspace file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Workspace {
+    /// the shared cache, `<workspace file name>.cache` next to the workspace file by default
+    #[serde(default)]
+    pub cache_dir: Option<String>,
+    /// name -> settings that override the `assetpacker.json` of the projects using the profile
+    #[serde(default)]
+    pub profiles: BTreeMap<String, serde_json::Map<String, serde_json::Value>>,
+    pub projects: Vec<WorkspaceProject>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceProject {
+    pub src: String,
+    pub dest: String,
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+/// Packs all projects of the workspace file at `path`. A failing project does not stop the others,
+/// the errors of all of them are returned together.
+pub fn pack_workspace(path: &str) -> PackResult<()> {
+    let bytes = std::fs::read(path).map_err(PackError::io(path))?;
+    let workspace: Workspace = serde_json::from_slice(&bytes).map_err(|e| PackError::Config {
+        path: PathBuf::from(path),
+        message: e.to_string(),
+    })?;
+    let base = Path::new(path).parent().unwrap_or(Path::new(""));
+    let relative = |e: &str| base.join(e).to_string_lossy().into_owned();
+    let cache_dir = match &workspace.cache_dir {
+        Some(cache_dir) => relative(cache_dir),
+        None => format!("{path}.cache"),
+    };
+    let mut cache = Cache::load(cache_dir)?;
+
+    cache.start_batch();
+    let mut errors: Vec<PackError> = vec![];
+    for project in workspace.projects.iter() {
+        let (src, dest) = (relative(&project.src), relative(&project.dest));
+        println!("project {src} -> {dest}:");
+        let mut packer = Packer::new(&src);
+        if let Some(name) = &project.profile {
+            let Some(profile) = workspace.profiles.get(name) else {
+                errors.push(PackError::Config {
+                    path: PathBuf::from(path),
+                    message: format!("project {} uses the unknown profile {name}", project.src),
+                });
+                continue;
+            };
+            packer = packer.profile(profile);
+        }
+        if let Err(e) = packer.pack_with_cache(&dest, &mut cache) {
+            errors.push(e);
+        }
+    }
+    cache.finish_batch();
+    PackError::from_errors(errors)
+}